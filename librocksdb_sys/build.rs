@@ -1,5 +1,5 @@
 use std::{env, fs, str};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 macro_rules! t {
@@ -10,18 +10,19 @@ macro_rules! t {
 }
 
 fn main() {
+    let dst = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let want_static = env::var("ROCKSDB_SYS_STATIC").map(|s| s == "1").unwrap_or(false);
-    if !want_static {
-        return;
-    }
-
     let target = env::var("TARGET").unwrap();
-    if !target.contains("linux") && !target.contains("darwin") {
-        // only linux and apple support static link right now
+    // only linux and apple support static link right now
+    if !want_static || (!target.contains("linux") && !target.contains("darwin")) {
+        if env::var_os("CARGO_FEATURE_CACHE_DUMP").is_some() {
+            let include = env::var_os("ROCKSDB_INCLUDE_DIR").map(PathBuf::from);
+            compile_shim(&dst, include.as_ref().map(|p| p.as_path()));
+            println!("cargo:rustc-link-lib=rocksdb");
+        }
         return;
     }
 
-    let dst = PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let build = dst.join("build");
     t!(fs::create_dir_all(&build));
 
@@ -52,6 +53,17 @@ fn main() {
         }
     }
 
+    if env::var_os("CARGO_FEATURE_CACHE_DUMP").is_some() {
+        // The headers of the rocksdb build.sh unpacked.
+        let include = t!(fs::read_dir(&build))
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .find(|p| {
+                p.is_dir() &&
+                p.file_name().map_or(false, |n| n.to_string_lossy().starts_with("rocksdb-"))
+            })
+            .map(|p| p.join("include"));
+        compile_shim(&dst, include.as_ref().map(|p| p.as_path()));
+    }
     println!("cargo:rustc-link-lib=static=rocksdb");
     println!("cargo:rustc-link-lib=static=z");
     println!("cargo:rustc-link-lib=static=bz2");
@@ -90,6 +102,26 @@ fn main() {
     }
 }
 
+// Build the C++ shim for what the C API of rocksdb lacks, against the
+// headers in `include`, or in the default include paths if it is `None`.
+// It must be linked before rocksdb.
+fn compile_shim(dst: &Path, include: Option<&Path>) {
+    let fest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let src = PathBuf::from(fest_dir).join("shim").join("cache_dump.cc");
+    let obj = dst.join("cache_dump.o");
+    let cxx = env::var("CXX").unwrap_or("c++".to_owned());
+    let mut cmd = Command::new(cxx);
+    cmd.args(&["-std=c++17", "-O2", "-fPIC", "-c"]).arg(&src).arg("-o").arg(&obj);
+    if let Some(include) = include {
+        cmd.arg("-I").arg(include);
+    }
+    run(&mut cmd);
+    run(Command::new("ar").arg("crs").arg(dst.join("librocksdb_shim.a")).arg(&obj));
+
+    println!("cargo:rustc-link-lib=static=rocksdb_shim");
+    println!("cargo:rustc-link-search=native={}", dst.display());
+}
+
 fn run(cmd: &mut Command) {
     println!("running: {:?}", cmd);
     let status = match cmd.status() {
@@ -14,20 +14,46 @@ function error() {
     return 1
 }
 
-function md5_check() {
+function sha256_of() {
+    if which sha256sum &>/dev/null; then
+        sha256sum $1 | cut -d ' ' -f 1
+    elif which shasum &>/dev/null; then
+        shasum -a 256 $1 | cut -d ' ' -f 1
+    elif which openssl &>/dev/null; then
+        openssl dgst -sha256 -hex $1 | cut -d ' ' -f 2
+    else
+        error can\'t find sha256 tool.
+    fi
+}
+
+function md5_of() {
     if which md5sum &>/dev/null; then
-        hash=`md5sum $1 | cut -d ' ' -f 1`
+        md5sum $1 | cut -d ' ' -f 1
     elif which openssl &>/dev/null; then
-        hash=`openssl md5 -hex $1 | cut -d ' ' -f 2`
+        openssl md5 -hex $1 | cut -d ' ' -f 2
     else
         error can\'t find hash tool.
     fi
+}
+
+# Check $1 against the hash $2, a sha256 if it has 64 digits, else a md5.
+function hash_check() {
+    if [[ -z "$2" ]]; then
+        error $1: no hash to check against
+        return 1
+    fi
+
+    if [[ ${#2} -eq 64 ]]; then
+        hash=`sha256_of $1`
+    else
+        hash=`md5_of $1`
+    fi
 
     [[ "$hash" == "$2" ]] || error $1: hash not correct, expect $2, got $hash
 }
 
 function download() {
-    if [[ -f $2 ]] && md5_check $2 $3; then
+    if [[ -f $2 ]] && hash_check $2 $3; then
         return
     fi
 
@@ -39,7 +65,7 @@ function download() {
         error can\'t find wget and curl.
     fi
 
-    md5_check $2 $3
+    hash_check $2 $3
 }
 
 function compile_z() {
@@ -105,11 +131,13 @@ function compile_rocksdb() {
         return
     fi
 
-    version=v4.9
-	vernum=4.9
+    version=v8.10.0
+    vernum=8.10.0
+    # The sha256 of the $version tarball, as published with the release.
+    rocksdb_sha256=${ROCKSDB_SHA256:?set ROCKSDB_SHA256 to the sha256 of the rocksdb $version tarball}
     echo building rocksdb-$version
     rm -rf rocksdb-$vernum
-    download https://github.com/facebook/rocksdb/archive/$version.tar.gz rocksdb-$version.tar.gz ba59206bd041a1d37157b9423d53572e
+    download https://github.com/facebook/rocksdb/archive/$version.tar.gz rocksdb-$version.tar.gz $rocksdb_sha256
     tar xf rocksdb-$version.tar.gz
     wd=`pwd`
     cd rocksdb-$vernum
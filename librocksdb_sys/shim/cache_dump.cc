@@ -0,0 +1,129 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// Block cache dumps, which rocksdb only has in its C++ API, for the handles
+// of its C API.
+
+#include <stdlib.h>
+#include <string.h>
+
+#include <memory>
+#include <string>
+#include <vector>
+
+#include "rocksdb/cache.h"
+#include "rocksdb/db.h"
+#include "rocksdb/file_system.h"
+#include "rocksdb/secondary_cache.h"
+#include "rocksdb/system_clock.h"
+#include "rocksdb/table.h"
+#include "rocksdb/utilities/cache_dump_load.h"
+
+using rocksdb::BlockBasedTableOptions;
+using rocksdb::Cache;
+using rocksdb::CacheDumpOptions;
+using rocksdb::CacheDumpReader;
+using rocksdb::CacheDumpWriter;
+using rocksdb::CacheDumpedLoader;
+using rocksdb::CacheDumper;
+using rocksdb::CompressedSecondaryCacheOptions;
+using rocksdb::DB;
+using rocksdb::FileOptions;
+using rocksdb::FileSystem;
+using rocksdb::LRUCacheOptions;
+using rocksdb::SecondaryCache;
+using rocksdb::Status;
+using rocksdb::SystemClock;
+
+// The same as in rocksdb's c.cc.
+struct rocksdb_t {
+  DB* rep;
+};
+
+struct rocksdb_cache_t {
+  std::shared_ptr<Cache> rep;
+};
+
+static bool SaveError(char** errptr, const Status& s) {
+  if (s.ok()) {
+    return false;
+  }
+  free(*errptr);
+  *errptr = strdup(s.ToString().c_str());
+  return true;
+}
+
+static CacheDumpOptions DumpOptions() {
+  CacheDumpOptions opts;
+  opts.clock = SystemClock::Default().get();
+  return opts;
+}
+
+extern "C" {
+
+void rocksdb_shim_cache_dump(rocksdb_cache_t* cache, rocksdb_t* db,
+                             const char* path, char** errptr) {
+  std::unique_ptr<CacheDumpWriter> writer;
+  Status s = rocksdb::NewToFileCacheDumpWriter(FileSystem::Default(),
+                                               FileOptions(), path, &writer);
+  if (SaveError(errptr, s)) {
+    return;
+  }
+  std::unique_ptr<CacheDumper> dumper;
+  s = rocksdb::NewDefaultCacheDumper(DumpOptions(), cache->rep,
+                                     std::move(writer), &dumper);
+  if (SaveError(errptr, s)) {
+    return;
+  }
+  s = dumper->SetDumpFilter(std::vector<DB*>{db->rep});
+  if (SaveError(errptr, s)) {
+    return;
+  }
+  SaveError(errptr, dumper->DumpCacheEntriesToWriter());
+}
+
+rocksdb_cache_t* rocksdb_shim_cache_create_lru_from_dump(
+    size_t capacity, size_t secondary_capacity, const char* path,
+    char** errptr) {
+  CompressedSecondaryCacheOptions secondary_opts;
+  secondary_opts.capacity = secondary_capacity;
+  std::shared_ptr<SecondaryCache> secondary =
+      rocksdb::NewCompressedSecondaryCache(secondary_opts);
+
+  std::unique_ptr<CacheDumpReader> reader;
+  Status s = rocksdb::NewFromFileCacheDumpReader(FileSystem::Default(),
+                                                 FileOptions(), path, &reader);
+  if (SaveError(errptr, s)) {
+    return nullptr;
+  }
+  std::unique_ptr<CacheDumpedLoader> loader;
+  s = rocksdb::NewDefaultCacheDumpedLoader(DumpOptions(),
+                                           BlockBasedTableOptions(), secondary,
+                                           std::move(reader), &loader);
+  if (SaveError(errptr, s)) {
+    return nullptr;
+  }
+  if (SaveError(errptr, loader->RestoreCacheEntriesToSecondaryCache())) {
+    return nullptr;
+  }
+
+  LRUCacheOptions opts;
+  opts.capacity = capacity;
+  opts.secondary_cache = secondary;
+  rocksdb_cache_t* cache = new rocksdb_cache_t;
+  cache->rep = rocksdb::NewLRUCache(opts);
+  return cache;
+}
+
+}  // extern "C"
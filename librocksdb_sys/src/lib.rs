@@ -17,9 +17,9 @@ extern crate libc;
 #[cfg(test)]
 extern crate tempdir;
 
-use libc::{c_char, c_uchar, c_int, c_void, size_t, uint64_t};
+use libc::{c_char, c_double, c_uchar, c_int, c_uint, c_void, size_t,
+           uint64_t};
 use std::ffi::CStr;
-use std::str::from_utf8;
 
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -62,7 +62,55 @@ pub struct DBWriteBatch(pub *const c_void);
 pub struct DBComparator(pub *const c_void);
 #[derive(Copy, Clone)]
 #[repr(C)]
+pub struct DBCompactionFilter(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
 pub struct DBFlushOptions(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBPath(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBEnv(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBRateLimiter(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBLiveFiles(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBCheckpoint(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBBackupEngine(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBBackupEngineInfo(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBRestoreOptions(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBLRUCacheOptions(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBPinnableSlice(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBWalIterator(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBFIFOCompactionOptions(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBEnvOptions(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBSstFileWriter(pub *const c_void);
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DBIngestExternalFileOptions(pub *const c_void);
 
 pub fn new_bloom_filter(bits: c_int) -> DBFilterPolicy {
     unsafe { rocksdb_filterpolicy_create_bloom(bits) }
@@ -90,15 +138,44 @@ pub enum DBCompactionStyle {
     DBFifo = 2,
 }
 
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub enum DBAccessHint {
+    DBAccessNone = 0,
+    DBAccessNormal = 1,
+    DBAccessSequential = 2,
+    DBAccessWillNeed = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub enum DBChecksumType {
+    DBNoChecksum = 0,
+    DBCRC32c = 1,
+    DBxxHash = 2,
+    DBxxHash64 = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub enum DBDataBlockIndexType {
+    DBBinarySearch = 0,
+    DBBinarySearchAndHash = 1,
+}
+
 #[repr(C)]
 pub enum DBUniversalCompactionStyle {
     rocksdb_similar_size_compaction_stop_style = 0,
     rocksdb_total_size_compaction_stop_style = 1,
 }
 
+/// Take the error rocksdb returned in `ptr`, which is freed.
+///
+/// Only called once an error is set: the success path of a call is the
+/// null check of its error pointer, and allocates nothing.
 pub fn error_message(ptr: *const i8) -> String {
     let c_str = unsafe { CStr::from_ptr(ptr as *const _) };
-    let s = from_utf8(c_str.to_bytes()).unwrap().to_owned();
+    let s = String::from_utf8_lossy(c_str.to_bytes()).into_owned();
     unsafe {
         libc::free(ptr as *mut c_void);
     }
@@ -111,8 +188,19 @@ pub fn error_message(ptr: *const i8) -> String {
 extern "C" {
     pub fn rocksdb_options_create() -> DBOptions;
     pub fn rocksdb_options_destroy(opts: DBOptions);
+    pub fn rocksdb_options_create_copy(opts: DBOptions) -> DBOptions;
     pub fn rocksdb_cache_create_lru(capacity: size_t) -> DBCache;
     pub fn rocksdb_cache_destroy(cache: DBCache);
+    pub fn rocksdb_cache_get_usage(cache: DBCache) -> size_t;
+    pub fn rocksdb_cache_get_pinned_usage(cache: DBCache) -> size_t;
+    pub fn rocksdb_lru_cache_options_create() -> DBLRUCacheOptions;
+    pub fn rocksdb_lru_cache_options_destroy(opts: DBLRUCacheOptions);
+    pub fn rocksdb_lru_cache_options_set_capacity(opts: DBLRUCacheOptions,
+                                                  capacity: size_t);
+    pub fn rocksdb_lru_cache_options_set_high_pri_pool_ratio(
+        opts: DBLRUCacheOptions,
+        ratio: c_double);
+    pub fn rocksdb_cache_create_lru_opts(opts: DBLRUCacheOptions) -> DBCache;
     pub fn rocksdb_block_based_options_create() -> DBBlockBasedTableOptions;
     pub fn rocksdb_block_based_options_destroy(opts: DBBlockBasedTableOptions);
     pub fn rocksdb_block_based_options_set_block_size(
@@ -126,6 +214,10 @@ extern "C" {
         block_restart_interval: c_int);
     pub fn rocksdb_block_based_options_set_cache_index_and_filter_blocks(
         block_options: DBBlockBasedTableOptions, v: c_uchar);
+    pub fn rocksdb_block_based_options_set_cache_index_and_filter_blocks_with_high_priority(
+        block_options: DBBlockBasedTableOptions, v: c_uchar);
+    pub fn rocksdb_block_based_options_set_pin_top_level_index_and_filter(
+        block_options: DBBlockBasedTableOptions, v: c_uchar);
     pub fn rocksdb_block_based_options_set_filter_policy(
         block_options: DBBlockBasedTableOptions,
         filter_policy: DBFilterPolicy);
@@ -138,6 +230,15 @@ extern "C" {
         block_cache_compressed: DBCache);
     pub fn rocksdb_block_based_options_set_whole_key_filtering(
         ck_options: DBBlockBasedTableOptions, doit: bool);
+    pub fn rocksdb_block_based_options_set_checksum(
+        block_options: DBBlockBasedTableOptions, checksum: c_char);
+    pub fn rocksdb_block_based_options_set_format_version(
+        block_options: DBBlockBasedTableOptions, format_version: c_int);
+    pub fn rocksdb_block_based_options_set_data_block_index_type(
+        block_options: DBBlockBasedTableOptions,
+        index_type: DBDataBlockIndexType);
+    pub fn rocksdb_block_based_options_set_data_block_hash_ratio(
+        block_options: DBBlockBasedTableOptions, ratio: c_double);
     pub fn rocksdb_options_set_block_based_table_factory(
         options: DBOptions,
         block_options: DBBlockBasedTableOptions);
@@ -146,16 +247,36 @@ extern "C" {
     pub fn rocksdb_options_optimize_level_style_compaction(
         options: DBOptions, memtable_memory_budget: c_int);
     pub fn rocksdb_options_set_create_if_missing(options: DBOptions, v: bool);
+    pub fn rocksdb_options_set_create_missing_column_families(options: DBOptions,
+                                                              v: bool);
+    pub fn rocksdb_dbpath_create(path: *const c_char,
+                                 target_size: u64)
+                                 -> DBPath;
+    pub fn rocksdb_dbpath_destroy(path: DBPath);
+    pub fn rocksdb_options_set_db_paths(options: DBOptions,
+                                        path_values: *const DBPath,
+                                        num_paths: size_t);
+    pub fn rocksdb_options_set_wal_dir(options: DBOptions,
+                                       wal_dir: *const c_char);
     pub fn rocksdb_options_set_max_open_files(options: DBOptions,
                                               files: c_int);
     pub fn rocksdb_options_set_use_fsync(options: DBOptions, v: c_int);
     pub fn rocksdb_options_set_bytes_per_sync(options: DBOptions, bytes: u64);
-    pub fn rocksdb_options_set_disable_data_sync(options: DBOptions,
-                                                 v: c_int);
-    pub fn rocksdb_options_set_allow_os_buffer(options: DBOptions,
-                                               is_allow: bool);
+    pub fn rocksdb_options_set_use_direct_reads(options: DBOptions,
+                                                v: c_uchar);
+    pub fn rocksdb_options_set_use_direct_io_for_flush_and_compaction(
+        options: DBOptions,
+        v: c_uchar);
+    pub fn rocksdb_options_set_allow_mmap_reads(options: DBOptions, v: bool);
+    pub fn rocksdb_options_set_allow_mmap_writes(options: DBOptions, v: bool);
     pub fn rocksdb_options_optimize_for_point_lookup(options: DBOptions,
                                                      block_cache_size_mb: u64);
+    pub fn rocksdb_options_set_max_file_opening_threads(options: DBOptions,
+                                                        n: c_int);
+    pub fn rocksdb_options_set_skip_stats_update_on_db_open(options: DBOptions,
+                                                            v: bool);
+    pub fn rocksdb_options_set_skip_checking_sst_file_sizes_on_db_open(
+        options: DBOptions, v: bool);
     pub fn rocksdb_options_set_table_cache_numshardbits(options: DBOptions,
                                                         bits: c_int);
     pub fn rocksdb_options_set_max_write_buffer_number(options: DBOptions,
@@ -170,17 +291,54 @@ extern "C" {
                                                           no: c_int);
     pub fn rocksdb_options_set_write_buffer_size(options: DBOptions,
                                                  bytes: u64);
+    pub fn rocksdb_options_set_arena_block_size(options: DBOptions,
+                                                bytes: size_t);
+    pub fn rocksdb_options_set_memtable_huge_page_size(options: DBOptions,
+                                                       bytes: size_t);
+    pub fn rocksdb_options_set_memtable_prefix_bloom_size_ratio(
+        options: DBOptions,
+        ratio: c_double);
+    pub fn rocksdb_options_set_memtable_whole_key_filtering(options: DBOptions,
+                                                            v: c_uchar);
+    pub fn rocksdb_options_set_inplace_update_support(options: DBOptions,
+                                                      v: c_uchar);
+    pub fn rocksdb_options_set_max_successive_merges(options: DBOptions,
+                                                     n: size_t);
     pub fn rocksdb_options_set_target_file_size_base(options: DBOptions,
                                                      bytes: u64);
     pub fn rocksdb_options_set_target_file_size_multiplier(options: DBOptions,
                                                            mul: c_int);
     pub fn rocksdb_options_set_max_bytes_for_level_base(options: DBOptions,
                                                         bytes: u64);
-    pub fn rocksdb_options_set_max_bytes_for_level_multiplier(options: DBOptions, mul: c_int);
+    pub fn rocksdb_options_set_max_compaction_bytes(options: DBOptions,
+                                                    bytes: uint64_t);
+    pub fn rocksdb_options_get_num_levels(options: DBOptions) -> c_int;
+    pub fn rocksdb_options_get_level0_file_num_compaction_trigger(
+        options: DBOptions)
+        -> c_int;
+    pub fn rocksdb_options_get_max_bytes_for_level_base(options: DBOptions)
+                                                        -> uint64_t;
+    pub fn rocksdb_options_get_max_bytes_for_level_multiplier(
+        options: DBOptions)
+        -> c_double;
+    pub fn rocksdb_options_set_level_compaction_dynamic_level_bytes(
+        options: DBOptions,
+        v: c_uchar);
+    pub fn rocksdb_options_get_level_compaction_dynamic_level_bytes(
+        options: DBOptions)
+        -> c_uchar;
+    pub fn rocksdb_options_set_max_bytes_for_level_multiplier(
+        options: DBOptions, mul: c_double);
     pub fn rocksdb_options_set_max_log_file_size(options: DBOptions,
                                                  bytes: u64);
     pub fn rocksdb_options_set_max_manifest_file_size(options: DBOptions,
                                                       bytes: u64);
+    pub fn rocksdb_options_set_manifest_preallocation_size(options: DBOptions,
+                                                           bytes: size_t);
+    pub fn rocksdb_options_set_wal_bytes_per_sync(options: DBOptions,
+                                                  bytes: u64);
+    pub fn rocksdb_options_set_max_total_wal_size(options: DBOptions,
+                                                  bytes: u64);
     pub fn rocksdb_options_set_hash_skip_list_rep(options: DBOptions,
                                                   bytes: u64,
                                                   a1: i32,
@@ -196,10 +354,16 @@ extern "C" {
         options: DBOptions, max_bg_compactions: c_int);
     pub fn rocksdb_options_set_max_background_flushes(options: DBOptions,
                                                       max_bg_flushes: c_int);
-    pub fn rocksdb_options_set_filter_deletes(options: DBOptions, v: bool);
+    pub fn rocksdb_options_set_access_hint_on_compaction_start(options: DBOptions,
+                                                               hint: DBAccessHint);
     pub fn rocksdb_options_set_disable_auto_compactions(options: DBOptions,
                                                         v: c_int);
     pub fn rocksdb_options_set_report_bg_io_stats(options: DBOptions, v: c_int);
+    pub fn rocksdb_options_enable_statistics(options: DBOptions);
+    pub fn rocksdb_options_statistics_get_string(options: DBOptions)
+                                                 -> *mut c_char;
+    pub fn rocksdb_options_set_stats_dump_period_sec(options: DBOptions,
+                                                     v: c_uint);
     pub fn rocksdb_filterpolicy_create_bloom_full(bits_per_key: c_int)
                                                 -> DBFilterPolicy;
     pub fn rocksdb_filterpolicy_create_bloom(bits_per_key: c_int)
@@ -213,6 +377,8 @@ extern "C" {
     pub fn rocksdb_writeoptions_set_sync(writeopts: DBWriteOptions, v: bool);
     pub fn rocksdb_writeoptions_disable_WAL(writeopts: DBWriteOptions,
                                             v: c_int);
+    pub fn rocksdb_writeoptions_set_no_slowdown(writeopts: DBWriteOptions,
+                                                v: c_uchar);
     pub fn rocksdb_put(db: DBInstance,
                        writeopts: DBWriteOptions,
                        k: *const u8,
@@ -234,11 +400,60 @@ extern "C" {
                                                     v: bool);
     pub fn rocksdb_readoptions_set_fill_cache(readopts: DBReadOptions,
                                               v: bool);
+    pub fn rocksdb_readoptions_set_readahead_size(readopts: DBReadOptions,
+                                                  v: size_t);
     pub fn rocksdb_readoptions_set_snapshot(readopts: DBReadOptions,
                                             snapshot: DBSnapshot); //TODO how do I make this a const ref?
     pub fn rocksdb_readoptions_set_iterate_upper_bound(readopts: DBReadOptions,
                                                        k: *const u8,
                                                        kLen: size_t);
+    pub fn rocksdb_readoptions_set_iterate_lower_bound(readopts: DBReadOptions,
+                                                       k: *const u8,
+                                                       kLen: size_t);
+    pub fn rocksdb_readoptions_set_background_purge_on_iterator_cleanup(
+        readopts: DBReadOptions,
+        v: c_uchar);
+    pub fn rocksdb_readoptions_set_max_skippable_internal_keys(
+        readopts: DBReadOptions,
+        v: uint64_t);
+    pub fn rocksdb_readoptions_set_deadline(readopts: DBReadOptions,
+                                            microseconds: uint64_t);
+    pub fn rocksdb_readoptions_set_io_timeout(readopts: DBReadOptions,
+                                              microseconds: uint64_t);
+    pub fn rocksdb_readoptions_set_timestamp(readopts: DBReadOptions,
+                                             ts: *const u8,
+                                             tsLen: size_t);
+    pub fn rocksdb_readoptions_set_iter_start_ts(readopts: DBReadOptions,
+                                                 ts: *const u8,
+                                                 tsLen: size_t);
+    pub fn rocksdb_put_cf_with_ts(db: DBInstance,
+                                  writeopts: DBWriteOptions,
+                                  cf: DBCFHandle,
+                                  k: *const u8,
+                                  kLen: size_t,
+                                  ts: *const u8,
+                                  tsLen: size_t,
+                                  v: *const u8,
+                                  vLen: size_t,
+                                  err: *mut *const i8);
+    pub fn rocksdb_delete_cf_with_ts(db: DBInstance,
+                                     writeopts: DBWriteOptions,
+                                     cf: DBCFHandle,
+                                     k: *const u8,
+                                     kLen: size_t,
+                                     ts: *const u8,
+                                     tsLen: size_t,
+                                     err: *mut *const i8);
+    pub fn rocksdb_get_cf_with_ts(db: DBInstance,
+                                  readopts: DBReadOptions,
+                                  cf_handle: DBCFHandle,
+                                  k: *const u8,
+                                  kLen: size_t,
+                                  valLen: *mut size_t,
+                                  ts: *mut *mut u8,
+                                  tsLen: *mut size_t,
+                                  err: *mut *const i8)
+                                  -> *mut c_void;
     pub fn rocksdb_readoptions_set_read_tier(readopts: DBReadOptions,
                                              tier: c_int);
     pub fn rocksdb_readoptions_set_tailing(readopts: DBReadOptions, v: bool);
@@ -258,6 +473,56 @@ extern "C" {
                           valLen: *const size_t,
                           err: *mut *const i8)
                           -> *mut c_void;
+    pub fn rocksdb_key_may_exist(db: DBInstance,
+                                 readopts: DBReadOptions,
+                                 k: *const u8,
+                                 kLen: size_t,
+                                 val: *mut *mut u8,
+                                 valLen: *mut size_t,
+                                 ts: *const u8,
+                                 tsLen: size_t,
+                                 value_found: *mut u8)
+                                 -> u8;
+    pub fn rocksdb_key_may_exist_cf(db: DBInstance,
+                                    readopts: DBReadOptions,
+                                    cf_handle: DBCFHandle,
+                                    k: *const u8,
+                                    kLen: size_t,
+                                    val: *mut *mut u8,
+                                    valLen: *mut size_t,
+                                    ts: *const u8,
+                                    tsLen: size_t,
+                                    value_found: *mut u8)
+                                    -> u8;
+    pub fn rocksdb_multi_get_cf(db: DBInstance,
+                                readopts: DBReadOptions,
+                                cf_handles: *const DBCFHandle,
+                                num_keys: size_t,
+                                keys_list: *const *const u8,
+                                keys_list_sizes: *const size_t,
+                                values_list: *mut *mut u8,
+                                values_list_sizes: *mut size_t,
+                                errs: *mut *const i8);
+    pub fn rocksdb_batched_multi_get_cf(db: DBInstance,
+                                        readopts: DBReadOptions,
+                                        cf_handle: DBCFHandle,
+                                        num_keys: size_t,
+                                        keys_list: *const *const u8,
+                                        keys_list_sizes: *const size_t,
+                                        values: *mut DBPinnableSlice,
+                                        errs: *mut *const i8,
+                                        sorted_input: bool);
+    pub fn rocksdb_get_pinned_cf(db: DBInstance,
+                                 readopts: DBReadOptions,
+                                 cf_handle: DBCFHandle,
+                                 k: *const u8,
+                                 kLen: size_t,
+                                 err: *mut *const i8)
+                                 -> DBPinnableSlice;
+    pub fn rocksdb_pinnableslice_value(slice: DBPinnableSlice,
+                                       vlen: *mut size_t)
+                                       -> *const u8;
+    pub fn rocksdb_pinnableslice_destroy(slice: DBPinnableSlice);
     pub fn rocksdb_create_iterator(db: DBInstance,
                                    readopts: DBReadOptions)
                                    -> DBIterator;
@@ -265,6 +530,12 @@ extern "C" {
                                       readopts: DBReadOptions,
                                       cf_handle: DBCFHandle)
                                       -> DBIterator;
+    pub fn rocksdb_create_iterators(db: DBInstance,
+                                    readopts: DBReadOptions,
+                                    cf_handles: *const DBCFHandle,
+                                    iterators: *mut DBIterator,
+                                    size: size_t,
+                                    err: *mut *const i8);
     pub fn rocksdb_create_snapshot(db: DBInstance) -> DBSnapshot;
     pub fn rocksdb_release_snapshot(db: DBInstance, snapshot: DBSnapshot);
 
@@ -281,6 +552,25 @@ extern "C" {
                              kLen: size_t,
                              err: *mut *const i8)
                              -> *mut c_void;
+    pub fn rocksdb_singledelete(db: DBInstance,
+                                writeopts: DBWriteOptions,
+                                k: *const u8,
+                                kLen: size_t,
+                                err: *mut *const i8);
+    pub fn rocksdb_singledelete_cf(db: DBInstance,
+                                   writeopts: DBWriteOptions,
+                                   cf: DBCFHandle,
+                                   k: *const u8,
+                                   kLen: size_t,
+                                   err: *mut *const i8);
+    pub fn rocksdb_delete_range_cf(db: DBInstance,
+                                   writeopts: DBWriteOptions,
+                                   cf: DBCFHandle,
+                                   start_key: *const u8,
+                                   start_key_len: size_t,
+                                   end_key: *const u8,
+                                   end_key_len: size_t,
+                                   err: *mut *const i8);
     pub fn rocksdb_close(db: DBInstance);
     pub fn rocksdb_destroy_db(options: DBOptions,
                               path: *const i8,
@@ -343,6 +633,9 @@ extern "C" {
     pub fn rocksdb_iter_key(iter: DBIterator, klen: *mut size_t) -> *mut u8;
     pub fn rocksdb_iter_value(iter: DBIterator, vlen: *mut size_t) -> *mut u8;
     pub fn rocksdb_iter_get_error(iter: DBIterator, err: *mut *const u8);
+    pub fn rocksdb_iter_timestamp(iter: DBIterator,
+                                  tslen: *mut size_t)
+                                  -> *const u8;
     // Write batch
     pub fn rocksdb_write(db: DBInstance,
                          writeopts: DBWriteOptions,
@@ -355,6 +648,11 @@ extern "C" {
     pub fn rocksdb_writebatch_destroy(batch: DBWriteBatch);
     pub fn rocksdb_writebatch_clear(batch: DBWriteBatch);
     pub fn rocksdb_writebatch_count(batch: DBWriteBatch) -> c_int;
+    pub fn rocksdb_writebatch_set_save_point(batch: DBWriteBatch);
+    pub fn rocksdb_writebatch_rollback_to_save_point(batch: DBWriteBatch,
+                                                     err: *mut *const i8);
+    pub fn rocksdb_writebatch_pop_save_point(batch: DBWriteBatch,
+                                             err: *mut *const i8);
     pub fn rocksdb_writebatch_put(batch: DBWriteBatch,
                                   key: *const u8,
                                   klen: size_t,
@@ -384,6 +682,24 @@ extern "C" {
                                         cf: DBCFHandle,
                                         key: *const u8,
                                         klen: size_t);
+    pub fn rocksdb_writebatch_singledelete(batch: DBWriteBatch,
+                                           key: *const u8,
+                                           klen: size_t);
+    pub fn rocksdb_writebatch_singledelete_cf(batch: DBWriteBatch,
+                                              cf: DBCFHandle,
+                                              key: *const u8,
+                                              klen: size_t);
+    pub fn rocksdb_writebatch_delete_range(batch: DBWriteBatch,
+                                           start_key: *const u8,
+                                           start_key_len: size_t,
+                                           end_key: *const u8,
+                                           end_key_len: size_t);
+    pub fn rocksdb_writebatch_delete_range_cf(batch: DBWriteBatch,
+                                              cf: DBCFHandle,
+                                              start_key: *const u8,
+                                              start_key_len: size_t,
+                                              end_key: *const u8,
+                                              end_key_len: size_t);
     pub fn rocksdb_writebatch_iterate(
         batch: DBWriteBatch,
         state: *mut c_void,
@@ -410,8 +726,54 @@ extern "C" {
                                      name_fn: extern "C" fn(*mut c_void)
                                                             -> *const c_char)
                                      -> DBComparator;
+    pub fn rocksdb_comparator_with_ts_create(
+        state: *mut c_void,
+        destroy: extern "C" fn(*mut c_void) -> (),
+        compare: extern "C" fn(arg: *mut c_void,
+                               a: *const c_char,
+                               alen: size_t,
+                               b: *const c_char,
+                               blen: size_t)
+                               -> c_int,
+        compare_ts: extern "C" fn(arg: *mut c_void,
+                                  a_ts: *const c_char,
+                                  a_tslen: size_t,
+                                  b_ts: *const c_char,
+                                  b_tslen: size_t)
+                                  -> c_int,
+        compare_without_ts: extern "C" fn(arg: *mut c_void,
+                                          a: *const c_char,
+                                          alen: size_t,
+                                          a_has_ts: c_uchar,
+                                          b: *const c_char,
+                                          blen: size_t,
+                                          b_has_ts: c_uchar)
+                                          -> c_int,
+        name_fn: extern "C" fn(*mut c_void) -> *const c_char,
+        timestamp_size: size_t)
+        -> DBComparator;
     pub fn rocksdb_comparator_destroy(cmp: DBComparator);
 
+    // Compaction filter
+    pub fn rocksdb_options_set_compaction_filter(options: DBOptions,
+                                                 filter: DBCompactionFilter);
+    pub fn rocksdb_compactionfilter_create(
+        state: *mut c_void,
+        destroy: extern "C" fn(*mut c_void) -> (),
+        filter: extern "C" fn(arg: *mut c_void,
+                              level: c_int,
+                              key: *const c_char,
+                              key_len: size_t,
+                              existing_value: *const c_char,
+                              value_len: size_t,
+                              new_value: *mut *mut c_char,
+                              new_value_len: *mut size_t,
+                              value_changed: *mut c_uchar)
+                              -> c_uchar,
+        name_fn: extern "C" fn(*mut c_void) -> *const c_char)
+        -> DBCompactionFilter;
+    pub fn rocksdb_compactionfilter_destroy(filter: DBCompactionFilter);
+
     // Column Family
     pub fn rocksdb_open_column_families(options: DBOptions,
                                         path: *const i8,
@@ -421,6 +783,32 @@ extern "C" {
                                         column_family_handles: *const DBCFHandle,
                                         err: *mut *const i8
                                         ) -> DBInstance;
+    pub fn rocksdb_open_column_families_with_ttl(
+        options: DBOptions,
+        path: *const i8,
+        num_column_families: c_int,
+        column_family_names: *const *const i8,
+        column_family_options: *const DBOptions,
+        column_family_handles: *const DBCFHandle,
+        ttls: *const c_int,
+        err: *mut *const i8)
+        -> DBInstance;
+    pub fn rocksdb_open_for_read_only_column_families(
+        options: DBOptions,
+        path: *const i8,
+        num_column_families: c_int,
+        column_family_names: *const *const i8,
+        column_family_options: *const DBOptions,
+        column_family_handles: *const DBCFHandle,
+        error_if_log_file_exist: u8,
+        err: *mut *const i8)
+        -> DBInstance;
+    pub fn rocksdb_create_column_family_with_ttl(db: DBInstance,
+                                                 column_family_options: DBOptions,
+                                                 column_family_name: *const i8,
+                                                 ttl: c_int,
+                                                 err: *mut *const i8)
+                                                 -> DBCFHandle;
     pub fn rocksdb_create_column_family(db: DBInstance,
                                         column_family_options: DBOptions,
                                         column_family_name: *const i8,
@@ -430,16 +818,35 @@ extern "C" {
                                       column_family_handle: DBCFHandle,
                                       err: *mut *const i8);
     pub fn rocksdb_column_family_handle_destroy(column_family_handle: DBCFHandle);
+    pub fn rocksdb_list_column_families(options: DBOptions,
+                                        path: *const i8,
+                                        lencf: *mut size_t,
+                                        err: *mut *const i8)
+                                        -> *mut *mut c_char;
+    pub fn rocksdb_list_column_families_destroy(list: *mut *mut c_char,
+                                                len: size_t);
 
     // Flush options
     pub fn rocksdb_flushoptions_create() -> DBFlushOptions;
     pub fn rocksdb_flushoptions_destroy(opt: DBFlushOptions);
     pub fn rocksdb_flushoptions_set_wait(opt: DBFlushOptions,
                                          whether_wait: bool);
+    pub fn rocksdb_flushoptions_set_allow_write_stall(opt: DBFlushOptions,
+                                                      allow: u8);
 
     pub fn rocksdb_flush(db: DBInstance,
                          options: DBFlushOptions,
                          err: *mut *const i8);
+    pub fn rocksdb_set_options_cf(db: DBInstance,
+                                  cf: DBCFHandle,
+                                  count: c_int,
+                                  keys: *const *const c_char,
+                                  values: *const *const c_char,
+                                  err: *mut *const i8);
+    pub fn rocksdb_flush_cf(db: DBInstance,
+                            options: DBFlushOptions,
+                            cf: DBCFHandle,
+                            err: *mut *const i8);
 
     pub fn rocksdb_approximate_sizes(db: DBInstance,
                                      num_ranges: c_int,
@@ -447,7 +854,8 @@ extern "C" {
                                      range_start_key_len: *const size_t,
                                      range_limit_key: *const *const u8,
                                      range_limit_key_len: *const size_t,
-                                     sizes: *mut uint64_t);
+                                     sizes: *mut uint64_t,
+                                     err: *mut *const i8);
     pub fn rocksdb_approximate_sizes_cf(db: DBInstance,
                                         cf: DBCFHandle,
                                         num_ranges: c_int,
@@ -455,7 +863,8 @@ extern "C" {
                                         range_start_key_len: *const size_t,
                                         range_limit_key: *const *const u8,
                                         range_limit_key_len: *const size_t,
-                                        sizes: *mut uint64_t);
+                                        sizes: *mut uint64_t,
+                                        err: *mut *const i8);
     pub fn rocksdb_delete_file_in_range(db: DBInstance,
                                         range_start_key: *const u8,
                                         range_start_key_len: size_t,
@@ -469,6 +878,219 @@ extern "C" {
                                            range_limit_key: *const u8,
                                            range_limit_key_len: size_t,
                                            err: *mut *const i8);
+    pub fn rocksdb_compact_range(db: DBInstance,
+                                 start_key: *const u8,
+                                 start_key_len: size_t,
+                                 limit_key: *const u8,
+                                 limit_key_len: size_t);
+    pub fn rocksdb_compact_range_cf(db: DBInstance,
+                                    cf: DBCFHandle,
+                                    start_key: *const u8,
+                                    start_key_len: size_t,
+                                    limit_key: *const u8,
+                                    limit_key_len: size_t);
+    pub fn rocksdb_disable_manual_compaction(db: DBInstance);
+    pub fn rocksdb_enable_manual_compaction(db: DBInstance);
+    // Rate limiter
+    pub fn rocksdb_ratelimiter_create(rate_bytes_per_sec: i64,
+                                      refill_period_us: i64,
+                                      fairness: i32)
+                                      -> DBRateLimiter;
+    pub fn rocksdb_ratelimiter_create_auto_tuned(rate_bytes_per_sec: i64,
+                                                 refill_period_us: i64,
+                                                 fairness: i32)
+                                                 -> DBRateLimiter;
+    pub fn rocksdb_ratelimiter_destroy(limiter: DBRateLimiter);
+    pub fn rocksdb_ratelimiter_set_bytes_per_second(limiter: DBRateLimiter,
+                                                    bytes_per_second: i64);
+    pub fn rocksdb_options_set_ratelimiter(options: DBOptions,
+                                           limiter: DBRateLimiter);
+
+    // Live files
+    pub fn rocksdb_livefiles(db: DBInstance) -> DBLiveFiles;
+    pub fn rocksdb_livefiles_count(lf: DBLiveFiles) -> c_int;
+    pub fn rocksdb_livefiles_name(lf: DBLiveFiles, index: c_int) -> *const c_char;
+    pub fn rocksdb_livefiles_column_family_name(lf: DBLiveFiles,
+                                                index: c_int)
+                                                -> *const c_char;
+    pub fn rocksdb_livefiles_level(lf: DBLiveFiles, index: c_int) -> c_int;
+    pub fn rocksdb_livefiles_size(lf: DBLiveFiles, index: c_int) -> size_t;
+    pub fn rocksdb_livefiles_smallestkey(lf: DBLiveFiles,
+                                         index: c_int,
+                                         size: *mut size_t)
+                                         -> *const u8;
+    pub fn rocksdb_livefiles_largestkey(lf: DBLiveFiles,
+                                        index: c_int,
+                                        size: *mut size_t)
+                                        -> *const u8;
+    pub fn rocksdb_livefiles_entries(lf: DBLiveFiles,
+                                     index: c_int)
+                                     -> uint64_t;
+    pub fn rocksdb_livefiles_deletions(lf: DBLiveFiles,
+                                       index: c_int)
+                                       -> uint64_t;
+    pub fn rocksdb_livefiles_destroy(lf: DBLiveFiles);
+
+    // Checkpoint
+    pub fn rocksdb_checkpoint_object_create(db: DBInstance,
+                                            err: *mut *const i8)
+                                            -> DBCheckpoint;
+    pub fn rocksdb_checkpoint_create(checkpoint: DBCheckpoint,
+                                     checkpoint_dir: *const i8,
+                                     log_size_for_flush: u64,
+                                     err: *mut *const i8);
+    pub fn rocksdb_checkpoint_object_destroy(checkpoint: DBCheckpoint);
+
+    // Backup
+    pub fn rocksdb_backup_engine_open(options: DBOptions,
+                                      path: *const i8,
+                                      err: *mut *const i8)
+                                      -> DBBackupEngine;
+    pub fn rocksdb_backup_engine_create_new_backup_flush(
+        be: DBBackupEngine,
+        db: DBInstance,
+        flush_before_backup: c_uchar,
+        err: *mut *const i8);
+    pub fn rocksdb_backup_engine_purge_old_backups(be: DBBackupEngine,
+                                                   num_backups_to_keep: u32,
+                                                   err: *mut *const i8);
+    pub fn rocksdb_backup_engine_verify_backup(be: DBBackupEngine,
+                                               backup_id: u32,
+                                               err: *mut *const i8);
+    pub fn rocksdb_restore_options_create() -> DBRestoreOptions;
+    pub fn rocksdb_restore_options_destroy(opt: DBRestoreOptions);
+    pub fn rocksdb_restore_options_set_keep_log_files(opt: DBRestoreOptions,
+                                                      v: c_int);
+    pub fn rocksdb_backup_engine_restore_db_from_latest_backup(
+        be: DBBackupEngine,
+        db_dir: *const i8,
+        wal_dir: *const i8,
+        restore_options: DBRestoreOptions,
+        err: *mut *const i8);
+    pub fn rocksdb_backup_engine_restore_db_from_backup(
+        be: DBBackupEngine,
+        db_dir: *const i8,
+        wal_dir: *const i8,
+        restore_options: DBRestoreOptions,
+        backup_id: u32,
+        err: *mut *const i8);
+    pub fn rocksdb_backup_engine_get_backup_info(be: DBBackupEngine)
+                                                 -> DBBackupEngineInfo;
+    pub fn rocksdb_backup_engine_info_count(info: DBBackupEngineInfo) -> c_int;
+    pub fn rocksdb_backup_engine_info_timestamp(info: DBBackupEngineInfo,
+                                                index: c_int)
+                                                -> i64;
+    pub fn rocksdb_backup_engine_info_backup_id(info: DBBackupEngineInfo,
+                                                index: c_int)
+                                                -> u32;
+    pub fn rocksdb_backup_engine_info_size(info: DBBackupEngineInfo,
+                                           index: c_int)
+                                           -> uint64_t;
+    pub fn rocksdb_backup_engine_info_number_files(info: DBBackupEngineInfo,
+                                                   index: c_int)
+                                                   -> u32;
+    pub fn rocksdb_backup_engine_info_destroy(info: DBBackupEngineInfo);
+    pub fn rocksdb_backup_engine_close(be: DBBackupEngine);
+
+    // WAL
+    pub fn rocksdb_get_latest_sequence_number(db: DBInstance) -> uint64_t;
+    pub fn rocksdb_get_updates_since(db: DBInstance,
+                                     seq_number: uint64_t,
+                                     options: *const c_void,
+                                     err: *mut *const i8)
+                                     -> DBWalIterator;
+    pub fn rocksdb_wal_iter_next(iter: DBWalIterator);
+    pub fn rocksdb_wal_iter_valid(iter: DBWalIterator) -> c_uchar;
+    pub fn rocksdb_wal_iter_status(iter: DBWalIterator, err: *mut *const i8);
+    pub fn rocksdb_wal_iter_get_batch(iter: DBWalIterator,
+                                      seq: *mut uint64_t)
+                                      -> DBWriteBatch;
+    pub fn rocksdb_wal_iter_destroy(iter: DBWalIterator);
+    pub fn rocksdb_disable_file_deletions(db: DBInstance, err: *mut *const i8);
+    pub fn rocksdb_enable_file_deletions(db: DBInstance,
+                                         force: c_uchar,
+                                         err: *mut *const i8);
+    pub fn rocksdb_options_set_WAL_ttl_seconds(options: DBOptions,
+                                               ttl: uint64_t);
+    pub fn rocksdb_options_set_WAL_size_limit_MB(options: DBOptions,
+                                                 limit: uint64_t);
+    pub fn rocksdb_fifo_compaction_options_create() -> DBFIFOCompactionOptions;
+    pub fn rocksdb_fifo_compaction_options_set_max_table_files_size(
+        fifo_opts: DBFIFOCompactionOptions,
+        size: uint64_t);
+    pub fn rocksdb_fifo_compaction_options_destroy(
+        fifo_opts: DBFIFOCompactionOptions);
+
+    // SstFileWriter
+    pub fn rocksdb_envoptions_create() -> DBEnvOptions;
+    pub fn rocksdb_envoptions_destroy(opt: DBEnvOptions);
+    pub fn rocksdb_sstfilewriter_create(env: DBEnvOptions,
+                                        io_options: DBOptions)
+                                        -> DBSstFileWriter;
+    pub fn rocksdb_sstfilewriter_open(writer: DBSstFileWriter,
+                                      name: *const c_char,
+                                      err: *mut *const i8);
+    pub fn rocksdb_sstfilewriter_put(writer: DBSstFileWriter,
+                                     k: *const u8,
+                                     kLen: size_t,
+                                     v: *const u8,
+                                     vLen: size_t,
+                                     err: *mut *const i8);
+    pub fn rocksdb_sstfilewriter_finish(writer: DBSstFileWriter,
+                                        err: *mut *const i8);
+    pub fn rocksdb_sstfilewriter_file_size(writer: DBSstFileWriter,
+                                           file_size: *mut uint64_t);
+    pub fn rocksdb_sstfilewriter_destroy(writer: DBSstFileWriter);
+    pub fn rocksdb_ingestexternalfileoptions_create()
+        -> DBIngestExternalFileOptions;
+    pub fn rocksdb_ingestexternalfileoptions_set_move_files(
+        opt: DBIngestExternalFileOptions,
+        move_files: c_uchar);
+    pub fn rocksdb_ingestexternalfileoptions_set_snapshot_consistency(
+        opt: DBIngestExternalFileOptions,
+        snapshot_consistency: c_uchar);
+    pub fn rocksdb_ingestexternalfileoptions_set_allow_global_seqno(
+        opt: DBIngestExternalFileOptions,
+        allow_global_seqno: c_uchar);
+    pub fn rocksdb_ingestexternalfileoptions_set_allow_blocking_flush(
+        opt: DBIngestExternalFileOptions,
+        allow_blocking_flush: c_uchar);
+    pub fn rocksdb_ingestexternalfileoptions_destroy(
+        opt: DBIngestExternalFileOptions);
+    pub fn rocksdb_ingest_external_file(db: DBInstance,
+                                        file_list: *const *const c_char,
+                                        list_len: size_t,
+                                        opt: DBIngestExternalFileOptions,
+                                        err: *mut *const i8);
+    pub fn rocksdb_ingest_external_file_cf(db: DBInstance,
+                                           cf: DBCFHandle,
+                                           file_list: *const *const c_char,
+                                           list_len: size_t,
+                                           opt: DBIngestExternalFileOptions,
+                                           err: *mut *const i8);
+    pub fn rocksdb_options_set_fifo_compaction_options(
+        options: DBOptions,
+        fifo_opts: DBFIFOCompactionOptions);
+
+    // Env
+    pub fn rocksdb_create_default_env() -> DBEnv;
+    pub fn rocksdb_env_destroy(env: DBEnv);
+    pub fn rocksdb_env_set_background_threads(env: DBEnv, n: c_int);
+    pub fn rocksdb_env_get_background_threads(env: DBEnv) -> c_int;
+    pub fn rocksdb_env_set_high_priority_background_threads(env: DBEnv,
+                                                            n: c_int);
+    pub fn rocksdb_env_get_high_priority_background_threads(env: DBEnv)
+                                                            -> c_int;
+    pub fn rocksdb_env_set_bottom_priority_background_threads(env: DBEnv,
+                                                              n: c_int);
+    pub fn rocksdb_env_get_bottom_priority_background_threads(env: DBEnv)
+                                                              -> c_int;
+    pub fn rocksdb_env_lower_thread_pool_io_priority(env: DBEnv);
+    pub fn rocksdb_env_lower_high_priority_thread_pool_io_priority(env: DBEnv);
+    pub fn rocksdb_env_lower_thread_pool_cpu_priority(env: DBEnv);
+    pub fn rocksdb_env_lower_high_priority_thread_pool_cpu_priority(env: DBEnv);
+    pub fn rocksdb_options_set_env(options: DBOptions, env: DBEnv);
+
     pub fn rocksdb_property_value(db: DBInstance,
                                   propname: *const c_char)
                                   -> *mut c_char;
@@ -478,6 +1100,20 @@ extern "C" {
                                      -> *mut c_char;
 }
 
+// Implemented on top of the C++ API by the shim in shim/cache_dump.cc.
+#[cfg(feature = "cache-dump")]
+extern "C" {
+    pub fn rocksdb_shim_cache_dump(cache: DBCache,
+                                   db: DBInstance,
+                                   path: *const c_char,
+                                   err: *mut *const i8);
+    pub fn rocksdb_shim_cache_create_lru_from_dump(capacity: size_t,
+                                                   secondary_capacity: size_t,
+                                                   path: *const c_char,
+                                                   err: *mut *const i8)
+                                                   -> DBCache;
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -547,7 +1183,9 @@ mod test {
                                       vec![1].as_ptr(),
                                       vec![b"\xff\x00".as_ptr()].as_ptr(),
                                       vec![1].as_ptr(),
-                                      sizes.as_mut_ptr());
+                                      sizes.as_mut_ptr(),
+                                      &mut err);
+            assert!(err.is_null(), error_message(err));
             assert_eq!(sizes.len(), 1);
             assert!(sizes[0] > 0);
 
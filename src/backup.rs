@@ -0,0 +1,313 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::ffi::CString;
+
+use rocksdb::DB;
+use rocksdb_ffi::{self, DBBackupEngine, DBRestoreOptions, error_message};
+use rocksdb_options::Options;
+
+/// A backup of a `BackupEngine`, see `BackupEngine::get_backup_info`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupInfo {
+    pub backup_id: u32,
+    /// Seconds since the unix epoch when the backup was created.
+    pub timestamp: i64,
+    pub size: u64,
+    pub num_files: u32,
+}
+
+/// Options of `BackupEngine::restore_db_from_latest_backup`.
+pub struct RestoreOptions {
+    inner: DBRestoreOptions,
+}
+
+impl Drop for RestoreOptions {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_restore_options_destroy(self.inner);
+        }
+    }
+}
+
+impl Default for RestoreOptions {
+    fn default() -> RestoreOptions {
+        let opts = unsafe { rocksdb_ffi::rocksdb_restore_options_create() };
+        if opts.0.is_null() {
+            panic!("Could not create rocksdb restore options".to_string());
+        }
+        RestoreOptions { inner: opts }
+    }
+}
+
+impl RestoreOptions {
+    pub fn new() -> RestoreOptions {
+        RestoreOptions::default()
+    }
+
+    /// Keep the WAL files already in the WAL directory and replay them
+    /// after the restored ones, instead of deleting them.
+    pub fn set_keep_log_files(&mut self, keep: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_restore_options_set_keep_log_files(
+                self.inner,
+                keep as i32);
+        }
+    }
+}
+
+/// Incremental backups of DBs in a directory.
+///
+/// Each backup is a full copy of the DB as of when it was taken, but the
+/// sst files the backups share are stored once. Backups can be taken
+/// while the DB is written to.
+pub struct BackupEngine {
+    inner: DBBackupEngine,
+}
+
+unsafe impl Send for BackupEngine {}
+
+impl BackupEngine {
+    /// Open the backups in `path`, creating the directory if needed.
+    pub fn open(opts: &Options, path: &str) -> Result<BackupEngine, String> {
+        let cpath = match CString::new(path.as_bytes()) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err("Failed to convert path to CString when opening \
+                            backup engine"
+                    .to_owned())
+            }
+        };
+        let mut err: *const i8 = 0 as *const i8;
+        let be = unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_open(opts.inner,
+                                                    cpath.as_ptr(),
+                                                    &mut err)
+        };
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(BackupEngine { inner: be })
+    }
+
+    /// Back `db` up, flushing its memtables first so the backup doesn't
+    /// depend on the WAL.
+    pub fn create_new_backup(&mut self, db: &DB) -> Result<(), String> {
+        self.create_new_backup_flush(db, true)
+    }
+
+    /// Back `db` up, copying the WAL files instead of flushing the
+    /// memtables if `flush_before_backup` is false.
+    pub fn create_new_backup_flush(&mut self,
+                                   db: &DB,
+                                   flush_before_backup: bool)
+                                   -> Result<(), String> {
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_create_new_backup_flush(
+                self.inner,
+                db.as_raw(),
+                flush_before_backup as u8,
+                &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// List the backups, oldest first.
+    pub fn get_backup_info(&self) -> Vec<BackupInfo> {
+        use rocksdb_ffi::{rocksdb_backup_engine_info_backup_id as backup_id,
+                          rocksdb_backup_engine_info_number_files as num_files,
+                          rocksdb_backup_engine_info_size as size,
+                          rocksdb_backup_engine_info_timestamp as timestamp};
+        unsafe {
+            let info = rocksdb_ffi::rocksdb_backup_engine_get_backup_info(
+                self.inner);
+            let count = rocksdb_ffi::rocksdb_backup_engine_info_count(info);
+            let backups = (0..count)
+                .map(|i| {
+                    BackupInfo {
+                        backup_id: backup_id(info, i),
+                        timestamp: timestamp(info, i),
+                        size: size(info, i),
+                        num_files: num_files(info, i),
+                    }
+                })
+                .collect();
+            rocksdb_ffi::rocksdb_backup_engine_info_destroy(info);
+            backups
+        }
+    }
+
+    /// Delete all but the `num_backups_to_keep` latest backups.
+    pub fn purge_old_backups(&mut self,
+                             num_backups_to_keep: u32)
+                             -> Result<(), String> {
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_purge_old_backups(
+                self.inner,
+                num_backups_to_keep,
+                &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Check that the files of backup `backup_id` are all there with the
+    /// expected sizes. Their content is not read.
+    pub fn verify_backup(&self, backup_id: u32) -> Result<(), String> {
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_verify_backup(self.inner,
+                                                             backup_id,
+                                                             &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Restore the latest backup into `db_dir`, its WAL files into
+    /// `wal_dir`, usually the same directory.
+    ///
+    /// The DB must not be open, the files already there are replaced.
+    pub fn restore_db_from_latest_backup(&self,
+                                         db_dir: &str,
+                                         wal_dir: &str,
+                                         opts: &RestoreOptions)
+                                         -> Result<(), String> {
+        let (db_dir, wal_dir) = try!(restore_paths(db_dir, wal_dir));
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_restore_db_from_latest_backup(
+                self.inner,
+                db_dir.as_ptr(),
+                wal_dir.as_ptr(),
+                opts.inner,
+                &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Like `restore_db_from_latest_backup`, for backup `backup_id`.
+    pub fn restore_db_from_backup(&self,
+                                  db_dir: &str,
+                                  wal_dir: &str,
+                                  opts: &RestoreOptions,
+                                  backup_id: u32)
+                                  -> Result<(), String> {
+        let (db_dir, wal_dir) = try!(restore_paths(db_dir, wal_dir));
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_restore_db_from_backup(
+                self.inner,
+                db_dir.as_ptr(),
+                wal_dir.as_ptr(),
+                opts.inner,
+                backup_id,
+                &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BackupEngine {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_backup_engine_close(self.inner);
+        }
+    }
+}
+
+fn restore_paths(db_dir: &str,
+                 wal_dir: &str)
+                 -> Result<(CString, CString), String> {
+    match (CString::new(db_dir.as_bytes()), CString::new(wal_dir.as_bytes())) {
+        (Ok(db_dir), Ok(wal_dir)) => Ok((db_dir, wal_dir)),
+        _ => {
+            Err("Failed to convert path to CString when restoring backup"
+                .to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use tempdir::TempDir;
+
+    #[test]
+    fn backup_engine_test() {
+        let path = TempDir::new("_rust_rocksdb_backuptest").expect("");
+        let db_path = path.path().join("db");
+        let db_str = db_path.to_str().unwrap();
+        let backup_path = path.path().join("backup");
+        let restore_path = path.path().join("restore");
+        let restore_str = restore_path.to_str().unwrap();
+
+        let db = DB::open_default(db_str).unwrap();
+        let mut be = BackupEngine::open(&Options::new(),
+                                        backup_path.to_str().unwrap())
+            .unwrap();
+        assert!(be.get_backup_info().is_empty());
+        db.put(b"k1", b"v1").unwrap();
+        be.create_new_backup(&db).unwrap();
+        db.put(b"k1", b"v2").unwrap();
+        be.create_new_backup_flush(&db, false).unwrap();
+        db.put(b"k1", b"v3").unwrap();
+        be.create_new_backup(&db).unwrap();
+
+        let backups = be.get_backup_info();
+        assert_eq!(backups.len(), 3);
+        assert!(backups.windows(2).all(|w| w[0].backup_id < w[1].backup_id));
+        assert!(backups.iter().all(|b| b.size > 0 && b.num_files > 0));
+        for b in &backups {
+            be.verify_backup(b.backup_id).unwrap();
+        }
+        assert!(be.verify_backup(backups[2].backup_id + 1).is_err());
+
+        be.purge_old_backups(2).unwrap();
+        let kept = be.get_backup_info();
+        assert_eq!(kept, backups[1..].to_vec());
+
+        let opts = RestoreOptions::new();
+        be.restore_db_from_latest_backup(restore_str, restore_str, &opts)
+            .unwrap();
+        {
+            let restored = DB::open_default(restore_str).unwrap();
+            assert_eq!(&*restored.get(b"k1").unwrap().unwrap(), b"v3");
+        }
+        be.restore_db_from_backup(restore_str,
+                                  restore_str,
+                                  &opts,
+                                  kept[0].backup_id)
+            .unwrap();
+        let restored = DB::open_default(restore_str).unwrap();
+        assert_eq!(&*restored.get(b"k1").unwrap().unwrap(), b"v2");
+    }
+}
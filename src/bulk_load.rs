@@ -0,0 +1,353 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::ffi::CString;
+use std::fs;
+use std::path::PathBuf;
+
+use libc::size_t;
+
+use rocksdb::DB;
+use rocksdb_ffi::{self, DBCFHandle, error_message};
+use rocksdb_options::{IngestExternalFileOptions, Options};
+
+const DEFAULT_TARGET_FILE_SIZE: u64 = 64 << 20;
+
+/// Writes an sst file to be ingested with `DB::ingest_external_file`.
+///
+/// Keys must be added in increasing order of the comparator of `opts`,
+/// which must be the one of the column family the file is ingested into.
+pub struct SstFileWriter {
+    inner: rocksdb_ffi::DBSstFileWriter,
+    env_opts: rocksdb_ffi::DBEnvOptions,
+    // Rocksdb keeps a pointer to the comparator of the options.
+    _opts: Options,
+}
+
+impl SstFileWriter {
+    pub fn new(opts: &Options) -> SstFileWriter {
+        let opts = opts.clone();
+        unsafe {
+            let env_opts = rocksdb_ffi::rocksdb_envoptions_create();
+            let inner = rocksdb_ffi::rocksdb_sstfilewriter_create(env_opts,
+                                                                  opts.inner);
+            SstFileWriter {
+                inner: inner,
+                env_opts: env_opts,
+                _opts: opts,
+            }
+        }
+    }
+
+    /// Start writing a new file at `path`.
+    pub fn open(&mut self, path: &str) -> Result<(), String> {
+        let cpath = match CString::new(path.as_bytes()) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err("Failed to convert path to CString when opening \
+                            sst file"
+                    .to_owned())
+            }
+        };
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_sstfilewriter_open(self.inner,
+                                                    cpath.as_ptr(),
+                                                    &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_sstfilewriter_put(self.inner,
+                                                   key.as_ptr(),
+                                                   key.len() as size_t,
+                                                   value.as_ptr(),
+                                                   value.len() as size_t,
+                                                   &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Finish the file, which must have at least one key.
+    pub fn finish(&mut self) -> Result<(), String> {
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_sstfilewriter_finish(self.inner, &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Size of the file written so far.
+    pub fn file_size(&self) -> u64 {
+        let mut size = 0;
+        unsafe {
+            rocksdb_ffi::rocksdb_sstfilewriter_file_size(self.inner, &mut size);
+        }
+        size
+    }
+}
+
+impl Drop for SstFileWriter {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_sstfilewriter_destroy(self.inner);
+            rocksdb_ffi::rocksdb_envoptions_destroy(self.env_opts);
+        }
+    }
+}
+
+/// What a `BulkLoader` loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BulkLoadStats {
+    pub files: usize,
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// Loads a sorted stream of key values into a column family.
+///
+/// The entries are written to sst files in `dir`, cut once they reach the
+/// target file size, and all the files are ingested in one go by `finish`.
+/// This is much cheaper than writing the entries, which goes through the
+/// WAL, the memtable and then compaction.
+///
+/// Keys must be in strictly increasing order of the column family
+/// comparator. They may overlap keys already in the DB, the ingested files
+/// then get a sequence number above them so the loaded values win, and an
+/// overlapping memtable is flushed first.
+///
+/// The files left in `dir` are removed when the loader is dropped, so a
+/// failed load doesn't leak them. `dir` must not be shared by loaders
+/// running at the same time.
+pub struct BulkLoader<'a> {
+    db: &'a DB,
+    cf: Option<DBCFHandle>,
+    opts: Options,
+    dir: PathBuf,
+    target_file_size: u64,
+    writer: Option<SstFileWriter>,
+    files: Vec<String>,
+    // The last key of the last finished file, to check the order across
+    // files; the writer checks it within a file.
+    last_key: Option<Vec<u8>>,
+    stats: BulkLoadStats,
+}
+
+impl<'a> BulkLoader<'a> {
+    /// Load into the default column family of `db`, opened with `opts`.
+    pub fn new(db: &'a DB,
+               opts: &Options,
+               dir: &str)
+               -> Result<BulkLoader<'a>, String> {
+        BulkLoader::new_cfopt(db, None, opts, dir)
+    }
+
+    /// Load into the column family `cf`, opened with `opts`.
+    pub fn new_cf(db: &'a DB,
+                  cf: DBCFHandle,
+                  opts: &Options,
+                  dir: &str)
+                  -> Result<BulkLoader<'a>, String> {
+        BulkLoader::new_cfopt(db, Some(cf), opts, dir)
+    }
+
+    fn new_cfopt(db: &'a DB,
+                 cf: Option<DBCFHandle>,
+                 opts: &Options,
+                 dir: &str)
+                 -> Result<BulkLoader<'a>, String> {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create bulk load directory {}: {}",
+                               dir,
+                               e));
+        }
+        Ok(BulkLoader {
+            db: db,
+            cf: cf,
+            opts: opts.clone(),
+            dir: PathBuf::from(dir),
+            target_file_size: DEFAULT_TARGET_FILE_SIZE,
+            writer: None,
+            files: vec![],
+            last_key: None,
+            stats: BulkLoadStats::default(),
+        })
+    }
+
+    /// Cut files once they reach `size` bytes, 64MB by default.
+    pub fn set_target_file_size(&mut self, size: u64) {
+        self.target_file_size = size;
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        if self.writer.is_none() {
+            try!(self.check_order(key));
+            let name = format!("bulk-{:06}.sst", self.files.len());
+            let path = self.dir.join(name).to_string_lossy().into_owned();
+            let mut writer = SstFileWriter::new(&self.opts);
+            try!(writer.open(&path));
+            self.files.push(path);
+            self.writer = Some(writer);
+        }
+        let size = {
+            let writer = self.writer.as_mut().unwrap();
+            try!(writer.put(key, value));
+            writer.file_size()
+        };
+        self.stats.entries += 1;
+        if size >= self.target_file_size {
+            try!(self.finish_file());
+            self.last_key = Some(key.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Add all the entries of `iter`.
+    pub fn add_all<I, K, V>(&mut self, iter: I) -> Result<(), String>
+        where I: IntoIterator<Item = (K, V)>,
+              K: AsRef<[u8]>,
+              V: AsRef<[u8]>
+    {
+        for (k, v) in iter {
+            try!(self.add(k.as_ref(), v.as_ref()));
+        }
+        Ok(())
+    }
+
+    /// Ingest the files written, with the default ingestion options of a
+    /// bulk load: files are moved, and may be assigned a sequence number
+    /// and flush the memtable when they overlap existing keys.
+    pub fn finish(self) -> Result<BulkLoadStats, String> {
+        let mut opts = IngestExternalFileOptions::new();
+        opts.set_move_files(true);
+        opts.set_snapshot_consistency(true);
+        opts.set_allow_global_seqno(true);
+        opts.set_allow_blocking_flush(true);
+        self.finish_opt(&opts)
+    }
+
+    pub fn finish_opt(mut self,
+                      opts: &IngestExternalFileOptions)
+                      -> Result<BulkLoadStats, String> {
+        if self.writer.is_some() {
+            try!(self.finish_file());
+        }
+        if self.files.is_empty() {
+            return Ok(self.stats);
+        }
+        let files: Vec<&str> = self.files.iter().map(|f| f.as_str()).collect();
+        try!(match self.cf {
+            Some(cf) => self.db.ingest_external_file_cf(cf, opts, &files),
+            None => self.db.ingest_external_file(opts, &files),
+        });
+        self.stats.files = self.files.len();
+        Ok(self.stats)
+    }
+
+    fn finish_file(&mut self) -> Result<(), String> {
+        let mut writer = self.writer.take().unwrap();
+        try!(writer.finish());
+        self.stats.bytes += writer.file_size();
+        Ok(())
+    }
+
+    fn check_order(&self, key: &[u8]) -> Result<(), String> {
+        let last = match self.last_key {
+            Some(ref k) => k,
+            None => return Ok(()),
+        };
+        let cmp = match self.opts.get_compare_fn() {
+            Some(f) => f(last, key),
+            None => last.as_slice().cmp(key) as i32,
+        };
+        if cmp >= 0 {
+            return Err("Invalid argument: Keys must be added in strict \
+                        ascending order."
+                .to_owned());
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for BulkLoader<'a> {
+    fn drop(&mut self) {
+        self.writer.take();
+        for f in &self.files {
+            let _ = fs::remove_file(f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn bulk_load_test() {
+        let path = TempDir::new("_rust_rocksdb_bulkloadtest").expect("");
+        let sst_dir = TempDir::new("_rust_rocksdb_bulkloadtest_sst")
+            .expect("");
+        let sst_dir = sst_dir.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        // Overlaps the loaded keys, still in the memtable.
+        db.put(b"k0050", b"old").unwrap();
+
+        let mut loader = BulkLoader::new(&db, &opts, sst_dir).unwrap();
+        loader.set_target_file_size(1024);
+        let entries: Vec<(String, String)> = (0..1000)
+            .map(|i| (format!("k{:04}", i), format!("value-{}", i)))
+            .collect();
+        loader.add_all(entries).unwrap();
+        let stats = loader.finish().unwrap();
+        assert!(stats.files > 1, "{:?}", stats);
+        assert_eq!(stats.entries, 1000);
+        assert!(stats.bytes > 0);
+
+        assert_eq!(&*db.get(b"k0000").unwrap().unwrap(), b"value-0");
+        assert_eq!(&*db.get(b"k0050").unwrap().unwrap(), b"value-50");
+        assert_eq!(&*db.get(b"k0999").unwrap().unwrap(), b"value-999");
+        assert_eq!(fs::read_dir(sst_dir).unwrap().count(), 0);
+
+        // Out of order keys fail, within a file and across files.
+        let mut loader = BulkLoader::new(&db, &opts, sst_dir).unwrap();
+        loader.add(b"b", b"1").unwrap();
+        assert!(loader.add(b"a", b"1").is_err());
+        drop(loader);
+        let mut loader = BulkLoader::new(&db, &opts, sst_dir).unwrap();
+        loader.set_target_file_size(0);
+        loader.add(b"b", b"1").unwrap();
+        assert!(loader.add(b"b", b"2").is_err());
+        drop(loader);
+        assert_eq!(fs::read_dir(sst_dir).unwrap().count(), 0);
+        assert!(db.get(b"b").unwrap().is_none());
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Guards for the rust callbacks called by rocksdb.
+//!
+//! Unwinding out of a callback into C++ is undefined behavior, so every
+//! callback runs its user code through `guard`, which stops the panic at
+//! the boundary and handles it according to the process wide
+//! `PanicPolicy`.
+
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// What to do when a callback panics.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PanicPolicy {
+    /// Abort the process. This is the default.
+    Abort,
+    /// Fail the operation that called the callback and every later one
+    /// using the same callback, leaving the process running.
+    ///
+    /// Callbacks that have no way to report a failure to rocksdb, like
+    /// comparators, still abort.
+    Poison,
+}
+
+static POLICY: AtomicUsize = AtomicUsize::new(0);
+
+pub fn set_panic_policy(policy: PanicPolicy) {
+    let v = match policy {
+        PanicPolicy::Abort => 0,
+        PanicPolicy::Poison => 1,
+    };
+    POLICY.store(v, Ordering::SeqCst);
+}
+
+pub fn panic_policy() -> PanicPolicy {
+    match POLICY.load(Ordering::SeqCst) {
+        0 => PanicPolicy::Abort,
+        _ => PanicPolicy::Poison,
+    }
+}
+
+/// Run `f` for the callback `name`.
+///
+/// Return `None` if the callback is or gets poisoned, the caller must
+/// then report a failure to rocksdb. `poisoned` is `None` for callbacks
+/// that can't report failures, a panic aborts in that case.
+pub fn guard<F, R>(name: &str, poisoned: Option<&AtomicBool>, f: F) -> Option<R>
+    where F: FnOnce() -> R
+{
+    guard_with(panic_policy(), name, poisoned, f)
+}
+
+// `guard` handling a panic according to `policy` instead of the process
+// wide one.
+fn guard_with<F, R>(policy: PanicPolicy,
+                    name: &str,
+                    poisoned: Option<&AtomicBool>,
+                    f: F)
+                    -> Option<R>
+    where F: FnOnce() -> R
+{
+    if poisoned.map_or(false, |p| p.load(Ordering::SeqCst)) {
+        return None;
+    }
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => Some(r),
+        Err(_) => {
+            match (policy, poisoned) {
+                (PanicPolicy::Poison, Some(p)) => {
+                    p.store(true, Ordering::SeqCst);
+                    None
+                }
+                _ => {
+                    let _ = writeln!(io::stderr(),
+                                     "rocksdb callback {} panicked, aborting",
+                                     name);
+                    process::abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn guard_test() {
+        let poisoned = AtomicBool::new(false);
+        assert_eq!(guard("test", Some(&poisoned), || 1), Some(1));
+
+        // The process wide policy is left alone, other tests run
+        // callbacks concurrently.
+        let poison = PanicPolicy::Poison;
+        let r: Option<i32> =
+            guard_with(poison, "test", Some(&poisoned), || panic!("boom"));
+        assert_eq!(r, None);
+        assert!(poisoned.load(Ordering::SeqCst));
+        // Poisoned callbacks are not called anymore.
+        assert_eq!(guard_with(poison, "test", Some(&poisoned), || 1), None);
+        assert_eq!(guard("test", Some(&poisoned), || 1), None);
+        assert_eq!(panic_policy(), PanicPolicy::Abort);
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use rocksdb::{DB, SeekKey, Writable, WriteBatch};
+use rocksdb_ffi::DBCFHandle;
+use rocksdb_options::WriteOptions;
+
+// Prefix of the aliases in the metadata column family, which they can
+// share with other metadata.
+const ALIAS_PREFIX: &'static [u8] = b"cf_alias/";
+
+/// Logical names of column families, each mapped to the physical column
+/// family holding its data.
+///
+/// Rocksdb can't rename column families. To rebuild one, fill a new column
+/// family and point its logical name at it with `set_alias`, which takes
+/// effect atomically for the lookups that follow. A name without an alias
+/// is the name of a physical column family.
+///
+/// The aliases are stored in a metadata column family, and written with
+/// sync so that a swap survives a crash once it returns. Handles looked
+/// up before a swap still point to the old column family, which must only
+/// be dropped once they are no longer used.
+///
+/// An alias always points at a physical column family, never at another
+/// alias.
+pub struct CfAliases {
+    db: Arc<DB>,
+    meta_cf: String,
+    // Serializes the swaps, so each one returns the alias it replaced.
+    // Only those made through this instance: the swaps of two instances
+    // opened on the same DB may interleave.
+    update: Mutex<()>,
+}
+
+impl CfAliases {
+    /// Open the aliases stored in column family `meta_cf` of `db`.
+    pub fn open(db: Arc<DB>, meta_cf: &str) -> Result<CfAliases, String> {
+        try!(db.cf_handle_or_err(meta_cf));
+        Ok(CfAliases {
+            db: db,
+            meta_cf: meta_cf.to_owned(),
+            update: Mutex::new(()),
+        })
+    }
+
+    /// Return the physical column family `name` stands for.
+    pub fn resolve(&self, name: &str) -> Result<String, String> {
+        let meta = try!(self.db.cf_handle_or_err(&self.meta_cf));
+        match try!(self.db.get_cf(meta, &alias_key(name))) {
+            Some(v) => {
+                String::from_utf8(v.to_vec()).map_err(|_| {
+                    format!("Invalid alias of {}: {:?}", name, &*v)
+                })
+            }
+            None => Ok(name.to_owned()),
+        }
+    }
+
+    /// Return the handle of the physical column family `name` stands for.
+    pub fn cf_handle(&self, name: &str) -> Result<DBCFHandle, String> {
+        let physical = try!(self.resolve(name));
+        Ok(try!(self.db.cf_handle_or_err(&physical)))
+    }
+
+    /// Point `name` at the existing column family `physical`, return the
+    /// one it stood for before.
+    ///
+    /// Fails if `physical` is itself an alias, or if `name` is the column
+    /// family of another alias, either of which would chain them.
+    pub fn set_alias(&self,
+                     name: &str,
+                     physical: &str)
+                     -> Result<String, String> {
+        try!(self.db.cf_handle_or_err(physical));
+        let meta = try!(self.db.cf_handle_or_err(&self.meta_cf));
+        let _guard = self.update.lock().unwrap_or_else(|e| e.into_inner());
+        let batch = WriteBatch::new();
+        if name == physical {
+            try!(batch.delete_cf(meta, &alias_key(name)));
+        } else {
+            for (alias, target) in try!(self.aliases()) {
+                if alias == physical {
+                    return Err(format!("Invalid argument: {} is an alias \
+                                        of {}, not a column family",
+                                       physical,
+                                       target));
+                }
+                if target == name && alias != name {
+                    return Err(format!("Invalid argument: {} is the \
+                                        column family of alias {}",
+                                       name,
+                                       alias));
+                }
+            }
+            try!(batch.put_cf(meta, &alias_key(name), physical.as_bytes()));
+        }
+        let old = try!(self.resolve(name));
+        try!(self.db.write_opt(batch, &sync_writeopts()));
+        Ok(old)
+    }
+
+    /// Remove the alias of `name`, which then stands for the column family
+    /// of that name.
+    pub fn remove_alias(&self, name: &str) -> Result<(), String> {
+        let meta = try!(self.db.cf_handle_or_err(&self.meta_cf));
+        let _guard = self.update.lock().unwrap_or_else(|e| e.into_inner());
+        let batch = WriteBatch::new();
+        try!(batch.delete_cf(meta, &alias_key(name)));
+        self.db.write_opt(batch, &sync_writeopts())
+    }
+
+    /// List the aliases, by logical name.
+    pub fn aliases(&self) -> Result<BTreeMap<String, String>, String> {
+        let meta = try!(self.db.cf_handle_or_err(&self.meta_cf));
+        let mut aliases = BTreeMap::new();
+        let mut iter = self.db.iter_cf(meta);
+        iter.seek(SeekKey::Key(ALIAS_PREFIX));
+        while let Some(key) = iter.key() {
+            if !key.starts_with(ALIAS_PREFIX) {
+                break;
+            }
+            let name = String::from_utf8_lossy(&key[ALIAS_PREFIX.len()..]);
+            let physical = String::from_utf8_lossy(iter.value().unwrap());
+            aliases.insert(name.into_owned(), physical.into_owned());
+            iter.next();
+        }
+        try!(iter.status());
+        Ok(aliases)
+    }
+}
+
+fn sync_writeopts() -> WriteOptions {
+    let mut opts = WriteOptions::new();
+    opts.set_sync(true);
+    opts
+}
+
+fn alias_key(name: &str) -> Vec<u8> {
+    let mut key = ALIAS_PREFIX.to_vec();
+    key.extend_from_slice(name.as_bytes());
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[test]
+    fn cf_aliases_test() {
+        let path = TempDir::new("_rust_rocksdb_cfaliastest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let cfs = ["default", "meta", "users_v1", "users_v2"];
+        {
+            let db = DB::open_cf(&opts, path_str, &cfs, &[&opts; 4]).unwrap();
+            let db = Arc::new(db);
+            assert!(CfAliases::open(db.clone(), "missing").is_err());
+            let aliases = CfAliases::open(db.clone(), "meta").unwrap();
+            assert_eq!(aliases.resolve("users").unwrap(), "users");
+            assert!(aliases.cf_handle("users").is_err());
+            assert!(aliases.set_alias("users", "missing").is_err());
+
+            assert_eq!(aliases.set_alias("users", "users_v1").unwrap(),
+                       "users");
+            let v1 = aliases.cf_handle("users").unwrap();
+            db.put_cf(v1, b"k1", b"old").unwrap();
+
+            // Rebuild into users_v2 and swap.
+            let v2 = *db.cf_handle("users_v2").unwrap();
+            db.put_cf(v2, b"k1", b"new").unwrap();
+            assert_eq!(aliases.set_alias("users", "users_v2").unwrap(),
+                       "users_v1");
+            let handle = aliases.cf_handle("users").unwrap();
+            assert_eq!(&*db.get_cf(handle, b"k1").unwrap().unwrap(), b"new");
+
+            // Aliases don't chain, either way.
+            assert!(aliases.set_alias("users_v2", "users_v1").is_err());
+            aliases.set_alias("default", "users_v1").unwrap();
+            assert!(aliases.set_alias("accounts", "default").is_err());
+            aliases.remove_alias("default").unwrap();
+            assert_eq!(aliases.aliases().unwrap().len(), 1);
+        }
+
+        let db = DB::open_cf(&opts, path_str, &cfs, &[&opts; 4]).unwrap();
+        let aliases = CfAliases::open(Arc::new(db), "meta").unwrap();
+        assert_eq!(aliases.resolve("users").unwrap(), "users_v2");
+        assert_eq!(aliases.resolve("users_v1").unwrap(), "users_v1");
+        let all = aliases.aliases().unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all["users"], "users_v2");
+        aliases.remove_alias("users").unwrap();
+        assert_eq!(aliases.resolve("users").unwrap(), "users");
+        assert!(aliases.aliases().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocksdb::DB;
+
+const CHECKPOINT_PREFIX: &'static str = "checkpoint-";
+
+/// A checkpoint created by a `CheckpointDir`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckpointInfo {
+    pub path: PathBuf,
+    /// Seconds since the unix epoch when the checkpoint was created.
+    pub created_at: u64,
+}
+
+/// A directory holding the checkpoints of a process.
+///
+/// Checkpoints are named after their creation time, so those older than
+/// the ttl are found and removed even if the process that created them
+/// died before cleaning up. Only directories created by a `CheckpointDir`
+/// are ever removed.
+pub struct CheckpointDir {
+    dir: PathBuf,
+    ttl: Duration,
+    seq: AtomicUsize,
+}
+
+impl CheckpointDir {
+    /// Open `dir`, creating it if needed, and purge the checkpoints in it
+    /// that are older than `ttl`.
+    pub fn open(dir: &str, ttl: Duration) -> Result<CheckpointDir, String> {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Failed to create checkpoint directory {}: {}",
+                               dir,
+                               e));
+        }
+        let cd = CheckpointDir {
+            dir: PathBuf::from(dir),
+            ttl: ttl,
+            seq: AtomicUsize::new(0),
+        };
+        try!(cd.purge_expired());
+        Ok(cd)
+    }
+
+    /// Create a checkpoint of `db` and return where it was created.
+    pub fn create(&self, db: &DB) -> Result<PathBuf, String> {
+        let name = format!("{}{}-{}-{}",
+                           CHECKPOINT_PREFIX,
+                           now_secs(),
+                           process::id(),
+                           self.seq.fetch_add(1, Ordering::SeqCst));
+        let path = self.dir.join(name);
+        try!(db.create_checkpoint(path.to_str().unwrap(), 0));
+        Ok(path)
+    }
+
+    /// List the checkpoints in the directory, oldest first.
+    pub fn list(&self) -> Result<Vec<CheckpointInfo>, String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                return Err(format!("Failed to list {}: {}",
+                                   self.dir.display(),
+                                   e))
+            }
+        };
+        let mut checkpoints = vec![];
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    return Err(format!("Failed to list {}: {}",
+                                       self.dir.display(),
+                                       e))
+                }
+            };
+            if let Some(created_at) = parse_created_at(&path) {
+                checkpoints.push(CheckpointInfo {
+                    path: path,
+                    created_at: created_at,
+                });
+            }
+        }
+        checkpoints.sort_by(|a, b| {
+            (a.created_at, &a.path).cmp(&(b.created_at, &b.path))
+        });
+        Ok(checkpoints)
+    }
+
+    /// Remove the checkpoints older than the ttl, return how many were
+    /// removed.
+    pub fn purge_expired(&self) -> Result<usize, String> {
+        let now = now_secs();
+        let mut purged = 0;
+        for cp in try!(self.list()) {
+            if cp.created_at + self.ttl.as_secs() > now {
+                continue;
+            }
+            if let Err(e) = fs::remove_dir_all(&cp.path) {
+                return Err(format!("Failed to remove checkpoint {}: {}",
+                                   cp.path.display(),
+                                   e));
+            }
+            purged += 1;
+        }
+        Ok(purged)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn parse_created_at(path: &Path) -> Option<u64> {
+    if !path.is_dir() {
+        return None;
+    }
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return None,
+    };
+    if !name.starts_with(CHECKPOINT_PREFIX) {
+        return None;
+    }
+    let parts: Vec<&str> = name[CHECKPOINT_PREFIX.len()..].split('-').collect();
+    if parts.len() != 3 || parts.iter().any(|p| p.parse::<u64>().is_err()) {
+        return None;
+    }
+    parts[0].parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use std::fs;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    #[test]
+    fn checkpoint_dir_test() {
+        let path = TempDir::new("_rust_rocksdb_checkpointdir").expect("");
+        let db_path = path.path().join("db");
+        let cp_path = path.path().join("checkpoints");
+        let cp_str = cp_path.to_str().unwrap();
+        let db = DB::open_default(db_path.to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+
+        let cd = CheckpointDir::open(cp_str, Duration::from_secs(3600)).unwrap();
+        let cp = cd.create(&db).unwrap();
+        let cp_db = DB::open_default(cp.to_str().unwrap()).unwrap();
+        assert_eq!(cp_db.get(b"k1").unwrap().unwrap(), &b"v1"[..]);
+        drop(cp_db);
+
+        // Leftovers of another process and unrelated directories.
+        fs::create_dir(cp_path.join("checkpoint-1-2-3")).unwrap();
+        fs::create_dir(cp_path.join("checkpoint-1-x-3")).unwrap();
+        fs::create_dir(cp_path.join("backup")).unwrap();
+        assert_eq!(cd.list().unwrap().len(), 2);
+
+        let cd = CheckpointDir::open(cp_str, Duration::from_secs(3600)).unwrap();
+        let left = cd.list().unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].path, cp);
+        assert!(cp_path.join("checkpoint-1-x-3").exists());
+        assert!(cp_path.join("backup").exists());
+
+        let cd = CheckpointDir::open(cp_str, Duration::from_secs(0)).unwrap();
+        assert!(cd.list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::sync::Arc;
+
+use error::Error;
+use manifest::crc32c_extend;
+use rocksdb::{DB, DBIterator, Kv, SeekKey, Writable, WriteBatch};
+
+const CHECKSUM_LEN: usize = 4;
+
+/// A column family whose values carry a checksum, to catch corruptions
+/// happening before rocksdb gets them or after it returns them, such as
+/// bad memory or serialization bugs, which rocksdb's own block checksums
+/// can't see.
+///
+/// Values are stored followed by the CRC32c of the length of the key, 8
+/// bytes little endian, the key and the value, 4 bytes little endian,
+/// checked by every read. The length keeps the bytes of the key and of the
+/// value from being swapped between them.
+pub struct ChecksummedCf {
+    db: Arc<DB>,
+    cf: String,
+}
+
+impl ChecksummedCf {
+    pub fn new(db: Arc<DB>, cf: &str) -> Result<ChecksummedCf, Error> {
+        try!(db.cf_handle_or_err(cf));
+        Ok(ChecksummedCf {
+            db: db,
+            cf: cf.to_owned(),
+        })
+    }
+
+    pub fn db(&self) -> &Arc<DB> {
+        &self.db
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        match try!(self.db.get_cf(cf, key)) {
+            Some(v) => decode(key, &v).map(|v| Some(v.to_vec())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        try!(self.db.put_cf(cf, key, &encode(key, value)));
+        Ok(())
+    }
+
+    /// Add putting `value` under `key` to `batch`.
+    pub fn put_in(&self,
+                  batch: &WriteBatch,
+                  key: &[u8],
+                  value: &[u8])
+                  -> Result<(), Error> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        try!(batch.put_cf(cf, key, &encode(key, value)));
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), Error> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        try!(self.db.delete_cf(cf, key));
+        Ok(())
+    }
+
+    /// Iterate over the records from `from` on, checking each value.
+    pub fn iter(&self, from: SeekKey) -> Result<ChecksummedIter, Error> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let mut iter = self.db.iter_cf(cf);
+        iter.seek(from);
+        Ok(ChecksummedIter { iter: iter })
+    }
+}
+
+/// An iterator over the records of a `ChecksummedCf`, see
+/// `ChecksummedCf::iter`.
+pub struct ChecksummedIter<'a> {
+    iter: DBIterator<'a>,
+}
+
+impl<'a> Iterator for ChecksummedIter<'a> {
+    type Item = Result<Kv, Error>;
+
+    fn next(&mut self) -> Option<Result<Kv, Error>> {
+        let item = match self.iter.entry() {
+            Some((k, v)) => decode(k, v).map(|v| (k.to_vec(), v.to_vec())),
+            None => {
+                return match self.iter.status() {
+                    Ok(()) => None,
+                    Err(e) => Some(Err(Error::from(e))),
+                }
+            }
+        };
+        self.iter.next();
+        Some(item)
+    }
+}
+
+fn checksum(key: &[u8], value: &[u8]) -> u32 {
+    let mut key_len = [0; 8];
+    for (i, b) in key_len.iter_mut().enumerate() {
+        *b = (key.len() as u64 >> (8 * i)) as u8;
+    }
+    let crc = crc32c_extend(crc32c_extend(0, &key_len), key);
+    crc32c_extend(crc, value)
+}
+
+fn encode(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let crc = checksum(key, value);
+    let mut buf = Vec::with_capacity(value.len() + CHECKSUM_LEN);
+    buf.extend_from_slice(value);
+    for i in 0..CHECKSUM_LEN {
+        buf.push((crc >> (8 * i)) as u8);
+    }
+    buf
+}
+
+fn decode<'a>(key: &[u8],
+              stored: &'a [u8])
+              -> Result<&'a [u8], Error> {
+    if stored.len() < CHECKSUM_LEN {
+        return Err(Error::Corruption(format!("Corruption: value of {:?} \
+                                              has no checksum",
+                                             key)));
+    }
+    let (value, crc) = stored.split_at(stored.len() - CHECKSUM_LEN);
+    let expected = crc.iter()
+        .rev()
+        .fold(0, |c, b| (c << 8) | *b as u32);
+    let actual = checksum(key, value);
+    if expected != actual {
+        return Err(Error::ChecksumMismatch {
+            key: key.to_vec(),
+            expected: expected,
+            actual: actual,
+        });
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, SeekKey, Writable, WriteBatch};
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[test]
+    fn checksummed_cf_test() {
+        let path = TempDir::new("_rust_rocksdb_checksumtest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        let db = Arc::new(db);
+        match ChecksummedCf::new(db.clone(), "missing") {
+            Err(Error::InvalidColumnFamily(name)) => {
+                assert_eq!(name, Some("missing".to_owned()))
+            }
+            _ => panic!("expected an invalid column family"),
+        }
+        let cf = ChecksummedCf::new(db.clone(), "default").unwrap();
+
+        cf.put(b"k1", b"v1").unwrap();
+        let batch = WriteBatch::new();
+        cf.put_in(&batch, b"k2", b"v2").unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(cf.get(b"k1").unwrap(), Some(b"v1".to_vec()));
+        assert_eq!(cf.get(b"k2").unwrap(), Some(b"v2".to_vec()));
+        assert_eq!(cf.get(b"k3").unwrap(), None);
+        assert_eq!(db.get(b"k1").unwrap().unwrap().len(), 6);
+
+        // A value moved to another key, or damaged, is caught.
+        let stored = db.get(b"k1").unwrap().unwrap().to_vec();
+        db.put(b"k3", &stored).unwrap();
+        match cf.get(b"k3") {
+            Err(Error::ChecksumMismatch { key, .. }) => {
+                assert_eq!(key, b"k3".to_vec())
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        db.put(b"k4", b"value4").unwrap();
+        assert_eq!(cf.get(b"k4"),
+                   Err(Error::ChecksumMismatch {
+                       key: b"k4".to_vec(),
+                       expected: 0x3465756c,
+                       actual: checksum(b"k4", b"va"),
+                   }));
+        // The same bytes split differently between the key and the value.
+        db.put(b"k", &encode(b"", b"kv1")[1..]).unwrap();
+        assert!(cf.get(b"k").is_err());
+        db.delete(b"k").unwrap();
+        db.put(b"k5", b"v").unwrap();
+        match cf.get(b"k5") {
+            Err(Error::Corruption(e)) => assert!(e.contains("no checksum")),
+            r => panic!("unexpected {:?}", r),
+        }
+
+        let items: Vec<_> = cf.iter(SeekKey::Start).unwrap().collect();
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[0], Ok((b"k1".to_vec(), b"v1".to_vec())));
+        assert_eq!(items[1], Ok((b"k2".to_vec(), b"v2".to_vec())));
+        assert!(items[2..].iter().all(|r| r.is_err()));
+
+        cf.delete(b"k1").unwrap();
+        assert_eq!(cf.get(b"k1").unwrap(), None);
+    }
+}
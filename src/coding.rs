@@ -0,0 +1,113 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Decoding of the integers and slices of rocksdb's serialized formats,
+//! from its util/coding.h.
+
+// A serialized write batch starts with the sequence number of its first
+// write, 8 bytes, and its number of writes, 4 bytes, both little endian.
+pub const WRITE_BATCH_HEADER_SIZE: usize = 12;
+
+/// Read a varint64 from the front of `input`, advancing it past it.
+pub fn get_varint64(input: &mut &[u8]) -> Result<u64, String> {
+    let mut result = 0u64;
+    for i in 0..10 {
+        if i >= input.len() {
+            break;
+        }
+        let b = input[i];
+        result |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            *input = &input[i + 1..];
+            return Ok(result);
+        }
+    }
+    Err("Corruption: bad varint".to_owned())
+}
+
+pub fn get_varint32(input: &mut &[u8]) -> Result<u32, String> {
+    let v = try!(get_varint64(input));
+    if v > u32::max_value() as u64 {
+        return Err("Corruption: bad varint32".to_owned());
+    }
+    Ok(v as u32)
+}
+
+/// Read a slice prefixed by its varint32 length from the front of
+/// `input`, advancing it past it.
+pub fn get_length_prefixed<'a>(input: &mut &'a [u8])
+                               -> Result<&'a [u8], String> {
+    let len = try!(get_varint32(input)) as usize;
+    if input.len() < len {
+        return Err("Corruption: truncated length prefixed slice".to_owned());
+    }
+    let s = *input;
+    *input = &s[len..];
+    Ok(&s[..len])
+}
+
+/// The number of bytes of `v` encoded as a varint.
+pub fn varint_length(mut v: u64) -> usize {
+    let mut len = 1;
+    while v >= 0x80 {
+        v >>= 7;
+        len += 1;
+    }
+    len
+}
+
+pub fn decode_fixed32(buf: &[u8]) -> u32 {
+    buf[0] as u32 | (buf[1] as u32) << 8 | (buf[2] as u32) << 16 |
+    (buf[3] as u32) << 24
+}
+
+pub fn decode_fixed64(buf: &[u8]) -> u64 {
+    decode_fixed32(buf) as u64 | (decode_fixed32(&buf[4..]) as u64) << 32
+}
+
+// Added to the CRC32c checksums rocksdb stores, from its util/crc32c.h.
+const MASK_DELTA: u32 = 0xa282ead8;
+
+/// The CRC32c checksum stored masked as `masked`.
+pub fn unmask_crc(masked: u32) -> u32 {
+    let rot = masked.wrapping_sub(MASK_DELTA);
+    (rot >> 17) | (rot << 15)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn coding_test() {
+        let data = [0xac, 0x02, 0x03, b'a', b'b', b'c', 0xff];
+        let mut input = &data[..];
+        assert_eq!(get_varint64(&mut input).unwrap(), 300);
+        assert_eq!(get_length_prefixed(&mut input).unwrap(), b"abc");
+        assert!(get_varint32(&mut input).is_err());
+
+        let mut input = &[0x05, b'a'][..];
+        assert!(get_length_prefixed(&mut input).is_err());
+        let mut input = &[0xff, 0xff, 0xff, 0xff, 0x7f][..];
+        assert!(get_varint32(&mut input).is_err());
+
+        assert_eq!(varint_length(0), 1);
+        assert_eq!(varint_length(127), 1);
+        assert_eq!(varint_length(300), 2);
+        assert_eq!(varint_length(u64::max_value()), 10);
+        assert_eq!(decode_fixed32(&[1, 2, 3, 4]), 0x04030201);
+        assert_eq!(decode_fixed64(&[1, 0, 0, 0, 2, 0, 0, 0]), 0x200000001);
+        assert_eq!(unmask_crc(0xa282ead8), 0);
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use libc::{c_char, c_int, c_uchar, c_void, size_t};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::mem;
+use std::slice;
+use std::sync::atomic::AtomicBool;
+
+use callback;
+
+/// What a `CompactionFilter` does with an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompactionDecision {
+    Keep,
+    /// Drop the entry, older versions of the key it hid may show again
+    /// until they are compacted too.
+    Remove,
+    /// Keep the key with this value instead.
+    ChangeValue(Vec<u8>),
+}
+
+/// Decides which entries compactions keep, see
+/// `Options::set_compaction_filter`.
+///
+/// Only the latest put of each key is passed, not deletes nor merge
+/// operands. Rocksdb calls the filter from its compaction threads, and
+/// keeps the entries of a filter that panicked.
+pub trait CompactionFilter: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Decide what to do with `key` and its `value`, compacted into
+    /// `level`.
+    fn filter(&self,
+              level: u32,
+              key: &[u8],
+              value: &[u8])
+              -> CompactionDecision;
+}
+
+pub(crate) struct CompactionFilterCallback {
+    pub(crate) name: CString,
+    pub(crate) filter: Box<CompactionFilter>,
+    pub(crate) poisoned: AtomicBool,
+}
+
+// Rocksdb copies a changed value as soon as the filter returns, without
+// freeing it, so it is kept here until the next one.
+thread_local!(static NEW_VALUE: RefCell<Vec<u8>> = RefCell::new(vec![]));
+
+pub(crate) extern "C" fn destructor_callback(raw_cb: *mut c_void) {
+    // turn this back into a local variable so rust will reclaim it
+    let _: Box<CompactionFilterCallback> = unsafe { mem::transmute(raw_cb) };
+}
+
+pub(crate) extern "C" fn name_callback(raw_cb: *mut c_void) -> *const c_char {
+    unsafe {
+        let cb: &mut CompactionFilterCallback =
+            &mut *(raw_cb as *mut CompactionFilterCallback);
+        cb.name.as_ptr() as *const c_char
+    }
+}
+
+pub(crate) extern "C" fn filter_callback(raw_cb: *mut c_void,
+                                          level: c_int,
+                                          raw_key: *const c_char,
+                                          key_len: size_t,
+                                          raw_value: *const c_char,
+                                          value_len: size_t,
+                                          new_value: *mut *mut c_char,
+                                          new_value_len: *mut size_t,
+                                          value_changed: *mut c_uchar)
+                                          -> c_uchar {
+    unsafe {
+        let cb: &mut CompactionFilterCallback =
+            &mut *(raw_cb as *mut CompactionFilterCallback);
+        let key: &[u8] = slice::from_raw_parts(raw_key as *const u8,
+                                               key_len as usize);
+        let value: &[u8] = slice::from_raw_parts(raw_value as *const u8,
+                                                 value_len as usize);
+        let filter = &cb.filter;
+        let decision = callback::guard(cb.name.to_str().unwrap_or(""),
+                                       Some(&cb.poisoned),
+                                       || filter.filter(level as u32,
+                                                        key,
+                                                        value));
+        match decision {
+            Some(CompactionDecision::Remove) => 1,
+            Some(CompactionDecision::ChangeValue(v)) => {
+                NEW_VALUE.with(|buf| {
+                    let mut buf = buf.borrow_mut();
+                    *buf = v;
+                    *new_value = buf.as_ptr() as *mut c_char;
+                    *new_value_len = buf.len() as size_t;
+                });
+                *value_changed = 1;
+                0
+            }
+            Some(CompactionDecision::Keep) | None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use tempdir::TempDir;
+
+    struct ExpiryFilter;
+
+    impl CompactionFilter for ExpiryFilter {
+        fn name(&self) -> &str {
+            "expiry"
+        }
+
+        fn filter(&self,
+                  _: u32,
+                  key: &[u8],
+                  value: &[u8])
+                  -> CompactionDecision {
+            if value == b"expired" {
+                CompactionDecision::Remove
+            } else if key.starts_with(b"upper") {
+                CompactionDecision::ChangeValue(value.to_ascii_uppercase())
+            } else {
+                CompactionDecision::Keep
+            }
+        }
+    }
+
+    #[test]
+    fn compaction_filter_test() {
+        let path = TempDir::new("_rust_rocksdb_compactionfiltertest")
+            .expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_compaction_filter(ExpiryFilter);
+        assert_eq!(opts.compaction_filter_name(), Some("expiry"));
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"expired").unwrap();
+        db.put(b"k2", b"live").unwrap();
+        db.put(b"upper1", b"value").unwrap();
+        db.flush(true).unwrap();
+        db.compact_range(None, None);
+
+        assert!(db.get(b"k1").unwrap().is_none());
+        assert_eq!(&*db.get(b"k2").unwrap().unwrap(), b"live");
+        assert_eq!(&*db.get(b"upper1").unwrap().unwrap(), b"VALUE");
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rocksdb::DB;
+
+/// How far a `CompactionJob` got.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompactionProgress {
+    /// Size of the sst files in the range when the job was created.
+    pub estimated_bytes: u64,
+    /// Size of those files that were compacted since.
+    pub compacted_bytes: u64,
+}
+
+impl CompactionProgress {
+    /// The compacted ratio of the estimated bytes, 1 if there were none.
+    pub fn fraction(&self) -> f64 {
+        if self.estimated_bytes == 0 {
+            return 1.0;
+        }
+        self.compacted_bytes as f64 / self.estimated_bytes as f64
+    }
+}
+
+/// A manual compaction of a key range that can be watched and cancelled
+/// from other threads while `run` blocks in one.
+///
+/// Progress is measured on the sst files overlapping the range when the
+/// job is created: a file counts as compacted once it is no longer live.
+/// Data still in the memtables, flushed by the compaction first, is not
+/// part of the estimate.
+pub struct CompactionJob {
+    cf: String,
+    start_key: Option<Vec<u8>>,
+    end_key: Option<Vec<u8>>,
+    files: Vec<(String, u64)>,
+    done: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl CompactionJob {
+    /// Prepare the compaction of the keys in [`start_key`, `end_key`] of
+    /// column family `cf`, `None` bounds meaning from the first key or up
+    /// to the last one, see `DB::compact_range_cf`.
+    pub fn new(db: &DB,
+               cf: &str,
+               start_key: Option<&[u8]>,
+               end_key: Option<&[u8]>)
+               -> Result<CompactionJob, String> {
+        let handle = try!(db.cf_handle_or_err(cf));
+        let compare = try!(db.compare_fn_cf(handle));
+        let files = try!(db.live_files_cf(handle))
+            .into_iter()
+            .filter(|f| {
+                start_key.map_or(true, |k| compare(&f.largest_key, k) >= 0) &&
+                end_key.map_or(true, |k| compare(&f.smallest_key, k) <= 0)
+            })
+            .map(|f| (f.name, f.size))
+            .collect();
+        Ok(CompactionJob {
+            cf: cf.to_owned(),
+            start_key: start_key.map(|k| k.to_vec()),
+            end_key: end_key.map(|k| k.to_vec()),
+            files: files,
+            done: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+        })
+    }
+
+    /// Compact the range, blocking until done or cancelled.
+    ///
+    /// Fails with an `Incomplete` status when the compaction was, or may
+    /// have been, aborted by a cancel, see `DB::try_compact_range_cf`.
+    pub fn run(&self, db: &DB) -> Result<(), String> {
+        let handle = try!(db.cf_handle_or_err(&self.cf));
+        if self.is_cancelled() {
+            return Err("Incomplete: Manual compaction paused".to_owned());
+        }
+        try!(db.try_compact_range_cf(handle,
+                                     self.start_key.as_ref().map(|k| &k[..]),
+                                     self.end_key.as_ref().map(|k| &k[..])));
+        self.done.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn progress(&self, db: &DB) -> Result<CompactionProgress, String> {
+        let mut progress = CompactionProgress {
+            estimated_bytes: self.files.iter().map(|&(_, size)| size).sum(),
+            compacted_bytes: 0,
+        };
+        if self.is_done() {
+            progress.compacted_bytes = progress.estimated_bytes;
+            return Ok(progress);
+        }
+        let handle = try!(db.cf_handle_or_err(&self.cf));
+        let live: HashSet<String> = try!(db.live_files_cf(handle))
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        progress.compacted_bytes = self.files
+            .iter()
+            .filter(|&&(ref name, _)| !live.contains(name))
+            .map(|&(_, size)| size)
+            .sum();
+        Ok(progress)
+    }
+
+    /// Abort the job, making `run` return an error.
+    ///
+    /// The C API of rocksdb can only cancel all the manual compactions of
+    /// the DB at once, see `DB::cancel_manual_compactions`, so those of
+    /// other jobs running meanwhile are aborted too and their `run` fails
+    /// the same way. Returns once they are.
+    pub fn cancel(&self, db: &DB) {
+        if self.is_done() || self.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        db.cancel_manual_compactions();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use tempdir::TempDir;
+
+    #[test]
+    fn compaction_job_test() {
+        let path = TempDir::new("_rust_rocksdb_compactionjobtest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        for i in 0..4 {
+            for j in 0..10 {
+                let key = format!("k{}{}", j, i);
+                db.put(key.as_bytes(), &[0; 100]).unwrap();
+            }
+            db.flush(true).unwrap();
+        }
+        assert!(CompactionJob::new(&db, "missing", None, None).is_err());
+
+        let job = CompactionJob::new(&db, "default", None, None).unwrap();
+        let progress = job.progress(&db).unwrap();
+        assert!(progress.estimated_bytes > 0);
+        assert_eq!(progress.compacted_bytes, 0);
+        assert_eq!(progress.fraction(), 0.0);
+
+        // A cancelled job doesn't compact anything.
+        let cancelled = CompactionJob::new(&db, "default", None, None)
+            .unwrap();
+        cancelled.cancel(&db);
+        assert!(cancelled.is_cancelled());
+        assert!(cancelled.run(&db).is_err());
+        assert_eq!(db.live_files().len(), 4);
+
+        // Other compactions still run after a cancel.
+        job.run(&db).unwrap();
+        let cf = db.cf_handle("default").unwrap();
+        assert!(db.try_compact_range_cf(*cf, Some(b"k5"), None).is_ok());
+        assert!(job.is_done());
+        assert_eq!(db.live_files().len(), 1);
+        let progress = job.progress(&db).unwrap();
+        assert_eq!(progress.compacted_bytes, progress.estimated_bytes);
+        assert_eq!(progress.fraction(), 1.0);
+        job.cancel(&db);
+        assert!(!job.is_cancelled());
+    }
+}
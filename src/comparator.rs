@@ -17,6 +17,8 @@ use std::ffi::CString;
 use std::mem;
 use std::slice;
 
+use callback;
+
 pub struct ComparatorCallback {
     pub name: CString,
     pub f: fn(&[u8], &[u8]) -> i32,
@@ -49,6 +51,117 @@ pub extern "C" fn compare_callback(raw_cb: *mut c_void,
                                              a_len as usize);
         let b: &[u8] = slice::from_raw_parts(b_raw as *const u8,
                                              b_len as usize);
-        (cb.f)(a, b)
+        let f = cb.f;
+        // A comparator can't fail, a panic always aborts.
+        callback::guard(cb.name.to_str().unwrap_or(""), None, || f(a, b))
+            .unwrap()
+    }
+}
+
+/// Size of the timestamps of comparators added with
+/// `Options::add_comparator_with_u64_ts`.
+pub const TIMESTAMP_SIZE: usize = 8;
+
+/// Encode `ts` the way rocksdb's built-in u64 timestamp comparator does,
+/// as a fixed 64 bit little-endian integer.
+pub fn encode_u64_ts(ts: u64) -> [u8; TIMESTAMP_SIZE] {
+    let mut buf = [0; TIMESTAMP_SIZE];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (ts >> (8 * i)) as u8;
+    }
+    buf
+}
+
+pub fn decode_u64_ts(buf: &[u8]) -> u64 {
+    assert_eq!(buf.len(), TIMESTAMP_SIZE);
+    buf.iter().rev().fold(0, |ts, b| (ts << 8) | *b as u64)
+}
+
+fn strip_ts(key: &[u8]) -> (&[u8], &[u8]) {
+    key.split_at(key.len() - TIMESTAMP_SIZE)
+}
+
+/// Compare keys with their timestamps: by user key first, then newest
+/// timestamp first.
+pub extern "C" fn compare_with_ts_callback(raw_cb: *mut c_void,
+                                           a_raw: *const c_char,
+                                           a_len: size_t,
+                                           b_raw: *const c_char,
+                                           b_len: size_t)
+                                           -> c_int {
+    unsafe {
+        let cb: &mut ComparatorCallback =
+            &mut *(raw_cb as *mut ComparatorCallback);
+        let a: &[u8] = slice::from_raw_parts(a_raw as *const u8,
+                                             a_len as usize);
+        let b: &[u8] = slice::from_raw_parts(b_raw as *const u8,
+                                             b_len as usize);
+        let f = cb.f;
+        callback::guard(cb.name.to_str().unwrap_or(""), None, || {
+                let (a_key, a_ts) = strip_ts(a);
+                let (b_key, b_ts) = strip_ts(b);
+                match f(a_key, b_key) {
+                    0 => {
+                        decode_u64_ts(b_ts).cmp(&decode_u64_ts(a_ts)) as i32
+                    }
+                    r => r,
+                }
+            })
+            .unwrap()
+    }
+}
+
+pub extern "C" fn compare_ts_callback(_: *mut c_void,
+                                      a_raw: *const c_char,
+                                      a_len: size_t,
+                                      b_raw: *const c_char,
+                                      b_len: size_t)
+                                      -> c_int {
+    unsafe {
+        let a: &[u8] = slice::from_raw_parts(a_raw as *const u8,
+                                             a_len as usize);
+        let b: &[u8] = slice::from_raw_parts(b_raw as *const u8,
+                                             b_len as usize);
+        decode_u64_ts(a).cmp(&decode_u64_ts(b)) as c_int
+    }
+}
+
+pub extern "C" fn compare_without_ts_callback(raw_cb: *mut c_void,
+                                              a_raw: *const c_char,
+                                              a_len: size_t,
+                                              a_has_ts: u8,
+                                              b_raw: *const c_char,
+                                              b_len: size_t,
+                                              b_has_ts: u8)
+                                              -> c_int {
+    unsafe {
+        let cb: &mut ComparatorCallback =
+            &mut *(raw_cb as *mut ComparatorCallback);
+        let mut a: &[u8] = slice::from_raw_parts(a_raw as *const u8,
+                                                 a_len as usize);
+        let mut b: &[u8] = slice::from_raw_parts(b_raw as *const u8,
+                                                 b_len as usize);
+        if a_has_ts != 0 {
+            a = strip_ts(a).0;
+        }
+        if b_has_ts != 0 {
+            b = strip_ts(b).0;
+        }
+        let f = cb.f;
+        callback::guard(cb.name.to_str().unwrap_or(""), None, || f(a, b))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn u64_ts_test() {
+        assert_eq!(encode_u64_ts(0x0102), [2, 1, 0, 0, 0, 0, 0, 0]);
+        for ts in &[0, 1, 255, 256, 1 << 40, u64::max_value()] {
+            assert_eq!(decode_u64_ts(&encode_u64_ts(*ts)), *ts);
+        }
     }
 }
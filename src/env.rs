@@ -0,0 +1,144 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use libc::c_int;
+
+use rocksdb_ffi;
+
+/// The thread pools of an env.
+///
+/// Flushes run in the `High` pool and compactions in the `Low` one.
+/// Compactions into the bottommost level go to the `Bottom` pool if it
+/// has any threads.
+///
+/// The C API doesn't expose how many jobs are queued in a pool, use
+/// `DB::get_background_jobs` for the jobs of a DB instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Bottom,
+    Low,
+    High,
+}
+
+/// A handle to rocksdb's default env.
+///
+/// The default env is shared by every DB in the process, so the thread
+/// pool settings made here apply to all of them.
+pub struct Env {
+    pub inner: rocksdb_ffi::DBEnv,
+}
+
+unsafe impl Send for Env {}
+unsafe impl Sync for Env {}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_env_destroy(self.inner);
+        }
+    }
+}
+
+impl Default for Env {
+    fn default() -> Env {
+        let env = unsafe { rocksdb_ffi::rocksdb_create_default_env() };
+        if env.0.is_null() {
+            panic!("Could not create rocksdb env".to_string());
+        }
+        Env { inner: env }
+    }
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env::default()
+    }
+
+    /// Resize the thread pool of `pri` to `n` threads.
+    ///
+    /// It can be called while DBs are running, the pool grows at once and
+    /// shrinks as the busy threads finish their current job.
+    pub fn set_background_threads(&mut self, pri: Priority, n: c_int) {
+        unsafe {
+            match pri {
+                Priority::Bottom => {
+                    rocksdb_ffi::rocksdb_env_set_bottom_priority_background_threads(self.inner, n)
+                }
+                Priority::Low => {
+                    rocksdb_ffi::rocksdb_env_set_background_threads(self.inner,
+                                                                    n)
+                }
+                Priority::High => {
+                    rocksdb_ffi::rocksdb_env_set_high_priority_background_threads(self.inner, n)
+                }
+            }
+        }
+    }
+
+    /// Number of threads in the thread pool of `pri`.
+    pub fn get_background_threads(&self, pri: Priority) -> c_int {
+        unsafe {
+            match pri {
+                Priority::Bottom => {
+                    rocksdb_ffi::rocksdb_env_get_bottom_priority_background_threads(self.inner)
+                }
+                Priority::Low => {
+                    rocksdb_ffi::rocksdb_env_get_background_threads(self.inner)
+                }
+                Priority::High => {
+                    rocksdb_ffi::rocksdb_env_get_high_priority_background_threads(self.inner)
+                }
+            }
+        }
+    }
+
+    /// Give compactions into the bottommost level their own `n` threads,
+    /// so that they can't hold up the smaller compactions above them.
+    pub fn set_bottom_priority_background_threads(&mut self, n: c_int) {
+        self.set_background_threads(Priority::Bottom, n);
+    }
+
+    /// Lower the IO priority of the compaction threads, so that they
+    /// yield the disk to foreground reads and writes.
+    ///
+    /// Only supported on Linux, it does nothing elsewhere.
+    pub fn lower_thread_pool_io_priority(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_env_lower_thread_pool_io_priority(self.inner);
+        }
+    }
+
+    /// Lower the IO priority of the flush threads.
+    pub fn lower_high_priority_thread_pool_io_priority(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_env_lower_high_priority_thread_pool_io_priority(self.inner);
+        }
+    }
+
+    /// Lower the CPU priority of the compaction threads.
+    ///
+    /// Only supported on Linux, it does nothing elsewhere.
+    pub fn lower_thread_pool_cpu_priority(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_env_lower_thread_pool_cpu_priority(self.inner);
+        }
+    }
+
+    /// Lower the CPU priority of the flush threads.
+    pub fn lower_high_priority_thread_pool_cpu_priority(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_env_lower_high_priority_thread_pool_cpu_priority(self.inner);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fmt::{self, Formatter};
+
+use rocksdb::StallReason;
+
+/// The error of the operations that tell the causes of their failures
+/// apart.
+///
+/// The other operations return rocksdb's message as a `String`. It
+/// converts into an `Error` by the status the message starts with, and
+/// an `Error` converts back into its message, so both work with `try!`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A column family that is not one of the DB's, by name if it is
+    /// known.
+    InvalidColumnFamily(Option<String>),
+    InvalidArgument(String),
+    /// A property not supported by the linked rocksdb, or not for this
+    /// column family.
+    UnknownProperty(String),
+    /// A property whose value is not valid UTF-8, or not an integer when
+    /// one was asked for.
+    InvalidPropertyValue(String),
+    /// The DB is already open for writing, by the process with this pid
+    /// if it is known.
+    AlreadyLocked(Option<u32>),
+    /// A write would stall, or was rejected by a stall.
+    WriteStall(StallReason),
+    /// A lock or resource is held by someone else.
+    Busy(String),
+    TryAgain(String),
+    Corruption(String),
+    /// A value doesn't match the checksum stored with it, see
+    /// `ChecksummedCf`.
+    ChecksumMismatch {
+        key: Vec<u8>,
+        expected: u32,
+        actual: u32,
+    },
+    /// Any other failure, with its message.
+    Other(String),
+}
+
+impl Error {
+    /// Classify an error message returned by rocksdb.
+    pub fn from_message(msg: &str) -> Error {
+        if msg.starts_with("Resource busy") {
+            Error::Busy(msg.to_owned())
+        } else if msg.starts_with("Operation failed. Try again.") {
+            Error::TryAgain(msg.to_owned())
+        } else if msg.starts_with("Result incomplete: Write stall") {
+            Error::WriteStall(StallReason::Rejected)
+        } else if msg.starts_with("Corruption") {
+            Error::Corruption(msg.to_owned())
+        } else if msg.starts_with("Invalid argument") {
+            Error::InvalidArgument(msg.to_owned())
+        } else {
+            Error::Other(msg.to_owned())
+        }
+    }
+
+    /// Whether the error may go away if the operation is simply retried.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            Error::Busy(_) |
+            Error::TryAgain(_) |
+            Error::WriteStall(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidColumnFamily(Some(ref name)) => {
+                write!(f, "Invalid column family: {}", name)
+            }
+            Error::InvalidColumnFamily(None) => {
+                write!(f, "Invalid column family handle")
+            }
+            Error::UnknownProperty(ref name) => {
+                write!(f, "Unknown property {}", name)
+            }
+            Error::InvalidPropertyValue(ref name) => {
+                write!(f, "Invalid value for property {}", name)
+            }
+            Error::AlreadyLocked(Some(pid)) => {
+                write!(f, "DB is already open by process {}", pid)
+            }
+            Error::AlreadyLocked(None) => {
+                write!(f, "DB is already open by another process")
+            }
+            Error::WriteStall(StallReason::Rejected) => {
+                write!(f, "Result incomplete: Write stall")
+            }
+            Error::WriteStall(reason) => {
+                write!(f, "Write would stall: {:?}", reason)
+            }
+            Error::ChecksumMismatch { ref key, expected, actual } => {
+                write!(f,
+                       "Corruption: checksum mismatch for {:?}, expected \
+                        {:#010x} got {:#010x}",
+                       key,
+                       expected,
+                       actual)
+            }
+            Error::InvalidArgument(ref msg) |
+            Error::Busy(ref msg) |
+            Error::TryAgain(ref msg) |
+            Error::Corruption(ref msg) |
+            Error::Other(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Error {
+        Error::from_message(&msg)
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> String {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::StallReason;
+
+    #[test]
+    fn classify_test() {
+        assert_eq!(Error::from_message("Resource busy: "),
+                   Error::Busy("Resource busy: ".to_owned()));
+        assert!(Error::from_message("Operation failed. Try again.: ")
+            .is_transient());
+        assert_eq!(Error::from_message("Result incomplete: Write stall"),
+                   Error::WriteStall(StallReason::Rejected));
+        let e = Error::from_message("Corruption: bad block");
+        assert_eq!(e, Error::Corruption("Corruption: bad block".to_owned()));
+        assert!(!e.is_transient());
+        assert_eq!(String::from(e), "Corruption: bad block");
+        assert_eq!(Error::from("IO error: no space".to_owned()),
+                   Error::Other("IO error: no space".to_owned()));
+    }
+}
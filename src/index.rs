@@ -0,0 +1,308 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::sync::{Arc, Mutex};
+
+use rocksdb::{DB, Kv, SeekKey, Writable, WriteBatch};
+
+/// Extracts the indexed value from a record, `None` leaves the record out
+/// of the index.
+pub type KeyExtractor = Box<Fn(&[u8], &[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+struct Index {
+    name: String,
+    cf: String,
+    extract: KeyExtractor,
+}
+
+/// A column family whose records are indexed in other column families.
+///
+/// Every write goes through `put` or `delete`, which update the record and
+/// its index entries in the same write batch, so the indexes never point
+/// to a missing record or miss one. Writes are serialized, as they have to
+/// read the previous value to find the entries to remove.
+///
+/// An index entry is stored under the indexed value prefixed with its
+/// length and followed by the record key, so records sharing an indexed
+/// value are next to each other and `lookup` is a prefix scan.
+pub struct IndexedCf {
+    db: Arc<DB>,
+    cf: String,
+    indexes: Vec<Index>,
+    write_lock: Mutex<()>,
+}
+
+impl IndexedCf {
+    /// Index the records in column family `cf` of `db`.
+    pub fn new(db: Arc<DB>, cf: &str) -> Result<IndexedCf, String> {
+        try!(db.cf_handle_or_err(cf));
+        Ok(IndexedCf {
+            db: db,
+            cf: cf.to_owned(),
+            indexes: vec![],
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Register the index `name`, stored in column family `index_cf`.
+    ///
+    /// Records written before the index was added are not in it, use
+    /// `rebuild_index` to index them.
+    pub fn add_index<F>(&mut self,
+                        name: &str,
+                        index_cf: &str,
+                        extract: F)
+                        -> Result<(), String>
+        where F: Fn(&[u8], &[u8]) -> Option<Vec<u8>> + Send + Sync + 'static
+    {
+        if self.indexes.iter().any(|i| i.name == name) {
+            return Err(format!("Index {} already exists", name));
+        }
+        if index_cf == self.cf ||
+           self.indexes.iter().any(|i| i.cf == index_cf) {
+            return Err(format!("Column family {} is already used",
+                               index_cf));
+        }
+        try!(self.db.cf_handle_or_err(index_cf));
+        self.indexes.push(Index {
+            name: name.to_owned(),
+            cf: index_cf.to_owned(),
+            extract: Box::new(extract),
+        });
+        Ok(())
+    }
+
+    pub fn db(&self) -> &Arc<DB> {
+        &self.db
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let value = try!(self.db.get_cf(cf, key));
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.write_with(WriteBatch::new(), key, Some(value))
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), String> {
+        self.write_with(WriteBatch::new(), key, None)
+    }
+
+    /// Write `value` under `key` together with the other writes of `batch`,
+    /// `None` deletes the record.
+    pub fn write_with(&self,
+                      batch: WriteBatch,
+                      key: &[u8],
+                      value: Option<&[u8]>)
+                      -> Result<(), String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let _guard = self.write_lock.lock().unwrap();
+        let old = try!(self.db.get_cf(cf, key));
+        for index in &self.indexes {
+            let index_cf = try!(self.db.cf_handle_or_err(&index.cf));
+            let old_entry = old.as_ref()
+                .and_then(|v| (index.extract)(key, v))
+                .map(|iv| entry_key(&iv, key));
+            let new_entry = value.and_then(|v| (index.extract)(key, v))
+                .map(|iv| entry_key(&iv, key));
+            if old_entry == new_entry {
+                continue;
+            }
+            if let Some(e) = old_entry {
+                try!(batch.delete_cf(index_cf, &e));
+            }
+            if let Some(e) = new_entry {
+                try!(batch.put_cf(index_cf, &e, b""));
+            }
+        }
+        match value {
+            Some(v) => try!(batch.put_cf(cf, key, v)),
+            None => try!(batch.delete_cf(cf, key)),
+        }
+        self.db.write(batch)
+    }
+
+    /// Keys of the records whose value for index `name` is `value`, in key
+    /// order.
+    pub fn lookup_keys(&self,
+                       name: &str,
+                       value: &[u8])
+                       -> Result<Vec<Vec<u8>>, String> {
+        let index = try!(self.index(name));
+        let index_cf = try!(self.db.cf_handle_or_err(&index.cf));
+        let prefix = entry_key(value, b"");
+        let mut iter = self.db.iter_cf(index_cf);
+        let mut keys = vec![];
+        iter.seek(SeekKey::Key(&prefix));
+        while let Some(key) = iter.key() {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            keys.push(key[prefix.len()..].to_vec());
+            iter.next();
+        }
+        try!(iter.status());
+        Ok(keys)
+    }
+
+    /// The records whose value for index `name` is `value`, in key order.
+    ///
+    /// The index and the records are read from the same snapshot.
+    pub fn lookup(&self, name: &str, value: &[u8]) -> Result<Vec<Kv>, String> {
+        let index = try!(self.index(name));
+        let index_cf = try!(self.db.cf_handle_or_err(&index.cf));
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let snap = self.db.snapshot();
+        let prefix = entry_key(value, b"");
+        let mut iter = snap.iter_cf(index_cf);
+        let mut records = vec![];
+        iter.seek(SeekKey::Key(&prefix));
+        while let Some(key) = iter.key() {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let key = &key[prefix.len()..];
+            match try!(snap.get_cf(cf, key)) {
+                Some(v) => records.push((key.to_vec(), v.to_vec())),
+                None => {
+                    return Err(format!("Index {} points to missing key {:?}",
+                                       name,
+                                       key))
+                }
+            }
+            iter.next();
+        }
+        try!(iter.status());
+        Ok(records)
+    }
+
+    /// Clear index `name` and index every record again.
+    ///
+    /// Writes made while rebuilding are serialized with it, so they are
+    /// indexed exactly once.
+    pub fn rebuild_index(&self, name: &str) -> Result<(), String> {
+        let index = try!(self.index(name));
+        let index_cf = try!(self.db.cf_handle_or_err(&index.cf));
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let _guard = self.write_lock.lock().unwrap();
+
+        let batch = WriteBatch::new();
+        let mut iter = self.db.iter_cf(index_cf);
+        iter.seek(SeekKey::Start);
+        while let Some(key) = iter.key() {
+            try!(batch.delete_cf(index_cf, key));
+            iter.next();
+        }
+        try!(iter.status());
+
+        let mut iter = self.db.iter_cf(cf);
+        iter.seek(SeekKey::Start);
+        while let Some((key, value)) = iter.entry() {
+            if let Some(iv) = (index.extract)(key, value) {
+                try!(batch.put_cf(index_cf, &entry_key(&iv, key), b""));
+            }
+            iter.next();
+        }
+        try!(iter.status());
+        self.db.write(batch)
+    }
+
+    fn index(&self, name: &str) -> Result<&Index, String> {
+        self.indexes
+            .iter()
+            .find(|i| i.name == name)
+            .ok_or_else(|| format!("Invalid index: {}", name))
+    }
+}
+
+fn entry_key(indexed: &[u8], key: &[u8]) -> Vec<u8> {
+    let len = indexed.len() as u32;
+    let mut entry = Vec::with_capacity(4 + indexed.len() + key.len());
+    entry.extend_from_slice(&[(len >> 24) as u8,
+                              (len >> 16) as u8,
+                              (len >> 8) as u8,
+                              len as u8]);
+    entry.extend_from_slice(indexed);
+    entry.extend_from_slice(key);
+    entry
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::DB;
+    use rocksdb_options::Options;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    // Values look like "<color>:<size>".
+    fn color(_: &[u8], v: &[u8]) -> Option<Vec<u8>> {
+        v.split(|b| *b == b':').next().map(|c| c.to_vec())
+    }
+
+    fn size(_: &[u8], v: &[u8]) -> Option<Vec<u8>> {
+        v.split(|b| *b == b':').nth(1).map(|s| s.to_vec())
+    }
+
+    #[test]
+    fn indexed_cf_test() {
+        let path = TempDir::new("_rust_rocksdb_indexedcftest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts,
+                             path.path().to_str().unwrap(),
+                             &["default", "by_color", "by_size"],
+                             &[&opts, &opts, &opts])
+            .unwrap();
+        let db = Arc::new(db);
+
+        let mut items = IndexedCf::new(db.clone(), "default").unwrap();
+        items.add_index("color", "by_color", color).unwrap();
+        assert!(items.add_index("color", "by_size", size).is_err());
+        assert!(items.add_index("other", "by_color", size).is_err());
+        assert!(items.add_index("size", "missing", size).is_err());
+
+        items.put(b"a", b"red:s").unwrap();
+        items.put(b"b", b"blue:m").unwrap();
+        items.put(b"c", b"red:m").unwrap();
+        items.put(b"d", b"red").unwrap();
+        assert_eq!(items.lookup_keys("color", b"red").unwrap(),
+                   vec![b"a".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        assert_eq!(items.lookup("color", b"blue").unwrap(),
+                   vec![(b"b".to_vec(), b"blue:m".to_vec())]);
+        // "re" must not match the entries of "red".
+        assert!(items.lookup_keys("color", b"re").unwrap().is_empty());
+        assert!(items.lookup_keys("size", b"m").is_err());
+
+        items.put(b"a", b"blue:s").unwrap();
+        items.delete(b"c").unwrap();
+        assert_eq!(items.lookup_keys("color", b"red").unwrap(),
+                   vec![b"d".to_vec()]);
+        assert_eq!(items.lookup_keys("color", b"blue").unwrap(),
+                   vec![b"a".to_vec(), b"b".to_vec()]);
+
+        // The records written before the index was added need a rebuild.
+        items.add_index("size", "by_size", size).unwrap();
+        assert!(items.lookup_keys("size", b"s").unwrap().is_empty());
+        items.rebuild_index("size").unwrap();
+        assert_eq!(items.lookup_keys("size", b"s").unwrap(),
+                   vec![b"a".to_vec()]);
+        assert_eq!(items.lookup_keys("size", b"m").unwrap(),
+                   vec![b"b".to_vec()]);
+        assert_eq!(items.get(b"d").unwrap(), Some(b"red".to_vec()));
+    }
+}
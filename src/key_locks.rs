@@ -0,0 +1,437 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::Hasher;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use rocksdb::{DB, DBVector, Writable};
+use rocksdb_ffi::DBCFHandle;
+
+/// Locks on keys, to serialize the read-modify-write cycles of a key
+/// without a transaction DB.
+///
+/// Keys are hashed to a fixed number of stripes, each a lock, so keys
+/// sharing a stripe wait for each other: more stripes mean fewer false
+/// conflicts. The locks are only advisory, writes that don't take them
+/// are not held off.
+///
+/// A lock can be waited for by blocking the thread, with `lock`, or by
+/// an async task, with `lock_async`, which leaves the thread to the
+/// other tasks meanwhile. Both kinds of waiters share the same stripes.
+pub struct KeyLocks {
+    stripes: Vec<Stripe>,
+}
+
+struct Stripe {
+    state: Mutex<StripeState>,
+    // Signaled when the stripe is released, for the blocked threads.
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct StripeState {
+    locked: bool,
+    // The tasks waiting for the stripe, woken when it is released.
+    wakers: Vec<Waker>,
+}
+
+/// Holds the locks of keys until dropped, see `KeyLocks::lock`.
+pub struct KeyGuard<'a> {
+    locks: &'a KeyLocks,
+    stripes: Vec<usize>,
+}
+
+/// The future of `KeyLocks::lock_all_async`, resolving to the guard of
+/// the keys.
+pub struct KeyLockFuture<'a> {
+    // The stripes locked so far, in the guard that releases them if the
+    // future is dropped before it resolves.
+    guard: Option<KeyGuard<'a>>,
+    stripes: Vec<usize>,
+}
+
+impl Stripe {
+    fn state(&self) -> MutexGuard<StripeState> {
+        // The state is consistent at all times, a panic elsewhere while it
+        // was held doesn't make the stripe unusable.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) {
+        let mut state = self.state();
+        while state.locked {
+            state = self.released
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        state.locked = true;
+    }
+
+    fn try_lock(&self, waker: &Waker) -> bool {
+        let mut state = self.state();
+        if state.locked {
+            state.wakers.push(waker.clone());
+            return false;
+        }
+        state.locked = true;
+        true
+    }
+
+    fn unlock(&self) {
+        let wakers = {
+            let mut state = self.state();
+            state.locked = false;
+            state.wakers.split_off(0)
+        };
+        // Whoever is first takes the stripe, the others wait again.
+        self.released.notify_one();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl KeyLocks {
+    pub fn new(stripes: usize) -> KeyLocks {
+        assert!(stripes > 0);
+        KeyLocks {
+            stripes: (0..stripes)
+                .map(|_| {
+                    Stripe {
+                        state: Mutex::new(StripeState::default()),
+                        released: Condvar::new(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn lock(&self, key: &[u8]) -> KeyGuard {
+        self.lock_all(&[key])
+    }
+
+    /// Lock all of `keys`, always in the same order so that threads
+    /// locking overlapping sets of keys can't deadlock.
+    pub fn lock_all(&self, keys: &[&[u8]]) -> KeyGuard {
+        let mut guard = KeyGuard {
+            locks: self,
+            stripes: vec![],
+        };
+        for i in self.stripes_of(keys) {
+            self.stripes[i].lock();
+            guard.stripes.push(i);
+        }
+        guard
+    }
+
+    /// Like `lock`, without blocking the thread while waiting.
+    pub fn lock_async(&self, key: &[u8]) -> KeyLockFuture {
+        self.lock_all_async(&[key])
+    }
+
+    /// Like `lock_all`, without blocking the thread while waiting.
+    pub fn lock_all_async(&self, keys: &[&[u8]]) -> KeyLockFuture {
+        KeyLockFuture {
+            guard: Some(KeyGuard {
+                locks: self,
+                stripes: vec![],
+            }),
+            stripes: self.stripes_of(keys),
+        }
+    }
+
+    /// Lock `key` and read its value, to update it before dropping the
+    /// guard.
+    pub fn get_for_update<'a>(&'a self,
+                              db: &DB,
+                              key: &[u8])
+                              -> Result<(KeyGuard<'a>, Option<DBVector>),
+                                        String> {
+        let guard = self.lock(key);
+        let value = try!(db.get(key));
+        Ok((guard, value))
+    }
+
+    /// Replace the value of `key` by `f(value)` under the key's lock,
+    /// `None` deleting it, and return the new value.
+    pub fn update<F>(&self,
+                     db: &DB,
+                     key: &[u8],
+                     f: F)
+                     -> Result<Option<Vec<u8>>, String>
+        where F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>
+    {
+        self.update_cf(db, default_cf(db), key, f)
+    }
+
+    /// Like `update`, for a key of column family `cf`.
+    ///
+    /// The keys of all column families share the stripes.
+    pub fn update_cf<F>(&self,
+                        db: &DB,
+                        cf: DBCFHandle,
+                        key: &[u8],
+                        f: F)
+                        -> Result<Option<Vec<u8>>, String>
+        where F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>
+    {
+        let _guard = self.lock(key);
+        let old = try!(db.get_cf(cf, key));
+        let new = f(old.as_ref().map(|v| &v[..]));
+        match new {
+            Some(ref v) => try!(db.put_cf(cf, key, v)),
+            None if old.is_some() => try!(db.delete_cf(cf, key)),
+            None => {}
+        }
+        Ok(new)
+    }
+
+    /// Delete `key` if its value is `expected`, returning whether it was.
+    pub fn delete_if_equals(&self,
+                            db: &DB,
+                            key: &[u8],
+                            expected: &[u8])
+                            -> Result<bool, String> {
+        self.delete_if_equals_cf(db, default_cf(db), key, expected)
+    }
+
+    pub fn delete_if_equals_cf(&self,
+                               db: &DB,
+                               cf: DBCFHandle,
+                               key: &[u8],
+                               expected: &[u8])
+                               -> Result<bool, String> {
+        let _guard = self.lock(key);
+        match try!(db.get_cf(cf, key)) {
+            Some(ref v) if &v[..] == expected => {}
+            _ => return Ok(false),
+        }
+        try!(db.delete_cf(cf, key));
+        Ok(true)
+    }
+
+    /// Put `value` under `key` if it has none, returning whether it had
+    /// none.
+    pub fn put_if_absent(&self,
+                         db: &DB,
+                         key: &[u8],
+                         value: &[u8])
+                         -> Result<bool, String> {
+        self.put_if_absent_cf(db, default_cf(db), key, value)
+    }
+
+    pub fn put_if_absent_cf(&self,
+                            db: &DB,
+                            cf: DBCFHandle,
+                            key: &[u8],
+                            value: &[u8])
+                            -> Result<bool, String> {
+        let _guard = self.lock(key);
+        if try!(db.get_cf(cf, key)).is_some() {
+            return Ok(false);
+        }
+        try!(db.put_cf(cf, key, value));
+        Ok(true)
+    }
+
+    // The stripes of `keys`, sorted and without duplicates.
+    fn stripes_of(&self, keys: &[&[u8]]) -> Vec<usize> {
+        let mut stripes: Vec<usize> =
+            keys.iter().map(|k| self.stripe(k)).collect();
+        stripes.sort();
+        stripes.dedup();
+        stripes
+    }
+
+    fn stripe(&self, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(key);
+        (hasher.finish() % self.stripes.len() as u64) as usize
+    }
+}
+
+impl<'a> Future for KeyLockFuture<'a> {
+    type Output = KeyGuard<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<KeyGuard<'a>> {
+        let this = self.get_mut();
+        {
+            let guard = this.guard.as_mut().expect("polled after completion");
+            // Take the stripes in order, like `lock_all`.
+            while guard.stripes.len() < this.stripes.len() {
+                let i = this.stripes[guard.stripes.len()];
+                if !guard.locks.stripes[i].try_lock(cx.waker()) {
+                    return Poll::Pending;
+                }
+                guard.stripes.push(i);
+            }
+        }
+        Poll::Ready(this.guard.take().unwrap())
+    }
+}
+
+impl<'a> Drop for KeyGuard<'a> {
+    fn drop(&mut self) {
+        for &i in &self.stripes {
+            self.locks.stripes[i].unlock();
+        }
+    }
+}
+
+fn default_cf(db: &DB) -> DBCFHandle {
+    *db.cf_handle("default").unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use std::future::Future;
+    use std::mem;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread;
+    use tempdir::TempDir;
+
+    fn decode(v: Option<&[u8]>) -> u64 {
+        v.map_or(0, |v| String::from_utf8_lossy(v).parse().unwrap())
+    }
+
+    #[test]
+    fn key_locks_test() {
+        let path = TempDir::new("_rust_rocksdb_keylockstest").expect("");
+        let db = Arc::new(DB::open_default(path.path().to_str().unwrap())
+            .unwrap());
+        let locks = Arc::new(KeyLocks::new(16));
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                let locks = locks.clone();
+                thread::spawn(move || for _ in 0..100 {
+                    locks.update(&db, b"counter", |v| {
+                            Some((decode(v) + 1).to_string().into_bytes())
+                        })
+                        .unwrap();
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(decode(db.get(b"counter").unwrap().as_ref().map(|v| &v[..])),
+                   400);
+
+        assert_eq!(locks.update(&db, b"counter", |_| None).unwrap(), None);
+        assert!(db.get(b"counter").unwrap().is_none());
+        let cf = *db.cf_handle("default").unwrap();
+        let new = locks.update_cf(&db, cf, b"k1", |v| {
+                assert!(v.is_none());
+                Some(b"v1".to_vec())
+            })
+            .unwrap();
+        assert_eq!(new, Some(b"v1".to_vec()));
+
+        let (guard, value) = locks.get_for_update(&db, b"k1").unwrap();
+        assert_eq!(&*value.unwrap(), b"v1");
+        db.put(b"k1", b"v2").unwrap();
+        drop(guard);
+
+        // Locking the same key twice in one call doesn't deadlock.
+        let _guard = locks.lock_all(&[b"k1", b"k2", b"k1"]);
+    }
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn lock_async_test() {
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        // With one stripe, all the keys wait for each other.
+        let locks = KeyLocks::new(1);
+        let guard = locks.lock(b"k1");
+        let mut future = locks.lock_async(b"k2");
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+        drop(guard);
+        assert!(flag.0.load(Ordering::SeqCst));
+        let guard = match Pin::new(&mut future).poll(&mut cx) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("should be locked"),
+        };
+
+        // The blocking and async waiters share the locks.
+        let mut other = locks.lock_async(b"k3");
+        assert!(Pin::new(&mut other).poll(&mut cx).is_pending());
+        drop(guard);
+        let guard = locks.lock(b"k1");
+        assert!(Pin::new(&mut other).poll(&mut cx).is_pending());
+        drop(guard);
+        assert!(Pin::new(&mut other).poll(&mut cx).is_ready());
+
+        // A future dropped before it resolves releases what it locked.
+        let locks = KeyLocks::new(16);
+        let (mut first, mut last) = (&b"k1"[..], &b"k2"[..]);
+        assert!(locks.stripe(first) != locks.stripe(last));
+        if locks.stripe(first) > locks.stripe(last) {
+            mem::swap(&mut first, &mut last);
+        }
+        let guard = locks.lock(last);
+        let mut future = locks.lock_all_async(&[first, last]);
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        drop(future);
+        assert!(Pin::new(&mut locks.lock_async(first))
+            .poll(&mut cx)
+            .is_ready());
+        drop(guard);
+    }
+
+    #[test]
+    fn conditional_ops_test() {
+        let path = TempDir::new("_rust_rocksdb_conditionalopstest")
+            .expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        let locks = KeyLocks::new(4);
+
+        assert!(locks.put_if_absent(&db, b"k1", b"v1").unwrap());
+        assert!(!locks.put_if_absent(&db, b"k1", b"v2").unwrap());
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"v1");
+        assert!(!locks.put_if_absent_cf(&db, cf, b"k1", b"v2").unwrap());
+        assert!(locks.put_if_absent_cf(&db, cf, b"k2", b"v2").unwrap());
+
+        assert!(!locks.delete_if_equals(&db, b"k1", b"v2").unwrap());
+        assert!(db.get(b"k1").unwrap().is_some());
+        assert!(locks.delete_if_equals(&db, b"k1", b"v1").unwrap());
+        assert!(db.get(b"k1").unwrap().is_none());
+        assert!(!locks.delete_if_equals(&db, b"k1", b"v1").unwrap());
+        assert!(!locks.delete_if_equals_cf(&db, cf, b"k2", b"").unwrap());
+        assert!(locks.delete_if_equals_cf(&db, cf, b"k2", b"v2").unwrap());
+        assert!(db.get(b"k2").unwrap().is_none());
+    }
+}
@@ -15,7 +15,7 @@
 
 extern crate libc;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "test-util"))]
 extern crate tempdir;
 pub extern crate librocksdb_sys;
 
@@ -23,10 +23,80 @@ pub mod rocksdb;
 pub mod rocksdb_options;
 pub mod merge_operator;
 pub mod comparator;
+pub mod env;
+pub mod manifest;
+pub mod write_coordinator;
+pub mod checkpoint;
+pub mod callback;
+pub mod retry;
+pub mod wal;
+pub mod index;
+pub mod queue;
+pub mod sharded;
+pub mod read_view;
+pub mod options_file;
+pub mod metrics;
+pub mod bulk_load;
+pub mod verify;
+pub mod metadata;
+pub mod replace;
+pub mod options_builder;
+pub mod sync_db;
+pub mod compaction_job;
+pub mod key_locks;
+pub mod write_batch;
+pub mod backup;
+pub mod sst_reader;
+pub mod cf_alias;
+pub mod compaction_filter;
+pub mod lock_file;
+pub mod error;
+mod coding;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "checksum")]
+pub mod checksum;
 
-pub use librocksdb_sys::{DBCompactionStyle, DBComparator, DBCompressionType,
+pub use librocksdb_sys::{DBAccessHint, DBChecksumType, DBCompactionStyle,
+                         DBComparator, DBCompressionType, DBDataBlockIndexType,
                          new_bloom_filter, self as rocksdb_ffi};
-pub use rocksdb::{DB, DBIterator, DBVector, Kv, ReadOptions, SeekKey,
+pub use rocksdb::{BackgroundJobs, CfOp, CompactionHint, DB, DBIterator,
+                  DBVector, DiskUsage, EntryOverheadReport, HealthSnapshot,
+                  Kv, LevelSize, LevelSizes, LevelSpace, LiveFile,
+                  RangeTombstones, ReadOptions, SeekKey, SnapshotHandle,
+                  SpaceAmpReport, StallReason, TombstoneSpan, TryWriteError,
                   Writable, WriteBatch};
-pub use rocksdb_options::{BlockBasedOptions, Options, WriteOptions};
-pub use merge_operator::MergeOperands;
+pub use rocksdb_options::{BlockBasedOptions, Cache, CacheStats, FlushOptions,
+                          IngestExternalFileOptions, Options, RateLimiter,
+                          WriteOptions};
+pub use merge_operator::{MergeOperands, MergeOperator, UInt64AddOperator};
+pub use env::{Env, Priority};
+pub use write_coordinator::WriteCoordinator;
+pub use checkpoint::CheckpointDir;
+pub use callback::{PanicPolicy, set_panic_policy};
+pub use retry::RetryPolicy;
+pub use wal::{WalIterator, WalReader};
+pub use index::IndexedCf;
+pub use queue::Queue;
+pub use sharded::ShardedDb;
+pub use read_view::{ReadView, ReadViews};
+pub use options_file::{OptionChange, PersistedOptions};
+pub use metrics::{MetricsConfig, MetricsSample};
+pub use bulk_load::{BulkLoadStats, BulkLoader, SstFileWriter};
+pub use verify::{DiffReport, KeyDiff};
+pub use metadata::Metadata;
+pub use replace::{recover_replaced_db, replace_db};
+pub use options_builder::{OptionsBuilder, parse_size};
+pub use sync_db::SyncDb;
+pub use compaction_job::{CompactionJob, CompactionProgress};
+pub use key_locks::{KeyGuard, KeyLocks};
+pub use write_batch::{WriteBatchHandler, iterate_write_batch};
+pub use backup::{BackupEngine, BackupInfo, RestoreOptions};
+pub use sst_reader::{EntryType, SstEntry, SstFileIterator, SstFileReader,
+                     TableProperties};
+pub use cf_alias::CfAliases;
+pub use compaction_filter::{CompactionDecision, CompactionFilter};
+pub use lock_file::LockStatus;
+pub use error::Error;
+#[cfg(feature = "checksum")]
+pub use checksum::ChecksummedCf;
@@ -0,0 +1,199 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::path::Path;
+
+use error::Error;
+
+// The file rocksdb locks while a DB is open for writing.
+const LOCK_FILE: &'static str = "LOCK";
+
+/// Whether a DB is open for writing, see `DB::lock_status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockStatus {
+    Unlocked,
+    /// Locked by the process with this pid, this one included, `None`
+    /// when the lock doesn't tell.
+    Locked(Option<u32>),
+}
+
+/// Find out who holds the lock of the DB at `path`.
+///
+/// The lock file is never opened: closing it would release the lock of
+/// a DB this process has open. Only supported on linux, where the locks
+/// are listed in /proc/locks.
+pub fn lock_status(path: &str) -> Result<LockStatus, String> {
+    let lock_path = Path::new(path).join(LOCK_FILE);
+    match lock_path.metadata() {
+        Ok(_) => sys::lock_status(&lock_path),
+        Err(_) => Ok(LockStatus::Unlocked),
+    }
+}
+
+/// Classify the error of opening the DB at `path`.
+///
+/// rocksdb fails to lock with "While lock file: <path>/LOCK: ..." when
+/// another process holds the lock, and with "lock hold by current
+/// process, ... lock file: <path>/LOCK: ..." when this one does. The lock
+/// is only looked up to tell its holder.
+pub fn open_error(path: &str, e: String) -> Error {
+    let suffix = format!("/{}:", LOCK_FILE);
+    let locked = e.contains(&suffix) &&
+                 (e.contains("While lock file") ||
+                  e.contains("lock hold by current process"));
+    if !locked {
+        return Error::from(e);
+    }
+    match lock_status(path) {
+        Ok(LockStatus::Locked(pid)) => Error::AlreadyLocked(pid),
+        _ => Error::AlreadyLocked(None),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    use super::LockStatus;
+
+    pub fn lock_status(lock_path: &Path) -> Result<LockStatus, String> {
+        let meta = try!(lock_path.metadata().map_err(|e| {
+            format!("Failed to stat {}: {}", lock_path.display(), e)
+        }));
+        let mut locks = String::new();
+        try!(File::open("/proc/locks")
+            .and_then(|mut f| f.read_to_string(&mut locks))
+            .map_err(|e| format!("Failed to read /proc/locks: {}", e)));
+        let dev = meta.dev();
+        let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+        let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+        for line in locks.lines() {
+            if let Some((pid, id)) = parse_lock(line) {
+                if id == (major, minor, meta.ino()) {
+                    return Ok(LockStatus::Locked(pid));
+                }
+            }
+        }
+        Ok(LockStatus::Unlocked)
+    }
+
+    // Parse a lock held with fcntl, like rocksdb's, from a line of
+    // /proc/locks:
+    //
+    //   1: POSIX  ADVISORY  WRITE 1234 08:02:131090 0 EOF
+    //
+    // into the pid of its holder and the device and inode of its file.
+    // Open file description locks have no pid, and locks being waited
+    // for, marked with "->", are skipped.
+    fn parse_lock(line: &str) -> Option<(Option<u32>, (u64, u64, u64))> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || (fields[1] != "POSIX" && fields[1] != "OFDLCK") {
+            return None;
+        }
+        let pid = fields[4].parse().ok();
+        let id: Vec<&str> = fields[5].split(':').collect();
+        if id.len() != 3 {
+            return None;
+        }
+        match (u64::from_str_radix(id[0], 16),
+               u64::from_str_radix(id[1], 16),
+               id[2].parse()) {
+            (Ok(major), Ok(minor), Ok(ino)) => Some((pid, (major, minor, ino))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::path::Path;
+
+    use super::LockStatus;
+
+    pub fn lock_status(_: &Path) -> Result<LockStatus, String> {
+        Err("Lock status is not supported on this platform".to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use std::process;
+    use tempdir::TempDir;
+
+    #[test]
+    fn lock_status_test() {
+        let path = TempDir::new("_rust_rocksdb_lockstatustest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        assert_eq!(DB::lock_status(path_str).unwrap(), LockStatus::Unlocked);
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        {
+            let db = DB::try_open(&opts, path_str).unwrap();
+            let pid = Some(process::id());
+            if cfg!(target_os = "linux") {
+                assert_eq!(DB::lock_status(path_str).unwrap(),
+                           LockStatus::Locked(pid));
+            }
+            match DB::try_open(&opts, path_str) {
+                Err(Error::AlreadyLocked(p)) => {
+                    if cfg!(target_os = "linux") {
+                        assert_eq!(p, pid);
+                    }
+                }
+                _ => panic!("should be locked"),
+            }
+            // The failed open leaves the DB usable.
+            assert!(db.put(b"k1", b"v1").is_ok());
+        }
+        if cfg!(target_os = "linux") {
+            assert_eq!(DB::lock_status(path_str).unwrap(),
+                       LockStatus::Unlocked);
+        }
+
+        opts.create_if_missing(false);
+        let missing = path.path().join("missing");
+        match DB::try_open(&opts, missing.to_str().unwrap()) {
+            Err(Error::InvalidArgument(_)) => {}
+            _ => panic!("should fail"),
+        }
+    }
+
+    #[test]
+    fn open_error_test() {
+        let path = TempDir::new("_rust_rocksdb_openerrortest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let other = format!("IO error: While lock file: {}/LOCK: Resource \
+                             temporarily unavailable",
+                            path_str);
+        assert_eq!(open_error(path_str, other), Error::AlreadyLocked(None));
+        let current = format!("IO error: lock hold by current process, \
+                               acquire time 0 acquiring thread 0: {}/LOCK: \
+                               No locks available",
+                              path_str);
+        assert_eq!(open_error(path_str, current), Error::AlreadyLocked(None));
+        // Only the LOCK file itself counts, not one it prefixes.
+        let e = format!("IO error: While lock file: {}/LOCKED: failed",
+                        path_str);
+        assert_eq!(open_error(path_str, e.clone()), Error::Other(e));
+        let e = "Invalid argument: LOCK: does not exist".to_owned();
+        assert_eq!(open_error(path_str, e.clone()),
+                   Error::InvalidArgument(e));
+    }
+}
@@ -0,0 +1,545 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Offline inspection of the MANIFEST of a closed DB.
+//!
+//! This reads the files directly instead of going through rocksdb, so it
+//! still works when the DB can't be opened anymore.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use coding::{decode_fixed32, get_length_prefixed, get_varint32, get_varint64,
+             unmask_crc};
+
+const BLOCK_SIZE: usize = 32768;
+const HEADER_SIZE: usize = 7;
+const RECYCLABLE_HEADER_SIZE: usize = 11;
+
+// Log record types.
+const ZERO_TYPE: u8 = 0;
+const FULL_TYPE: u8 = 1;
+const FIRST_TYPE: u8 = 2;
+const MIDDLE_TYPE: u8 = 3;
+const LAST_TYPE: u8 = 4;
+const RECYCLABLE_FULL_TYPE: u8 = 5;
+const RECYCLABLE_LAST_TYPE: u8 = 8;
+
+// VersionEdit tags.
+const TAG_COMPARATOR: u32 = 1;
+const TAG_LOG_NUMBER: u32 = 2;
+const TAG_NEXT_FILE_NUMBER: u32 = 3;
+const TAG_LAST_SEQUENCE: u32 = 4;
+const TAG_COMPACT_POINTER: u32 = 5;
+const TAG_DELETED_FILE: u32 = 6;
+const TAG_NEW_FILE: u32 = 7;
+const TAG_PREV_LOG_NUMBER: u32 = 9;
+const TAG_MIN_LOG_NUMBER_TO_KEEP: u32 = 10;
+const TAG_NEW_FILE2: u32 = 100;
+const TAG_NEW_FILE3: u32 = 102;
+const TAG_NEW_FILE4: u32 = 103;
+const TAG_COLUMN_FAMILY: u32 = 200;
+const TAG_COLUMN_FAMILY_ADD: u32 = 201;
+const TAG_COLUMN_FAMILY_DROP: u32 = 202;
+const TAG_MAX_COLUMN_FAMILY: u32 = 203;
+const TAG_IN_ATOMIC_GROUP: u32 = 300;
+const TAG_BLOB_FILE_ADDITION: u32 = 400;
+const TAG_BLOB_FILE_GARBAGE: u32 = 401;
+// Tags with this bit set are followed by a length prefixed value and can
+// be ignored by readers that don't know them, like the DB id, the WAL
+// additions and deletions and the full history timestamp low.
+const TAG_SAFE_IGNORE_MASK: u32 = 1 << 13;
+
+// Custom fields of TAG_NEW_FILE4.
+const NEW_FILE_TERMINATE: u32 = 1;
+const NEW_FILE_PATH_ID: u32 = 65;
+// Fields with this bit set can't be ignored by readers that don't know
+// them.
+const NEW_FILE_NON_SAFE_IGNORE_MASK: u32 = 1 << 6;
+
+// Custom fields of the blob file tags.
+const BLOB_FILE_END_MARKER: u32 = 0;
+const BLOB_FILE_FORWARD_INCOMPATIBLE_MASK: u32 = 1 << 6;
+
+const FILE_NUMBER_MASK: u64 = 0x3FFFFFFFFFFFFFFF;
+
+/// An sst file that is part of the current version.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SstFileMeta {
+    pub number: u64,
+    /// Index into the db paths the file lives in.
+    pub path_id: u32,
+    pub size: u64,
+    /// The smallest user key in the file.
+    pub smallest_key: Vec<u8>,
+    /// The largest user key in the file.
+    pub largest_key: Vec<u8>,
+    pub smallest_seqno: u64,
+    pub largest_seqno: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnFamilyMeta {
+    pub id: u32,
+    pub name: String,
+    /// Live files keyed by level.
+    pub levels: BTreeMap<u32, Vec<SstFileMeta>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestInfo {
+    /// Name of the MANIFEST file CURRENT points to.
+    pub manifest: String,
+    pub comparator: Option<String>,
+    pub log_number: u64,
+    pub prev_log_number: u64,
+    pub next_file_number: u64,
+    pub last_sequence: u64,
+    pub column_families: Vec<ColumnFamilyMeta>,
+}
+
+struct CfState {
+    name: String,
+    files: BTreeMap<(u32, u64), SstFileMeta>,
+}
+
+/// Read the MANIFEST that the CURRENT file of the DB at `path` points to
+/// and return the column families and live files it records.
+///
+/// The DB should be closed, a MANIFEST that is being written may end in
+/// a partial record, which is reported as an error.
+pub fn dump_manifest(path: &str) -> Result<ManifestInfo, String> {
+    let dir = Path::new(path);
+    let current = try!(read_file(&dir.join("CURRENT")));
+    let manifest = match String::from_utf8(current) {
+        Ok(s) => s.trim_right().to_owned(),
+        Err(_) => return Err("CURRENT is not valid utf-8".to_owned()),
+    };
+    if manifest.is_empty() || manifest.contains('/') {
+        return Err(format!("CURRENT points to invalid manifest {:?}",
+                           manifest));
+    }
+    let data = try!(read_file(&dir.join(&manifest)));
+
+    let mut info = ManifestInfo {
+        manifest: manifest,
+        comparator: None,
+        log_number: 0,
+        prev_log_number: 0,
+        next_file_number: 0,
+        last_sequence: 0,
+        column_families: vec![],
+    };
+    let mut cfs = BTreeMap::new();
+    cfs.insert(0,
+               CfState {
+                   name: "default".to_owned(),
+                   files: BTreeMap::new(),
+               });
+    for record in try!(read_records(&data)) {
+        try!(apply_edit(&record, &mut info, &mut cfs));
+    }
+
+    info.column_families = cfs.into_iter()
+        .map(|(id, cf)| {
+            let mut levels: BTreeMap<u32, Vec<SstFileMeta>> = BTreeMap::new();
+            for ((level, _), f) in cf.files {
+                levels.entry(level).or_insert_with(Vec::new).push(f);
+            }
+            ColumnFamilyMeta {
+                id: id,
+                name: cf.name,
+                levels: levels,
+            }
+        })
+        .collect();
+    Ok(info)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    let mut f = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("failed to open {}: {}", path.display(), e)),
+    };
+    let mut buf = vec![];
+    if let Err(e) = f.read_to_end(&mut buf) {
+        return Err(format!("failed to read {}: {}", path.display(), e));
+    }
+    Ok(buf)
+}
+
+/// Split a log file into its logical records.
+fn read_records(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut records = vec![];
+    let mut pending: Option<Vec<u8>> = None;
+    let mut offset = 0;
+    while offset < data.len() {
+        let block_left = BLOCK_SIZE - offset % BLOCK_SIZE;
+        if block_left < HEADER_SIZE {
+            // Trailer of a block is zero-filled.
+            offset += block_left;
+            continue;
+        }
+        if data.len() - offset < HEADER_SIZE {
+            return Err(format!("truncated record header at offset {}", offset));
+        }
+        let header = &data[offset..offset + HEADER_SIZE];
+        let checksum = decode_fixed32(&header[0..4]);
+        let len = header[4] as usize | (header[5] as usize) << 8;
+        let t = header[6];
+        if t == ZERO_TYPE && len == 0 {
+            // Preallocated space with nothing written yet.
+            offset += block_left;
+            continue;
+        }
+        let header_size = if t >= RECYCLABLE_FULL_TYPE &&
+                             t <= RECYCLABLE_LAST_TYPE {
+            RECYCLABLE_HEADER_SIZE
+        } else {
+            HEADER_SIZE
+        };
+        if offset + header_size + len > data.len() ||
+           header_size + len > block_left {
+            return Err(format!("truncated record at offset {}", offset));
+        }
+        // The checksum covers the type byte, the log number of recyclable
+        // records and the payload.
+        let payload = &data[offset + header_size..offset + header_size + len];
+        let actual = crc32c_extend(crc32c_extend(0,
+                                                 &data[offset + 6..offset +
+                                                                   header_size]),
+                                   payload);
+        if unmask_crc(checksum) != actual {
+            return Err(format!("checksum mismatch at offset {}", offset));
+        }
+        offset += header_size + len;
+
+        let t = if header_size == RECYCLABLE_HEADER_SIZE {
+            t - RECYCLABLE_FULL_TYPE + FULL_TYPE
+        } else {
+            t
+        };
+        match t {
+            FULL_TYPE => records.push(payload.to_vec()),
+            FIRST_TYPE => pending = Some(payload.to_vec()),
+            MIDDLE_TYPE | LAST_TYPE => {
+                let mut buf = match pending.take() {
+                    Some(buf) => buf,
+                    None => {
+                        return Err(format!("fragment without a first record \
+                                            at offset {}",
+                                           offset))
+                    }
+                };
+                buf.extend_from_slice(payload);
+                if t == LAST_TYPE {
+                    records.push(buf);
+                } else {
+                    pending = Some(buf);
+                }
+            }
+            _ => return Err(format!("unknown record type {} at offset {}", t, offset)),
+        }
+    }
+    if pending.is_some() {
+        return Err("manifest ends with a partial record".to_owned());
+    }
+    Ok(records)
+}
+
+fn apply_edit(record: &[u8],
+              info: &mut ManifestInfo,
+              cfs: &mut BTreeMap<u32, CfState>)
+              -> Result<(), String> {
+    let mut input = record;
+    let mut cf_id = 0;
+    let mut cf_add = None;
+    let mut cf_drop = false;
+    let mut deleted = vec![];
+    let mut added = vec![];
+
+    while !input.is_empty() {
+        let tag = try!(get_varint32(&mut input));
+        match tag {
+            TAG_COMPARATOR => {
+                let name = try!(get_length_prefixed(&mut input));
+                info.comparator = Some(String::from_utf8_lossy(name)
+                    .into_owned());
+            }
+            TAG_LOG_NUMBER => info.log_number = try!(get_varint64(&mut input)),
+            TAG_PREV_LOG_NUMBER => {
+                info.prev_log_number = try!(get_varint64(&mut input))
+            }
+            TAG_NEXT_FILE_NUMBER => {
+                info.next_file_number = try!(get_varint64(&mut input))
+            }
+            TAG_LAST_SEQUENCE => {
+                info.last_sequence = try!(get_varint64(&mut input))
+            }
+            TAG_MIN_LOG_NUMBER_TO_KEEP => {
+                try!(get_varint64(&mut input));
+            }
+            TAG_MAX_COLUMN_FAMILY => {
+                try!(get_varint32(&mut input));
+            }
+            TAG_COMPACT_POINTER => {
+                try!(get_varint32(&mut input));
+                try!(get_length_prefixed(&mut input));
+            }
+            TAG_DELETED_FILE => {
+                let level = try!(get_varint32(&mut input));
+                let number = try!(get_varint64(&mut input));
+                deleted.push((level, number));
+            }
+            TAG_NEW_FILE | TAG_NEW_FILE2 | TAG_NEW_FILE3 | TAG_NEW_FILE4 => {
+                added.push(try!(get_new_file(tag, &mut input)));
+            }
+            TAG_COLUMN_FAMILY => cf_id = try!(get_varint32(&mut input)),
+            TAG_COLUMN_FAMILY_ADD => {
+                let name = try!(get_length_prefixed(&mut input));
+                cf_add = Some(String::from_utf8_lossy(name).into_owned());
+            }
+            TAG_COLUMN_FAMILY_DROP => cf_drop = true,
+            TAG_IN_ATOMIC_GROUP => {
+                // The number of edits left in the group, each edit of it
+                // still applies to its own column family.
+                try!(get_varint32(&mut input));
+            }
+            TAG_BLOB_FILE_ADDITION => {
+                // Number, blob count and bytes, then the checksum method
+                // and value.
+                for _ in 0..3 {
+                    try!(get_varint64(&mut input));
+                }
+                try!(get_length_prefixed(&mut input));
+                try!(get_length_prefixed(&mut input));
+                try!(skip_blob_file_fields(&mut input));
+            }
+            TAG_BLOB_FILE_GARBAGE => {
+                // Number, garbage blob count and bytes.
+                for _ in 0..3 {
+                    try!(get_varint64(&mut input));
+                }
+                try!(skip_blob_file_fields(&mut input));
+            }
+            _ if tag & TAG_SAFE_IGNORE_MASK != 0 => {
+                try!(get_length_prefixed(&mut input));
+            }
+            _ => return Err(format!("unknown version edit tag {}", tag)),
+        }
+    }
+
+    if let Some(name) = cf_add {
+        cfs.insert(cf_id,
+                   CfState {
+                       name: name,
+                       files: BTreeMap::new(),
+                   });
+        return Ok(());
+    }
+    if cf_drop {
+        cfs.remove(&cf_id);
+        return Ok(());
+    }
+    let cf = match cfs.get_mut(&cf_id) {
+        Some(cf) => cf,
+        None => return Err(format!("edit for unknown column family {}", cf_id)),
+    };
+    for key in deleted {
+        cf.files.remove(&key);
+    }
+    for (level, f) in added {
+        cf.files.insert((level, f.number), f);
+    }
+    Ok(())
+}
+
+fn get_new_file(tag: u32, input: &mut &[u8]) -> Result<(u32, SstFileMeta), String> {
+    let level = try!(get_varint32(input));
+    let mut number = try!(get_varint64(input));
+    let mut path_id = 0;
+    if tag == TAG_NEW_FILE3 {
+        path_id = (number / (FILE_NUMBER_MASK + 1)) as u32;
+        number &= FILE_NUMBER_MASK;
+    }
+    let size = try!(get_varint64(input));
+    let smallest = try!(get_length_prefixed(input));
+    let largest = try!(get_length_prefixed(input));
+    let (smallest_seqno, largest_seqno) = if tag == TAG_NEW_FILE {
+        (0, 0)
+    } else {
+        (try!(get_varint64(input)), try!(get_varint64(input)))
+    };
+    if tag == TAG_NEW_FILE4 {
+        loop {
+            let field = try!(get_varint32(input));
+            if field == NEW_FILE_TERMINATE {
+                break;
+            }
+            let value = try!(get_length_prefixed(input));
+            if field == NEW_FILE_PATH_ID {
+                if value.len() != 1 {
+                    return Err("invalid path id in new file".to_owned());
+                }
+                path_id = value[0] as u32;
+            } else if field & NEW_FILE_NON_SAFE_IGNORE_MASK != 0 {
+                return Err(format!("unknown new file field {}", field));
+            }
+        }
+    }
+    Ok((level,
+        SstFileMeta {
+        number: number,
+        path_id: path_id,
+        size: size,
+        smallest_key: try!(user_key(smallest)).to_vec(),
+        largest_key: try!(user_key(largest)).to_vec(),
+        smallest_seqno: smallest_seqno,
+        largest_seqno: largest_seqno,
+    }))
+}
+
+fn skip_blob_file_fields(input: &mut &[u8]) -> Result<(), String> {
+    loop {
+        let field = try!(get_varint32(input));
+        if field == BLOB_FILE_END_MARKER {
+            return Ok(());
+        }
+        if field & BLOB_FILE_FORWARD_INCOMPATIBLE_MASK != 0 {
+            return Err(format!("unknown blob file field {}", field));
+        }
+        try!(get_length_prefixed(input));
+    }
+}
+
+fn user_key(internal_key: &[u8]) -> Result<&[u8], String> {
+    if internal_key.len() < 8 {
+        return Err("invalid internal key in new file".to_owned());
+    }
+    Ok(&internal_key[..internal_key.len() - 8])
+}
+
+/// Extend the CRC32c `crc` of some bytes with `data`, 0 starting a new one.
+pub fn crc32c_extend(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f63b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{CfState, ManifestInfo, TAG_NEW_FILE4, apply_edit,
+                crc32c_extend, get_new_file, get_varint64, unmask_crc};
+
+    #[test]
+    fn test_crc32c() {
+        // Test vectors from rfc3720.
+        assert_eq!(crc32c_extend(0, &[0; 32]), 0x8a9136aa);
+        assert_eq!(crc32c_extend(0, &[0xff; 32]), 0x62a8ab43);
+        let split = crc32c_extend(crc32c_extend(0, b"hello "), b"world");
+        assert_eq!(split, crc32c_extend(0, b"hello world"));
+        // mask(crc) = ((crc >> 15) | (crc << 17)) + 0xa282ead8
+        let crc = 0x12345678u32;
+        let masked = ((crc >> 15) | (crc << 17)).wrapping_add(0xa282ead8);
+        assert_eq!(unmask_crc(masked), crc);
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut input: &[u8] = &[0x96, 0x01, 0x7f, 0x80];
+        assert_eq!(get_varint64(&mut input).unwrap(), 150);
+        assert_eq!(get_varint64(&mut input).unwrap(), 127);
+        assert!(get_varint64(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_new_file4_fields() {
+        let mut record = vec![1, 7, 100];
+        for key in &[b"a", b"z"] {
+            record.push(9);
+            record.extend_from_slice(&key[..]);
+            record.extend_from_slice(&[0; 8]);
+        }
+        record.extend_from_slice(&[5, 6]);
+        // A file creation time, which can be ignored, and a path id.
+        record.extend_from_slice(&[6, 1, 42, 65, 1, 2]);
+
+        let mut fields = record.clone();
+        fields.push(1);
+        let mut input = &fields[..];
+        let (level, meta) = get_new_file(TAG_NEW_FILE4, &mut input).unwrap();
+        assert_eq!((level, meta.number, meta.path_id), (1, 7, 2));
+        assert_eq!(meta.smallest_key, b"a".to_vec());
+        assert_eq!((meta.smallest_seqno, meta.largest_seqno), (5, 6));
+        assert!(input.is_empty());
+
+        // An unknown field that can't be ignored.
+        record.extend_from_slice(&[66, 1, 0, 1]);
+        let mut input = &record[..];
+        assert!(get_new_file(TAG_NEW_FILE4, &mut input).is_err());
+    }
+
+    #[test]
+    fn test_ignored_tags() {
+        let mut info = ManifestInfo {
+            manifest: "MANIFEST-000001".to_owned(),
+            comparator: None,
+            log_number: 0,
+            prev_log_number: 0,
+            next_file_number: 0,
+            last_sequence: 0,
+            column_families: vec![],
+        };
+        let mut cfs = BTreeMap::new();
+        cfs.insert(0,
+                   CfState {
+                       name: "default".to_owned(),
+                       files: BTreeMap::new(),
+                   });
+
+        // A DB id (8192), a WAL addition (8195), an atomic group with one
+        // edit left (300), a blob file addition and its garbage (400, 401)
+        // with an ignorable custom field, then the log number.
+        let mut record = vec![0x80, 0x40, 3, b'i', b'd', b'1'];
+        record.extend_from_slice(&[0x83, 0x40, 2, 7, 0]);
+        record.extend_from_slice(&[0xac, 0x02, 1]);
+        record.extend_from_slice(&[0x90, 0x03, 9, 2, 100, 1, b'c', 0, 1, 1, 42,
+                                   0]);
+        record.extend_from_slice(&[0x91, 0x03, 9, 1, 50, 0]);
+        record.extend_from_slice(&[2, 12]);
+        apply_edit(&record, &mut info, &mut cfs).unwrap();
+        assert_eq!(info.log_number, 12);
+
+        // A blob file field that can't be ignored.
+        let record = [0x91, 0x03, 9, 1, 50, 64, 0, 0];
+        assert!(apply_edit(&record, &mut info, &mut cfs).is_err());
+        // A tag that can't be ignored.
+        let record = [0xae, 0x02, 0];
+        assert!(apply_edit(&record, &mut info, &mut cfs).is_err());
+    }
+}
@@ -17,13 +17,129 @@ use std::ffi::CString;
 use std::mem;
 use std::ptr;
 use std::slice;
+use std::sync::atomic::AtomicBool;
+
+use callback;
 
 
 pub type MergeFn = fn(&[u8], Option<&[u8]>, &mut MergeOperands) -> Vec<u8>;
 
+/// A merge operator, for operators with state or with a partial merge of
+/// their own, see `Options::set_merge_operator`.
+///
+/// The operands are given oldest first. Rocksdb calls the operator from
+/// its own threads, during reads as well as flushes and compactions.
+pub trait MergeOperator: Send + Sync {
+    /// The name stored in the DB, which must not change once data was
+    /// merged with it.
+    fn name(&self) -> &str;
+
+    /// Apply `operands` to `existing_value`, `None` if there is none.
+    ///
+    /// Return `None` if they can't be, the read or compaction that needed
+    /// the value then fails with a corruption error.
+    fn full_merge(&self,
+                  key: &[u8],
+                  existing_value: Option<&[u8]>,
+                  operands: &mut MergeOperands)
+                  -> Option<Vec<u8>>;
+
+    /// Combine `operands` into one, without the value they apply to.
+    ///
+    /// Return `None` if they can't be, which is not an error: rocksdb then
+    /// keeps them for a `full_merge`. It is what the default does.
+    #[allow(unused_variables)]
+    fn partial_merge(&self,
+                     key: &[u8],
+                     operands: &mut MergeOperands)
+                     -> Option<Vec<u8>> {
+        None
+    }
+}
+
+// The operator of `Options::add_merge_operator`, also used to combine
+// operands.
+pub struct FnMergeOperator {
+    pub name: String,
+    pub merge_fn: MergeFn,
+}
+
+impl MergeOperator for FnMergeOperator {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn full_merge(&self,
+                  key: &[u8],
+                  existing_value: Option<&[u8]>,
+                  operands: &mut MergeOperands)
+                  -> Option<Vec<u8>> {
+        Some((self.merge_fn)(key, existing_value, operands))
+    }
+
+    fn partial_merge(&self,
+                     key: &[u8],
+                     operands: &mut MergeOperands)
+                     -> Option<Vec<u8>> {
+        Some((self.merge_fn)(key, None, operands))
+    }
+}
+
+/// Adds up unsigned 64 bit integers stored as 8 bytes little endian, see
+/// `DB::increment`.
+///
+/// It is named like the `uint64add` operator built into rocksdb and
+/// merges the same way, so either can open DBs written with the other:
+/// sums wrap around and values of another size count as 0.
+pub struct UInt64AddOperator;
+
+impl MergeOperator for UInt64AddOperator {
+    fn name(&self) -> &str {
+        "uint64add"
+    }
+
+    fn full_merge(&self,
+                  _: &[u8],
+                  existing_value: Option<&[u8]>,
+                  operands: &mut MergeOperands)
+                  -> Option<Vec<u8>> {
+        let init = existing_value.map_or(0, |v| decode_u64(v).unwrap_or(0));
+        let sum = operands.fold(init, |sum, op| {
+            sum.wrapping_add(decode_u64(op).unwrap_or(0))
+        });
+        Some(encode_u64(sum).to_vec())
+    }
+
+    fn partial_merge(&self,
+                     key: &[u8],
+                     operands: &mut MergeOperands)
+                     -> Option<Vec<u8>> {
+        self.full_merge(key, None, operands)
+    }
+}
+
+/// Encode `n` the way `UInt64AddOperator` stores it.
+pub fn encode_u64(n: u64) -> [u8; 8] {
+    let mut buf = [0; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (n >> (8 * i)) as u8;
+    }
+    buf
+}
+
+/// Decode a value stored by `UInt64AddOperator`, `None` if it is not 8
+/// bytes long.
+pub fn decode_u64(buf: &[u8]) -> Option<u64> {
+    if buf.len() != 8 {
+        return None;
+    }
+    Some(buf.iter().rev().fold(0, |n, b| (n << 8) | *b as u64))
+}
+
 pub struct MergeOperatorCallback {
     pub name: CString,
-    pub merge_fn: MergeFn,
+    pub op: Box<MergeOperator>,
+    pub poisoned: AtomicBool,
 }
 
 pub extern "C" fn destructor_callback(raw_cb: *mut c_void) {
@@ -60,17 +176,17 @@ pub extern "C" fn full_merge_callback(raw_cb: *mut c_void,
                                                num_operands);
         let key: &[u8] = slice::from_raw_parts(raw_key as *const u8,
                                                key_len as usize);
-        let oldval: &[u8] = slice::from_raw_parts(existing_value as *const u8,
-                                                  existing_value_len as usize);
-        let mut result = (cb.merge_fn)(key, Some(oldval), operands);
-        result.shrink_to_fit();
-        // TODO(tan) investigate zero-copy techniques to improve performance
-        let buf = libc::malloc(result.len() as size_t);
-        assert!(!buf.is_null());
-        *new_value_length = result.len() as size_t;
-        *success = 1 as u8;
-        ptr::copy(result.as_ptr() as *mut c_void, &mut *buf, result.len());
-        buf as *const c_char
+        let oldval = if existing_value.is_null() {
+            None
+        } else {
+            Some(slice::from_raw_parts(existing_value as *const u8,
+                                       existing_value_len as usize))
+        };
+        let op = &cb.op;
+        let result = callback::guard(cb.name.to_str().unwrap_or(""),
+                                     Some(&cb.poisoned),
+                                     || op.full_merge(key, oldval, operands));
+        merge_result(result.and_then(|r| r), success, new_value_length)
     }
 }
 
@@ -91,18 +207,36 @@ pub extern "C" fn partial_merge_callback(raw_cb: *mut c_void,
                                                num_operands);
         let key: &[u8] = slice::from_raw_parts(raw_key as *const u8,
                                                key_len as usize);
-        let mut result = (cb.merge_fn)(key, None, operands);
-        result.shrink_to_fit();
-        // TODO(tan) investigate zero-copy techniques to improve performance
-        let buf = libc::malloc(result.len() as size_t);
-        assert!(!buf.is_null());
-        *new_value_length = 1 as size_t;
-        *success = 1 as u8;
-        ptr::copy(result.as_ptr() as *mut c_void, &mut *buf, result.len());
-        buf as *const c_char
+        let op = &cb.op;
+        let result = callback::guard(cb.name.to_str().unwrap_or(""),
+                                     Some(&cb.poisoned),
+                                     || op.partial_merge(key, operands));
+        merge_result(result.and_then(|r| r), success, new_value_length)
     }
 }
 
+// Hand a merged value to rocksdb, which frees it.
+unsafe fn merge_result(result: Option<Vec<u8>>,
+                       success: *mut u8,
+                       new_value_length: *mut size_t)
+                       -> *const c_char {
+    let mut result = match result {
+        Some(result) => result,
+        None => {
+            *success = 0 as u8;
+            return ptr::null();
+        }
+    };
+    result.shrink_to_fit();
+    // TODO(tan) investigate zero-copy techniques to improve performance
+    let buf = libc::malloc(result.len() as size_t);
+    assert!(!buf.is_null());
+    *new_value_length = result.len() as size_t;
+    *success = 1 as u8;
+    ptr::copy(result.as_ptr() as *mut c_void, &mut *buf, result.len());
+    buf as *const c_char
+}
+
 
 pub struct MergeOperands {
     operands_list: *const *const c_char,
@@ -215,4 +349,64 @@ mod test {
         assert!(db.delete(b"k1").is_ok());
         assert!(db.get(b"k1").unwrap().is_none());
     }
+
+    // Adds up 8 byte little endian counters.
+    struct Counter;
+
+    impl MergeOperator for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn full_merge(&self,
+                      _: &[u8],
+                      existing_value: Option<&[u8]>,
+                      operands: &mut MergeOperands)
+                      -> Option<Vec<u8>> {
+            let mut n = match existing_value.map(decode_u64) {
+                Some(None) => return None,
+                Some(Some(n)) => n,
+                None => 0,
+            };
+            for op in operands {
+                match decode_u64(op) {
+                    Some(v) => n += v,
+                    None => return None,
+                }
+            }
+            Some(encode_u64(n).to_vec())
+        }
+
+        fn partial_merge(&self,
+                         key: &[u8],
+                         operands: &mut MergeOperands)
+                         -> Option<Vec<u8>> {
+            self.full_merge(key, None, operands)
+        }
+    }
+
+    #[test]
+    fn merge_operator_trait_test() {
+        let path = TempDir::new("_rust_rocksdb_mergetraittest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_merge_operator(Counter);
+        assert_eq!(opts.merge_operator_name(), Some("counter"));
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", &encode_u64(1)).unwrap();
+        for i in 0..10 {
+            db.merge(b"k1", &encode_u64(i)).unwrap();
+            db.merge(b"k2", &encode_u64(i)).unwrap();
+        }
+        db.flush(true).unwrap();
+        db.compact_range(None, None);
+        assert_eq!(decode_u64(&db.get(b"k1").unwrap().unwrap()), Some(46));
+        assert_eq!(decode_u64(&db.get(b"k2").unwrap().unwrap()), Some(45));
+
+        // A failed merge fails the read instead of poisoning the operator.
+        db.merge(b"k3", b"bad").unwrap();
+        assert!(db.get(b"k3").is_err());
+        db.merge(b"k4", &encode_u64(2)).unwrap();
+        assert_eq!(decode_u64(&db.get(b"k4").unwrap().unwrap()), Some(2));
+    }
 }
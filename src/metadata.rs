@@ -0,0 +1,166 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::sync::Arc;
+
+use rocksdb::{DB, Writable, WriteBatch};
+
+/// Named values, such as applied indices, epochs or format versions,
+/// stored in a column family.
+///
+/// Integers are stored as 8 bytes big endian and blobs as is, under their
+/// name. The `*_in` methods add the update to a `WriteBatch` instead, so
+/// it is written atomically with the data it describes.
+pub struct Metadata {
+    db: Arc<DB>,
+    cf: String,
+}
+
+impl Metadata {
+    /// Open the metadata stored in column family `cf` of `db`.
+    pub fn open(db: Arc<DB>, cf: &str) -> Result<Metadata, String> {
+        try!(db.cf_handle_or_err(cf));
+        Ok(Metadata {
+            db: db,
+            cf: cf.to_owned(),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let value = try!(self.db.get_cf(cf, name.as_bytes()));
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    pub fn put(&self, name: &str, value: &[u8]) -> Result<(), String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        self.db.put_cf(cf, name.as_bytes(), value)
+    }
+
+    /// Add putting `value` under `name` to `batch`.
+    pub fn put_in(&self,
+                  batch: &WriteBatch,
+                  name: &str,
+                  value: &[u8])
+                  -> Result<(), String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        batch.put_cf(cf, name.as_bytes(), value)
+    }
+
+    /// Get the integer stored under `name`, failing if the value there is
+    /// not one.
+    pub fn get_u64(&self, name: &str) -> Result<Option<u64>, String> {
+        match try!(self.get(name)) {
+            Some(v) => decode_u64(name, &v).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_u64(&self, name: &str, value: u64) -> Result<(), String> {
+        self.put(name, &encode_u64(value))
+    }
+
+    /// Add putting the integer `value` under `name` to `batch`.
+    pub fn put_u64_in(&self,
+                      batch: &WriteBatch,
+                      name: &str,
+                      value: u64)
+                      -> Result<(), String> {
+        self.put_in(batch, name, &encode_u64(value))
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        self.db.delete_cf(cf, name.as_bytes())
+    }
+
+    /// Add deleting `name` to `batch`.
+    pub fn delete_in(&self,
+                     batch: &WriteBatch,
+                     name: &str)
+                     -> Result<(), String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        batch.delete_cf(cf, name.as_bytes())
+    }
+}
+
+fn encode_u64(value: u64) -> [u8; 8] {
+    let mut buf = [0; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (value >> (56 - 8 * i)) as u8;
+    }
+    buf
+}
+
+fn decode_u64(name: &str, value: &[u8]) -> Result<u64, String> {
+    if value.len() != 8 {
+        return Err(format!("Invalid u64 metadata {}: {:?}", name, value));
+    }
+    Ok(value.iter().fold(0, |v, b| (v << 8) | *b as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable, WriteBatch};
+    use rocksdb_options::Options;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[test]
+    fn metadata_test() {
+        let path = TempDir::new("_rust_rocksdb_metadatatest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        {
+            let db = DB::open_cf(&opts,
+                                 path_str,
+                                 &["default", "meta"],
+                                 &[&opts, &opts])
+                .unwrap();
+            let db = Arc::new(db);
+            assert!(Metadata::open(db.clone(), "missing").is_err());
+            let meta = Metadata::open(db.clone(), "meta").unwrap();
+            assert_eq!(meta.get_u64("applied_index").unwrap(), None);
+            meta.put_u64("applied_index", 5).unwrap();
+            assert_eq!(meta.get_u64("applied_index").unwrap(), Some(5));
+            meta.put("format", b"v2").unwrap();
+            assert_eq!(meta.get("format").unwrap(), Some(b"v2".to_vec()));
+            assert!(meta.get_u64("format").is_err());
+
+            // The index moves along with the data it covers.
+            let batch = WriteBatch::new();
+            batch.put(b"k6", b"v6").unwrap();
+            meta.put_u64_in(&batch, "applied_index", 6).unwrap();
+            meta.delete_in(&batch, "format").unwrap();
+            assert_eq!(meta.get_u64("applied_index").unwrap(), Some(5));
+            db.write(batch).unwrap();
+            assert_eq!(meta.get_u64("applied_index").unwrap(), Some(6));
+            assert_eq!(meta.get("format").unwrap(), None);
+            assert_eq!(&*db.get(b"k6").unwrap().unwrap(), b"v6");
+        }
+
+        let db = DB::open_cf(&opts,
+                             path_str,
+                             &["default", "meta"],
+                             &[&opts, &opts])
+            .unwrap();
+        let meta = Metadata::open(Arc::new(db), "meta").unwrap();
+        assert_eq!(meta.get_u64("applied_index").unwrap(), Some(6));
+        meta.delete("applied_index").unwrap();
+        assert_eq!(meta.get_u64("applied_index").unwrap(), None);
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use libc::{self, c_void};
+
+use rocksdb_ffi;
+use rocksdb_options::Options;
+
+/// What the metrics sampler of a DB records, see
+/// `DB::start_metrics_sampler`.
+pub struct MetricsConfig {
+    interval: Duration,
+    capacity: usize,
+    properties: Vec<String>,
+    tickers: Vec<String>,
+    stats_opts: Option<Options>,
+}
+
+impl MetricsConfig {
+    /// Take a sample every `interval`, keeping the last `capacity` ones.
+    pub fn new(interval: Duration, capacity: usize) -> MetricsConfig {
+        MetricsConfig {
+            interval: interval,
+            capacity: capacity,
+            properties: vec![],
+            tickers: vec![],
+            stats_opts: None,
+        }
+    }
+
+    /// Record the int property `name` of the DB, like
+    /// `rocksdb.estimate-pending-compaction-bytes`.
+    pub fn add_property(&mut self, name: &str) {
+        self.properties.push(name.to_owned());
+    }
+
+    /// Record the ticker `name`, like `rocksdb.block.cache.miss`, from the
+    /// statistics of `opts`, the options the DB was opened with after
+    /// `enable_statistics`.
+    pub fn add_ticker(&mut self, opts: &Options, name: &str) {
+        if self.stats_opts.is_none() {
+            self.stats_opts = Some(opts.clone());
+        }
+        self.tickers.push(name.to_owned());
+    }
+}
+
+/// The values of the sampled metrics at one point in time, in the order
+/// they were added to the `MetricsConfig`, properties first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsSample {
+    pub time: SystemTime,
+    /// A value is `None` if the metric couldn't be read.
+    pub values: Vec<(String, Option<u64>)>,
+}
+
+impl MetricsSample {
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.values.iter().find(|v| v.0 == name).and_then(|v| v.1)
+    }
+}
+
+struct State {
+    history: VecDeque<MetricsSample>,
+    stop: bool,
+}
+
+// Only used while the sampler runs, and the DB stops it before closing.
+struct RawDb(rocksdb_ffi::DBInstance);
+
+unsafe impl Send for RawDb {}
+
+/// A thread sampling metrics, stopped when dropped.
+pub struct MetricsSampler {
+    state: Arc<(Mutex<State>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsSampler {
+    /// Sample the metrics of `db` until the sampler is dropped, which must
+    /// happen before `db` is closed.
+    pub unsafe fn start(db: rocksdb_ffi::DBInstance,
+                        config: MetricsConfig)
+                        -> MetricsSampler {
+        let state = Arc::new((Mutex::new(State {
+                                  history: VecDeque::new(),
+                                  stop: false,
+                              }),
+                              Condvar::new()));
+        let thread_state = state.clone();
+        let db = RawDb(db);
+        let thread = thread::spawn(move || {
+            let db = db;
+            let &(ref lock, ref cond) = &*thread_state;
+            loop {
+                let sample = take_sample(&db, &config);
+                let mut state = lock.lock().unwrap();
+                if state.history.len() >= config.capacity {
+                    state.history.pop_front();
+                }
+                if config.capacity > 0 {
+                    state.history.push_back(sample);
+                }
+                let deadline = Instant::now() + config.interval;
+                while !state.stop {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = cond.wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0;
+                }
+                if state.stop {
+                    return;
+                }
+            }
+        });
+        MetricsSampler {
+            state: state,
+            thread: Some(thread),
+        }
+    }
+
+    /// The samples taken, oldest first.
+    pub fn history(&self) -> Vec<MetricsSample> {
+        let state = self.state.0.lock().unwrap();
+        state.history.iter().cloned().collect()
+    }
+}
+
+impl Drop for MetricsSampler {
+    fn drop(&mut self) {
+        self.state.0.lock().unwrap().stop = true;
+        self.state.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn take_sample(db: &RawDb, config: &MetricsConfig) -> MetricsSample {
+    let mut values = Vec::with_capacity(config.properties.len() +
+                                        config.tickers.len());
+    for name in &config.properties {
+        values.push((name.clone(), property_int(db, name)));
+    }
+    let stats = config.stats_opts.as_ref().and_then(|o| o.get_statistics());
+    for name in &config.tickers {
+        let value = stats.as_ref().and_then(|s| ticker(s, name));
+        values.push((name.clone(), value));
+    }
+    MetricsSample {
+        time: SystemTime::now(),
+        values: values,
+    }
+}
+
+fn property_int(db: &RawDb, name: &str) -> Option<u64> {
+    let prop_name = match CString::new(name) {
+        Ok(n) => n,
+        Err(_) => return None,
+    };
+    unsafe {
+        let value =
+            rocksdb_ffi::rocksdb_property_value(db.0,
+                                                prop_name.as_ptr() as *const _);
+        if value.is_null() {
+            return None;
+        }
+        let s = CStr::from_ptr(value as *const _)
+            .to_string_lossy()
+            .into_owned();
+        libc::free(value as *mut c_void);
+        s.parse().ok()
+    }
+}
+
+/// Read the ticker `name` from statistics dumped by
+/// `Options::get_statistics`.
+pub fn ticker(stats: &str, name: &str) -> Option<u64> {
+    // Tickers are printed as `<name> COUNT : <value>`.
+    let prefix = format!("{} COUNT : ", name);
+    stats.lines()
+        .find(|l| l.starts_with(&prefix))
+        .and_then(|l| l[prefix.len()..].trim().parse().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use std::thread;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    #[test]
+    fn ticker_test() {
+        let stats = "rocksdb.block.cache.miss COUNT : 12\n\
+                     rocksdb.block.cache.miss.extra COUNT : 3\n\
+                     rocksdb.db.get.micros P50 : 1.0 COUNT : 7 SUM : 9\n";
+        assert_eq!(ticker(stats, "rocksdb.block.cache.miss"), Some(12));
+        assert_eq!(ticker(stats, "rocksdb.db.get.micros"), None);
+        assert_eq!(ticker(stats, "rocksdb.missing"), None);
+    }
+
+    #[test]
+    fn metrics_sampler_test() {
+        let path = TempDir::new("_rust_rocksdb_metricstest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.enable_statistics();
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        assert!(db.metrics_history().is_empty());
+
+        let mut config = MetricsConfig::new(Duration::from_millis(10), 3);
+        config.add_property("rocksdb.num-immutable-mem-table");
+        config.add_property("rocksdb.no-such-property");
+        config.add_ticker(&opts, "rocksdb.number.keys.written");
+        db.start_metrics_sampler(config);
+        db.put(b"k1", b"v1").unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        let history = db.metrics_history();
+        assert_eq!(history.len(), 3);
+        assert!(history[0].time <= history[2].time);
+        let last = &history[2];
+        assert_eq!(last.get("rocksdb.num-immutable-mem-table"), Some(0));
+        assert_eq!(last.get("rocksdb.no-such-property"), None);
+        assert_eq!(last.get("rocksdb.number.keys.written"), Some(1));
+
+        db.stop_metrics_sampler();
+        assert!(db.metrics_history().is_empty());
+    }
+}
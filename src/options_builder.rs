@@ -0,0 +1,415 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Building `Options` from sizes in human units, checking the options that
+//! depend on each other before the DB is opened.
+
+use libc::size_t;
+
+use rocksdb_options::Options;
+
+// The rocksdb defaults, to check the options that are left unset.
+const DEFAULT_MAX_WRITE_BUFFER_NUMBER: i32 = 2;
+const DEFAULT_MIN_WRITE_BUFFER_NUMBER_TO_MERGE: i32 = 1;
+const DEFAULT_LEVEL0_COMPACTION_TRIGGER: i32 = 4;
+const DEFAULT_LEVEL0_SLOWDOWN_TRIGGER: i32 = 20;
+const DEFAULT_LEVEL0_STOP_TRIGGER: i32 = 24;
+const DEFAULT_TARGET_FILE_SIZE_BASE: u64 = 64 << 20;
+const DEFAULT_MAX_BYTES_FOR_LEVEL_BASE: u64 = 256 << 20;
+
+/// Parse a size such as `"128MB"`, `"64 KiB"` or `"4096"` into bytes.
+///
+/// The units are powers of 1024 and case insensitive: `B`, `K`, `KB`,
+/// `KiB`, and so on up to `T`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_digit(10)).unwrap_or(s.len());
+    let (number, unit) = (&s[..split], s[split..].trim());
+    let number: u64 = try!(number.parse()
+        .map_err(|_| format!("Invalid size {:?}", s)));
+    let shift = match unit.to_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => return Err(format!("Invalid size unit in {:?}", s)),
+    };
+    if number > u64::max_value() >> shift {
+        return Err(format!("Size {:?} overflows", s));
+    }
+    Ok(number << shift)
+}
+
+/// A builder of `Options` taking sizes in human units and checking the
+/// options that depend on each other when built.
+///
+/// Misconfigurations that rocksdb would only show as stalled writes, such
+/// as level 0 triggers out of order, are rejected by `build`. The options
+/// left unset keep their rocksdb defaults and are checked against them.
+#[derive(Clone, Debug, Default)]
+pub struct OptionsBuilder {
+    create_if_missing: Option<bool>,
+    write_buffer_size: Option<u64>,
+    max_write_buffer_number: Option<i32>,
+    min_write_buffer_number_to_merge: Option<i32>,
+    target_file_size_base: Option<u64>,
+    target_file_size_multiplier: Option<i32>,
+    max_compaction_bytes: Option<u64>,
+    max_bytes_for_level_base: Option<u64>,
+    max_bytes_for_level_multiplier: Option<f64>,
+    level0_compaction_trigger: Option<i32>,
+    level0_slowdown_trigger: Option<i32>,
+    level0_stop_trigger: Option<i32>,
+    max_total_wal_size: Option<u64>,
+    bytes_per_sync: Option<u64>,
+    max_background_compactions: Option<i32>,
+    max_background_flushes: Option<i32>,
+    // The first size that failed to parse, reported by `build`.
+    err: Option<String>,
+}
+
+impl OptionsBuilder {
+    pub fn new() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    pub fn create_if_missing(mut self, create: bool) -> OptionsBuilder {
+        self.create_if_missing = Some(create);
+        self
+    }
+
+    pub fn write_buffer_size(mut self, size: &str) -> OptionsBuilder {
+        self.write_buffer_size = self.size("write_buffer_size", size);
+        self
+    }
+
+    pub fn max_write_buffer_number(mut self, n: i32) -> OptionsBuilder {
+        self.max_write_buffer_number = Some(n);
+        self
+    }
+
+    pub fn min_write_buffer_number_to_merge(mut self,
+                                            n: i32)
+                                            -> OptionsBuilder {
+        self.min_write_buffer_number_to_merge = Some(n);
+        self
+    }
+
+    pub fn target_file_size_base(mut self, size: &str) -> OptionsBuilder {
+        self.target_file_size_base = self.size("target_file_size_base", size);
+        self
+    }
+
+    pub fn target_file_size_multiplier(mut self, mul: i32) -> OptionsBuilder {
+        self.target_file_size_multiplier = Some(mul);
+        self
+    }
+
+    pub fn max_compaction_bytes(mut self, size: &str) -> OptionsBuilder {
+        self.max_compaction_bytes = self.size("max_compaction_bytes", size);
+        self
+    }
+
+    pub fn max_bytes_for_level_base(mut self, size: &str) -> OptionsBuilder {
+        self.max_bytes_for_level_base = self.size("max_bytes_for_level_base",
+                                                  size);
+        self
+    }
+
+    pub fn max_bytes_for_level_multiplier(mut self,
+                                          mul: f64)
+                                          -> OptionsBuilder {
+        self.max_bytes_for_level_multiplier = Some(mul);
+        self
+    }
+
+    pub fn level0_file_num_compaction_trigger(mut self,
+                                              n: i32)
+                                              -> OptionsBuilder {
+        self.level0_compaction_trigger = Some(n);
+        self
+    }
+
+    pub fn level0_slowdown_writes_trigger(mut self, n: i32) -> OptionsBuilder {
+        self.level0_slowdown_trigger = Some(n);
+        self
+    }
+
+    pub fn level0_stop_writes_trigger(mut self, n: i32) -> OptionsBuilder {
+        self.level0_stop_trigger = Some(n);
+        self
+    }
+
+    pub fn max_total_wal_size(mut self, size: &str) -> OptionsBuilder {
+        self.max_total_wal_size = self.size("max_total_wal_size", size);
+        self
+    }
+
+    pub fn bytes_per_sync(mut self, size: &str) -> OptionsBuilder {
+        self.bytes_per_sync = self.size("bytes_per_sync", size);
+        self
+    }
+
+    pub fn max_background_compactions(mut self, n: i32) -> OptionsBuilder {
+        self.max_background_compactions = Some(n);
+        self
+    }
+
+    pub fn max_background_flushes(mut self, n: i32) -> OptionsBuilder {
+        self.max_background_flushes = Some(n);
+        self
+    }
+
+    /// Check the options and build them.
+    pub fn build(&self) -> Result<Options, String> {
+        if let Some(ref e) = self.err {
+            return Err(e.clone());
+        }
+        try!(self.validate());
+
+        let mut opts = Options::new();
+        if let Some(v) = self.create_if_missing {
+            opts.create_if_missing(v);
+        }
+        if let Some(v) = self.write_buffer_size {
+            opts.set_write_buffer_size(v as size_t);
+        }
+        if let Some(v) = self.max_write_buffer_number {
+            opts.set_max_write_buffer_number(v);
+        }
+        if let Some(v) = self.min_write_buffer_number_to_merge {
+            opts.set_min_write_buffer_number_to_merge(v);
+        }
+        if let Some(v) = self.target_file_size_base {
+            opts.set_target_file_size_base(v);
+        }
+        if let Some(v) = self.target_file_size_multiplier {
+            opts.set_target_file_size_multiplier(v);
+        }
+        if let Some(v) = self.max_compaction_bytes {
+            opts.set_max_compaction_bytes(v);
+        }
+        if let Some(v) = self.max_bytes_for_level_base {
+            opts.set_max_bytes_for_level_base(v);
+        }
+        if let Some(v) = self.max_bytes_for_level_multiplier {
+            opts.set_max_bytes_for_level_multiplier(v);
+        }
+        if let Some(v) = self.level0_compaction_trigger {
+            opts.set_level_zero_file_num_compaction_trigger(v);
+        }
+        if let Some(v) = self.level0_slowdown_trigger {
+            opts.set_level_zero_slowdown_writes_trigger(v);
+        }
+        if let Some(v) = self.level0_stop_trigger {
+            opts.set_level_zero_stop_writes_trigger(v);
+        }
+        if let Some(v) = self.max_total_wal_size {
+            opts.set_max_total_wal_size(v);
+        }
+        if let Some(v) = self.bytes_per_sync {
+            opts.set_bytes_per_sync(v);
+        }
+        if let Some(v) = self.max_background_compactions {
+            opts.set_max_background_compactions(v);
+        }
+        if let Some(v) = self.max_background_flushes {
+            opts.set_max_background_flushes(v);
+        }
+        Ok(opts)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.write_buffer_size == Some(0) {
+            return Err("write_buffer_size must not be 0".to_owned());
+        }
+        try!(positive("max_write_buffer_number",
+                      self.max_write_buffer_number));
+        try!(positive("min_write_buffer_number_to_merge",
+                      self.min_write_buffer_number_to_merge));
+        try!(positive("target_file_size_multiplier",
+                      self.target_file_size_multiplier));
+        if let Some(mul) = self.max_bytes_for_level_multiplier {
+            if mul.is_nan() || mul <= 0.0 {
+                return Err(format!("max_bytes_for_level_multiplier must be \
+                                    positive, not {}",
+                                   mul));
+            }
+        }
+        try!(positive("level0_file_num_compaction_trigger",
+                      self.level0_compaction_trigger));
+
+        let max_buffers = self.max_write_buffer_number
+            .unwrap_or(DEFAULT_MAX_WRITE_BUFFER_NUMBER);
+        let to_merge = self.min_write_buffer_number_to_merge
+            .unwrap_or(DEFAULT_MIN_WRITE_BUFFER_NUMBER_TO_MERGE);
+        if to_merge >= max_buffers {
+            return Err(format!("min_write_buffer_number_to_merge {} must be \
+                                less than max_write_buffer_number {}, or \
+                                writes stall until the memtables are full",
+                               to_merge,
+                               max_buffers));
+        }
+
+        let trigger = self.level0_compaction_trigger
+            .unwrap_or(DEFAULT_LEVEL0_COMPACTION_TRIGGER);
+        let slowdown = self.level0_slowdown_trigger
+            .unwrap_or(DEFAULT_LEVEL0_SLOWDOWN_TRIGGER);
+        let stop = self.level0_stop_trigger
+            .unwrap_or(DEFAULT_LEVEL0_STOP_TRIGGER);
+        if trigger >= slowdown {
+            return Err(format!("level0_file_num_compaction_trigger {} must \
+                                be less than level0_slowdown_writes_trigger \
+                                {}, or writes slow down before level 0 is \
+                                compacted",
+                               trigger,
+                               slowdown));
+        }
+        if slowdown > stop {
+            return Err(format!("level0_slowdown_writes_trigger {} must not \
+                                be more than level0_stop_writes_trigger {}",
+                               slowdown,
+                               stop));
+        }
+
+        let file_size = self.target_file_size_base
+            .unwrap_or(DEFAULT_TARGET_FILE_SIZE_BASE);
+        let level_base = self.max_bytes_for_level_base
+            .unwrap_or(DEFAULT_MAX_BYTES_FOR_LEVEL_BASE);
+        if file_size == 0 || file_size > level_base {
+            return Err(format!("target_file_size_base {} must be between 1 \
+                                and max_bytes_for_level_base {}",
+                               file_size,
+                               level_base));
+        }
+        match self.max_compaction_bytes {
+            Some(v) if v != 0 && v < file_size => {
+                return Err(format!("max_compaction_bytes {} must be 0 or at \
+                                    least target_file_size_base {}",
+                                   v,
+                                   file_size));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn size(&mut self, name: &str, size: &str) -> Option<u64> {
+        match parse_size(size) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                if self.err.is_none() {
+                    self.err = Some(format!("Invalid {}: {}", name, e));
+                }
+                None
+            }
+        }
+    }
+}
+
+fn positive(name: &str, v: Option<i32>) -> Result<(), String> {
+    match v {
+        Some(n) if n <= 0 => {
+            Err(format!("{} must be positive, not {}", name, n))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use tempdir::TempDir;
+
+    #[test]
+    fn parse_size_test() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("128MB").unwrap(), 128 << 20);
+        assert_eq!(parse_size(" 64 kib ").unwrap(), 64 << 10);
+        assert_eq!(parse_size("1G").unwrap(), 1 << 30);
+        assert_eq!(parse_size("2tb").unwrap(), 2 << 40);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("1.5GB").is_err());
+        assert!(parse_size("12 parsecs").is_err());
+        assert!(parse_size("99999999999TB").is_err());
+    }
+
+    #[test]
+    fn options_builder_test() {
+        let err = OptionsBuilder::new()
+            .write_buffer_size("lots")
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.contains("write_buffer_size"), "{}", err);
+
+        // Checked against the defaults of the options left unset.
+        let err = OptionsBuilder::new()
+            .level0_file_num_compaction_trigger(30)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.contains("level0_slowdown_writes_trigger"), "{}", err);
+        assert!(OptionsBuilder::new()
+            .level0_slowdown_writes_trigger(40)
+            .level0_stop_writes_trigger(30)
+            .build()
+            .is_err());
+        assert!(OptionsBuilder::new()
+            .max_write_buffer_number(2)
+            .min_write_buffer_number_to_merge(2)
+            .build()
+            .is_err());
+        assert!(OptionsBuilder::new()
+            .target_file_size_base("1GB")
+            .build()
+            .is_err());
+        assert!(OptionsBuilder::new()
+            .max_write_buffer_number(0)
+            .build()
+            .is_err());
+        assert!(OptionsBuilder::new()
+            .target_file_size_multiplier(0)
+            .build()
+            .is_err());
+        assert!(OptionsBuilder::new()
+            .target_file_size_base("8MB")
+            .max_compaction_bytes("4MB")
+            .build()
+            .is_err());
+
+        let opts = OptionsBuilder::new()
+            .create_if_missing(true)
+            .write_buffer_size("4MB")
+            .max_write_buffer_number(4)
+            .min_write_buffer_number_to_merge(2)
+            .target_file_size_base("8MB")
+            .target_file_size_multiplier(2)
+            .max_compaction_bytes("200MB")
+            .max_bytes_for_level_base("32MB")
+            .level0_file_num_compaction_trigger(8)
+            .level0_slowdown_writes_trigger(30)
+            .level0_stop_writes_trigger(40)
+            .bytes_per_sync("1MB")
+            .build()
+            .unwrap();
+        let path = TempDir::new("_rust_rocksdb_optionsbuildertest").expect("");
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"v1");
+    }
+}
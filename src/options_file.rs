@@ -0,0 +1,320 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Reading the OPTIONS files rocksdb persists in the DB directory, to check
+//! the options a DB is opened with against the ones it was last run with.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use rocksdb_options::Options;
+
+const OPTIONS_FILE_PREFIX: &'static str = "OPTIONS-";
+// Table options are stored with the column family options they belong to,
+// under this prefix.
+const TABLE_OPTIONS_PREFIX: &'static str = "table_options.";
+
+/// The options of a DB as written in one of its OPTIONS files.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PersistedOptions {
+    pub db: BTreeMap<String, String>,
+    /// The options of each column family, table options included with
+    /// their names prefixed by `table_options.`.
+    pub cfs: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// An option whose value differs between two `PersistedOptions`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptionChange {
+    /// The column family of the option, `None` for a DB option.
+    pub cf: Option<String>,
+    pub name: String,
+    /// The old value, `None` if the option or its column family is new.
+    pub old: Option<String>,
+    /// The new value, `None` if the option or its column family is gone.
+    pub new: Option<String>,
+}
+
+impl PersistedOptions {
+    /// Read the most recent OPTIONS file of the DB at `path`, `None` if it
+    /// has none, like a DB that was never opened.
+    pub fn load_latest(path: &str)
+                       -> Result<Option<PersistedOptions>, String> {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(None),
+        };
+        let mut latest: Option<(u64, String)> = None;
+        for entry in entries {
+            let entry = try!(entry.map_err(|e| e.to_string()));
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(OPTIONS_FILE_PREFIX) {
+                continue;
+            }
+            // Skips the temporary files being written, OPTIONS-N.dbtmp.
+            let number = match name[OPTIONS_FILE_PREFIX.len()..].parse() {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+            if latest.as_ref().map_or(true, |l| number > l.0) {
+                latest = Some((number, name));
+            }
+        }
+        match latest {
+            Some((_, name)) => {
+                let opts = try!(PersistedOptions::load(&Path::new(path)
+                    .join(name)));
+                Ok(Some(opts))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Read the OPTIONS file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PersistedOptions, String> {
+        let path = path.as_ref();
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e)));
+        PersistedOptions::parse(&text).map_err(|e| {
+            format!("Invalid options file {}: {}", path.display(), e)
+        })
+    }
+
+    /// Parse the content of an OPTIONS file.
+    pub fn parse(text: &str) -> Result<PersistedOptions, String> {
+        let mut opts = PersistedOptions::default();
+        // The map options go to, and the prefix of their names.
+        let mut section: Option<(Option<String>, &str)> = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(format!("line {}: unterminated section",
+                                       n + 1));
+                }
+                section = try!(parse_section(&line[1..line.len() - 1])
+                    .map_err(|e| format!("line {}: {}", n + 1, e)));
+                continue;
+            }
+            let eq = match line.find('=') {
+                Some(eq) => eq,
+                None => {
+                    return Err(format!("line {}: expected name=value", n + 1))
+                }
+            };
+            let (name, value) = (line[..eq].trim(), line[eq + 1..].trim());
+            match section {
+                Some((None, _)) => {
+                    opts.db.insert(name.to_owned(), value.to_owned());
+                }
+                Some((Some(ref cf), prefix)) => {
+                    opts.cfs
+                        .entry(cf.clone())
+                        .or_insert_with(BTreeMap::new)
+                        .insert(format!("{}{}", prefix, name),
+                                value.to_owned());
+                }
+                None => {}
+            }
+        }
+        Ok(opts)
+    }
+
+    /// The options that differ from `self` to `new`, DB options first and
+    /// then by column family and name.
+    pub fn diff(&self, new: &PersistedOptions) -> Vec<OptionChange> {
+        let mut changes = diff_maps(None, &self.db, &new.db);
+        let empty = BTreeMap::new();
+        let mut cfs: Vec<&String> =
+            self.cfs.keys().chain(new.cfs.keys()).collect();
+        cfs.sort();
+        cfs.dedup();
+        for cf in cfs {
+            changes.extend(diff_maps(Some(cf),
+                                     self.cfs.get(cf).unwrap_or(&empty),
+                                     new.cfs.get(cf).unwrap_or(&empty)));
+        }
+        changes
+    }
+
+    /// Check that `opts` can open the column family `cf`, as recorded in
+    /// these options.
+    ///
+    /// rocksdb fails to open a DB with another comparator, and a missing
+    /// merge operator only shows up as an error when reading a merged key,
+    /// so both are checked here.
+    pub fn check_cf(&self, cf: &str, opts: &Options) -> Result<(), String> {
+        let persisted = match self.cfs.get(cf) {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if let Some(comparator) = persisted.get("comparator") {
+            if comparator != opts.comparator_name() {
+                return Err(format!("Invalid argument: column family {} uses \
+                                    comparator {}, not {}",
+                                   cf,
+                                   comparator,
+                                   opts.comparator_name()));
+            }
+        }
+        if let Some(merge_operator) = persisted.get("merge_operator") {
+            if merge_operator != "nullptr" &&
+               opts.merge_operator_name() != Some(merge_operator.as_str()) {
+                return Err(format!("Invalid argument: column family {} uses \
+                                    merge operator {}, not {}",
+                                   cf,
+                                   merge_operator,
+                                   opts.merge_operator_name()
+                                       .unwrap_or("none")));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Section headers look like `DBOptions` or `CFOptions "name"`.
+fn parse_section(header: &str)
+                 -> Result<Option<(Option<String>, &'static str)>, String> {
+    let (kind, arg) = match header.find(' ') {
+        Some(sp) => (&header[..sp], Some(header[sp + 1..].trim())),
+        None => (header, None),
+    };
+    let cf = match arg {
+        Some(a) if a.len() >= 2 && a.starts_with('"') && a.ends_with('"') => {
+            Some(a[1..a.len() - 1].to_owned())
+        }
+        Some(a) => return Err(format!("invalid section argument {}", a)),
+        None => None,
+    };
+    match (kind, cf) {
+        ("DBOptions", None) => Ok(Some((None, ""))),
+        ("CFOptions", Some(cf)) => Ok(Some((Some(cf), ""))),
+        (k, Some(cf)) if k.starts_with("TableOptions/") => {
+            Ok(Some((Some(cf), TABLE_OPTIONS_PREFIX)))
+        }
+        // Version and unknown sections are skipped.
+        _ => Ok(None),
+    }
+}
+
+fn diff_maps(cf: Option<&String>,
+             old: &BTreeMap<String, String>,
+             new: &BTreeMap<String, String>)
+             -> Vec<OptionChange> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    names.into_iter()
+        .filter(|n| old.get(*n) != new.get(*n))
+        .map(|n| {
+            OptionChange {
+                cf: cf.cloned(),
+                name: n.clone(),
+                old: old.get(n).cloned(),
+                new: new.get(n).cloned(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb_options::Options;
+
+    const OPTIONS: &'static str = "
+# This is a RocksDB option file.
+[Version]
+  rocksdb_version=6.4.6
+  options_file_version=1.1
+
+[DBOptions]
+  max_open_files=-1
+  create_if_missing=true
+
+[CFOptions \"default\"]
+  comparator=leveldb.BytewiseComparator
+  merge_operator=nullptr
+  write_buffer_size=67108864
+
+[TableOptions/BlockBasedTable \"default\"]
+  block_size=4096
+
+[CFOptions \"cf1\"]
+  comparator=leveldb.BytewiseComparator
+  merge_operator=concat
+";
+
+    #[test]
+    fn options_file_test() {
+        let old = PersistedOptions::parse(OPTIONS).unwrap();
+        assert_eq!(old.db["max_open_files"], "-1");
+        assert_eq!(old.cfs["default"]["write_buffer_size"], "67108864");
+        assert_eq!(old.cfs["default"]["table_options.block_size"], "4096");
+        assert!(PersistedOptions::parse("[DBOptions]\nbad").is_err());
+
+        let mut new = old.clone();
+        new.db.insert("max_open_files".to_owned(), "100".to_owned());
+        new.cfs.remove("cf1");
+        assert_eq!(old.diff(&new),
+                   vec![OptionChange {
+                            cf: None,
+                            name: "max_open_files".to_owned(),
+                            old: Some("-1".to_owned()),
+                            new: Some("100".to_owned()),
+                        },
+                        OptionChange {
+                            cf: Some("cf1".to_owned()),
+                            name: "comparator".to_owned(),
+                            old: Some("leveldb.BytewiseComparator".to_owned()),
+                            new: None,
+                        },
+                        OptionChange {
+                            cf: Some("cf1".to_owned()),
+                            name: "merge_operator".to_owned(),
+                            old: Some("concat".to_owned()),
+                            new: None,
+                        }]);
+
+        let mut opts = Options::new();
+        old.check_cf("default", &opts).unwrap();
+        old.check_cf("new_cf", &opts).unwrap();
+        assert!(old.check_cf("cf1", &opts).is_err());
+        opts.add_merge_operator("concat", concat);
+        old.check_cf("cf1", &opts).unwrap();
+        opts.add_comparator("reverse", reverse);
+        let err = old.check_cf("cf1", &opts).unwrap_err();
+        assert!(err.contains("comparator leveldb.BytewiseComparator"),
+                "{}",
+                err);
+    }
+
+    fn concat(_: &[u8],
+              _: Option<&[u8]>,
+              _: &mut ::merge_operator::MergeOperands)
+              -> Vec<u8> {
+        vec![]
+    }
+
+    fn reverse(a: &[u8], b: &[u8]) -> i32 {
+        b.cmp(a) as i32
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::cmp;
+use std::sync::{Arc, Mutex};
+
+use rocksdb::{DB, DBIterator, ReadOptions, SeekKey, Writable, WriteBatch};
+use rocksdb_ffi::DBCompactionStyle;
+use rocksdb_options::Options;
+
+// Holds the sequence the queue was last truncated at.
+const META_KEY: [u8; 8] = [0; 8];
+
+/// A FIFO queue, or log, stored in a column family.
+///
+/// Entries are appended under increasing sequence numbers, starting at 1,
+/// which are stored big endian so the key order is the append order. The
+/// column family must not be written to other than through the queue.
+///
+/// Consumed entries are removed with `truncate`, which drops the sst files
+/// holding only truncated entries instead of waiting for compaction to
+/// discard them. A queue that may lose its oldest entries once it grows
+/// past a size can use `fifo_options` instead.
+pub struct Queue {
+    db: Arc<DB>,
+    cf: String,
+    // The sequence of the first entry and of the next one to append.
+    bounds: Mutex<(u64, u64)>,
+}
+
+impl Queue {
+    /// Open the queue stored in column family `cf` of `db`.
+    pub fn open(db: Arc<DB>, cf: &str) -> Result<Queue, String> {
+        let handle = try!(db.cf_handle_or_err(cf));
+        let (first, next) = {
+            let truncated = match try!(db.get_cf(handle, &META_KEY)) {
+                Some(v) => try!(decode_seq(&v)),
+                None => 1,
+            };
+            let mut iter = db.iter_cf(handle);
+            iter.seek(SeekKey::Key(&encode_seq(1)));
+            let first = match iter.key() {
+                Some(key) => try!(decode_seq(key)),
+                None => truncated,
+            };
+            iter.seek(SeekKey::End);
+            let next = match iter.key() {
+                Some(key) if key != &META_KEY[..] => try!(decode_seq(key)) + 1,
+                _ => first,
+            };
+            try!(iter.status());
+            (first, next)
+        };
+        Ok(Queue {
+            db: db,
+            cf: cf.to_owned(),
+            bounds: Mutex::new((first, next)),
+        })
+    }
+
+    /// Options for a queue column family using FIFO compaction: once the
+    /// sst files take more than `max_size` bytes, the oldest ones are
+    /// deleted with the entries they hold, consumed or not.
+    pub fn fifo_options(max_size: u64) -> Options {
+        let mut opts = Options::new();
+        opts.set_compaction_style(DBCompactionStyle::DBFifo);
+        opts.set_fifo_max_table_files_size(max_size);
+        opts
+    }
+
+    /// Sequence of the oldest entry still queued.
+    pub fn first_seq(&self) -> u64 {
+        self.bounds.lock().unwrap().0
+    }
+
+    /// Sequence the next appended entry will get.
+    pub fn next_seq(&self) -> u64 {
+        self.bounds.lock().unwrap().1
+    }
+
+    pub fn len(&self) -> u64 {
+        let bounds = self.bounds.lock().unwrap();
+        bounds.1 - bounds.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append `value` and return its sequence.
+    pub fn append(&self, value: &[u8]) -> Result<u64, String> {
+        self.append_all(&[value])
+    }
+
+    /// Append `values` in a single write and return the sequence of the
+    /// first one.
+    pub fn append_all(&self, values: &[&[u8]]) -> Result<u64, String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let mut bounds = self.bounds.lock().unwrap();
+        let first = bounds.1;
+        let batch = WriteBatch::new();
+        for (i, value) in values.iter().enumerate() {
+            try!(batch.put_cf(cf, &encode_seq(first + i as u64), value));
+        }
+        try!(self.db.write(batch));
+        bounds.1 += values.len() as u64;
+        Ok(first)
+    }
+
+    /// Read at most `max` entries, starting at sequence `from`.
+    pub fn read(&self,
+                from: u64,
+                max: usize)
+                -> Result<Vec<(u64, Vec<u8>)>, String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let mut readopts = ReadOptions::new();
+        readopts.set_iterate_upper_bound(&encode_seq(self.next_seq()));
+        let mut iter = DBIterator::new_cf(&self.db, cf, readopts);
+        let mut entries = vec![];
+        iter.seek(SeekKey::Key(&encode_seq(cmp::max(from, 1))));
+        while entries.len() < max {
+            let (seq, value) = match iter.entry() {
+                Some((key, value)) => (try!(decode_seq(key)), value.to_vec()),
+                None => break,
+            };
+            entries.push((seq, value));
+            iter.next();
+        }
+        try!(iter.status());
+        Ok(entries)
+    }
+
+    /// Remove the entries before sequence `seq`.
+    pub fn truncate(&self, seq: u64) -> Result<(), String> {
+        let cf = try!(self.db.cf_handle_or_err(&self.cf));
+        let mut bounds = self.bounds.lock().unwrap();
+        let seq = if seq > bounds.1 { bounds.1 } else { seq };
+        if seq <= bounds.0 {
+            return Ok(());
+        }
+        // The truncation point is kept so an emptied queue doesn't reuse
+        // sequences after a reopen.
+        let (begin, end) = (encode_seq(1), encode_seq(seq));
+        let batch = WriteBatch::new();
+        try!(batch.delete_range_cf(cf, &begin, &end));
+        try!(batch.put_cf(cf, &META_KEY, &end));
+        try!(self.db.write(batch));
+        // Unlike the range deletion, the range of files to drop includes
+        // its end.
+        let last = encode_seq(seq - 1);
+        try!(self.db.delete_file_in_range_cf(cf, &begin, &last));
+        bounds.0 = seq;
+        Ok(())
+    }
+}
+
+fn encode_seq(seq: u64) -> [u8; 8] {
+    let mut buf = [0; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (seq >> (56 - 8 * i)) as u8;
+    }
+    buf
+}
+
+fn decode_seq(key: &[u8]) -> Result<u64, String> {
+    if key.len() != 8 {
+        return Err(format!("Invalid queue key: {:?}", key));
+    }
+    Ok(key.iter().fold(0, |seq, b| (seq << 8) | *b as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::DB;
+    use rocksdb_options::Options;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[test]
+    fn queue_test() {
+        let path = TempDir::new("_rust_rocksdb_queuetest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let fifo_opts = Queue::fifo_options(64 << 20);
+        {
+            let db = DB::open_cf(&opts,
+                                 path_str,
+                                 &["default", "queue"],
+                                 &[&opts, &fifo_opts])
+                .unwrap();
+            let queue = Queue::open(Arc::new(db), "queue").unwrap();
+            assert!(queue.is_empty());
+            assert_eq!(queue.append(b"a").unwrap(), 1);
+            let values: Vec<&[u8]> = vec![b"b", b"c", b"d"];
+            assert_eq!(queue.append_all(&values).unwrap(), 2);
+            assert_eq!(queue.len(), 4);
+
+            assert_eq!(queue.read(2, 2).unwrap(),
+                       vec![(2, b"b".to_vec()), (3, b"c".to_vec())]);
+            assert_eq!(queue.read(4, 10).unwrap(), vec![(4, b"d".to_vec())]);
+            assert!(queue.read(5, 10).unwrap().is_empty());
+
+            queue.truncate(3).unwrap();
+            assert_eq!((queue.first_seq(), queue.next_seq()), (3, 5));
+            assert_eq!(queue.read(0, 10).unwrap().len(), 2);
+            // Truncating past the end only empties the queue.
+            queue.truncate(100).unwrap();
+            assert!(queue.is_empty());
+        }
+
+        // An emptied queue keeps counting from where it was.
+        let db = DB::open_cf(&opts,
+                             path_str,
+                             &["default", "queue"],
+                             &[&opts, &fifo_opts])
+            .unwrap();
+        let queue = Queue::open(Arc::new(db), "queue").unwrap();
+        assert_eq!((queue.first_seq(), queue.next_seq()), (5, 5));
+        assert_eq!(queue.append(b"e").unwrap(), 5);
+        drop(queue);
+
+        // The sequences survive a reopen.
+        let db = DB::open_cf(&opts,
+                             path_str,
+                             &["default", "queue"],
+                             &[&opts, &fifo_opts])
+            .unwrap();
+        let queue = Queue::open(Arc::new(db), "queue").unwrap();
+        assert_eq!((queue.first_seq(), queue.next_seq()), (5, 6));
+        assert_eq!(queue.read(1, 10).unwrap(), vec![(5, b"e".to_vec())]);
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocksdb::{DB, DBIterator, DBVector, ReadOptions, UnsafeSnap};
+use rocksdb_ffi::DBCFHandle;
+
+/// Creates `ReadView`s over a DB and keeps track of the live ones.
+///
+/// Every live view pins the data it sees, so an old view holds back
+/// compaction. `oldest_view_age` tells how long the oldest one has been
+/// alive, to find views that are never dropped.
+pub struct ReadViews {
+    db: Arc<DB>,
+    live: Arc<Mutex<Live>>,
+}
+
+struct Live {
+    next_id: u64,
+    created: BTreeMap<u64, Instant>,
+}
+
+impl ReadViews {
+    pub fn new(db: Arc<DB>) -> ReadViews {
+        ReadViews {
+            db: db,
+            live: Arc::new(Mutex::new(Live {
+                next_id: 0,
+                created: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Take a snapshot of the DB as it is now.
+    pub fn create(&self) -> ReadView {
+        let mut live = self.live.lock().unwrap();
+        let id = live.next_id;
+        live.next_id += 1;
+        live.created.insert(id, Instant::now());
+        let snap = unsafe { self.db.unsafe_snap() };
+        ReadView {
+            inner: Arc::new(ViewInner {
+                db: self.db.clone(),
+                snap: snap,
+                id: id,
+                live: self.live.clone(),
+            }),
+        }
+    }
+
+    /// Number of snapshots still held by a view.
+    pub fn live_views(&self) -> usize {
+        self.live.lock().unwrap().created.len()
+    }
+
+    pub fn oldest_view_age(&self) -> Option<Duration> {
+        let live = self.live.lock().unwrap();
+        live.created.values().next().map(|t| t.elapsed())
+    }
+}
+
+/// A consistent view of a DB at the time it was created.
+///
+/// Clones share the same snapshot, which is released when the last of
+/// them is dropped. Unlike `Snapshot`, a view doesn't borrow the DB, so it
+/// can be stored or sent to other threads.
+#[derive(Clone)]
+pub struct ReadView {
+    inner: Arc<ViewInner>,
+}
+
+struct ViewInner {
+    db: Arc<DB>,
+    snap: UnsafeSnap,
+    id: u64,
+    live: Arc<Mutex<Live>>,
+}
+
+// The snapshot is immutable, rocksdb allows reading from it on any thread.
+unsafe impl Send for ViewInner {}
+unsafe impl Sync for ViewInner {}
+
+impl Drop for ViewInner {
+    fn drop(&mut self) {
+        unsafe {
+            self.db.release_snap(&self.snap);
+        }
+        self.live.lock().unwrap().created.remove(&self.id);
+    }
+}
+
+impl ReadView {
+    pub fn db(&self) -> &Arc<DB> {
+        &self.inner.db
+    }
+
+    /// Read options reading from this view, the view must outlive every
+    /// read made with them.
+    pub unsafe fn readopts(&self) -> ReadOptions {
+        let mut opts = ReadOptions::new();
+        opts.set_snapshot(&self.inner.snap);
+        opts
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<DBVector>, String> {
+        let readopts = unsafe { self.readopts() };
+        self.inner.db.get_opt(key, &readopts)
+    }
+
+    pub fn get_cf(&self,
+                  cf: DBCFHandle,
+                  key: &[u8])
+                  -> Result<Option<DBVector>, String> {
+        let readopts = unsafe { self.readopts() };
+        self.inner.db.get_cf_opt(cf, key, &readopts)
+    }
+
+    pub fn iter(&self) -> DBIterator {
+        let readopts = unsafe { self.readopts() };
+        DBIterator::new(&self.inner.db, readopts)
+    }
+
+    pub fn iter_cf(&self, cf: DBCFHandle) -> DBIterator {
+        let readopts = unsafe { self.readopts() };
+        DBIterator::new_cf(&self.inner.db, cf, readopts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, SeekKey, Writable};
+    use std::sync::Arc;
+    use std::thread;
+    use tempdir::TempDir;
+
+    #[test]
+    fn read_view_test() {
+        let path = TempDir::new("_rust_rocksdb_readviewtest").expect("");
+        let db = Arc::new(DB::open_default(path.path().to_str().unwrap())
+            .unwrap());
+        let views = ReadViews::new(db.clone());
+        assert_eq!(views.live_views(), 0);
+        assert!(views.oldest_view_age().is_none());
+
+        db.put(b"k1", b"v1").unwrap();
+        let view = views.create();
+        db.put(b"k1", b"v2").unwrap();
+        db.put(b"k2", b"v2").unwrap();
+
+        let clone = view.clone();
+        assert_eq!(views.live_views(), 1);
+        drop(view);
+        assert_eq!(views.live_views(), 1);
+
+        // The view can move to another thread without the DB.
+        let handle = thread::spawn(move || {
+            let v = clone.get(b"k1").unwrap().unwrap();
+            assert_eq!(&*v, b"v1");
+            assert!(clone.get(b"k2").unwrap().is_none());
+            let mut iter = clone.iter();
+            iter.seek(SeekKey::Start);
+            assert_eq!((&mut iter).collect::<Vec<_>>(),
+                       vec![(b"k1".to_vec(), b"v1".to_vec())]);
+        });
+        handle.join().unwrap();
+        assert_eq!(views.live_views(), 0);
+
+        let view = views.create();
+        assert!(views.oldest_view_age().is_some());
+        assert_eq!(&*view.get(b"k1").unwrap().unwrap(), b"v2");
+    }
+}
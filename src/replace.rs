@@ -0,0 +1,192 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rocksdb::DB;
+
+// Appended to the path of a DB being replaced while the swap is going on.
+const REPLACED_SUFFIX: &'static str = ".replaced";
+
+/// Replace the directory of `db` by `new_path`, holding a DB built
+/// elsewhere such as a checkpoint or the result of a bulk load, and open
+/// it in its place with `open`.
+///
+/// `db` is closed, moved aside and the new directory renamed to its path,
+/// syncing the parent directories so the renames survive a crash. The old
+/// directory is removed once the new DB opens, and put back if it fails
+/// to. Both directories must be on the same filesystem.
+///
+/// A crash in the middle of the swap leaves the old directory aside:
+/// `recover_replaced_db` must be called on the path before opening it.
+pub fn replace_db<F>(db: DB, new_path: &str, open: F) -> Result<DB, String>
+    where F: FnOnce(&str) -> Result<DB, String>
+{
+    let path = PathBuf::from(db.path());
+    let new_path = PathBuf::from(new_path);
+    let aside = aside_path(&path);
+    if !new_path.is_dir() {
+        return Err(format!("Invalid replacement DB directory {}",
+                           new_path.display()));
+    }
+    if aside.exists() {
+        return Err(format!("Unfinished replacement of {}, see \
+                            recover_replaced_db",
+                           path.display()));
+    }
+    try!(sync_dir(&new_path));
+    try!(sync_dir(&parent(&new_path)));
+    drop(db);
+
+    try!(rename(&path, &aside));
+    if let Err(e) = rename(&new_path, &path) {
+        try!(rename(&aside, &path));
+        return Err(e);
+    }
+    try!(sync_dir(&parent(&path)));
+    if new_path.parent() != path.parent() {
+        try!(sync_dir(&parent(&new_path)));
+    }
+
+    match open(path.to_str().unwrap()) {
+        Ok(db) => {
+            try!(remove_dir(&aside));
+            Ok(db)
+        }
+        Err(e) => {
+            try!(rename(&path, &new_path));
+            try!(rename(&aside, &path));
+            try!(sync_dir(&parent(&path)));
+            Err(format!("Failed to open replacement DB, {} was put back: {}",
+                        path.display(),
+                        e))
+        }
+    }
+}
+
+/// Finish or roll back a `replace_db` of `path` interrupted by a crash,
+/// return whether there was one.
+///
+/// The new directory is kept if it was already in place, otherwise the
+/// old one is put back.
+pub fn recover_replaced_db(path: &str) -> Result<bool, String> {
+    let path = PathBuf::from(path);
+    let aside = aside_path(&path);
+    if !aside.exists() {
+        return Ok(false);
+    }
+    if path.exists() {
+        try!(remove_dir(&aside));
+    } else {
+        try!(rename(&aside, &path));
+        try!(sync_dir(&parent(&path)));
+    }
+    Ok(true)
+}
+
+fn aside_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(REPLACED_SUFFIX);
+    path.with_file_name(name)
+}
+
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), String> {
+    fs::rename(from, to).map_err(|e| {
+        format!("Failed to rename {} to {}: {}",
+                from.display(),
+                to.display(),
+                e)
+    })
+}
+
+fn remove_dir(path: &Path) -> Result<(), String> {
+    fs::remove_dir_all(path)
+        .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))
+}
+
+// Directories can't be opened, let alone synced, on windows, where
+// renames are durable once they return.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<(), String> {
+    fs::File::open(path)
+        .and_then(|f| f.sync_all())
+        .map_err(|e| format!("Failed to sync {}: {}", path.display(), e))
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn replace_db_test() {
+        let path = TempDir::new("_rust_rocksdb_replacedbtest").expect("");
+        let db_path = path.path().join("db");
+        let db_str = db_path.to_str().unwrap();
+        let new_path = path.path().join("new");
+        let new_str = new_path.to_str().unwrap();
+
+        let db = DB::open_default(db_str).unwrap();
+        db.put(b"k1", b"old").unwrap();
+        let built = DB::open_default(new_str).unwrap();
+        built.put(b"k1", b"new").unwrap();
+        drop(built);
+
+        let db = replace_db(db, new_str, DB::open_default).unwrap();
+        assert_eq!(db.path(), db_str);
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"new");
+        assert!(!new_path.exists());
+        assert!(!aside_path(&db_path).exists());
+
+        // The old DB is put back when the new one doesn't open.
+        fs::create_dir(&new_path).unwrap();
+        let err = replace_db(db, new_str, |_| Err("boom".to_owned()))
+            .err()
+            .unwrap();
+        assert!(err.contains("boom"), "{}", err);
+        assert!(new_path.exists());
+        let db = DB::open_default(db_str).unwrap();
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"new");
+        drop(db);
+
+        // A crash between the renames.
+        assert!(!recover_replaced_db(db_str).unwrap());
+        fs::rename(&db_path, aside_path(&db_path)).unwrap();
+        assert!(recover_replaced_db(db_str).unwrap());
+        let db = DB::open_default(db_str).unwrap();
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"new");
+        drop(db);
+
+        // A crash before the old directory is removed.
+        fs::create_dir(aside_path(&db_path)).unwrap();
+        assert!(recover_replaced_db(db_str).unwrap());
+        assert!(!aside_path(&db_path).exists());
+        assert!(db_path.exists());
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::cmp;
+use std::thread;
+use std::time::Duration;
+
+use error::Error;
+
+/// How to retry operations that fail with a transient `Error`.
+///
+/// The backoff starts at `initial_backoff` and doubles after each attempt
+/// up to `max_backoff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new(5, Duration::from_millis(10))
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> RetryPolicy {
+        assert!(max_attempts > 0);
+        RetryPolicy {
+            max_attempts: max_attempts,
+            initial_backoff: initial_backoff,
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    pub fn set_max_backoff(&mut self, max_backoff: Duration) {
+        self.max_backoff = max_backoff;
+    }
+
+    /// Call `f` until it succeeds, fails with an error that is not
+    /// transient, or the attempts run out. The last error is returned.
+    pub fn run<T, F>(&self, mut f: F) -> Result<T, String>
+        where F: FnMut() -> Result<T, String>
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(ref e) if attempt < self.max_attempts &&
+                              Error::from_message(e).is_transient() => {}
+                res => return res,
+            }
+            thread::sleep(backoff);
+            backoff = cmp::min(backoff * 2, self.max_backoff);
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn retry_test() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+
+        let mut calls = 0;
+        let res = policy.run(|| {
+            calls += 1;
+            if calls < 3 {
+                Err("Resource busy: ".to_owned())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(res, Ok(3));
+
+        // Attempts run out.
+        calls = 0;
+        let res: Result<(), String> = policy.run(|| {
+            calls += 1;
+            Err("Operation failed. Try again.: ".to_owned())
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 3);
+
+        // Permanent errors are not retried.
+        calls = 0;
+        let res: Result<(), String> = policy.run(|| {
+            calls += 1;
+            Err("Corruption: bad block".to_owned())
+        });
+        assert_eq!(res, Err("Corruption: bad block".to_owned()));
+        assert_eq!(calls, 1);
+    }
+}
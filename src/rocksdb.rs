@@ -12,25 +12,130 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
+use std::cell::RefCell;
+use std::cmp;
 use std::collections::BTreeMap;
 use std::ffi::{CStr, CString};
+use std::fmt::{self, Debug, Formatter};
 use std::fs;
+use std::mem;
 use std::ops::Deref;
 use std::path::Path;
+use std::ptr;
+use std::rc::Rc;
 use std::slice;
 use std::str::from_utf8;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use libc::{self, c_int, c_void, size_t};
 
 use rocksdb_ffi::{self, DBCFHandle, error_message};
-use rocksdb_options::{Options, WriteOptions};
+use rocksdb_options::{FlushOptions, IngestExternalFileOptions, Options,
+                      WriteOptions};
+use retry::RetryPolicy;
+use comparator::{decode_u64_ts, encode_u64_ts};
+use merge_operator::{decode_u64, encode_u64};
+use options_file::{OptionChange, PersistedOptions};
+use metrics::{MetricsConfig, MetricsSample, MetricsSampler};
+use wal::WalIterator;
+use lock_file::{self, LockStatus};
+use error::Error;
+use coding::{WRITE_BATCH_HEADER_SIZE, decode_fixed32, decode_fixed64,
+             varint_length};
 
 const DEFAULT_COLUMN_FAMILY: &'static str = "default";
 
+thread_local! {
+    // The options of the reads and writes not given any, created once per
+    // thread instead of once per call, which shows at high rates of small
+    // operations.
+    static DEFAULT_READOPTS: ReadOptions = ReadOptions::new();
+    static DEFAULT_WRITEOPTS: WriteOptions = WriteOptions::new();
+    static MULTI_GET_BUFS: RefCell<MultiGetBufs> =
+        RefCell::new(MultiGetBufs::default());
+}
+
+// The arrays the multi gets pass to rocksdb, the keys and the slots of
+// their values and errors, kept per thread and reused so that a call
+// only allocates its results.
+#[derive(Default)]
+struct MultiGetBufs {
+    cfs: Vec<DBCFHandle>,
+    key_ptrs: Vec<*const u8>,
+    key_lens: Vec<size_t>,
+    vals: Vec<*mut u8>,
+    val_lens: Vec<size_t>,
+    slices: Vec<rocksdb_ffi::DBPinnableSlice>,
+    errs: Vec<*const i8>,
+}
+
+impl MultiGetBufs {
+    // Set up the buffers for `keys`, with empty error slots.
+    fn reset(&mut self, keys: &[&[u8]]) {
+        self.cfs.clear();
+        self.key_ptrs.clear();
+        self.key_ptrs.extend(keys.iter().map(|k| k.as_ptr()));
+        self.key_lens.clear();
+        self.key_lens.extend(keys.iter().map(|k| k.len() as size_t));
+        self.vals.clear();
+        self.val_lens.clear();
+        self.slices.clear();
+        self.errs.clear();
+        self.errs.resize(keys.len(), 0 as *const i8);
+    }
+}
+
+// Run `f` with this thread's multi get buffers set up for `keys`, or new
+// ones if they are in use, by a callback called from a multi get.
+fn with_multi_get_bufs<T, F>(keys: &[&[u8]], f: F) -> T
+    where F: FnOnce(&mut MultiGetBufs) -> T
+{
+    MULTI_GET_BUFS.with(|bufs| match bufs.try_borrow_mut() {
+        Ok(mut bufs) => {
+            bufs.reset(keys);
+            f(&mut bufs)
+        }
+        Err(_) => {
+            let mut bufs = MultiGetBufs::default();
+            bufs.reset(keys);
+            f(&mut bufs)
+        }
+    })
+}
+
+/// A rocksdb database.
+///
+/// Reads, writes, iterators and snapshots take `&self`: rocksdb
+/// synchronizes them, so a `DB` can be shared between threads, e.g. in an
+/// `Arc`. Creating and dropping column families take `&mut self`, as they
+/// change the handles the other methods use; `SyncDb` allows them on a
+/// shared DB.
 pub struct DB {
     inner: rocksdb_ffi::DBInstance,
     cfs: BTreeMap<String, DBCFHandle>,
+    // The handles of the dropped column families, which may still be used
+    // through their copies.
+    dropped_cfs: Vec<DBCFHandle>,
     path: String,
+    // Copies of the options the DB and its column families were created
+    // with, which keep their callbacks alive until the DB is closed.
+    opts: Vec<Options>,
+    db_paths: Vec<String>,
+    wal_dir: String,
+    // Whether the DB was opened with a ttl, which the column families
+    // created with one need.
+    with_ttl: bool,
+    // The custom comparators of the column families, by name.
+    comparators: BTreeMap<String, fn(&[u8], &[u8]) -> i32>,
+    metrics: Mutex<Option<MetricsSampler>>,
+    // Number of `SnapshotHandle`s not released yet.
+    snapshot_handles: AtomicUsize,
+    // Bumped before and after each `cancel_manual_compactions`, so odd
+    // while one is running.
+    compaction_cancels: AtomicUsize,
 }
 
 unsafe impl Send for DB {}
@@ -38,10 +143,16 @@ unsafe impl Sync for DB {}
 
 pub struct WriteBatch {
     inner: rocksdb_ffi::DBWriteBatch,
+    max_bytes: Option<usize>,
+    max_ops: Option<usize>,
 }
 
 pub struct ReadOptions {
     inner: rocksdb_ffi::DBReadOptions,
+    lower_bound: Vec<u8>,
+    upper_bound: Vec<u8>,
+    timestamp: Vec<u8>,
+    iter_start_ts: Vec<u8>,
 }
 
 /// The UnsafeSnap must be destroyed by db, it maybe be leaked
@@ -58,10 +169,50 @@ pub struct Snapshot<'a> {
     snap: UnsafeSnap,
 }
 
-// We need to find a better way to add a lifetime in here.
+/// A snapshot that doesn't borrow its DB, for registries keeping
+/// snapshots by id or handing them through FFI.
+///
+/// Unlike a plain `UnsafeSnap`, the DB counts the handles it created and
+/// hasn't got back through `DB::release_snapshot_handle`. In debug builds,
+/// dropping a DB whose handles are not all released panics, instead of
+/// leaking their snapshots silently.
+pub struct SnapshotHandle {
+    snap: UnsafeSnap,
+}
+
+unsafe impl Send for SnapshotHandle {}
+unsafe impl Sync for SnapshotHandle {}
+
+impl SnapshotHandle {
+    /// Take back a handle given away by `into_raw`.
+    pub unsafe fn from_raw(raw: rocksdb_ffi::DBSnapshot) -> SnapshotHandle {
+        SnapshotHandle { snap: UnsafeSnap { inner: raw } }
+    }
+
+    /// Give away the handle, it stays counted by its DB until it is taken
+    /// back with `from_raw` and released.
+    pub fn into_raw(self) -> rocksdb_ffi::DBSnapshot {
+        self.snap.inner
+    }
+
+    /// The snapshot, to read from with `ReadOptions::set_snapshot`.
+    pub fn as_unsafe_snap(&self) -> &UnsafeSnap {
+        &self.snap
+    }
+}
+
+/// An iterator over a DB or one of its column families.
+///
+/// The iterator owns the `ReadOptions` it was created with, so bounds set
+/// on them stay valid as long as it lives. Iterators created from a
+/// `Snapshot` borrow it, so the snapshot can't be released under them.
 #[allow(dead_code)]
 pub struct DBIterator<'a> {
     db: &'a DB,
+    // Rocksdb keeps pointers to the bounds stored in the read options,
+    // so they have to live as long as the iterator. They are shared by
+    // the iterators of `DB::new_iterators`.
+    readopts: Rc<ReadOptions>,
     inner: rocksdb_ffi::DBIterator,
 }
 
@@ -78,13 +229,14 @@ impl<'a> From<&'a [u8]> for SeekKey<'a> {
 }
 
 impl<'a> DBIterator<'a> {
-    pub fn new(db: &'a DB, readopts: &ReadOptions) -> DBIterator<'a> {
+    pub fn new(db: &'a DB, readopts: ReadOptions) -> DBIterator<'a> {
         unsafe {
             let iterator = rocksdb_ffi::rocksdb_create_iterator(db.inner,
                                                                 readopts.inner);
 
             DBIterator {
                 db: db,
+                readopts: Rc::new(readopts),
                 inner: iterator,
             }
         }
@@ -123,43 +275,93 @@ impl<'a> DBIterator<'a> {
         self.valid()
     }
 
-    pub fn key(&self) -> &[u8] {
-        assert!(self.valid());
+    /// The key of the current entry, `None` if the iterator is not valid.
+    pub fn key(&self) -> Option<&[u8]> {
+        if !self.valid() {
+            return None;
+        }
         let mut key_len: size_t = 0;
         let key_len_ptr: *mut size_t = &mut key_len;
         unsafe {
             let key_ptr = rocksdb_ffi::rocksdb_iter_key(self.inner,
                                                         key_len_ptr);
-            slice::from_raw_parts(key_ptr, key_len as usize)
+            Some(slice::from_raw_parts(key_ptr, key_len as usize))
         }
     }
 
-    pub fn value(&self) -> &[u8] {
-        assert!(self.valid());
+    /// The value of the current entry, `None` if the iterator is not
+    /// valid.
+    pub fn value(&self) -> Option<&[u8]> {
+        if !self.valid() {
+            return None;
+        }
         let mut val_len: size_t = 0;
         let val_len_ptr: *mut size_t = &mut val_len;
         unsafe {
             let val_ptr = rocksdb_ffi::rocksdb_iter_value(self.inner,
                                                           val_len_ptr);
-            slice::from_raw_parts(val_ptr, val_len as usize)
+            Some(slice::from_raw_parts(val_ptr, val_len as usize))
         }
     }
 
-    pub fn kv(&self) -> Option<(Vec<u8>, Vec<u8>)> {
-        if self.valid() {
-            Some((self.key().to_vec(), self.value().to_vec()))
-        } else {
-            None
+    /// The raw iterator handle, only valid while the `DBIterator` is
+    /// alive.
+    pub unsafe fn as_raw(&self) -> rocksdb_ffi::DBIterator {
+        self.inner
+    }
+
+    /// The timestamp of the current entry, `None` if the column family has
+    /// no timestamps or the iterator is not valid.
+    pub fn timestamp(&self) -> Option<u64> {
+        if !self.valid() {
+            return None;
         }
+        let mut ts_len: size_t = 0;
+        unsafe {
+            let ts_ptr = rocksdb_ffi::rocksdb_iter_timestamp(self.inner,
+                                                             &mut ts_len);
+            if ts_len == 0 {
+                return None;
+            }
+            Some(decode_u64_ts(slice::from_raw_parts(ts_ptr, ts_len as usize)))
+        }
+    }
+
+    /// The key and value of the current entry, `None` if the iterator is
+    /// not valid.
+    pub fn entry(&self) -> Option<(&[u8], &[u8])> {
+        match (self.key(), self.value()) {
+            (Some(key), Some(value)) => Some((key, value)),
+            _ => None,
+        }
+    }
+
+    pub fn kv(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.entry().map(|(key, value)| (key.to_vec(), value.to_vec()))
     }
 
     pub fn valid(&self) -> bool {
         unsafe { rocksdb_ffi::rocksdb_iter_valid(self.inner) }
     }
 
+    /// Return the error hit by the iterator, if any.
+    ///
+    /// An iterator becomes invalid both at the end of data and on error,
+    /// check this after `valid` returns false to tell them apart.
+    pub fn status(&self) -> Result<(), String> {
+        let mut err: *const u8 = 0 as *const u8;
+        unsafe {
+            rocksdb_ffi::rocksdb_iter_get_error(self.inner, &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err as *const i8));
+        }
+        Ok(())
+    }
+
     pub fn new_cf(db: &'a DB,
                   cf_handle: DBCFHandle,
-                  readopts: &ReadOptions)
+                  readopts: ReadOptions)
                   -> DBIterator<'a> {
         unsafe {
             let iterator =
@@ -168,6 +370,7 @@ impl<'a> DBIterator<'a> {
                                                         cf_handle);
             DBIterator {
                 db: db,
+                readopts: Rc::new(readopts),
                 inner: iterator,
             }
         }
@@ -215,7 +418,22 @@ impl<'a> Snapshot<'a> {
         unsafe {
             opt.set_snapshot(&self.snap);
         }
-        DBIterator::new(self.db, &opt)
+        DBIterator::new(self.db, opt)
+    }
+
+    pub fn iter_cf(&self, cf_handle: DBCFHandle) -> DBIterator {
+        let readopts = ReadOptions::new();
+        self.iter_cf_opt(cf_handle, readopts)
+    }
+
+    pub fn iter_cf_opt(&self,
+                       cf_handle: DBCFHandle,
+                       mut opt: ReadOptions)
+                       -> DBIterator {
+        unsafe {
+            opt.set_snapshot(&self.snap);
+        }
+        DBIterator::new_cf(self.db, cf_handle, opt)
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<DBVector>, String> {
@@ -236,6 +454,23 @@ impl<'a> Snapshot<'a> {
         }
         self.db.get_cf_opt(cf, key, &readopts)
     }
+
+    fn bounded_readopts(&self,
+                        start_key: Option<&[u8]>,
+                        end_key: Option<&[u8]>)
+                        -> ReadOptions {
+        let mut opts = ReadOptions::new();
+        unsafe {
+            opts.set_snapshot(&self.snap);
+        }
+        if let Some(k) = start_key {
+            opts.set_iterate_lower_bound(k);
+        }
+        if let Some(k) = end_key {
+            opts.set_iterate_upper_bound(k);
+        }
+        opts
+    }
 }
 
 impl<'a> Drop for Snapshot<'a> {
@@ -260,11 +495,346 @@ pub trait Writable {
                 -> Result<(), String>;
     fn delete(&self, key: &[u8]) -> Result<(), String>;
     fn delete_cf(&self, cf: DBCFHandle, key: &[u8]) -> Result<(), String>;
+    /// Delete a key written at most once since it was last deleted.
+    ///
+    /// Unlike a delete, the tombstone is dropped along with the put it
+    /// meets in a compaction, instead of being carried to the last level.
+    /// The result is undefined if the key was put or merged several
+    /// times, or is mixed with deletes.
+    fn single_delete(&self, key: &[u8]) -> Result<(), String>;
+    fn single_delete_cf(&self,
+                        cf: DBCFHandle,
+                        key: &[u8])
+                        -> Result<(), String>;
+    /// Delete all the keys in [`begin_key`, `end_key`).
+    fn delete_range(&self,
+                    begin_key: &[u8],
+                    end_key: &[u8])
+                    -> Result<(), String>;
+    fn delete_range_cf(&self,
+                       cf: DBCFHandle,
+                       begin_key: &[u8],
+                       end_key: &[u8])
+                       -> Result<(), String>;
+}
+
+/// Disk space used by a DB, see `DB::get_disk_usage`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiskUsage {
+    /// Size of the live sst files in each path.
+    pub sst_files_size: BTreeMap<String, u64>,
+    /// Size of the WAL files not archived yet, in the WAL directory.
+    pub wal_files_size: BTreeMap<String, u64>,
+    /// Size of the blob files in each path, including the obsolete ones
+    /// not deleted yet.
+    pub blob_files_size: BTreeMap<String, u64>,
+}
+
+impl DiskUsage {
+    pub fn total(&self) -> u64 {
+        self.sst_files_size
+            .values()
+            .chain(self.wal_files_size.values())
+            .chain(self.blob_files_size.values())
+            .fold(0, |a, b| a + b)
+    }
+}
+
+/// An sst file of a DB, see `DB::live_files`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveFile {
+    pub name: String,
+    pub cf: String,
+    pub level: i32,
+    pub size: u64,
+    pub smallest_key: Vec<u8>,
+    pub largest_key: Vec<u8>,
+    pub entries: u64,
+    /// Number of deletions among the entries.
+    pub deletions: u64,
+}
+
+/// The files of a level of a column family, see `DB::space_amp_report`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelSpace {
+    pub level: i32,
+    pub files: usize,
+    pub size: u64,
+    pub entries: u64,
+    pub deletions: u64,
+    /// Size of the level over the size of the last level, which holds most
+    /// of the live data. It estimates how much space the level adds, as its
+    /// keys are mostly newer versions of keys below it.
+    pub size_amp: f64,
+}
+
+/// The size of a level of a column family against its target, see
+/// `DB::level_sizes_cf`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelSize {
+    pub level: i32,
+    pub files: usize,
+    pub size: u64,
+    /// The size above which the level gets compacted into the next one,
+    /// `None` for level 0, which is compacted by number of files, and for
+    /// the levels that dynamic level sizes leave empty.
+    pub target_size: Option<u64>,
+    /// Size over target size, or files over the compaction trigger for
+    /// level 0. Levels above 1.0 are due for compaction.
+    pub fill_ratio: f64,
+}
+
+/// The levels of a column family, see `DB::level_sizes_cf`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelSizes {
+    /// All the levels, from level 0.
+    pub levels: Vec<LevelSize>,
+    /// Estimated number of bytes compactions have to rewrite to bring the
+    /// levels back under their targets.
+    pub pending_compaction_bytes: u64,
+}
+
+/// A key range worth compacting by hand to reclaim space, with
+/// `DB::compact_range_cf`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactionHint {
+    pub start: Vec<u8>,
+    /// The last key of the range, included.
+    pub end: Vec<u8>,
+    /// Estimated number of bytes compacting the range frees.
+    pub reclaimable: u64,
+}
+
+/// Where the space of a column family goes, see `DB::space_amp_report`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpaceAmpReport {
+    /// The non empty levels, from the top.
+    pub levels: Vec<LevelSpace>,
+    pub sst_files_size: u64,
+    /// Estimated size of the live data.
+    pub live_data_size: u64,
+    /// Size of the sst files over the size of the live data, 1.0 when
+    /// there is nothing to reclaim.
+    pub space_amp: f64,
+    /// Ranges to compact by hand, the most space reclaimed first.
+    pub hints: Vec<CompactionHint>,
+}
+
+/// A key range holding range tombstones, see `DB::range_tombstones`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TombstoneSpan {
+    pub start: Vec<u8>,
+    /// The last key of the range, included.
+    pub end: Vec<u8>,
+}
+
+/// The range tombstones of the sst files of a column family, see
+/// `DB::range_tombstones`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RangeTombstones {
+    pub count: u64,
+    /// Number of range tombstones of the levels holding some, from the top.
+    pub levels: Vec<(i32, u64)>,
+    /// Key ranges of the files of the levels holding range tombstones, the
+    /// overlapping ones merged, in key order.
+    pub spans: Vec<TombstoneSpan>,
+}
+
+// Readahead of `DB::prewarm`, which reads whole ranges.
+const PREWARM_READAHEAD: usize = 2 << 20;
+
+// Files with at least this ratio of deletions are worth compacting.
+const HINT_DELETION_RATIO: f64 = 0.5;
+// Above this space amplification, compacting everything is worth it.
+const HINT_SPACE_AMP: f64 = 2.0;
+
+/// Why writes would stall, see `DB::write_stall_state`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StallReason {
+    /// Writes are stopped until flushes or compactions catch up.
+    Stopped,
+    /// Writes are slowed down to this rate, in bytes per second.
+    Delayed(u64),
+    /// rocksdb rejected the write because of a stall that started after
+    /// the state was checked.
+    Rejected,
+}
+
+/// The error of `DB::try_write`, handing the batch back to be queued
+/// or retried later.
+///
+/// `error` is `Error::WriteStall` if the write would have stalled, so it
+/// wasn't made.
+pub struct TryWriteError {
+    pub error: Error,
+    pub batch: WriteBatch,
+}
+
+impl fmt::Debug for TryWriteError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f,
+               "TryWriteError({:?}, <batch of {} ops>)",
+               self.error,
+               self.batch.count())
+    }
+}
+
+impl fmt::Display for TryWriteError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+/// The properties operators usually watch, see `DB::get_health_snapshot`.
+///
+/// A field is `None` when the property is not supported by the linked
+/// rocksdb.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HealthSnapshot {
+    pub num_files_at_level0: Option<u64>,
+    pub num_immutable_memtables: Option<u64>,
+    /// Size of the active and unflushed immutable memtables.
+    pub cur_size_all_memtables: Option<u64>,
+    /// Size of all the memtables, including the pinned ones.
+    pub size_all_memtables: Option<u64>,
+    pub estimate_pending_compaction_bytes: Option<u64>,
+    pub num_running_compactions: Option<u64>,
+    pub num_running_flushes: Option<u64>,
+    /// Rate writes are delayed to, 0 when they are not delayed.
+    pub actual_delayed_write_rate: Option<u64>,
+    pub is_write_stopped: Option<bool>,
+    pub estimate_num_keys: Option<u64>,
+}
+
+/// The flushes and compactions of a DB, running and waiting for a thread,
+/// see `DB::get_background_jobs`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BackgroundJobs {
+    /// Flushes running in the high priority pool.
+    pub running_flushes: u64,
+    /// Compactions running in the low and bottom priority pools.
+    pub running_compactions: u64,
+    /// Immutable memtables waiting to be flushed, in all the column
+    /// families.
+    pub pending_flushes: u64,
+    /// Column families that need a compaction which hasn't started yet.
+    pub pending_compactions: u64,
+}
+
+/// How many bytes entries take, in the memtables and in the sst files,
+/// see `DB::entry_overhead_cf`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntryOverheadReport {
+    /// Entries of the active and immutable memtables, deletions included.
+    pub memtable_entries: u64,
+    /// Memory held by the memtables, including the pinned ones.
+    pub memtable_bytes: u64,
+    pub sst_entries: u64,
+    /// Size of the keys and values of the sst files, as written.
+    pub raw_key_bytes: u64,
+    pub raw_value_bytes: u64,
+    pub sst_file_bytes: u64,
+}
+
+impl EntryOverheadReport {
+    pub fn memtable_bytes_per_entry(&self) -> f64 {
+        per_entry(self.memtable_bytes, self.memtable_entries)
+    }
+
+    /// Average size of the key and value of an entry of the sst files.
+    pub fn raw_bytes_per_entry(&self) -> f64 {
+        per_entry(self.raw_key_bytes + self.raw_value_bytes, self.sst_entries)
+    }
+
+    pub fn sst_bytes_per_entry(&self) -> f64 {
+        per_entry(self.sst_file_bytes, self.sst_entries)
+    }
+
+    /// Memory a memtable entry takes beyond its key and value, assuming
+    /// they are as large as those of the sst files.
+    pub fn memtable_overhead_per_entry(&self) -> f64 {
+        (self.memtable_bytes_per_entry() - self.raw_bytes_per_entry()).max(0.0)
+    }
+}
+
+fn per_entry(bytes: u64, entries: u64) -> f64 {
+    if entries == 0 {
+        0.0
+    } else {
+        bytes as f64 / entries as f64
+    }
+}
+
+/// Logical size of a range estimated by sampling, see
+/// `DB::estimate_range_size`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeSizeEstimate {
+    /// Sizes of the sampled values, sorted.
+    pub value_sizes: Vec<usize>,
+    pub avg_key_size: f64,
+    pub avg_value_size: f64,
+    pub estimated_num_keys: u64,
+    /// Estimated uncompressed size of the keys and values in the range.
+    pub estimated_logical_size: u64,
+}
+
+impl RangeSizeEstimate {
+    /// Return the sampled value size below which `p` percent of the
+    /// samples are, or 0 if nothing was sampled.
+    pub fn value_size_percentile(&self, p: f64) -> usize {
+        if self.value_sizes.is_empty() {
+            return 0;
+        }
+        let idx = (p / 100.0 * (self.value_sizes.len() - 1) as f64).round();
+        self.value_sizes[idx as usize]
+    }
+}
+
+/// A write to a named column family, see `DB::write_cf_ops`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CfOp<'a> {
+    Put(&'a str, &'a [u8], &'a [u8]),
+    Merge(&'a str, &'a [u8], &'a [u8]),
+    Delete(&'a str, &'a [u8]),
+    /// Delete all the keys in [begin, end).
+    DeleteRange(&'a str, &'a [u8], &'a [u8]),
+}
+
+impl<'a> CfOp<'a> {
+    pub fn cf(&self) -> &'a str {
+        match *self {
+            CfOp::Put(cf, _, _) |
+            CfOp::Merge(cf, _, _) |
+            CfOp::Delete(cf, _) |
+            CfOp::DeleteRange(cf, _, _) => cf,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            CfOp::Put(..) => "put",
+            CfOp::Merge(..) => "merge",
+            CfOp::Delete(..) => "delete",
+            CfOp::DeleteRange(..) => "delete_range",
+        }
+    }
+
+    fn apply(&self, batch: &WriteBatch, cf: DBCFHandle) -> Result<(), String> {
+        match *self {
+            CfOp::Put(_, key, value) => batch.put_cf(cf, key, value),
+            CfOp::Merge(_, key, value) => batch.merge_cf(cf, key, value),
+            CfOp::Delete(_, key) => batch.delete_cf(cf, key),
+            CfOp::DeleteRange(_, begin, end) => {
+                batch.delete_range_cf(cf, begin, end)
+            }
+        }
+    }
 }
 
 /// A range of keys, `start_key` is included, but not `end_key`.
 ///
-/// You should make sure `end_key` is not less than `start_key`.
+/// `end_key` must not be less than `start_key` in the order of the column
+/// family the range is used with, `DB::check_range` checks it.
 pub struct Range<'a> {
     start_key: &'a [u8],
     end_key: &'a [u8],
@@ -272,12 +842,26 @@ pub struct Range<'a> {
 
 impl<'a> Range<'a> {
     pub fn new(start_key: &'a [u8], end_key: &'a [u8]) -> Range<'a> {
-        assert!(start_key <= end_key);
         Range {
             start_key: start_key,
             end_key: end_key,
         }
     }
+
+    pub fn start_key(&self) -> &'a [u8] {
+        self.start_key
+    }
+
+    pub fn end_key(&self) -> &'a [u8] {
+        self.end_key
+    }
+}
+
+// How `DB::open_cf_internal` opens the DB.
+enum OpenMode<'a> {
+    ReadWrite,
+    Ttl(&'a [i32]),
+    ReadOnly(bool),
 }
 
 impl DB {
@@ -296,6 +880,141 @@ impl DB {
                    cfs: &[&str],
                    cf_opts: &[&Options])
                    -> Result<DB, String> {
+        DB::open_cf_internal(opts, path, cfs, cf_opts, OpenMode::ReadWrite)
+    }
+
+    /// Open the DB at `path` without locking it, so another process may
+    /// have it open for writing, e.g. to inspect it.
+    ///
+    /// The DB is as of when it is opened, later writes of the other
+    /// process are not seen, and writes through it fail. The WAL is read
+    /// but not replayed into new files, or rejected by
+    /// `error_if_log_file_exist`.
+    pub fn open_for_read_only(opts: &Options,
+                              path: &str,
+                              error_if_log_file_exist: bool)
+                              -> Result<DB, String> {
+        DB::open_cf_for_read_only(opts, path, &[], &[], error_if_log_file_exist)
+    }
+
+    /// Like `open_for_read_only`, opening column families `cfs`.
+    ///
+    /// Unlike `open_cf`, other column families may be left out.
+    pub fn open_cf_for_read_only(opts: &Options,
+                                 path: &str,
+                                 cfs: &[&str],
+                                 cf_opts: &[&Options],
+                                 error_if_log_file_exist: bool)
+                                 -> Result<DB, String> {
+        DB::open_cf_internal(opts,
+                             path,
+                             cfs,
+                             cf_opts,
+                             OpenMode::ReadOnly(error_if_log_file_exist))
+    }
+
+    /// Like `open`, telling a DB already open for writing, by this
+    /// process or another, apart from the other errors.
+    pub fn try_open(opts: &Options, path: &str) -> Result<DB, Error> {
+        DB::try_open_cf(opts, path, &[], &[])
+    }
+
+    /// Like `open_cf`, see `try_open`.
+    pub fn try_open_cf(opts: &Options,
+                       path: &str,
+                       cfs: &[&str],
+                       cf_opts: &[&Options])
+                       -> Result<DB, Error> {
+        DB::open_cf(opts, path, cfs, cf_opts)
+            .map_err(|e| lock_file::open_error(path, e))
+    }
+
+    /// Whether the DB at `path` is open for writing, and by which
+    /// process, without trying to open it. Only supported on linux.
+    ///
+    /// The answer may be out of date as soon as it is returned, use
+    /// `try_open` to open the DB if it is not in use.
+    pub fn lock_status(path: &str) -> Result<LockStatus, String> {
+        lock_file::lock_status(path)
+    }
+
+    /// Like `open_cf`, also returning the handles of `cfs`, in the same
+    /// order.
+    pub fn open_cf_with_handles(opts: &Options,
+                                path: &str,
+                                cfs: &[&str],
+                                cf_opts: &[&Options])
+                                -> Result<(DB, Vec<DBCFHandle>), String> {
+        let db = try!(DB::open_cf(opts, path, cfs, cf_opts));
+        let handles = cfs.iter().map(|cf| db.cfs[*cf]).collect();
+        Ok((db, handles))
+    }
+
+    /// Like `open_cf`, checking `cf_opts` against the options the DB was
+    /// last opened with.
+    ///
+    /// A comparator or merge operator that doesn't match the persisted
+    /// one is rejected before opening, see `PersistedOptions::check_cf`.
+    /// The other options that changed since then are returned with the DB.
+    pub fn open_cf_checked(opts: &Options,
+                           path: &str,
+                           cfs: &[&str],
+                           cf_opts: &[&Options])
+                           -> Result<(DB, Vec<OptionChange>), String> {
+        let old = try!(PersistedOptions::load_latest(path));
+        if let Some(ref old) = old {
+            for (cf, cf_opt) in cfs.iter().zip(cf_opts) {
+                try!(old.check_cf(cf, cf_opt));
+            }
+            if !cfs.contains(&DEFAULT_COLUMN_FAMILY) {
+                try!(old.check_cf(DEFAULT_COLUMN_FAMILY, opts));
+            }
+        }
+        let db = try!(DB::open_cf(opts, path, cfs, cf_opts));
+        // Opening writes the options in use to a new OPTIONS file.
+        let changes = match (old, try!(PersistedOptions::load_latest(path))) {
+            (Some(old), Some(new)) => old.diff(&new),
+            _ => vec![],
+        };
+        Ok((db, changes))
+    }
+
+    /// Open a DB whose keys expire `ttl` seconds after they are written.
+    ///
+    /// Expired keys are removed by compactions, so they may still be read
+    /// for a while after they expire. A `ttl` of 0 or less never expires.
+    pub fn open_with_ttl(opts: &Options,
+                         path: &str,
+                         ttl: i32)
+                         -> Result<DB, String> {
+        DB::open_cf_with_ttl(opts,
+                             path,
+                             &[DEFAULT_COLUMN_FAMILY],
+                             &[opts],
+                             &[ttl])
+    }
+
+    /// Like `open_with_ttl`, with a ttl for each column family.
+    ///
+    /// The default column family never expires if it is not in `cfs`.
+    pub fn open_cf_with_ttl(opts: &Options,
+                            path: &str,
+                            cfs: &[&str],
+                            cf_opts: &[&Options],
+                            ttls: &[i32])
+                            -> Result<DB, String> {
+        if cfs.len() != ttls.len() {
+            return Err(format!("cfs.len() and ttls.len() not match."));
+        }
+        DB::open_cf_internal(opts, path, cfs, cf_opts, OpenMode::Ttl(ttls))
+    }
+
+    fn open_cf_internal(opts: &Options,
+                        path: &str,
+                        cfs: &[&str],
+                        cf_opts: &[&Options],
+                        mode: OpenMode)
+                        -> Result<DB, String> {
         let cpath = match CString::new(path.as_bytes()) {
             Ok(c) => c,
             Err(_) => {
@@ -304,7 +1023,20 @@ impl DB {
                     .to_owned())
             }
         };
-        if let Err(e) = fs::create_dir_all(&Path::new(path)) {
+        let read_only = match mode {
+            OpenMode::ReadOnly(_) => true,
+            _ => false,
+        };
+        let with_ttl = match mode {
+            OpenMode::Ttl(_) => true,
+            _ => false,
+        };
+        if read_only {
+            if !Path::new(path).is_dir() {
+                return Err(format!("Invalid argument: {} does not exist",
+                                   path));
+            }
+        } else if let Err(e) = fs::create_dir_all(&Path::new(path)) {
             return Err(format!("Failed to create rocksdb directory: \
                                 src/rocksdb.rs:                              \
                                 {:?}",
@@ -317,10 +1049,34 @@ impl DB {
 
         let mut cfs_v = cfs.to_vec();
         let mut cf_opts_v = cf_opts.to_vec();
+        let mut ttls_v: Vec<c_int> = match mode {
+            OpenMode::Ttl(ttls) => ttls.to_vec(),
+            _ => vec![],
+        };
         // Always open the default column family
         if !cfs_v.contains(&DEFAULT_COLUMN_FAMILY) {
             cfs_v.push(DEFAULT_COLUMN_FAMILY);
             cf_opts_v.push(opts);
+            ttls_v.push(0);
+        }
+
+        // Check the column families against the existing db first, so that
+        // a mismatch is reported by name instead of by rocksdb's message.
+        let mut missing = vec![];
+        if let Ok(existing) = DB::list_column_families(opts, path) {
+            let unopened: Vec<&str> = existing.iter()
+                .map(|cf| cf.as_str())
+                .filter(|cf| !cfs_v.contains(cf))
+                .collect();
+            if !unopened.is_empty() && !read_only {
+                return Err(format!("Invalid argument: You have to open all \
+                                    column families. Not opened: {:?}",
+                                   unopened));
+            }
+            missing = cfs_v.iter()
+                .filter(|cf| !existing.iter().any(|e| e == *cf))
+                .map(|cf| (*cf).to_owned())
+                .collect();
         }
 
         // We need to store our CStrings in an intermediate vector
@@ -345,16 +1101,50 @@ impl DB {
         let mut err: *const i8 = 0 as *const i8;
         let err_ptr: *mut *const i8 = &mut err;
         unsafe {
-            db = rocksdb_ffi::rocksdb_open_column_families(opts.inner,
-                    cpath.as_ptr() as *const _,
-                    cfs_v.len() as c_int,
-                    cfnames.as_ptr() as *const _,
-                    cfopts.as_ptr(),
-                    cfhandles.as_ptr(),
-                    err_ptr);
+            db = match mode {
+                OpenMode::Ttl(_) => {
+                    rocksdb_ffi::rocksdb_open_column_families_with_ttl(
+                        opts.inner,
+                        cpath.as_ptr() as *const _,
+                        cfs_v.len() as c_int,
+                        cfnames.as_ptr() as *const _,
+                        cfopts.as_ptr(),
+                        cfhandles.as_ptr(),
+                        ttls_v.as_ptr(),
+                        err_ptr)
+                }
+                OpenMode::ReadOnly(error_if_log_file_exist) => {
+                    rocksdb_ffi::rocksdb_open_for_read_only_column_families(
+                        opts.inner,
+                        cpath.as_ptr() as *const _,
+                        cfs_v.len() as c_int,
+                        cfnames.as_ptr() as *const _,
+                        cfopts.as_ptr(),
+                        cfhandles.as_ptr(),
+                        error_if_log_file_exist as u8,
+                        err_ptr)
+                }
+                OpenMode::ReadWrite => {
+                    rocksdb_ffi::rocksdb_open_column_families(opts.inner,
+                        cpath.as_ptr() as *const _,
+                        cfs_v.len() as c_int,
+                        cfnames.as_ptr() as *const _,
+                        cfopts.as_ptr(),
+                        cfhandles.as_ptr(),
+                        err_ptr)
+                }
+            };
         }
         if !err.is_null() {
-            return Err(error_message(err));
+            let e = error_message(err);
+            if !missing.is_empty() {
+                return Err(format!("{} Column families not found: {:?}, \
+                                    set create_missing_column_families \
+                                    to create them",
+                                   e,
+                                   missing));
+            }
+            return Err(e);
         }
 
         for handle in &cfhandles {
@@ -376,10 +1166,60 @@ impl DB {
         Ok(DB {
             inner: db,
             cfs: cf_map,
+            dropped_cfs: vec![],
             path: path.to_owned(),
+            opts: cf_opts_v.iter().map(|o| (*o).clone()).collect(),
+            db_paths: opts.get_db_paths().to_vec(),
+            wal_dir: opts.get_wal_dir().unwrap_or(path).to_owned(),
+            with_ttl: with_ttl,
+            comparators: cfs_v.iter()
+                .zip(&cf_opts_v)
+                .filter_map(|(n, o)| {
+                    o.get_compare_fn().map(|f| ((*n).to_owned(), f))
+                })
+                .collect(),
+            metrics: Mutex::new(None),
+            snapshot_handles: AtomicUsize::new(0),
+            compaction_cancels: AtomicUsize::new(0),
         })
     }
 
+    /// List the column families of the DB at `path`, `default` included.
+    ///
+    /// `open_cf` must be given all of them, so this finds out what to
+    /// open a DB with when the names are not known in advance. Fails if
+    /// there is no DB at `path`.
+    pub fn list_column_families(opts: &Options,
+                                path: &str)
+                                -> Result<Vec<String>, String> {
+        let cpath = match CString::new(path.as_bytes()) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err("Failed to convert path to CString when listing \
+                            column families"
+                    .to_owned())
+            }
+        };
+        let mut cflen: size_t = 0;
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            let list =
+                rocksdb_ffi::rocksdb_list_column_families(opts.inner,
+                                                          cpath.as_ptr() as *const _,
+                                                          &mut cflen,
+                                                          &mut err);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            let names = slice::from_raw_parts(list, cflen as usize)
+                .iter()
+                .map(|n| CStr::from_ptr(*n as *const _).to_string_lossy().into_owned())
+                .collect();
+            rocksdb_ffi::rocksdb_list_column_families_destroy(list, cflen);
+            Ok(names)
+        }
+    }
+
     pub fn destroy(opts: &Options, path: &str) -> Result<(), String> {
         let cpath = CString::new(path.as_bytes()).unwrap();
         let cpath_ptr = cpath.as_ptr();
@@ -418,10 +1258,82 @@ impl DB {
         &self.path
     }
 
+    /// The raw rocksdb handle, to call C functions this crate doesn't wrap.
+    ///
+    /// Unsafe because the handle is only valid while the DB is alive, and
+    /// must not be closed or have its column families dropped through it.
+    pub unsafe fn as_raw(&self) -> rocksdb_ffi::DBInstance {
+        self.inner
+    }
+
+    /// Create a consistent copy of the DB in `path`, which must not exist.
+    ///
+    /// Sst files are hard linked when `path` is on the same filesystem.
+    /// The memtables are flushed first unless the WALs are smaller than
+    /// `log_size_for_flush`, in which case they are copied instead.
+    pub fn create_checkpoint(&self,
+                             path: &str,
+                             log_size_for_flush: u64)
+                             -> Result<(), String> {
+        let cpath = match CString::new(path.as_bytes()) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err("Failed to convert path to CString when creating \
+                            checkpoint"
+                    .to_owned())
+            }
+        };
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            let checkpoint =
+                rocksdb_ffi::rocksdb_checkpoint_object_create(self.inner,
+                                                              &mut err);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            rocksdb_ffi::rocksdb_checkpoint_create(checkpoint,
+                                                   cpath.as_ptr() as *const _,
+                                                   log_size_for_flush,
+                                                   &mut err);
+            rocksdb_ffi::rocksdb_checkpoint_object_destroy(checkpoint);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
     pub fn write_opt(&self,
                      batch: WriteBatch,
                      writeopts: &WriteOptions)
                      -> Result<(), String> {
+        self.write_batch(&batch, writeopts)
+    }
+
+    /// Write `batch`, retrying it as `policy` says while it fails with a
+    /// transient error.
+    pub fn write_with_retry(&self,
+                            batch: WriteBatch,
+                            writeopts: &WriteOptions,
+                            policy: &RetryPolicy)
+                            -> Result<(), String> {
+        policy.run(|| self.write_batch(&batch, writeopts))
+    }
+
+    /// Read `key`, retrying the read as `policy` says while it fails with
+    /// a transient error.
+    pub fn get_with_retry(&self,
+                          key: &[u8],
+                          readopts: &ReadOptions,
+                          policy: &RetryPolicy)
+                          -> Result<Option<DBVector>, String> {
+        policy.run(|| self.get_opt(key, readopts))
+    }
+
+    fn write_batch(&self,
+                   batch: &WriteBatch,
+                   writeopts: &WriteOptions)
+                   -> Result<(), String> {
         let mut err: *const i8 = 0 as *const i8;
         let err_ptr: *mut *const i8 = &mut err;
         unsafe {
@@ -437,35 +1349,184 @@ impl DB {
     }
 
     pub fn write(&self, batch: WriteBatch) -> Result<(), String> {
-        self.write_opt(batch, &WriteOptions::new())
+        DEFAULT_WRITEOPTS.with(|o| self.write_opt(batch, o))
     }
 
-    pub fn write_without_wal(&self, batch: WriteBatch) -> Result<(), String> {
-        let mut wo = WriteOptions::new();
-        wo.disable_wal(true);
-        self.write_opt(batch, &wo)
+    /// Apply a batch read from the WAL of another DB, for a replica
+    /// following it, see `apply_wal_batches_opt`.
+    pub fn apply_wal_batch(&self,
+                           data: &[u8],
+                           expected_seq: u64)
+                           -> Result<u64, String> {
+        DEFAULT_WRITEOPTS.with(|o| {
+            self.apply_wal_batches_opt(&[data], expected_seq, o)
+        })
     }
 
-    pub fn get_opt(&self,
-                   key: &[u8],
-                   readopts: &ReadOptions)
-                   -> Result<Option<DBVector>, String> {
-        if readopts.inner.0.is_null() {
-            return Err("Unable to create rocksdb read options.  This is a \
-                        fairly trivial call, and its failure may be \
-                        indicative of a mis-compiled or mis-loaded rocksdb \
-                        library."
-                .to_owned());
-        }
+    pub fn apply_wal_batch_opt(&self,
+                               data: &[u8],
+                               expected_seq: u64,
+                               writeopts: &WriteOptions)
+                               -> Result<u64, String> {
+        self.apply_wal_batches_opt(&[data], expected_seq, writeopts)
+    }
 
-        unsafe {
-            let val_len: size_t = 0;
-            let val_len_ptr = &val_len as *const size_t;
-            let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            let val =
-                rocksdb_ffi::rocksdb_get(self.inner,
-                                         readopts.inner,
+    /// Apply the serialized batches `batches`, read in order from the WAL
+    /// of another DB, as a single write, and return the sequence number
+    /// the next batch must start at.
+    ///
+    /// The first batch must start at `expected_seq`, the sequence number
+    /// following the last write of this DB, and each batch must follow the
+    /// previous one, so the replica ends up with the sequence numbers of
+    /// the primary. A gap or a batch applied twice fails without writing
+    /// anything. The replica must not be written to in any other way.
+    ///
+    /// A replica that can catch up from its primary after a crash can skip
+    /// its own WAL with `WriteOptions::disable_wal`.
+    pub fn apply_wal_batches_opt(&self,
+                                 batches: &[&[u8]],
+                                 expected_seq: u64,
+                                 writeopts: &WriteOptions)
+                                 -> Result<u64, String> {
+        let latest = self.get_latest_sequence_number();
+        if latest + 1 != expected_seq {
+            return Err(format!("Invalid argument: the DB is at sequence {}, \
+                                expected a batch at {}",
+                               latest + 1,
+                               expected_seq));
+        }
+        let mut merged = vec![0; WRITE_BATCH_HEADER_SIZE];
+        let mut next = expected_seq;
+        for data in batches {
+            if data.len() < WRITE_BATCH_HEADER_SIZE {
+                return Err("Corruption: malformed WriteBatch (too small)"
+                    .to_owned());
+            }
+            let seq = decode_fixed64(data);
+            if seq != next {
+                return Err(format!("Invalid argument: batch at sequence {} \
+                                    doesn't follow sequence {}",
+                                   seq,
+                                   next - 1));
+            }
+            next += decode_fixed32(&data[8..]) as u64;
+            merged.extend_from_slice(&data[WRITE_BATCH_HEADER_SIZE..]);
+        }
+        let count = next - expected_seq;
+        if count == 0 {
+            return Ok(next);
+        }
+        for i in 0..8 {
+            merged[i] = (expected_seq >> (8 * i)) as u8;
+        }
+        for i in 0..4 {
+            merged[8 + i] = (count >> (8 * i)) as u8;
+        }
+        let batch = try!(WriteBatch::from_data(&merged));
+        try!(self.write_batch(&batch, writeopts));
+        Ok(next)
+    }
+
+    /// Whether writes are currently stopped or slowed down.
+    pub fn write_stall_state(&self) -> Option<StallReason> {
+        if self.get_property_int("rocksdb.is-write-stopped").unwrap_or(0) != 0 {
+            return Some(StallReason::Stopped);
+        }
+        match self.get_property_int("rocksdb.actual-delayed-write-rate") {
+            Ok(rate) if rate > 0 => Some(StallReason::Delayed(rate)),
+            _ => None,
+        }
+    }
+
+    /// Write `batch` only if it can be done without stalling, for front
+    /// ends that would rather queue a write than wait on it.
+    ///
+    /// The stall state is checked first, and the write is made with
+    /// `no_slowdown` so that a stall starting in between rejects it too.
+    pub fn try_write(&self, batch: WriteBatch) -> Result<(), TryWriteError> {
+        let mut writeopts = WriteOptions::new();
+        writeopts.set_no_slowdown(true);
+        self.try_write_opt(batch, &writeopts)
+    }
+
+    /// Like `try_write`, `writeopts` must set `no_slowdown`.
+    pub fn try_write_opt(&self,
+                         batch: WriteBatch,
+                         writeopts: &WriteOptions)
+                         -> Result<(), TryWriteError> {
+        let error = if !writeopts.get_no_slowdown() {
+            Error::InvalidArgument("Invalid argument: try_write needs \
+                                    no_slowdown write options"
+                .to_owned())
+        } else if let Some(reason) = self.write_stall_state() {
+            Error::WriteStall(reason)
+        } else {
+            match self.write_batch(&batch, writeopts) {
+                Ok(()) => return Ok(()),
+                Err(e) => Error::from(e),
+            }
+        };
+        Err(TryWriteError {
+            error: error,
+            batch: batch,
+        })
+    }
+
+    /// Apply `ops` atomically, as a single write batch.
+    ///
+    /// Nothing is written if any op fails, the error names the index, kind
+    /// and column family of the failing op.
+    pub fn write_cf_ops(&self, ops: &[CfOp]) -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.write_cf_ops_opt(ops, o))
+    }
+
+    pub fn write_cf_ops_opt(&self,
+                            ops: &[CfOp],
+                            writeopts: &WriteOptions)
+                            -> Result<(), String> {
+        let batch = WriteBatch::new();
+        for (i, op) in ops.iter().enumerate() {
+            let res = match self.cfs.get(op.cf()) {
+                Some(cf) => op.apply(&batch, *cf),
+                None => Err(format!("Invalid column family: {}", op.cf())),
+            };
+            if let Err(e) = res {
+                return Err(format!("op {} ({} on {}): {}",
+                                   i,
+                                   op.name(),
+                                   op.cf(),
+                                   e));
+            }
+        }
+        self.write_opt(batch, writeopts)
+    }
+
+    pub fn write_without_wal(&self, batch: WriteBatch) -> Result<(), String> {
+        let mut wo = WriteOptions::new();
+        wo.disable_wal(true);
+        self.write_opt(batch, &wo)
+    }
+
+    pub fn get_opt(&self,
+                   key: &[u8],
+                   readopts: &ReadOptions)
+                   -> Result<Option<DBVector>, String> {
+        if readopts.inner.0.is_null() {
+            return Err("Unable to create rocksdb read options.  This is a \
+                        fairly trivial call, and its failure may be \
+                        indicative of a mis-compiled or mis-loaded rocksdb \
+                        library."
+                .to_owned());
+        }
+
+        unsafe {
+            let val_len: size_t = 0;
+            let val_len_ptr = &val_len as *const size_t;
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            let val =
+                rocksdb_ffi::rocksdb_get(self.inner,
+                                         readopts.inner,
                                          key.as_ptr(),
                                          key.len() as size_t,
                                          val_len_ptr,
@@ -482,7 +1543,7 @@ impl DB {
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<DBVector>, String> {
-        self.get_opt(key, &ReadOptions::new())
+        DEFAULT_READOPTS.with(|o| self.get_opt(key, o))
     }
 
     pub fn get_cf_opt(&self,
@@ -526,13 +1587,201 @@ impl DB {
                   cf: DBCFHandle,
                   key: &[u8])
                   -> Result<Option<DBVector>, String> {
-        self.get_cf_opt(cf, key, &ReadOptions::new())
+        DEFAULT_READOPTS.with(|o| self.get_cf_opt(cf, key, o))
+    }
+
+    /// Return false if `key` is certainly not in the DB, true if it may be.
+    ///
+    /// Only the memtables, the block cache and the bloom filters are
+    /// looked at, no block is read from disk, so it is much cheaper than a
+    /// `get` of a key that is absent, but may give false positives.
+    pub fn key_may_exist(&self, key: &[u8]) -> bool {
+        self.key_may_exist_cf_opt(None, key, false).0
+    }
+
+    pub fn key_may_exist_cf(&self, cf: DBCFHandle, key: &[u8]) -> bool {
+        self.key_may_exist_cf_opt(Some(cf), key, false).0
+    }
+
+    /// Like `key_may_exist`, also returning the value when it was found
+    /// along the way, typically in a memtable.
+    pub fn key_may_exist_value(&self, key: &[u8]) -> (bool, Option<DBVector>) {
+        self.key_may_exist_cf_opt(None, key, true)
+    }
+
+    pub fn key_may_exist_value_cf(&self,
+                                  cf: DBCFHandle,
+                                  key: &[u8])
+                                  -> (bool, Option<DBVector>) {
+        self.key_may_exist_cf_opt(Some(cf), key, true)
+    }
+
+    fn key_may_exist_cf_opt(&self,
+                            cf: Option<DBCFHandle>,
+                            key: &[u8],
+                            want_value: bool)
+                            -> (bool, Option<DBVector>) {
+        let readopts = ReadOptions::new();
+        let mut val: *mut u8 = ptr::null_mut();
+        let mut val_len: size_t = 0;
+        let mut value_found: u8 = 0;
+        let (val_ptr, found_ptr) = if want_value {
+            (&mut val as *mut *mut u8, &mut value_found as *mut u8)
+        } else {
+            (ptr::null_mut(), ptr::null_mut())
+        };
+        let may_exist = unsafe {
+            match cf {
+                None => {
+                    rocksdb_ffi::rocksdb_key_may_exist(self.inner,
+                                                       readopts.inner,
+                                                       key.as_ptr(),
+                                                       key.len() as size_t,
+                                                       val_ptr,
+                                                       &mut val_len,
+                                                       ptr::null(),
+                                                       0,
+                                                       found_ptr)
+                }
+                Some(cf) => {
+                    rocksdb_ffi::rocksdb_key_may_exist_cf(self.inner,
+                                                          readopts.inner,
+                                                          cf,
+                                                          key.as_ptr(),
+                                                          key.len() as size_t,
+                                                          val_ptr,
+                                                          &mut val_len,
+                                                          ptr::null(),
+                                                          0,
+                                                          found_ptr)
+                }
+            }
+        };
+        let value = if val.is_null() {
+            None
+        } else if value_found != 0 {
+            Some(DBVector::from_c(val, val_len))
+        } else {
+            // Not expected, but the buffer is ours either way.
+            unsafe { libc::free(val as *mut c_void) };
+            None
+        };
+        (may_exist != 0, value)
+    }
+
+    /// Read `keys` in a single call, each with its own result.
+    pub fn multi_get(&self,
+                     keys: &[&[u8]])
+                     -> Vec<Result<Option<DBVector>, String>> {
+        DEFAULT_READOPTS.with(|o| self.multi_get_opt(keys, o))
+    }
+
+    pub fn multi_get_opt(&self,
+                         keys: &[&[u8]],
+                         readopts: &ReadOptions)
+                         -> Vec<Result<Option<DBVector>, String>> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.multi_get_cf_opt(cf, keys, readopts)
+    }
+
+    pub fn multi_get_cf(&self,
+                        cf: DBCFHandle,
+                        keys: &[&[u8]])
+                        -> Vec<Result<Option<DBVector>, String>> {
+        DEFAULT_READOPTS.with(|o| self.multi_get_cf_opt(cf, keys, o))
+    }
+
+    pub fn multi_get_cf_opt(&self,
+                            cf: DBCFHandle,
+                            keys: &[&[u8]],
+                            readopts: &ReadOptions)
+                            -> Vec<Result<Option<DBVector>, String>> {
+        with_multi_get_bufs(keys, |bufs| {
+            bufs.cfs.resize(keys.len(), cf);
+            bufs.vals.resize(keys.len(), 0 as *mut u8);
+            bufs.val_lens.resize(keys.len(), 0);
+            unsafe {
+                rocksdb_ffi::rocksdb_multi_get_cf(self.inner,
+                                                  readopts.inner,
+                                                  bufs.cfs.as_ptr(),
+                                                  keys.len() as size_t,
+                                                  bufs.key_ptrs.as_ptr(),
+                                                  bufs.key_lens.as_ptr(),
+                                                  bufs.vals.as_mut_ptr(),
+                                                  bufs.val_lens.as_mut_ptr(),
+                                                  bufs.errs.as_mut_ptr());
+            }
+            bufs.vals
+                .iter()
+                .zip(&bufs.val_lens)
+                .zip(&bufs.errs)
+                .map(|((&val, &len), &err)| {
+                    if !err.is_null() {
+                        return Err(error_message(err));
+                    }
+                    if val.is_null() {
+                        Ok(None)
+                    } else {
+                        Ok(Some(DBVector::from_c(val, len)))
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Read `key` from each of the column families `cfs`, all from the
+    /// same snapshot, so that related records kept in separate column
+    /// families are seen consistently.
+    pub fn get_all_cf(&self,
+                      cfs: &[&str],
+                      key: &[u8])
+                      -> Result<BTreeMap<String, Option<DBVector>>, String> {
+        let mut handles = Vec::with_capacity(cfs.len());
+        for name in cfs {
+            handles.push(try!(self.cf_handle_or_err(name)));
+        }
+        let snap = self.snapshot();
+        let mut readopts = ReadOptions::new();
+        unsafe {
+            readopts.set_snapshot(&snap.snap);
+        }
+        let mut values = BTreeMap::new();
+        for (name, cf) in cfs.iter().zip(handles) {
+            let value = try!(self.get_cf_opt(cf, key, &readopts));
+            values.insert((*name).to_owned(), value);
+        }
+        Ok(values)
     }
 
     pub fn create_cf(&mut self,
                      name: &str,
                      opts: &Options)
                      -> Result<DBCFHandle, String> {
+        self.create_cf_internal(name, opts, None)
+    }
+
+    /// Create a column family whose keys expire `ttl` seconds after they
+    /// are written, the DB must have been opened with `open_with_ttl` or
+    /// `open_cf_with_ttl`.
+    pub fn create_cf_with_ttl(&mut self,
+                              name: &str,
+                              opts: &Options,
+                              ttl: i32)
+                              -> Result<DBCFHandle, String> {
+        if !self.with_ttl {
+            return Err(format!("Invalid argument: can't create column family \
+                                {} with a ttl, the DB was not opened with \
+                                one",
+                               name));
+        }
+        self.create_cf_internal(name, opts, Some(ttl))
+    }
+
+    fn create_cf_internal(&mut self,
+                          name: &str,
+                          opts: &Options,
+                          ttl: Option<i32>)
+                          -> Result<DBCFHandle, String> {
         let cname = match CString::new(name.as_bytes()) {
             Ok(c) => c,
             Err(_) => {
@@ -545,37 +1794,61 @@ impl DB {
         let mut err: *const i8 = 0 as *const i8;
         let err_ptr: *mut *const i8 = &mut err;
         let cf_handler = unsafe {
-            let cf_handler =
-                rocksdb_ffi::rocksdb_create_column_family(self.inner,
-                                                          opts.inner,
-                                                          cname_ptr as *const _,
-                                                          err_ptr);
-            self.cfs.insert(name.to_owned(), cf_handler);
+            let cf_handler = match ttl {
+                Some(ttl) => {
+                    rocksdb_ffi::rocksdb_create_column_family_with_ttl(self.inner,
+                                                                       opts.inner,
+                                                                       cname_ptr as *const _,
+                                                                       ttl,
+                                                                       err_ptr)
+                }
+                None => {
+                    rocksdb_ffi::rocksdb_create_column_family(self.inner,
+                                                              opts.inner,
+                                                              cname_ptr as *const _,
+                                                              err_ptr)
+                }
+            };
             cf_handler
         };
         if !err.is_null() {
             return Err(error_message(err));
         }
+        self.cfs.insert(name.to_owned(), cf_handler);
+        self.opts.push(opts.clone());
+        if let Some(f) = opts.get_compare_fn() {
+            self.comparators.insert(name.to_owned(), f);
+        }
         Ok(cf_handler)
     }
 
-    pub fn drop_cf(&mut self, name: &str) -> Result<(), String> {
-        let cf = self.cfs.get(name);
-        if cf.is_none() {
-            return Err(format!("Invalid column family: {}", name).clone());
+    /// Drop the column family `name`, which `cf_handle` and `cf_names`
+    /// stop returning.
+    ///
+    /// The default column family can't be dropped. Its handle is only
+    /// destroyed when the DB is closed, so the copies of it taken from
+    /// `cf_handle` don't dangle: they keep reading the data of the dropped
+    /// column family, which is deleted once the DB is closed.
+    pub fn drop_cf(&mut self, name: &str) -> Result<(), Error> {
+        if name == DEFAULT_COLUMN_FAMILY {
+            return Err(Error::InvalidArgument("Invalid argument: Can't drop \
+                                               default column family"
+                .to_owned()));
         }
+        let cf = try!(self.cf_handle_or_err(name));
 
         let mut err: *const i8 = 0 as *const i8;
         let err_ptr: *mut *const i8 = &mut err;
         unsafe {
-            rocksdb_ffi::rocksdb_drop_column_family(self.inner,
-                                                    *cf.unwrap(),
-                                                    err_ptr);
+            rocksdb_ffi::rocksdb_drop_column_family(self.inner, cf, err_ptr);
         }
         if !err.is_null() {
-            return Err(error_message(err));
+            return Err(Error::from(error_message(err)));
         }
 
+        self.cfs.remove(name);
+        self.comparators.remove(name);
+        self.dropped_cfs.push(cf);
         Ok(())
     }
 
@@ -583,6 +1856,15 @@ impl DB {
         self.cfs.get(name)
     }
 
+    /// Like `cf_handle`, failing with `Error::InvalidColumnFamily` when
+    /// there is no column family `name`.
+    pub fn cf_handle_or_err(&self, name: &str) -> Result<DBCFHandle, Error> {
+        self.cfs
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::InvalidColumnFamily(Some(name.to_owned())))
+    }
+
     /// get all column family names, including 'default'.
     pub fn cf_names(&self) -> Vec<&str> {
         self.cfs.iter().map(|(k, _)| k.as_str()).collect()
@@ -590,690 +1872,4058 @@ impl DB {
 
     pub fn iter(&self) -> DBIterator {
         let opts = ReadOptions::new();
-        self.iter_opt(&opts)
+        self.iter_opt(opts)
     }
 
-    pub fn iter_opt(&self, opt: &ReadOptions) -> DBIterator {
+    pub fn iter_opt(&self, opt: ReadOptions) -> DBIterator {
         DBIterator::new(&self, opt)
     }
 
     pub fn iter_cf(&self, cf_handle: DBCFHandle) -> DBIterator {
         let opts = ReadOptions::new();
-        DBIterator::new_cf(&self, cf_handle, &opts)
-    }
-
-    pub fn snapshot(&self) -> Snapshot {
-        Snapshot::new(self)
-    }
-
-    pub unsafe fn unsafe_snap(&self) -> UnsafeSnap {
-        UnsafeSnap { inner: rocksdb_ffi::rocksdb_create_snapshot(self.inner) }
+        DBIterator::new_cf(&self, cf_handle, opts)
     }
 
-    pub unsafe fn release_snap(&self, snap: &UnsafeSnap) {
-        rocksdb_ffi::rocksdb_release_snapshot(self.inner, snap.inner)
+    /// Create an iterator over the keys in [`start_key`, `end_key`) as
+    /// seen by `snap`, positioned at the first of them.
+    ///
+    /// `None` leaves that side of the range unbounded.
+    pub fn iter_at<'a>(&'a self,
+                       snap: &'a Snapshot,
+                       start_key: Option<&[u8]>,
+                       end_key: Option<&[u8]>)
+                       -> DBIterator<'a> {
+        let opts = snap.bounded_readopts(start_key, end_key);
+        let mut iter = DBIterator::new(self, opts);
+        seek_to_start(&mut iter, start_key);
+        iter
     }
 
-    pub fn put_opt(&self,
-                   key: &[u8],
-                   value: &[u8],
-                   writeopts: &WriteOptions)
-                   -> Result<(), String> {
+    /// Create iterators over column families `cfs` in a single call, all
+    /// reading the same point in time.
+    ///
+    /// Without a snapshot in `readopts`, they share an implicit one taken
+    /// by the call, so scanning several column families is consistent
+    /// without creating a `Snapshot`.
+    pub fn new_iterators(&self,
+                         cfs: &[DBCFHandle],
+                         readopts: ReadOptions)
+                         -> Result<Vec<DBIterator>, String> {
+        let mut iters = vec![rocksdb_ffi::DBIterator(0 as *const c_void);
+                             cfs.len()];
         unsafe {
             let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            rocksdb_ffi::rocksdb_put(self.inner,
-                                     writeopts.inner,
-                                     key.as_ptr(),
-                                     key.len() as size_t,
-                                     value.as_ptr(),
-                                     value.len() as size_t,
-                                     err_ptr);
+            rocksdb_ffi::rocksdb_create_iterators(self.inner,
+                                                  readopts.inner,
+                                                  cfs.as_ptr(),
+                                                  iters.as_mut_ptr(),
+                                                  cfs.len() as size_t,
+                                                  &mut err);
             if !err.is_null() {
                 return Err(error_message(err));
             }
-            Ok(())
         }
+        let readopts = Rc::new(readopts);
+        Ok(iters.into_iter()
+            .map(|inner| {
+                DBIterator {
+                    db: self,
+                    readopts: readopts.clone(),
+                    inner: inner,
+                }
+            })
+            .collect())
     }
 
-    pub fn put_cf_opt(&self,
-                      cf: DBCFHandle,
-                      key: &[u8],
-                      value: &[u8],
-                      writeopts: &WriteOptions)
-                      -> Result<(), String> {
-        unsafe {
-            let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            rocksdb_ffi::rocksdb_put_cf(self.inner,
-                                        writeopts.inner,
-                                        cf,
-                                        key.as_ptr(),
-                                        key.len() as size_t,
-                                        value.as_ptr(),
-                                        value.len() as size_t,
-                                        err_ptr);
-            if !err.is_null() {
-                return Err(error_message(err));
+    pub fn iter_cf_at<'a>(&'a self,
+                          cf: DBCFHandle,
+                          snap: &'a Snapshot,
+                          start_key: Option<&[u8]>,
+                          end_key: Option<&[u8]>)
+                          -> DBIterator<'a> {
+        let opts = snap.bounded_readopts(start_key, end_key);
+        let mut iter = DBIterator::new_cf(self, cf, opts);
+        seek_to_start(&mut iter, start_key);
+        iter
+    }
+
+    /// Count the keys in [`start_key`, `end_key`), stopping at `limit`.
+    ///
+    /// Only keys are visited and blocks read are not added to the block
+    /// cache, so counting a large range won't evict hot data.
+    pub fn count_range(&self,
+                       start_key: &[u8],
+                       end_key: &[u8],
+                       limit: usize)
+                       -> Result<usize, String> {
+        let mut opts = ReadOptions::new();
+        opts.fill_cache(false);
+        opts.set_iterate_upper_bound(end_key);
+        let iter = DBIterator::new(self, opts);
+        count_iter(iter, start_key, limit)
+    }
+
+    pub fn count_range_cf(&self,
+                          cf: DBCFHandle,
+                          start_key: &[u8],
+                          end_key: &[u8],
+                          limit: usize)
+                          -> Result<usize, String> {
+        let mut opts = ReadOptions::new();
+        opts.fill_cache(false);
+        opts.set_iterate_upper_bound(end_key);
+        let iter = DBIterator::new_cf(self, cf, opts);
+        count_iter(iter, start_key, limit)
+    }
+
+    /// Load the blocks of the keys in `ranges` of column family `cf` into
+    /// the block cache, returning the bytes of keys and values read.
+    ///
+    /// Meant to be run after opening a DB, so the first requests served
+    /// don't all miss the cache. The ranges are read with a large
+    /// readahead, and evict other blocks if they don't fit in the cache.
+    ///
+    /// With the `cache-dump` feature, the blocks in the cache can instead
+    /// be saved at shutdown with `Cache::dump` and loaded back with
+    /// `Cache::new_lru_from_dump`.
+    pub fn prewarm(&self,
+                   cf: DBCFHandle,
+                   ranges: &[Range])
+                   -> Result<u64, String> {
+        let mut read = 0;
+        for range in ranges {
+            try!(self.check_range_cf(cf, range));
+            let mut opts = ReadOptions::new();
+            opts.fill_cache(true);
+            opts.set_readahead_size(PREWARM_READAHEAD);
+            opts.set_iterate_upper_bound(range.end_key);
+            let mut iter = DBIterator::new_cf(self, cf, opts);
+            iter.seek(SeekKey::Key(range.start_key));
+            while let Some((key, value)) = iter.entry() {
+                read += (key.len() + value.len()) as u64;
+                iter.next();
             }
-            Ok(())
+            try!(iter.status());
         }
+        Ok(read)
     }
-    pub fn merge_opt(&self,
-                     key: &[u8],
-                     value: &[u8],
-                     writeopts: &WriteOptions)
-                     -> Result<(), String> {
-        unsafe {
-            let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            rocksdb_ffi::rocksdb_merge(self.inner,
-                                       writeopts.inner,
-                                       key.as_ptr(),
-                                       key.len() as size_t,
-                                       value.as_ptr(),
-                                       value.len() as size_t,
-                                       err_ptr);
-            if !err.is_null() {
-                return Err(error_message(err));
+
+    /// Delete the keys in `range` for which `predicate(key, value)` is true,
+    /// returning how many were deleted.
+    ///
+    /// Deletes are committed every `batch_size` keys, so memory use stays
+    /// bounded however large the range is. The scan runs on the state of
+    /// the DB when it started, and if it fails the batches committed so
+    /// far stay deleted.
+    pub fn delete_matching<F>(&self,
+                              range: &Range,
+                              batch_size: usize,
+                              predicate: F)
+                              -> Result<usize, String>
+        where F: FnMut(&[u8], &[u8]) -> bool
+    {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.delete_matching_cf(cf, range, batch_size, predicate)
+    }
+
+    pub fn delete_matching_cf<F>(&self,
+                                 cf: DBCFHandle,
+                                 range: &Range,
+                                 batch_size: usize,
+                                 mut predicate: F)
+                                 -> Result<usize, String>
+        where F: FnMut(&[u8], &[u8]) -> bool
+    {
+        if batch_size == 0 {
+            return Err("Invalid argument: batch size must be positive"
+                .to_owned());
+        }
+        try!(self.check_range_cf(cf, range));
+        let mut opts = ReadOptions::new();
+        opts.fill_cache(false);
+        opts.set_iterate_upper_bound(range.end_key);
+        let mut iter = DBIterator::new_cf(self, cf, opts);
+        let mut batch = WriteBatch::new();
+        let mut pending = 0;
+        let mut deleted = 0;
+        iter.seek(SeekKey::Key(range.start_key));
+        while let Some((key, value)) = iter.entry() {
+            if predicate(key, value) {
+                try!(batch.delete_cf(cf, key));
+                pending += 1;
+                if pending == batch_size {
+                    try!(self.write(batch));
+                    batch = WriteBatch::new();
+                    deleted += pending;
+                    pending = 0;
+                }
             }
-            Ok(())
+            iter.next();
+        }
+        try!(iter.status());
+        if pending > 0 {
+            try!(self.write(batch));
+            deleted += pending;
         }
+        Ok(deleted)
     }
-    fn merge_cf_opt(&self,
-                    cf: DBCFHandle,
-                    key: &[u8],
-                    value: &[u8],
-                    writeopts: &WriteOptions)
-                    -> Result<(), String> {
+
+    /// Write `value` as the version of `key` at `ts`, in a column family
+    /// with a timestamp comparator.
+    pub fn put_with_ts(&self,
+                       key: &[u8],
+                       ts: u64,
+                       value: &[u8])
+                       -> Result<(), String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.put_cf_with_ts(cf, key, ts, value)
+    }
+
+    pub fn put_cf_with_ts(&self,
+                          cf: DBCFHandle,
+                          key: &[u8],
+                          ts: u64,
+                          value: &[u8])
+                          -> Result<(), String> {
+        let ts = encode_u64_ts(ts);
+        let writeopts = WriteOptions::new();
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_put_cf_with_ts(self.inner,
+                                                writeopts.inner,
+                                                cf,
+                                                key.as_ptr(),
+                                                key.len() as size_t,
+                                                ts.as_ptr(),
+                                                ts.len() as size_t,
+                                                value.as_ptr(),
+                                                value.len() as size_t,
+                                                &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Delete `key` as of `ts`, older versions stay readable at their
+    /// timestamps.
+    pub fn delete_with_ts(&self, key: &[u8], ts: u64) -> Result<(), String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.delete_cf_with_ts(cf, key, ts)
+    }
+
+    pub fn delete_cf_with_ts(&self,
+                             cf: DBCFHandle,
+                             key: &[u8],
+                             ts: u64)
+                             -> Result<(), String> {
+        let ts = encode_u64_ts(ts);
+        let writeopts = WriteOptions::new();
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_delete_cf_with_ts(self.inner,
+                                                   writeopts.inner,
+                                                   cf,
+                                                   key.as_ptr(),
+                                                   key.len() as size_t,
+                                                   ts.as_ptr(),
+                                                   ts.len() as size_t,
+                                                   &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Read the version of `key` visible at the timestamp set on
+    /// `readopts`, with the timestamp it was written at.
+    pub fn get_with_ts(&self,
+                       key: &[u8],
+                       readopts: &ReadOptions)
+                       -> Result<Option<(DBVector, u64)>, String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.get_cf_with_ts(cf, key, readopts)
+    }
+
+    pub fn get_cf_with_ts(&self,
+                          cf: DBCFHandle,
+                          key: &[u8],
+                          readopts: &ReadOptions)
+                          -> Result<Option<(DBVector, u64)>, String> {
+        if readopts.timestamp.is_empty() {
+            return Err("Invalid argument: read timestamp is not set"
+                .to_owned());
+        }
         unsafe {
+            let mut val_len: size_t = 0;
+            let mut ts: *mut u8 = 0 as *mut u8;
+            let mut ts_len: size_t = 0;
             let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            rocksdb_ffi::rocksdb_merge_cf(self.inner,
-                                          writeopts.inner,
-                                          cf,
-                                          key.as_ptr(),
-                                          key.len() as size_t,
-                                          value.as_ptr(),
-                                          value.len() as size_t,
-                                          err_ptr);
+            let val = rocksdb_ffi::rocksdb_get_cf_with_ts(self.inner,
+                                                          readopts.inner,
+                                                          cf,
+                                                          key.as_ptr(),
+                                                          key.len() as size_t,
+                                                          &mut val_len,
+                                                          &mut ts,
+                                                          &mut ts_len,
+                                                          &mut err) as *mut u8;
             if !err.is_null() {
                 return Err(error_message(err));
             }
-            Ok(())
+            if val.is_null() {
+                if !ts.is_null() {
+                    libc::free(ts as *mut c_void);
+                }
+                return Ok(None);
+            }
+            let value = DBVector::from_c(val, val_len);
+            let write_ts =
+                decode_u64_ts(slice::from_raw_parts(ts, ts_len as usize));
+            libc::free(ts as *mut c_void);
+            Ok(Some((value, write_ts)))
         }
     }
-    fn delete_opt(&self,
-                  key: &[u8],
-                  writeopts: &WriteOptions)
-                  -> Result<(), String> {
-        unsafe {
-            let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            rocksdb_ffi::rocksdb_delete(self.inner,
-                                        writeopts.inner,
-                                        key.as_ptr(),
-                                        key.len() as size_t,
-                                        err_ptr);
-            if !err.is_null() {
-                return Err(error_message(err));
+
+    /// Check that `ts` is newer than the latest version of `key`, so a
+    /// write at `ts` can't be hidden behind an existing one. Deletions
+    /// count as versions.
+    ///
+    /// The check and the write are not atomic, callers racing on the same
+    /// key must serialize them.
+    pub fn check_ts_increasing_cf(&self,
+                                  cf: DBCFHandle,
+                                  key: &[u8],
+                                  ts: u64)
+                                  -> Result<(), String> {
+        // Only an iterator over all the versions sees the deletions.
+        let mut readopts = ReadOptions::new();
+        readopts.set_timestamp(u64::max_value());
+        readopts.set_iter_start_ts(0);
+        let mut iter = DBIterator::new_cf(self, cf, readopts);
+        iter.seek(SeekKey::Key(key));
+        // The keys are internal keys, the user key followed by its 8 byte
+        // timestamp and 8 bytes of sequence number and type.
+        let latest = match iter.key() {
+            Some(k) if k.len() == key.len() + 16 && k.starts_with(key) => {
+                iter.timestamp()
             }
-            Ok(())
-        }
-    }
-    fn delete_cf_opt(&self,
-                     cf: DBCFHandle,
-                     key: &[u8],
-                     writeopts: &WriteOptions)
-                     -> Result<(), String> {
-        unsafe {
-            let mut err: *const i8 = 0 as *const i8;
-            let err_ptr: *mut *const i8 = &mut err;
-            rocksdb_ffi::rocksdb_delete_cf(self.inner,
-                                           writeopts.inner,
-                                           cf,
-                                           key.as_ptr(),
-                                           key.len() as size_t,
-                                           err_ptr);
-            if !err.is_null() {
-                return Err(error_message(err));
+            _ => None,
+        };
+        try!(iter.status());
+        if let Some(latest) = latest {
+            if ts <= latest {
+                return Err(format!("Invalid argument: timestamp {} is not \
+                                    newer than {}",
+                                   ts,
+                                   latest));
             }
-            Ok(())
         }
+        Ok(())
     }
 
-    /// Flush all memtable data.
-    ///
-    /// Due to lack of abi, only default cf is supported.
-    ///
-    /// If sync, the flush will wait until the flush is done.
-    pub fn flush(&self, sync: bool) -> Result<(), String> {
-        unsafe {
-            let opts = rocksdb_ffi::rocksdb_flushoptions_create();
-            rocksdb_ffi::rocksdb_flushoptions_set_wait(opts, sync);
-            let mut err = 0 as *const i8;
-            rocksdb_ffi::rocksdb_flush(self.inner, opts, &mut err);
-            rocksdb_ffi::rocksdb_flushoptions_destroy(opts);
-            if !err.is_null() {
-                return Err(error_message(err));
-            }
-            Ok(())
+    /// Check that `range` is not reversed in the key order of the default
+    /// column family.
+    pub fn check_range(&self, range: &Range) -> Result<(), String> {
+        self.check_range_cf(self.cfs[DEFAULT_COLUMN_FAMILY], range)
+    }
+
+    /// The key order of `cf`, the function of its custom comparator or
+    /// the bytewise order.
+    pub fn compare_fn_cf(&self,
+                         cf: DBCFHandle)
+                         -> Result<fn(&[u8], &[u8]) -> i32, String> {
+        let name = match self.cfs.iter().find(|&(_, h)| h.0 == cf.0) {
+            Some((name, _)) => name,
+            None => return Err("Invalid column family handle".to_owned()),
+        };
+        Ok(match self.comparators.get(name) {
+            Some(f) => *f,
+            None => bytewise_compare,
+        })
+    }
+
+    pub fn check_range_cf(&self,
+                          cf: DBCFHandle,
+                          range: &Range)
+                          -> Result<(), String> {
+        let compare = try!(self.compare_fn_cf(cf));
+        if compare(range.start_key, range.end_key) > 0 {
+            return Err(format!("Invalid argument: range start {:?} is after \
+                                range end {:?}",
+                               range.start_key,
+                               range.end_key));
         }
+        Ok(())
     }
 
-    /// Return the approximate file system space used by keys in each ranges.
-    ///
-    /// Note that the returned sizes measure file system space usage, so
-    /// if the user data compresses by a factor of ten, the returned
-    /// sizes will be one-tenth the size of the corresponding user data size.
+    /// Return the length of the value of `key`, without copying it.
     ///
-    /// Due to lack of abi, only data flushed to disk is taken into account.
-    pub fn get_approximate_sizes(&self, ranges: &[Range]) -> Vec<u64> {
-        self.get_approximate_sizes_cfopt(None, ranges)
+    /// The value is still read from disk, but the blocks read are not
+    /// added to the block cache, so sizing large values doesn't evict hot
+    /// data.
+    pub fn get_value_size(&self, key: &[u8]) -> Result<Option<usize>, String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.get_value_size_cf(cf, key)
     }
 
-    pub fn get_approximate_sizes_cf(&self,
-                                    cf: DBCFHandle,
-                                    ranges: &[Range])
-                                    -> Vec<u64> {
-        self.get_approximate_sizes_cfopt(Some(cf), ranges)
+    pub fn get_value_size_cf(&self,
+                             cf: DBCFHandle,
+                             key: &[u8])
+                             -> Result<Option<usize>, String> {
+        let mut readopts = ReadOptions::new();
+        readopts.fill_cache(false);
+        self.get_value_size_cf_opt(cf, key, &readopts)
     }
 
-    fn get_approximate_sizes_cfopt(&self,
-                                   cf: Option<DBCFHandle>,
-                                   ranges: &[Range])
-                                   -> Vec<u64> {
-        let start_keys: Vec<*const u8> = ranges.iter()
-            .map(|x| x.start_key.as_ptr())
-            .collect();
-        let start_key_lens: Vec<u64> = ranges.iter()
-            .map(|x| x.start_key.len() as u64)
-            .collect();
-        let end_keys: Vec<*const u8> = ranges.iter()
-            .map(|x| x.end_key.as_ptr())
-            .collect();
-        let end_key_lens: Vec<u64> = ranges.iter()
-            .map(|x| x.end_key.len() as u64)
-            .collect();
-        let mut sizes: Vec<u64> = vec![0; ranges.len()];
-        let (n,
-             start_key_ptr,
-             start_key_len_ptr,
-             end_key_ptr,
-             end_key_len_ptr,
-             size_ptr) = (ranges.len() as i32,
-                          start_keys.as_ptr(),
-                          start_key_lens.as_ptr(),
-                          end_keys.as_ptr(),
-                          end_key_lens.as_ptr(),
-                          sizes.as_mut_ptr());
-        match cf {
-            None => unsafe {
-                rocksdb_ffi::rocksdb_approximate_sizes(self.inner,
-                                                       n,
-                                                       start_key_ptr,
-                                                       start_key_len_ptr as *const _,
-                                                       end_key_ptr,
-                                                       end_key_len_ptr as *const _,
-                                                       size_ptr)
-            },
-            Some(cf) => unsafe {
-                rocksdb_ffi::rocksdb_approximate_sizes_cf(self.inner,
-                                                          cf,
-                                                          n,
-                                                          start_key_ptr,
-                                                          start_key_len_ptr as *const _,
-                                                          end_key_ptr,
-                                                          end_key_len_ptr as *const _,
-                                                          size_ptr)
-            },
-        }
-        sizes
+    pub fn get_value_size_cf_opt(&self,
+                                 cf: DBCFHandle,
+                                 key: &[u8],
+                                 readopts: &ReadOptions)
+                                 -> Result<Option<usize>, String> {
+        self.get_with_cf_opt(cf, key, readopts, |v| v.len())
     }
 
-    pub fn delete_file_in_range(&self,
-                                start_key: &[u8],
-                                end_key: &[u8])
-                                -> Result<(), String> {
-        unsafe {
-            let mut err: *const i8 = 0 as *const i8;
+    /// Call `f` on the value of `key` and return its result, `None` if the
+    /// key is not found.
+    ///
+    /// `f` reads the value where rocksdb pinned it, in the block cache or
+    /// the memtable, so no copy of the value is allocated.
+    pub fn get_with<T, F>(&self, key: &[u8], f: F) -> Result<Option<T>, String>
+        where F: FnOnce(&[u8]) -> T
+    {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.get_with_cf(cf, key, f)
+    }
 
-            rocksdb_ffi::rocksdb_delete_file_in_range(self.inner,
-                                        start_key.as_ptr(),
-                                        start_key.len() as size_t,
-                                        end_key.as_ptr(),
-                                        end_key.len() as size_t,
-                                        &mut err);
-            if !err.is_null() {
-                return Err(error_message(err));
-            }
-            Ok(())
-        }
+    pub fn get_with_cf<T, F>(&self,
+                             cf: DBCFHandle,
+                             key: &[u8],
+                             f: F)
+                             -> Result<Option<T>, String>
+        where F: FnOnce(&[u8]) -> T
+    {
+        DEFAULT_READOPTS.with(|o| self.get_with_cf_opt(cf, key, o, f))
     }
 
-    pub fn delete_file_in_range_cf(&self,
-                                   cf: DBCFHandle,
-                                   start_key: &[u8],
-                                   end_key: &[u8])
-                                   -> Result<(), String> {
+    pub fn get_with_cf_opt<T, F>(&self,
+                                 cf: DBCFHandle,
+                                 key: &[u8],
+                                 readopts: &ReadOptions,
+                                 f: F)
+                                 -> Result<Option<T>, String>
+        where F: FnOnce(&[u8]) -> T
+    {
         unsafe {
             let mut err: *const i8 = 0 as *const i8;
-
-            rocksdb_ffi::rocksdb_delete_file_in_range_cf(self.inner,
-                                        cf,
-                                        start_key.as_ptr(),
-                                        start_key.len() as size_t,
-                                        end_key.as_ptr(),
-                                        end_key.len() as size_t,
-                                        &mut err);
+            let slice = rocksdb_ffi::rocksdb_get_pinned_cf(self.inner,
+                                                           readopts.inner,
+                                                           cf,
+                                                           key.as_ptr(),
+                                                           key.len() as size_t,
+                                                           &mut err);
             if !err.is_null() {
                 return Err(error_message(err));
             }
-            Ok(())
+            if slice.0.is_null() {
+                return Ok(None);
+            }
+            // Unpin the value even if `f` panics.
+            let pinned = PinnedSlice(slice);
+            let mut len: size_t = 0;
+            let val = rocksdb_ffi::rocksdb_pinnableslice_value(pinned.0,
+                                                               &mut len);
+            Ok(Some(f(slice::from_raw_parts(val, len as usize))))
         }
     }
 
-    pub fn get_property_value(&self, name: &str) -> Option<String> {
-        self.get_property_value_cf_opt(None, name)
+    /// Copy the value of `key` into `buf` and return whether it was found.
+    ///
+    /// `buf` is cleared first and keeps its capacity, so readers reusing
+    /// buffers don't allocate once they are large enough.
+    pub fn get_into(&self,
+                    key: &[u8],
+                    buf: &mut Vec<u8>)
+                    -> Result<bool, String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.get_into_cf(cf, key, buf)
     }
 
-    pub fn get_property_value_cf(&self,
-                                 cf: DBCFHandle,
-                                 name: &str)
-                                 -> Option<String> {
-        self.get_property_value_cf_opt(Some(cf), name)
+    pub fn get_into_cf(&self,
+                       cf: DBCFHandle,
+                       key: &[u8],
+                       buf: &mut Vec<u8>)
+                       -> Result<bool, String> {
+        DEFAULT_READOPTS.with(|o| self.get_into_cf_opt(cf, key, o, buf))
     }
 
-    /// Return the int property in rocksdb.
-    /// Return None if the property not exists or not int type.
-    pub fn get_property_int(&self, name: &str) -> Option<u64> {
-        self.get_property_int_cf_opt(None, name)
+    pub fn get_into_cf_opt(&self,
+                           cf: DBCFHandle,
+                           key: &[u8],
+                           readopts: &ReadOptions,
+                           buf: &mut Vec<u8>)
+                           -> Result<bool, String> {
+        buf.clear();
+        let found = try!(self.get_with_cf_opt(cf,
+                                              key,
+                                              readopts,
+                                              |v| buf.extend_from_slice(v)));
+        Ok(found.is_some())
     }
 
-    pub fn get_property_int_cf(&self,
-                               cf: DBCFHandle,
-                               name: &str)
-                               -> Option<u64> {
-        self.get_property_int_cf_opt(Some(cf), name)
+    /// Call `f` on every key and value in `range`, in order, until it
+    /// returns false.
+    ///
+    /// The slices passed to `f` point into the iterator and are only valid
+    /// during the call, nothing is copied per record.
+    pub fn scan_with<F>(&self, range: &Range, f: F) -> Result<(), String>
+        where F: FnMut(&[u8], &[u8]) -> bool
+    {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.scan_with_cf(cf, range, f)
     }
 
-    fn get_property_value_cf_opt(&self,
-                                 cf: Option<DBCFHandle>,
-                                 name: &str)
-                                 -> Option<String> {
-        unsafe {
-            let prop_name = CString::new(name).unwrap();
-
-            let value = match cf {
-                None => {
-                    rocksdb_ffi::rocksdb_property_value(self.inner,
-                                                        prop_name.as_ptr() as *const _)
-                }
-                Some(cf) => {
-                    rocksdb_ffi::rocksdb_property_value_cf(self.inner,
-                                                           cf,
-                                                           prop_name.as_ptr() as *const _)
-                }
-            };
-
-            if value.is_null() {
-                return None;
+    pub fn scan_with_cf<F>(&self,
+                           cf: DBCFHandle,
+                           range: &Range,
+                           mut f: F)
+                           -> Result<(), String>
+        where F: FnMut(&[u8], &[u8]) -> bool
+    {
+        let mut opts = ReadOptions::new();
+        opts.set_iterate_upper_bound(range.end_key);
+        let mut iter = DBIterator::new_cf(self, cf, opts);
+        iter.seek(SeekKey::Key(range.start_key));
+        while let Some((key, value)) = iter.entry() {
+            if !f(key, value) {
+                break;
             }
-
-            // Must valid UTF-8 format.
-            let s = CStr::from_ptr(value as *const _).to_str().unwrap().to_owned();
-            libc::free(value as *mut c_void);
-            Some(s)
+            iter.next();
         }
+        iter.status()
     }
 
-    fn get_property_int_cf_opt(&self,
-                               cf: Option<DBCFHandle>,
-                               name: &str)
-                               -> Option<u64> {
-        // Rocksdb guarantees that the return property int
-        // value is u64 if exists.
-        if let Some(value) = self.get_property_value_cf_opt(cf, name) {
-            if let Ok(num) = value.as_str().parse::<u64>() {
-                return Some(num);
-            }
-        }
-
-        None
+    /// Like `multi_get`, for `keys` sorted in the key order of the default
+    /// column family.
+    ///
+    /// Sorted keys let rocksdb look up the keys falling in the same block
+    /// or file together, which is much faster than one get per key. If the
+    /// keys are not sorted, every key gets the same error.
+    pub fn multi_get_sorted(&self,
+                            keys: &[&[u8]])
+                            -> Vec<Result<Option<DBVector>, String>> {
+        DEFAULT_READOPTS.with(|o| self.multi_get_sorted_opt(keys, o))
     }
-}
 
-impl Writable for DB {
-    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
-        self.put_opt(key, value, &WriteOptions::new())
+    pub fn multi_get_sorted_opt(&self,
+                                keys: &[&[u8]],
+                                readopts: &ReadOptions)
+                                -> Vec<Result<Option<DBVector>, String>> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.multi_get_sorted_cf_opt(cf, keys, readopts)
     }
 
-    fn put_cf(&self,
-              cf: DBCFHandle,
-              key: &[u8],
-              value: &[u8])
-              -> Result<(), String> {
-        self.put_cf_opt(cf, key, value, &WriteOptions::new())
+    pub fn multi_get_sorted_cf(&self,
+                               cf: DBCFHandle,
+                               keys: &[&[u8]])
+                               -> Vec<Result<Option<DBVector>, String>> {
+        DEFAULT_READOPTS.with(|o| self.multi_get_sorted_cf_opt(cf, keys, o))
     }
 
-    fn merge(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
-        self.merge_opt(key, value, &WriteOptions::new())
+    pub fn multi_get_sorted_cf_opt(&self,
+                                   cf: DBCFHandle,
+                                   keys: &[&[u8]],
+                                   readopts: &ReadOptions)
+                                   -> Vec<Result<Option<DBVector>, String>> {
+        if let Err(e) = self.check_sorted_cf(cf, keys) {
+            return keys.iter().map(|_| Err(e.clone())).collect();
+        }
+        self.batched_multi_get_cf(cf, keys, readopts, true)
     }
 
-    fn merge_cf(&self,
-                cf: DBCFHandle,
-                key: &[u8],
-                value: &[u8])
-                -> Result<(), String> {
-        self.merge_cf_opt(cf, key, value, &WriteOptions::new())
+    /// Like `multi_get_sorted_cf_opt` for several column families, with
+    /// the sorted keys of each one. Each column family is read in one
+    /// batch, all of them from the same snapshot if `readopts` has one.
+    pub fn multi_get_sorted_grouped(&self,
+                                    groups: &[(DBCFHandle, &[&[u8]])],
+                                    readopts: &ReadOptions)
+                                    -> Vec<Vec<Result<Option<DBVector>,
+                                                      String>>> {
+        groups.iter()
+            .map(|&(cf, keys)| self.multi_get_sorted_cf_opt(cf, keys, readopts))
+            .collect()
     }
 
-    fn delete(&self, key: &[u8]) -> Result<(), String> {
-        self.delete_opt(key, &WriteOptions::new())
+    fn check_sorted_cf(&self,
+                       cf: DBCFHandle,
+                       keys: &[&[u8]])
+                       -> Result<(), String> {
+        let compare = try!(self.compare_fn_cf(cf));
+        for w in keys.windows(2) {
+            if compare(w[0], w[1]) > 0 {
+                return Err(format!("Invalid argument: keys are not sorted, \
+                                    {:?} is before {:?}",
+                                   w[0],
+                                   w[1]));
+            }
+        }
+        Ok(())
     }
 
-    fn delete_cf(&self, cf: DBCFHandle, key: &[u8]) -> Result<(), String> {
-        self.delete_cf_opt(cf, key, &WriteOptions::new())
+    fn batched_multi_get_cf(&self,
+                            cf: DBCFHandle,
+                            keys: &[&[u8]],
+                            readopts: &ReadOptions,
+                            sorted_input: bool)
+                            -> Vec<Result<Option<DBVector>, String>> {
+        with_multi_get_bufs(keys, |bufs| {
+            bufs.slices
+                .resize(keys.len(),
+                        rocksdb_ffi::DBPinnableSlice(0 as *const c_void));
+            let (key_ptrs, key_lens) = (bufs.key_ptrs.as_ptr(),
+                                        bufs.key_lens.as_ptr());
+            let slices = bufs.slices.as_mut_ptr();
+            let errs = bufs.errs.as_mut_ptr();
+            unsafe {
+                rocksdb_ffi::rocksdb_batched_multi_get_cf(self.inner,
+                                                          readopts.inner,
+                                                          cf,
+                                                          keys.len() as size_t,
+                                                          key_ptrs,
+                                                          key_lens,
+                                                          slices,
+                                                          errs,
+                                                          sorted_input);
+            }
+
+            // Copy out every value, so that all the slices are freed even
+            // for the keys that failed.
+            let mut values = Vec::with_capacity(keys.len());
+            for (&slice, &err) in bufs.slices.iter().zip(&bufs.errs) {
+                let mut value = None;
+                if !slice.0.is_null() {
+                    unsafe {
+                        let mut len: size_t = 0;
+                        let val =
+                            rocksdb_ffi::rocksdb_pinnableslice_value(slice,
+                                                                     &mut len);
+                        let v = slice::from_raw_parts(val, len as usize);
+                        value = Some(DBVector::copy_from(v));
+                        rocksdb_ffi::rocksdb_pinnableslice_destroy(slice);
+                    }
+                }
+                if err.is_null() {
+                    values.push(Ok(value));
+                } else {
+                    values.push(Err(error_message(err)));
+                }
+            }
+            values
+        })
     }
-}
 
-impl Default for WriteBatch {
-    fn default() -> WriteBatch {
-        WriteBatch {
-            inner: unsafe { rocksdb_ffi::rocksdb_writebatch_create() },
-        }
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self)
     }
-}
 
-impl WriteBatch {
-    pub fn new() -> WriteBatch {
-        WriteBatch::default()
+    pub unsafe fn unsafe_snap(&self) -> UnsafeSnap {
+        UnsafeSnap { inner: rocksdb_ffi::rocksdb_create_snapshot(self.inner) }
     }
 
-    pub fn count(&self) -> usize {
-        unsafe { rocksdb_ffi::rocksdb_writebatch_count(self.inner) as usize }
+    pub unsafe fn release_snap(&self, snap: &UnsafeSnap) {
+        rocksdb_ffi::rocksdb_release_snapshot(self.inner, snap.inner)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.count() == 0
+    /// Take a snapshot to be kept outside of any borrow of the DB, see
+    /// `SnapshotHandle`.
+    pub fn snapshot_handle(&self) -> SnapshotHandle {
+        self.snapshot_handles.fetch_add(1, Ordering::SeqCst);
+        SnapshotHandle { snap: unsafe { self.unsafe_snap() } }
     }
-}
 
-impl Drop for WriteBatch {
-    fn drop(&mut self) {
-        unsafe { rocksdb_ffi::rocksdb_writebatch_destroy(self.inner) }
+    /// Release a snapshot taken with `snapshot_handle` on this DB.
+    ///
+    /// Unsafe because no read from the snapshot may still be in progress.
+    pub unsafe fn release_snapshot_handle(&self, handle: SnapshotHandle) {
+        self.release_snap(&handle.snap);
+        self.snapshot_handles.fetch_sub(1, Ordering::SeqCst);
     }
-}
 
-impl Drop for DB {
-    fn drop(&mut self) {
-        unsafe {
-            for cf in self.cfs.values() {
-                rocksdb_ffi::rocksdb_column_family_handle_destroy(*cf);
-            }
-            rocksdb_ffi::rocksdb_close(self.inner);
-        }
+    /// Number of `SnapshotHandle`s taken and not released yet.
+    pub fn live_snapshot_handles(&self) -> usize {
+        self.snapshot_handles.load(Ordering::SeqCst)
     }
-}
 
-impl Writable for WriteBatch {
-    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+    pub fn put_opt(&self,
+                   key: &[u8],
+                   value: &[u8],
+                   writeopts: &WriteOptions)
+                   -> Result<(), String> {
         unsafe {
-            rocksdb_ffi::rocksdb_writebatch_put(self.inner,
-                                                key.as_ptr(),
-                                                key.len() as size_t,
-                                                value.as_ptr(),
-                                                value.len() as size_t);
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_put(self.inner,
+                                     writeopts.inner,
+                                     key.as_ptr(),
+                                     key.len() as size_t,
+                                     value.as_ptr(),
+                                     value.len() as size_t,
+                                     err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
             Ok(())
         }
     }
 
-    fn put_cf(&self,
-              cf: DBCFHandle,
-              key: &[u8],
-              value: &[u8])
-              -> Result<(), String> {
+    pub fn put_cf_opt(&self,
+                      cf: DBCFHandle,
+                      key: &[u8],
+                      value: &[u8],
+                      writeopts: &WriteOptions)
+                      -> Result<(), String> {
         unsafe {
-            rocksdb_ffi::rocksdb_writebatch_put_cf(self.inner,
-                                                   cf,
-                                                   key.as_ptr(),
-                                                   key.len() as size_t,
-                                                   value.as_ptr(),
-                                                   value.len() as size_t);
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_put_cf(self.inner,
+                                        writeopts.inner,
+                                        cf,
+                                        key.as_ptr(),
+                                        key.len() as size_t,
+                                        value.as_ptr(),
+                                        value.len() as size_t,
+                                        err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+    pub fn merge_opt(&self,
+                     key: &[u8],
+                     value: &[u8],
+                     writeopts: &WriteOptions)
+                     -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_merge(self.inner,
+                                       writeopts.inner,
+                                       key.as_ptr(),
+                                       key.len() as size_t,
+                                       value.as_ptr(),
+                                       value.len() as size_t,
+                                       err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+    fn merge_cf_opt(&self,
+                    cf: DBCFHandle,
+                    key: &[u8],
+                    value: &[u8],
+                    writeopts: &WriteOptions)
+                    -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_merge_cf(self.inner,
+                                          writeopts.inner,
+                                          cf,
+                                          key.as_ptr(),
+                                          key.len() as size_t,
+                                          value.as_ptr(),
+                                          value.len() as size_t,
+                                          err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
             Ok(())
         }
     }
 
-    fn merge(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+    /// Add `delta` to the counter at `key`, a missing one counting as 0.
+    ///
+    /// This is a merge, which doesn't read the counter: the column family
+    /// must merge with `UInt64AddOperator`.
+    pub fn increment(&self, key: &[u8], delta: u64) -> Result<(), String> {
+        self.merge(key, &encode_u64(delta))
+    }
+
+    pub fn increment_cf(&self,
+                        cf: DBCFHandle,
+                        key: &[u8],
+                        delta: u64)
+                        -> Result<(), String> {
+        self.merge_cf(cf, key, &encode_u64(delta))
+    }
+
+    /// Read the counter at `key`, failing if its value is not one.
+    pub fn get_counter(&self, key: &[u8]) -> Result<Option<u64>, String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.get_counter_cf(cf, key)
+    }
+
+    pub fn get_counter_cf(&self,
+                          cf: DBCFHandle,
+                          key: &[u8])
+                          -> Result<Option<u64>, String> {
+        match try!(self.get_cf(cf, key)) {
+            Some(v) => {
+                decode_u64(&v)
+                    .map(Some)
+                    .ok_or_else(|| format!("Invalid counter value {:?}", &*v))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn delete_opt(&self,
+                  key: &[u8],
+                  writeopts: &WriteOptions)
+                  -> Result<(), String> {
         unsafe {
-            rocksdb_ffi::rocksdb_writebatch_merge(self.inner,
-                                                  key.as_ptr(),
-                                                  key.len() as size_t,
-                                                  value.as_ptr(),
-                                                  value.len() as size_t);
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_delete(self.inner,
+                                        writeopts.inner,
+                                        key.as_ptr(),
+                                        key.len() as size_t,
+                                        err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+    fn delete_cf_opt(&self,
+                     cf: DBCFHandle,
+                     key: &[u8],
+                     writeopts: &WriteOptions)
+                     -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_delete_cf(self.inner,
+                                           writeopts.inner,
+                                           cf,
+                                           key.as_ptr(),
+                                           key.len() as size_t,
+                                           err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    fn single_delete_opt(&self,
+                         key: &[u8],
+                         writeopts: &WriteOptions)
+                         -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_singledelete(self.inner,
+                                              writeopts.inner,
+                                              key.as_ptr(),
+                                              key.len() as size_t,
+                                              err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    fn single_delete_cf_opt(&self,
+                            cf: DBCFHandle,
+                            key: &[u8],
+                            writeopts: &WriteOptions)
+                            -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_singledelete_cf(self.inner,
+                                                 writeopts.inner,
+                                                 cf,
+                                                 key.as_ptr(),
+                                                 key.len() as size_t,
+                                                 err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn delete_range_cf_opt(&self,
+                               cf: DBCFHandle,
+                               begin_key: &[u8],
+                               end_key: &[u8],
+                               writeopts: &WriteOptions)
+                               -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+            let err_ptr: *mut *const i8 = &mut err;
+            rocksdb_ffi::rocksdb_delete_range_cf(self.inner,
+                                                 writeopts.inner,
+                                                 cf,
+                                                 begin_key.as_ptr(),
+                                                 begin_key.len() as size_t,
+                                                 end_key.as_ptr(),
+                                                 end_key.len() as size_t,
+                                                 err_ptr);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn delete_range_opt(&self,
+                            begin_key: &[u8],
+                            end_key: &[u8],
+                            writeopts: &WriteOptions)
+                            -> Result<(), String> {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.delete_range_cf_opt(cf, begin_key, end_key, writeopts)
+    }
+
+    /// Flush the memtables of the default column family, see `flush_cf`
+    /// for the others.
+    ///
+    /// If sync, the flush will wait until the flush is done.
+    pub fn flush(&self, sync: bool) -> Result<(), String> {
+        unsafe {
+            let opts = rocksdb_ffi::rocksdb_flushoptions_create();
+            rocksdb_ffi::rocksdb_flushoptions_set_wait(opts, sync);
+            let mut err = 0 as *const i8;
+            rocksdb_ffi::rocksdb_flush(self.inner, opts, &mut err);
+            rocksdb_ffi::rocksdb_flushoptions_destroy(opts);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    /// Return the sequence number of the last write, 0 for an empty DB.
+    pub fn get_latest_sequence_number(&self) -> u64 {
+        unsafe { rocksdb_ffi::rocksdb_get_latest_sequence_number(self.inner) }
+    }
+
+    /// Iterate over the batches written from sequence number `seq` on,
+    /// read from the WAL, e.g. to replicate them.
+    ///
+    /// The batch holding `seq` comes first even if it starts before. The
+    /// WAL files must be kept for long enough, see `WalReader`.
+    pub fn get_updates_since(&self, seq: u64) -> Result<WalIterator, String> {
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            let iter = rocksdb_ffi::rocksdb_get_updates_since(self.inner,
+                                                              seq,
+                                                              ptr::null(),
+                                                              &mut err);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(WalIterator::from_raw(self, iter))
+        }
+    }
+
+    /// Flush the memtables of column family `cf`, e.g. so its sst files
+    /// hold all its data before backing them up.
+    pub fn flush_cf(&self,
+                    cf: DBCFHandle,
+                    opts: &FlushOptions)
+                    -> Result<(), String> {
+        unsafe {
+            let mut err = 0 as *const i8;
+            rocksdb_ffi::rocksdb_flush_cf(self.inner, opts.inner, cf, &mut err);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
             Ok(())
         }
     }
 
-    fn merge_cf(&self,
-                cf: DBCFHandle,
-                key: &[u8],
-                value: &[u8])
-                -> Result<(), String> {
-        unsafe {
-            rocksdb_ffi::rocksdb_writebatch_merge_cf(self.inner,
-                                                     cf,
-                                                     key.as_ptr(),
-                                                     key.len() as size_t,
-                                                     value.as_ptr(),
-                                                     value.len() as size_t);
-            Ok(())
+    /// Schedule a flush of the memtables and return without waiting for
+    /// it, see `wait_for_flush`.
+    pub fn try_flush(&self) -> Result<(), String> {
+        self.flush(false)
+    }
+
+    /// Wait until no flush is pending or running, for at most `timeout`.
+    ///
+    /// Return whether the flushes finished in time.
+    pub fn wait_for_flush(&self, timeout: Duration) -> Result<bool, String> {
+        self.wait_for_flush_cfopt(None, timeout)
+    }
+
+    pub fn wait_for_flush_cf(&self,
+                             cf: DBCFHandle,
+                             timeout: Duration)
+                             -> Result<bool, String> {
+        self.wait_for_flush_cfopt(Some(cf), timeout)
+    }
+
+    fn wait_for_flush_cfopt(&self,
+                            cf: Option<DBCFHandle>,
+                            timeout: Duration)
+                            -> Result<bool, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut busy = false;
+            for name in &["rocksdb.mem-table-flush-pending",
+                          "rocksdb.num-immutable-mem-table",
+                          "rocksdb.num-running-flushes"] {
+                match self.get_property_int_cf_opt(cf, name) {
+                    Ok(0) => {}
+                    Ok(_) => busy = true,
+                    Err(e) => return Err(e.to_string()),
+                }
+            }
+            if !busy {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(cmp::min(deadline - now, Duration::from_millis(10)));
+        }
+    }
+
+    /// Return the approximate file system space used by keys in each ranges.
+    ///
+    /// Note that the returned sizes measure file system space usage, so
+    /// if the user data compresses by a factor of ten, the returned
+    /// sizes will be one-tenth the size of the corresponding user data size.
+    ///
+    /// Due to lack of abi, only data flushed to disk is taken into account.
+    ///
+    /// Fails if a range is reversed in the order of the column family,
+    /// rocksdb would silently count it as 0.
+    pub fn get_approximate_sizes<'r, 'k: 'r, I>(&self,
+                                                ranges: I)
+                                                -> Result<Vec<u64>, String>
+        where I: IntoIterator<Item = &'r Range<'k>>
+    {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.get_approximate_sizes_cfopt(cf, None, ranges)
+    }
+
+    pub fn get_approximate_sizes_cf<'r, 'k: 'r, I>(&self,
+                                                   cf: DBCFHandle,
+                                                   ranges: I)
+                                                   -> Result<Vec<u64>, String>
+        where I: IntoIterator<Item = &'r Range<'k>>
+    {
+        self.get_approximate_sizes_cfopt(cf, Some(cf), ranges)
+    }
+
+    /// Like `get_approximate_sizes_cf`, with the column family given by
+    /// name.
+    pub fn get_approximate_sizes_named_cf<'r, 'k: 'r, I>
+        (&self,
+         cf_name: &str,
+         ranges: I)
+         -> Result<Vec<u64>, String>
+        where I: IntoIterator<Item = &'r Range<'k>>
+    {
+        let cf = try!(self.cf_handle_or_err(cf_name));
+        self.get_approximate_sizes_cf(cf, ranges)
+    }
+
+    fn get_approximate_sizes_cfopt<'r, 'k: 'r, I>(&self,
+                                                  check_cf: DBCFHandle,
+                                                  cf: Option<DBCFHandle>,
+                                                  ranges: I)
+                                                  -> Result<Vec<u64>, String>
+        where I: IntoIterator<Item = &'r Range<'k>>
+    {
+        let ranges: Vec<&Range> = ranges.into_iter().collect();
+        for range in &ranges {
+            try!(self.check_range_cf(check_cf, range));
+        }
+        if ranges.is_empty() {
+            return Ok(vec![]);
+        }
+        let start_keys: Vec<*const u8> = ranges.iter()
+            .map(|x| x.start_key.as_ptr())
+            .collect();
+        let start_key_lens: Vec<u64> = ranges.iter()
+            .map(|x| x.start_key.len() as u64)
+            .collect();
+        let end_keys: Vec<*const u8> = ranges.iter()
+            .map(|x| x.end_key.as_ptr())
+            .collect();
+        let end_key_lens: Vec<u64> = ranges.iter()
+            .map(|x| x.end_key.len() as u64)
+            .collect();
+        let mut sizes: Vec<u64> = vec![0; ranges.len()];
+        let (n,
+             start_key_ptr,
+             start_key_len_ptr,
+             end_key_ptr,
+             end_key_len_ptr,
+             size_ptr) = (ranges.len() as i32,
+                          start_keys.as_ptr(),
+                          start_key_lens.as_ptr(),
+                          end_keys.as_ptr(),
+                          end_key_lens.as_ptr(),
+                          sizes.as_mut_ptr());
+        let mut err: *const i8 = 0 as *const i8;
+        match cf {
+            None => unsafe {
+                rocksdb_ffi::rocksdb_approximate_sizes(self.inner,
+                                                       n,
+                                                       start_key_ptr,
+                                                       start_key_len_ptr as *const _,
+                                                       end_key_ptr,
+                                                       end_key_len_ptr as *const _,
+                                                       size_ptr,
+                                                       &mut err)
+            },
+            Some(cf) => unsafe {
+                rocksdb_ffi::rocksdb_approximate_sizes_cf(self.inner,
+                                                          cf,
+                                                          n,
+                                                          start_key_ptr,
+                                                          start_key_len_ptr as *const _,
+                                                          end_key_ptr,
+                                                          end_key_len_ptr as *const _,
+                                                          size_ptr,
+                                                          &mut err)
+            },
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(sizes)
+    }
+
+    /// Estimate the logical size of the keys in `range` by seeking to
+    /// `samples` keys spread evenly over it.
+    ///
+    /// `get_approximate_sizes` measures compressed bytes on disk, this
+    /// measures the keys and values themselves. The key count is derived
+    /// from rocksdb's estimate for the whole DB, so it is only accurate
+    /// when keys are spread evenly; ranges that are not flushed yet are
+    /// counted exactly. With a custom comparator the keys can't be
+    /// interpolated, so the first `samples` keys of the range are sampled.
+    pub fn estimate_range_size(&self,
+                               range: &Range,
+                               samples: usize)
+                               -> Result<RangeSizeEstimate, String> {
+        self.estimate_range_size_cfopt(None, range, samples)
+    }
+
+    pub fn estimate_range_size_cf(&self,
+                                  cf: DBCFHandle,
+                                  range: &Range,
+                                  samples: usize)
+                                  -> Result<RangeSizeEstimate, String> {
+        self.estimate_range_size_cfopt(Some(cf), range, samples)
+    }
+
+    fn estimate_range_size_cfopt(&self,
+                                 cf: Option<DBCFHandle>,
+                                 range: &Range,
+                                 samples: usize)
+                                 -> Result<RangeSizeEstimate, String> {
+        let handle = cf.unwrap_or(self.cfs[DEFAULT_COLUMN_FAMILY]);
+        try!(self.check_range_cf(handle, range));
+        // Interpolated keys are only spread over the range in bytewise
+        // order, other orders sample the first keys of the range instead.
+        let bytewise = try!(self.compare_fn_cf(handle)) ==
+                       bytewise_compare as fn(&[u8], &[u8]) -> i32;
+        let mut opts = ReadOptions::new();
+        opts.fill_cache(false);
+        opts.set_iterate_upper_bound(range.end_key);
+        let mut iter = match cf {
+            None => DBIterator::new(self, opts),
+            Some(cf) => DBIterator::new_cf(self, cf, opts),
+        };
+
+        let mut key_sizes = 0;
+        let mut value_sizes = vec![];
+        let mut last_key: Option<Vec<u8>> = None;
+        for i in 0..samples {
+            if bytewise {
+                let target =
+                    interpolate_key(range.start_key, range.end_key, i, samples);
+                iter.seek(SeekKey::Key(&target));
+            } else if i == 0 {
+                iter.seek(SeekKey::Key(range.start_key));
+            } else {
+                iter.next();
+            }
+            let (key, value) = match iter.entry() {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // Sparse ranges make several targets land on the same key.
+            if last_key.as_ref().map_or(false, |k| &k[..] == key) {
+                continue;
+            }
+            key_sizes += key.len();
+            value_sizes.push(value.len());
+            last_key = Some(key.to_vec());
+        }
+        try!(iter.status());
+        value_sizes.sort();
+
+        let (avg_key_size, avg_value_size) = if value_sizes.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let n = value_sizes.len() as f64;
+            (key_sizes as f64 / n,
+             value_sizes.iter().fold(0, |a, b| a + b) as f64 / n)
+        };
+
+        let (total_keys, total_size, range_size) = match cf {
+            None => {
+                (self.get_property_int("rocksdb.estimate-num-keys").ok(),
+                 self.get_property_int("rocksdb.total-sst-files-size").ok(),
+                 try!(self.get_approximate_sizes(&[Range::new(range.start_key,
+                                                              range.end_key)]))[0])
+            }
+            Some(cf) => {
+                (self.get_property_int_cf(cf, "rocksdb.estimate-num-keys").ok(),
+                 self.get_property_int_cf(cf, "rocksdb.total-sst-files-size")
+                     .ok(),
+                 try!(self.get_approximate_sizes_cf(cf,
+                                                    &[Range::new(range.start_key,
+                                                                 range.end_key)]))[0])
+            }
+        };
+        let estimated_num_keys = match (total_keys, total_size) {
+            (Some(keys), Some(size)) if size > 0 && range_size > 0 => {
+                (keys as f64 * range_size as f64 / size as f64) as u64
+            }
+            // Nothing on disk for the range, it can only be in the
+            // memtables, which are small enough to count.
+            _ => {
+                let count = match cf {
+                    None => {
+                        self.count_range(range.start_key,
+                                         range.end_key,
+                                         usize::max_value())
+                    }
+                    Some(cf) => {
+                        self.count_range_cf(cf,
+                                            range.start_key,
+                                            range.end_key,
+                                            usize::max_value())
+                    }
+                };
+                try!(count) as u64
+            }
+        };
+
+        Ok(RangeSizeEstimate {
+            value_sizes: value_sizes,
+            avg_key_size: avg_key_size,
+            avg_value_size: avg_value_size,
+            estimated_num_keys: estimated_num_keys,
+            estimated_logical_size: (estimated_num_keys as f64 *
+                                     (avg_key_size + avg_value_size)) as
+                                    u64,
+        })
+    }
+
+    pub fn delete_file_in_range(&self,
+                                start_key: &[u8],
+                                end_key: &[u8])
+                                -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+
+            rocksdb_ffi::rocksdb_delete_file_in_range(self.inner,
+                                        start_key.as_ptr(),
+                                        start_key.len() as size_t,
+                                        end_key.as_ptr(),
+                                        end_key.len() as size_t,
+                                        &mut err);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    pub fn delete_file_in_range_cf(&self,
+                                   cf: DBCFHandle,
+                                   start_key: &[u8],
+                                   end_key: &[u8])
+                                   -> Result<(), String> {
+        unsafe {
+            let mut err: *const i8 = 0 as *const i8;
+
+            rocksdb_ffi::rocksdb_delete_file_in_range_cf(self.inner,
+                                        cf,
+                                        start_key.as_ptr(),
+                                        start_key.len() as size_t,
+                                        end_key.as_ptr(),
+                                        end_key.len() as size_t,
+                                        &mut err);
+            if !err.is_null() {
+                return Err(error_message(err));
+            }
+            Ok(())
+        }
+    }
+
+    /// Compact the keys in [`start_key`, `end_key`] of the default column
+    /// family down to the last level, `None` bounds meaning from the first
+    /// key or up to the last one.
+    ///
+    /// Drops the deleted and overwritten versions of the keys, e.g. to
+    /// reclaim the space left by a bulk delete. Blocks until done.
+    pub fn compact_range(&self,
+                         start_key: Option<&[u8]>,
+                         end_key: Option<&[u8]>) {
+        let cf = self.cfs[DEFAULT_COLUMN_FAMILY];
+        self.compact_range_cf(cf, start_key, end_key)
+    }
+
+    pub fn compact_range_cf(&self,
+                            cf: DBCFHandle,
+                            start_key: Option<&[u8]>,
+                            end_key: Option<&[u8]>) {
+        let (start, start_len) = match start_key {
+            Some(k) => (k.as_ptr(), k.len()),
+            None => (0 as *const u8, 0),
+        };
+        let (end, end_len) = match end_key {
+            Some(k) => (k.as_ptr(), k.len()),
+            None => (0 as *const u8, 0),
+        };
+        unsafe {
+            rocksdb_ffi::rocksdb_compact_range_cf(self.inner,
+                                                  cf,
+                                                  start,
+                                                  start_len as size_t,
+                                                  end,
+                                                  end_len as size_t);
+        }
+    }
+
+    /// Like `compact_range_cf`, but fails with an `Incomplete` status if
+    /// the compaction may have been aborted by `cancel_manual_compactions`.
+    ///
+    /// Rocksdb doesn't tell whether it was, so a compaction that overlaps
+    /// a cancel is reported as aborted even if it completed.
+    pub fn try_compact_range_cf(&self,
+                                cf: DBCFHandle,
+                                start_key: Option<&[u8]>,
+                                end_key: Option<&[u8]>)
+                                -> Result<(), String> {
+        let before = self.compaction_cancels.load(Ordering::SeqCst);
+        self.compact_range_cf(cf, start_key, end_key);
+        let after = self.compaction_cancels.load(Ordering::SeqCst);
+        if before % 2 == 1 || before != after {
+            return Err("Incomplete: Manual compaction paused".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Abort all the manual compactions running in the DB, of any column
+    /// family, and return once they are. The following ones run normally.
+    pub fn cancel_manual_compactions(&self) {
+        self.compaction_cancels.fetch_add(1, Ordering::SeqCst);
+        unsafe {
+            rocksdb_ffi::rocksdb_disable_manual_compaction(self.inner);
+            rocksdb_ffi::rocksdb_enable_manual_compaction(self.inner);
+        }
+        self.compaction_cancels.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Change the mutable options of column family `cf` while the DB is
+    /// open, given as `(name, value)` pairs in the format of an options
+    /// file, e.g. `("disable_auto_compactions", "true")`.
+    pub fn set_options_cf(&self,
+                          cf: DBCFHandle,
+                          opts: &[(&str, &str)])
+                          -> Result<(), String> {
+        let mut keys = Vec::with_capacity(opts.len());
+        let mut values = Vec::with_capacity(opts.len());
+        for &(k, v) in opts {
+            match (CString::new(k), CString::new(v)) {
+                (Ok(k), Ok(v)) => {
+                    keys.push(k);
+                    values.push(v);
+                }
+                _ => return Err(format!("Invalid option {}: {}", k, v)),
+            }
+        }
+        let key_ptrs: Vec<_> = keys.iter().map(|k| k.as_ptr()).collect();
+        let value_ptrs: Vec<_> = values.iter().map(|v| v.as_ptr()).collect();
+        let mut err = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_set_options_cf(self.inner,
+                                                cf,
+                                                opts.len() as c_int,
+                                                key_ptrs.as_ptr(),
+                                                value_ptrs.as_ptr(),
+                                                &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Stop the automatic compactions of column families `cfs`, e.g. while
+    /// bulk loading them, until `enable_auto_compactions` is called.
+    ///
+    /// Compactions already running are not stopped.
+    pub fn disable_auto_compactions(&self,
+                                    cfs: &[DBCFHandle])
+                                    -> Result<(), String> {
+        for &cf in cfs {
+            try!(self.set_options_cf(cf, &[("disable_auto_compactions",
+                                             "true")]));
+        }
+        Ok(())
+    }
+
+    /// Resume the automatic compactions of column families `cfs`.
+    ///
+    /// The compactions that became due in the meantime are scheduled
+    /// right away rather than on the next flush.
+    pub fn enable_auto_compactions(&self,
+                                   cfs: &[DBCFHandle])
+                                   -> Result<(), String> {
+        // Changing the options installs a new super version, which makes
+        // RocksDB schedule the pending compactions.
+        for &cf in cfs {
+            try!(self.set_options_cf(cf, &[("disable_auto_compactions",
+                                             "false")]));
+        }
+        Ok(())
+    }
+
+    /// Ingest the sst files at `files`, made with an `SstFileWriter`.
+    pub fn ingest_external_file(&self,
+                                opts: &IngestExternalFileOptions,
+                                files: &[&str])
+                                -> Result<(), String> {
+        self.ingest_external_file_cfopt(None, opts, files)
+    }
+
+    pub fn ingest_external_file_cf(&self,
+                                   cf: DBCFHandle,
+                                   opts: &IngestExternalFileOptions,
+                                   files: &[&str])
+                                   -> Result<(), String> {
+        self.ingest_external_file_cfopt(Some(cf), opts, files)
+    }
+
+    fn ingest_external_file_cfopt(&self,
+                                  cf: Option<DBCFHandle>,
+                                  opts: &IngestExternalFileOptions,
+                                  files: &[&str])
+                                  -> Result<(), String> {
+        let mut c_files = Vec::with_capacity(files.len());
+        for f in files {
+            match CString::new(f.as_bytes()) {
+                Ok(c) => c_files.push(c),
+                Err(_) => {
+                    return Err(format!("Failed to convert path to CString \
+                                        when ingesting {}",
+                                       f))
+                }
+            }
+        }
+        let c_ptrs: Vec<*const libc::c_char> =
+            c_files.iter().map(|c| c.as_ptr()).collect();
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            match cf {
+                Some(cf) => {
+                    rocksdb_ffi::rocksdb_ingest_external_file_cf(
+                        self.inner,
+                        cf,
+                        c_ptrs.as_ptr(),
+                        c_ptrs.len() as size_t,
+                        opts.inner,
+                        &mut err)
+                }
+                None => {
+                    rocksdb_ffi::rocksdb_ingest_external_file(
+                        self.inner,
+                        c_ptrs.as_ptr(),
+                        c_ptrs.len() as size_t,
+                        opts.inner,
+                        &mut err)
+                }
+            }
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Return the disk space used by the files of the DB, per directory.
+    ///
+    /// Only the sst files rocksdb still needs are counted, obsolete ones
+    /// waiting to be deleted are not. WAL and blob files are counted from
+    /// the listings of the WAL directory and the db paths.
+    pub fn get_disk_usage(&self) -> Result<DiskUsage, String> {
+        let mut paths = self.db_paths.clone();
+        if paths.is_empty() {
+            paths.push(self.path.clone());
+        }
+        let mut usage = DiskUsage::default();
+        for p in &paths {
+            usage.sst_files_size.insert(p.clone(), 0);
+        }
+
+        unsafe {
+            let files = rocksdb_ffi::rocksdb_livefiles(self.inner);
+            for i in 0..rocksdb_ffi::rocksdb_livefiles_count(files) {
+                let name = CStr::from_ptr(rocksdb_ffi::rocksdb_livefiles_name(files, i))
+                    .to_string_lossy()
+                    .into_owned();
+                let size = rocksdb_ffi::rocksdb_livefiles_size(files, i) as u64;
+                // Names are relative to the path the file was placed in,
+                // which is not reported, so look for it.
+                let name = name.trim_start_matches('/');
+                let p = paths.iter()
+                    .find(|p| Path::new(p).join(name).exists())
+                    .unwrap_or(&paths[0]);
+                *usage.sst_files_size.get_mut(p).unwrap() += size;
+            }
+            rocksdb_ffi::rocksdb_livefiles_destroy(files);
+        }
+
+        let wal_size = try!(files_size(&self.wal_dir, "log"));
+        usage.wal_files_size.insert(self.wal_dir.clone(), wal_size);
+        for p in &paths {
+            let blob_size = try!(files_size(p, "blob"));
+            usage.blob_files_size.insert(p.clone(), blob_size);
+        }
+        Ok(usage)
+    }
+
+    /// List the live sst files of the DB.
+    pub fn live_files(&self) -> Vec<LiveFile> {
+        let mut live = vec![];
+        unsafe {
+            let files = rocksdb_ffi::rocksdb_livefiles(self.inner);
+            for i in 0..rocksdb_ffi::rocksdb_livefiles_count(files) {
+                let name = rocksdb_ffi::rocksdb_livefiles_name(files, i);
+                let cf =
+                    rocksdb_ffi::rocksdb_livefiles_column_family_name(files, i);
+                let mut len = 0;
+                let key = rocksdb_ffi::rocksdb_livefiles_smallestkey(files,
+                                                                     i,
+                                                                     &mut len);
+                let smallest =
+                    slice::from_raw_parts(key, len as usize).to_vec();
+                let key = rocksdb_ffi::rocksdb_livefiles_largestkey(files,
+                                                                    i,
+                                                                    &mut len);
+                let largest =
+                    slice::from_raw_parts(key, len as usize).to_vec();
+                live.push(LiveFile {
+                    name: CStr::from_ptr(name).to_string_lossy().into_owned(),
+                    cf: CStr::from_ptr(cf).to_string_lossy().into_owned(),
+                    level: rocksdb_ffi::rocksdb_livefiles_level(files, i),
+                    size: rocksdb_ffi::rocksdb_livefiles_size(files, i) as u64,
+                    smallest_key: smallest,
+                    largest_key: largest,
+                    entries: rocksdb_ffi::rocksdb_livefiles_entries(files, i),
+                    deletions: rocksdb_ffi::rocksdb_livefiles_deletions(files,
+                                                                        i),
+                });
+            }
+            rocksdb_ffi::rocksdb_livefiles_destroy(files);
+        }
+        live
+    }
+
+    /// List the live sst files of `cf`.
+    pub fn live_files_cf(&self,
+                         cf: DBCFHandle)
+                         -> Result<Vec<LiveFile>, String> {
+        let name = match self.cfs.iter().find(|&(_, h)| h.0 == cf.0) {
+            Some((name, _)) => name.clone(),
+            None => return Err("Invalid column family handle".to_owned()),
+        };
+        Ok(self.live_files().into_iter().filter(|f| f.cf == name).collect())
+    }
+
+    /// Count the range tombstones in the sst files of `cf` and where they
+    /// are, to spot the ranges where deleted keys still slow down scans.
+    ///
+    /// Range tombstones still in the memtables are not counted. Rocksdb
+    /// only counts them by level, so a span is the key range of the files
+    /// of the levels holding tombstones: compacting it drops them, along
+    /// with files that may have none.
+    pub fn range_tombstones(&self,
+                            cf: DBCFHandle)
+                            -> Result<RangeTombstones, String> {
+        let compare = try!(self.compare_fn_cf(cf));
+        let files = try!(self.live_files_cf(cf));
+        let num_levels = files.iter().map(|f| f.level + 1).max().unwrap_or(0);
+
+        let mut tombstones = RangeTombstones::default();
+        for level in 0..num_levels {
+            // E.g. "# entries=2; # range deletions=1; ...".
+            let name = format!("rocksdb.aggregated-table-properties-at-level{}",
+                               level);
+            let props = try!(self.get_property_value_cf(cf, &name));
+            let count = table_property(&props, "# range deletions");
+            if count > 0 {
+                tombstones.count += count;
+                tombstones.levels.push((level, count));
+            }
+        }
+
+        let mut files: Vec<LiveFile> = files.into_iter()
+            .filter(|f| tombstones.levels.iter().any(|&(l, _)| l == f.level))
+            .collect();
+        files.sort_by(|a, b| {
+            compare(&a.smallest_key, &b.smallest_key).cmp(&0)
+        });
+        for f in files {
+            if let Some(last) = tombstones.spans.last_mut() {
+                if compare(&f.smallest_key, &last.end) <= 0 {
+                    if compare(&f.largest_key, &last.end) > 0 {
+                        last.end = f.largest_key;
+                    }
+                    continue;
+                }
+            }
+            tombstones.spans.push(TombstoneSpan {
+                start: f.smallest_key,
+                end: f.largest_key,
+            });
+        }
+        Ok(tombstones)
+    }
+
+    /// Report the size of each level of `cf` against its target, computed
+    /// from `opts`, the options `cf` was opened with.
+    pub fn level_sizes_cf(&self,
+                          cf: DBCFHandle,
+                          opts: &Options)
+                          -> Result<LevelSizes, String> {
+        let num_levels = cmp::max(opts.get_num_levels(), 1) as usize;
+        let mut levels: Vec<LevelSize> = (0..num_levels)
+            .map(|l| LevelSize { level: l as i32, ..LevelSize::default() })
+            .collect();
+        for f in try!(self.live_files_cf(cf)) {
+            if let Some(level) = levels.get_mut(f.level as usize) {
+                level.files += 1;
+                level.size += f.size;
+            }
+        }
+
+        let base = opts.get_max_bytes_for_level_base();
+        let mul = opts.get_max_bytes_for_level_multiplier().max(1.0);
+        if opts.get_level_compaction_dynamic_level_bytes() {
+            // Like rocksdb, size the levels up from the last one and leave
+            // the ones above the first target under base empty.
+            let mut target = cmp::max(levels[num_levels - 1].size, base) as f64;
+            for level in levels.iter_mut().skip(1).rev() {
+                level.target_size = Some(target as u64);
+                if target <= base as f64 {
+                    break;
+                }
+                target /= mul;
+            }
+        } else {
+            let mut target = base as f64;
+            for level in levels.iter_mut().skip(1) {
+                level.target_size = Some(target as u64);
+                target *= mul;
+            }
+        }
+
+        let trigger = opts.get_level_zero_file_num_compaction_trigger();
+        for level in &mut levels {
+            level.fill_ratio = match level.target_size {
+                Some(target) if target > 0 => {
+                    level.size as f64 / target as f64
+                }
+                None if level.level == 0 && trigger > 0 => {
+                    level.files as f64 / trigger as f64
+                }
+                _ => 0.0,
+            };
+        }
+        let pending = self.get_property_int_cf(cf,
+                                 "rocksdb.estimate-pending-compaction-bytes");
+        Ok(LevelSizes {
+            levels: levels,
+            pending_compaction_bytes: pending.unwrap_or(0),
+        })
+    }
+
+    /// Estimate how much more space than its live data `cf` takes, level
+    /// by level, and suggest ranges to compact by hand to reclaim it.
+    ///
+    /// Ranges of files made mostly of deletions are suggested first, as
+    /// compacting them drops the deletions and the data they shadow. The
+    /// whole column family is suggested when it takes more than twice the
+    /// size of its live data.
+    pub fn space_amp_report(&self,
+                            cf: DBCFHandle)
+                            -> Result<SpaceAmpReport, String> {
+        let compare = try!(self.compare_fn_cf(cf));
+        let files = try!(self.live_files_cf(cf));
+
+        let mut report = SpaceAmpReport::default();
+        let mut levels: BTreeMap<i32, LevelSpace> = BTreeMap::new();
+        for f in &files {
+            let level = levels.entry(f.level).or_insert_with(|| {
+                LevelSpace { level: f.level, ..LevelSpace::default() }
+            });
+            level.files += 1;
+            level.size += f.size;
+            level.entries += f.entries;
+            level.deletions += f.deletions;
+            report.sst_files_size += f.size;
+        }
+        let last_size = levels.values().last().map_or(0, |l| l.size);
+        for level in levels.values_mut() {
+            if last_size > 0 {
+                level.size_amp = level.size as f64 / last_size as f64;
+            }
+        }
+        report.levels = levels.into_iter().map(|(_, l)| l).collect();
+        let live_data_size =
+            self.get_property_int_cf(cf, "rocksdb.estimate-live-data-size");
+        report.live_data_size = match live_data_size {
+            Ok(size) if size > 0 => size,
+            _ => last_size,
+        };
+        report.space_amp = if report.live_data_size > 0 {
+            report.sst_files_size as f64 / report.live_data_size as f64
+        } else {
+            1.0
+        };
+
+        let mut candidates: Vec<&LiveFile> = files.iter()
+            .filter(|f| {
+                f.entries > 0 &&
+                f.deletions as f64 >= f.entries as f64 * HINT_DELETION_RATIO
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            compare(&a.smallest_key, &b.smallest_key).cmp(&0)
+        });
+        let mut hints: Vec<CompactionHint> = vec![];
+        for f in candidates {
+            let reclaimable = f.size * f.deletions / f.entries;
+            if let Some(last) = hints.last_mut() {
+                if compare(&f.smallest_key, &last.end) <= 0 {
+                    if compare(&f.largest_key, &last.end) > 0 {
+                        last.end = f.largest_key.clone();
+                    }
+                    last.reclaimable += reclaimable;
+                    continue;
+                }
+            }
+            hints.push(CompactionHint {
+                start: f.smallest_key.clone(),
+                end: f.largest_key.clone(),
+                reclaimable: reclaimable,
+            });
+        }
+        if report.space_amp > HINT_SPACE_AMP {
+            let start = files.iter()
+                .map(|f| &f.smallest_key)
+                .min_by(|a, b| compare(a, b).cmp(&0));
+            let end = files.iter()
+                .map(|f| &f.largest_key)
+                .max_by(|a, b| compare(a, b).cmp(&0));
+            if let (Some(start), Some(end)) = (start, end) {
+                hints.push(CompactionHint {
+                    start: start.clone(),
+                    end: end.clone(),
+                    reclaimable: report.sst_files_size - report.live_data_size,
+                });
+            }
+        }
+        hints.sort_by(|a, b| b.reclaimable.cmp(&a.reclaimable));
+        report.hints = hints;
+        Ok(report)
+    }
+
+    /// Start sampling the metrics of `config` in a background thread,
+    /// replacing the sampler already running.
+    ///
+    /// The last samples are kept in memory, to see how the DB behaved in
+    /// the minutes before an incident without an external scraper.
+    pub fn start_metrics_sampler(&self, config: MetricsConfig) {
+        let sampler = unsafe { MetricsSampler::start(self.inner, config) };
+        let old = mem::replace(&mut *self.metrics.lock().unwrap(),
+                               Some(sampler));
+        // Stopped once the lock is released, joining its thread can block.
+        drop(old);
+    }
+
+    /// Stop the metrics sampler and discard its samples.
+    pub fn stop_metrics_sampler(&self) {
+        let old = self.metrics.lock().unwrap().take();
+        // Stopped once the lock is released, joining its thread can block.
+        drop(old);
+    }
+
+    /// The samples taken by the metrics sampler, oldest first, empty if it
+    /// isn't running.
+    pub fn metrics_history(&self) -> Vec<MetricsSample> {
+        self.metrics
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(vec![], |m| m.history())
+    }
+
+    /// Collect the properties in `HealthSnapshot` for the default column
+    /// family.
+    pub fn get_health_snapshot(&self) -> HealthSnapshot {
+        self.get_health_snapshot_cfopt(None)
+    }
+
+    pub fn get_health_snapshot_cf(&self, cf: DBCFHandle) -> HealthSnapshot {
+        self.get_health_snapshot_cfopt(Some(cf))
+    }
+
+    fn get_health_snapshot_cfopt(&self,
+                                 cf: Option<DBCFHandle>)
+                                 -> HealthSnapshot {
+        let prop = |name| self.get_property_int_cf_opt(cf, name).ok();
+        HealthSnapshot {
+            num_files_at_level0: prop("rocksdb.num-files-at-level0"),
+            num_immutable_memtables: prop("rocksdb.num-immutable-mem-table"),
+            cur_size_all_memtables: prop("rocksdb.cur-size-all-mem-tables"),
+            size_all_memtables: prop("rocksdb.size-all-mem-tables"),
+            estimate_pending_compaction_bytes:
+                prop("rocksdb.estimate-pending-compaction-bytes"),
+            num_running_compactions: prop("rocksdb.num-running-compactions"),
+            num_running_flushes: prop("rocksdb.num-running-flushes"),
+            actual_delayed_write_rate: prop("rocksdb.actual-delayed-write-rate"),
+            is_write_stopped: prop("rocksdb.is-write-stopped").map(|v| v != 0),
+            estimate_num_keys: prop("rocksdb.estimate-num-keys"),
+        }
+    }
+
+    /// Count the running and pending flushes and compactions of the DB.
+    ///
+    /// rocksdb only reports whether a column family has flushes or a
+    /// compaction pending, so the pending counts add up the immutable
+    /// memtables and the column families waiting. The queue lengths of the
+    /// env thread pools, shared by every DB, are not reachable through the
+    /// C API.
+    pub fn get_background_jobs(&self) -> Result<BackgroundJobs, Error> {
+        let mut jobs = BackgroundJobs {
+            running_flushes:
+                try!(self.get_property_int("rocksdb.num-running-flushes")),
+            running_compactions:
+                try!(self.get_property_int("rocksdb.num-running-compactions")),
+            pending_flushes: 0,
+            pending_compactions: 0,
+        };
+        for &cf in self.cfs.values() {
+            let prop = |name| self.get_property_int_cf(cf, name);
+            if try!(prop("rocksdb.mem-table-flush-pending")) != 0 {
+                jobs.pending_flushes +=
+                    try!(prop("rocksdb.num-immutable-mem-table"));
+            }
+            jobs.pending_compactions +=
+                try!(prop("rocksdb.compaction-pending"));
+        }
+        Ok(jobs)
+    }
+
+    /// Report how much memory and disk the entries of `cf` take on
+    /// average, to tune the memtables and sst files of many tiny entries.
+    pub fn entry_overhead_cf(&self,
+                             cf: DBCFHandle)
+                             -> Result<EntryOverheadReport, Error> {
+        let prop = |name| self.get_property_int_cf(cf, name);
+        let active = try!(prop("rocksdb.num-entries-active-mem-table"));
+        let immutable = try!(prop("rocksdb.num-entries-imm-mem-tables"));
+        let mut report = EntryOverheadReport {
+            memtable_entries: active + immutable,
+            memtable_bytes: try!(prop("rocksdb.size-all-mem-tables")),
+            sst_file_bytes: try!(prop("rocksdb.live-sst-files-size")),
+            ..EntryOverheadReport::default()
+        };
+        // E.g. "# entries=2; raw key size=20; raw value size=4; ...".
+        let name = "rocksdb.aggregated-table-properties";
+        let props = try!(self.get_property_value_cf(cf, name));
+        report.sst_entries = table_property(&props, "# entries");
+        report.raw_key_bytes = table_property(&props, "raw key size");
+        report.raw_value_bytes = table_property(&props, "raw value size");
+        Ok(report)
+    }
+
+    pub fn get_property_value(&self,
+                              name: &str)
+                              -> Result<String, Error> {
+        self.get_property_value_cf_opt(None, name)
+    }
+
+    pub fn get_property_value_cf(&self,
+                                 cf: DBCFHandle,
+                                 name: &str)
+                                 -> Result<String, Error> {
+        self.get_property_value_cf_opt(Some(cf), name)
+    }
+
+    /// Return the int property in rocksdb.
+    pub fn get_property_int(&self, name: &str) -> Result<u64, Error> {
+        self.get_property_int_cf_opt(None, name)
+    }
+
+    pub fn get_property_int_cf(&self,
+                               cf: DBCFHandle,
+                               name: &str)
+                               -> Result<u64, Error> {
+        self.get_property_int_cf_opt(Some(cf), name)
+    }
+
+    /// Return the bytes held by the block cache of the default column
+    /// family.
+    ///
+    /// Column families sharing a `Cache` all report its whole usage, so
+    /// count it once per cache when adding them up.
+    pub fn get_block_cache_usage(&self) -> Result<u64, Error> {
+        self.get_property_int("rocksdb.block-cache-usage")
+    }
+
+    pub fn get_block_cache_usage_cf(&self,
+                                    cf: DBCFHandle)
+                                    -> Result<u64, Error> {
+        self.get_property_int_cf(cf, "rocksdb.block-cache-usage")
+    }
+
+    /// Return the bytes of the block cache of the default column family
+    /// held by entries in use, which can't be evicted.
+    pub fn get_pinned_usage(&self) -> Result<u64, Error> {
+        self.get_property_int("rocksdb.block-cache-pinned-usage")
+    }
+
+    pub fn get_pinned_usage_cf(&self,
+                               cf: DBCFHandle)
+                               -> Result<u64, Error> {
+        self.get_property_int_cf(cf, "rocksdb.block-cache-pinned-usage")
+    }
+
+    fn get_property_value_cf_opt(&self,
+                                 cf: Option<DBCFHandle>,
+                                 name: &str)
+                                 -> Result<String, Error> {
+        if let Some(cf) = cf {
+            if !self.cfs.values().any(|h| h.0 == cf.0) {
+                return Err(Error::InvalidColumnFamily(None));
+            }
+        }
+        let prop_name = match CString::new(name) {
+            Ok(n) => n,
+            Err(_) => {
+                return Err(Error::InvalidArgument(format!("Invalid argument: \
+                                                           property name \
+                                                           {:?}",
+                                                          name)))
+            }
+        };
+        unsafe {
+            let value = match cf {
+                None => {
+                    rocksdb_ffi::rocksdb_property_value(self.inner,
+                                                        prop_name.as_ptr() as *const _)
+                }
+                Some(cf) => {
+                    rocksdb_ffi::rocksdb_property_value_cf(self.inner,
+                                                           cf,
+                                                           prop_name.as_ptr() as *const _)
+                }
+            };
+
+            if value.is_null() {
+                return Err(Error::UnknownProperty(name.to_owned()));
+            }
+
+            let s = CStr::from_ptr(value as *const _)
+                .to_str()
+                .map(|s| s.to_owned());
+            libc::free(value as *mut c_void);
+            s.map_err(|_| Error::InvalidPropertyValue(name.to_owned()))
+        }
+    }
+
+    fn get_property_int_cf_opt(&self,
+                               cf: Option<DBCFHandle>,
+                               name: &str)
+                               -> Result<u64, Error> {
+        let value = try!(self.get_property_value_cf_opt(cf, name));
+        value.parse()
+            .map_err(|_| Error::InvalidPropertyValue(name.to_owned()))
+    }
+}
+
+// Return the total size of the files of `dir` with extension `ext`.
+fn files_size(dir: &str, ext: &str) -> Result<u64, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Failed to list {}: {}", dir, e)),
+    };
+    let mut size = 0;
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => return Err(format!("Failed to list {}: {}", dir, e)),
+        };
+        if entry.path().extension().map_or(true, |e| e != ext) {
+            continue;
+        }
+        match entry.metadata() {
+            Ok(m) => size += m.len(),
+            // Deleted since it was listed.
+            Err(_) => continue,
+        }
+    }
+    Ok(size)
+}
+
+/// Return the `i`th of `n` keys spread evenly over [`start`, `end`),
+/// `start` if it is not before `end`.
+///
+/// The 8 bytes following the common prefix of the bounds are interpolated
+/// as a big-endian number.
+fn interpolate_key(start: &[u8], end: &[u8], i: usize, n: usize) -> Vec<u8> {
+    let prefix_len = start.iter().zip(end).take_while(|&(a, b)| a == b).count();
+    let to_u64 = |k: &[u8]| {
+        let mut v = 0u64;
+        for j in 0..8 {
+            v = v << 8 | *k.get(prefix_len + j).unwrap_or(&0) as u64;
+        }
+        v
+    };
+    let (lo, hi) = (to_u64(start), to_u64(end));
+    let step = hi.saturating_sub(lo) as f64 / n as f64;
+    let point = lo + (step * i as f64) as u64;
+    let mut key = start[..prefix_len].to_vec();
+    for j in 0..8 {
+        key.push((point >> (8 * (7 - j))) as u8);
+    }
+    if i == 0 || key.as_slice() < start {
+        return start.to_vec();
+    }
+    key
+}
+
+// Return the integer property `key` of table properties printed as
+// "key1=value1; key2=value2; ...", 0 if it is missing.
+fn table_property(props: &str, key: &str) -> u64 {
+    props.split(';')
+        .filter_map(|field| {
+            let mut kv = field.splitn(2, '=');
+            if kv.next().map(|k| k.trim()) != Some(key) {
+                return None;
+            }
+            kv.next().and_then(|v| v.trim().parse().ok())
+        })
+        .next()
+        .unwrap_or(0)
+}
+
+fn seek_to_start(iter: &mut DBIterator, start_key: Option<&[u8]>) {
+    match start_key {
+        Some(k) => iter.seek(SeekKey::Key(k)),
+        None => iter.seek(SeekKey::Start),
+    };
+}
+
+fn bytewise_compare(a: &[u8], b: &[u8]) -> i32 {
+    a.cmp(b) as i32
+}
+
+struct PinnedSlice(rocksdb_ffi::DBPinnableSlice);
+
+impl Drop for PinnedSlice {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_pinnableslice_destroy(self.0);
+        }
+    }
+}
+
+fn count_iter(mut iter: DBIterator,
+              start_key: &[u8],
+              limit: usize)
+              -> Result<usize, String> {
+    let mut count = 0;
+    iter.seek(SeekKey::Key(start_key));
+    while count < limit && iter.valid() {
+        count += 1;
+        iter.next();
+    }
+    try!(iter.status());
+    Ok(count)
+}
+
+impl Writable for DB {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.put_opt(key, value, o))
+    }
+
+    fn put_cf(&self,
+              cf: DBCFHandle,
+              key: &[u8],
+              value: &[u8])
+              -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.put_cf_opt(cf, key, value, o))
+    }
+
+    fn merge(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.merge_opt(key, value, o))
+    }
+
+    fn merge_cf(&self,
+                cf: DBCFHandle,
+                key: &[u8],
+                value: &[u8])
+                -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.merge_cf_opt(cf, key, value, o))
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.delete_opt(key, o))
+    }
+
+    fn delete_cf(&self, cf: DBCFHandle, key: &[u8]) -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.delete_cf_opt(cf, key, o))
+    }
+
+    fn single_delete(&self, key: &[u8]) -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.single_delete_opt(key, o))
+    }
+
+    fn single_delete_cf(&self,
+                        cf: DBCFHandle,
+                        key: &[u8])
+                        -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.single_delete_cf_opt(cf, key, o))
+    }
+
+    fn delete_range(&self,
+                    begin_key: &[u8],
+                    end_key: &[u8])
+                    -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| self.delete_range_opt(begin_key, end_key, o))
+    }
+
+    fn delete_range_cf(&self,
+                       cf: DBCFHandle,
+                       begin_key: &[u8],
+                       end_key: &[u8])
+                       -> Result<(), String> {
+        DEFAULT_WRITEOPTS.with(|o| {
+            self.delete_range_cf_opt(cf, begin_key, end_key, o)
+        })
+    }
+}
+
+impl Default for WriteBatch {
+    fn default() -> WriteBatch {
+        WriteBatch {
+            inner: unsafe { rocksdb_ffi::rocksdb_writebatch_create() },
+            max_bytes: None,
+            max_ops: None,
+        }
+    }
+}
+
+impl WriteBatch {
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    /// Take ownership of a write batch created through the C API, it is
+    /// destroyed when the `WriteBatch` is dropped.
+    ///
+    /// Unsafe because `inner` must be a valid batch owned by no one else.
+    pub unsafe fn from_raw(inner: rocksdb_ffi::DBWriteBatch) -> WriteBatch {
+        WriteBatch {
+            inner: inner,
+            max_bytes: None,
+            max_ops: None,
+        }
+    }
+
+    /// The raw batch handle, only valid while the `WriteBatch` is alive.
+    pub unsafe fn as_raw(&self) -> rocksdb_ffi::DBWriteBatch {
+        self.inner
+    }
+
+    pub fn count(&self) -> usize {
+        unsafe { rocksdb_ffi::rocksdb_writebatch_count(self.inner) as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Return the serialized content of the batch.
+    pub fn data(&self) -> &[u8] {
+        unsafe {
+            let mut data_size: size_t = 0;
+            let data_ptr =
+                rocksdb_ffi::rocksdb_writebatch_data(self.inner, &mut data_size);
+            slice::from_raw_parts(data_ptr, data_size as usize)
+        }
+    }
+
+    /// Create a batch from the serialized content of another one.
+    ///
+    /// Fails if `data` is too short to hold the header of a batch. Its
+    /// records are checked when the batch is written.
+    pub fn from_data(data: &[u8]) -> Result<WriteBatch, String> {
+        if data.len() < WRITE_BATCH_HEADER_SIZE {
+            return Err("Corruption: malformed WriteBatch (too small)"
+                .to_owned());
+        }
+        Ok(WriteBatch {
+            inner: unsafe {
+                rocksdb_ffi::rocksdb_writebatch_create_from(data.as_ptr(),
+                                                            data.len() as size_t)
+            },
+            max_bytes: None,
+            max_ops: None,
+        })
+    }
+
+    /// Fail the writes that would make the serialized batch larger than
+    /// `max_bytes`, leaving the batch as it was.
+    ///
+    /// The size of a write is computed before it is added, counting the
+    /// id of its column family, if any, at its largest encoding.
+    ///
+    /// Bounds the memory of a batch built in a loop, and how long it holds
+    /// up the writes committed after it.
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = Some(max_bytes);
+    }
+
+    /// Fail the writes that would put more than `max_ops` operations in
+    /// the batch.
+    pub fn set_max_ops(&mut self, max_ops: usize) {
+        self.max_ops = Some(max_ops);
+    }
+
+    // Run `op`, adding one operation of `fields` to the batch, in a
+    // column family given by handle if `cf`, unless it goes over the
+    // limits.
+    fn check_limits<F>(&self,
+                       cf: bool,
+                       fields: &[&[u8]],
+                       op: F)
+                       -> Result<(), String>
+        where F: FnOnce()
+    {
+        if let Some(max) = self.max_ops {
+            if self.count() >= max {
+                return Err(format!("Memory limit: write batch already \
+                                    holds {} operations",
+                                   max));
+            }
+        }
+        if let Some(max) = self.max_bytes {
+            if self.data().len() + record_size(cf, fields) > max {
+                return Err(format!("Memory limit: write batch would take \
+                                    more than {} bytes",
+                                   max));
+            }
+        }
+        op();
+        Ok(())
+    }
+}
+
+// Upper bound of the size of a record of a write batch: its type, the id
+// of its column family, 5 bytes at most, and its fields, each prefixed by
+// its varint32 length.
+fn record_size(cf: bool, fields: &[&[u8]]) -> usize {
+    let cf_id = if cf { 5 } else { 0 };
+    fields.iter().fold(1 + cf_id, |size, f| {
+        size + varint_length(f.len() as u64) + f.len()
+    })
+}
+
+impl Drop for WriteBatch {
+    fn drop(&mut self) {
+        unsafe { rocksdb_ffi::rocksdb_writebatch_destroy(self.inner) }
+    }
+}
+
+impl Drop for DB {
+    fn drop(&mut self) {
+        let handles = self.live_snapshot_handles();
+        if cfg!(debug_assertions) && handles > 0 && !thread::panicking() {
+            panic!("{} snapshot handles of {} were never released",
+                   handles,
+                   self.path);
+        }
+        // The sampler reads from the DB, it must stop before closing it.
+        self.stop_metrics_sampler();
+        unsafe {
+            for cf in self.cfs.values().chain(&self.dropped_cfs) {
+                rocksdb_ffi::rocksdb_column_family_handle_destroy(*cf);
+            }
+            rocksdb_ffi::rocksdb_close(self.inner);
+        }
+    }
+}
+
+impl Writable for WriteBatch {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.check_limits(false, &[key, value], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_put(self.inner,
+                                                key.as_ptr(),
+                                                key.len() as size_t,
+                                                value.as_ptr(),
+                                                value.len() as size_t);
+        })
+    }
+
+    fn put_cf(&self,
+              cf: DBCFHandle,
+              key: &[u8],
+              value: &[u8])
+              -> Result<(), String> {
+        self.check_limits(true, &[key, value], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_put_cf(self.inner,
+                                                   cf,
+                                                   key.as_ptr(),
+                                                   key.len() as size_t,
+                                                   value.as_ptr(),
+                                                   value.len() as size_t);
+        })
+    }
+
+    fn merge(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        self.check_limits(false, &[key, value], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_merge(self.inner,
+                                                  key.as_ptr(),
+                                                  key.len() as size_t,
+                                                  value.as_ptr(),
+                                                  value.len() as size_t);
+        })
+    }
+
+    fn merge_cf(&self,
+                cf: DBCFHandle,
+                key: &[u8],
+                value: &[u8])
+                -> Result<(), String> {
+        self.check_limits(true, &[key, value], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_merge_cf(self.inner,
+                                                     cf,
+                                                     key.as_ptr(),
+                                                     key.len() as size_t,
+                                                     value.as_ptr(),
+                                                     value.len() as size_t);
+        })
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<(), String> {
+        self.check_limits(false, &[key], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_delete(self.inner,
+                                                   key.as_ptr(),
+                                                   key.len() as size_t);
+        })
+    }
+
+    fn delete_cf(&self, cf: DBCFHandle, key: &[u8]) -> Result<(), String> {
+        self.check_limits(true, &[key], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_delete_cf(self.inner,
+                                                      cf,
+                                                      key.as_ptr(),
+                                                      key.len() as size_t);
+        })
+    }
+
+    fn single_delete(&self, key: &[u8]) -> Result<(), String> {
+        self.check_limits(false, &[key], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_singledelete(self.inner,
+                                                         key.as_ptr(),
+                                                         key.len() as size_t);
+        })
+    }
+
+    fn single_delete_cf(&self,
+                        cf: DBCFHandle,
+                        key: &[u8])
+                        -> Result<(), String> {
+        self.check_limits(true, &[key], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_singledelete_cf(self.inner,
+                                                            cf,
+                                                            key.as_ptr(),
+                                                            key.len() as size_t);
+        })
+    }
+
+    fn delete_range(&self,
+                    begin_key: &[u8],
+                    end_key: &[u8])
+                    -> Result<(), String> {
+        self.check_limits(false, &[begin_key, end_key], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_delete_range(self.inner,
+                                                         begin_key.as_ptr(),
+                                                         begin_key.len() as size_t,
+                                                         end_key.as_ptr(),
+                                                         end_key.len() as size_t);
+        })
+    }
+
+    fn delete_range_cf(&self,
+                       cf: DBCFHandle,
+                       begin_key: &[u8],
+                       end_key: &[u8])
+                       -> Result<(), String> {
+        self.check_limits(true, &[begin_key, end_key], || unsafe {
+            rocksdb_ffi::rocksdb_writebatch_delete_range_cf(self.inner,
+                                                            cf,
+                                                            begin_key.as_ptr(),
+                                                            begin_key.len() as size_t,
+                                                            end_key.as_ptr(),
+                                                            end_key.len() as size_t);
+        })
+    }
+}
+
+impl Drop for ReadOptions {
+    fn drop(&mut self) {
+        unsafe { rocksdb_ffi::rocksdb_readoptions_destroy(self.inner) }
+    }
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        unsafe {
+            ReadOptions {
+                inner: rocksdb_ffi::rocksdb_readoptions_create(),
+                lower_bound: vec![],
+                upper_bound: vec![],
+                timestamp: vec![],
+                iter_start_ts: vec![],
+            }
+        }
+    }
+}
+
+impl ReadOptions {
+    pub fn new() -> ReadOptions {
+        ReadOptions::default()
+    }
+
+    /// Take ownership of read options created through the C API, they are
+    /// destroyed when the `ReadOptions` is dropped.
+    ///
+    /// Unsafe because `inner` must be valid options owned by no one else,
+    /// and bounds or timestamps already set on them must outlive them.
+    pub unsafe fn from_raw(inner: rocksdb_ffi::DBReadOptions) -> ReadOptions {
+        ReadOptions {
+            inner: inner,
+            lower_bound: vec![],
+            upper_bound: vec![],
+            timestamp: vec![],
+            iter_start_ts: vec![],
+        }
+    }
+
+    /// The raw options handle, only valid while the `ReadOptions` is alive.
+    ///
+    /// Bounds set through it are not copied, the keys must outlive the
+    /// options.
+    pub unsafe fn as_raw(&self) -> rocksdb_ffi::DBReadOptions {
+        self.inner
+    }
+    // TODO add snapshot setting here
+    // TODO add snapshot wrapper structs with proper destructors;
+    // that struct needs an "iterator" impl too.
+    #[allow(dead_code)]
+    pub fn fill_cache(&mut self, v: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_fill_cache(self.inner, v);
+        }
+    }
+
+    /// Read `size` bytes ahead of iterators in sst files, in one read,
+    /// instead of block by block. 0, the default, lets rocksdb pick when
+    /// to read ahead.
+    pub fn set_readahead_size(&mut self, size: usize) {
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_readahead_size(self.inner,
+                                                                size as size_t);
+        }
+    }
+
+    /// Read from `snapshot`.
+    ///
+    /// Unsafe because the snapshot must not be released while the options
+    /// or any iterator created with them are in use, prefer the methods
+    /// of `Snapshot` which guarantee it.
+    pub unsafe fn set_snapshot(&mut self, snapshot: &UnsafeSnap) {
+        rocksdb_ffi::rocksdb_readoptions_set_snapshot(self.inner,
+                                                      snapshot.inner);
+    }
+
+    /// Set the smallest key that iterators created with this option visit.
+    ///
+    /// The key is copied and kept by the options, like the upper bound.
+    pub fn set_iterate_lower_bound(&mut self, key: &[u8]) {
+        self.lower_bound = key.to_vec();
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_iterate_lower_bound(self.inner,
+                                                                     self.lower_bound.as_ptr(),
+                                                                     self.lower_bound.len() as size_t);
+        }
+    }
+
+    /// Delete the files made obsolete while an iterator pinned them in a
+    /// background job when the iterator is dropped, instead of in the
+    /// thread dropping it.
+    pub fn set_background_purge_on_iterator_cleanup(&mut self, v: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_background_purge_on_iterator_cleanup(self.inner, v as u8);
+        }
+    }
+
+    /// Fail a seek or next with an `Incomplete` error, see
+    /// `DBIterator::status`, after it skips `n` deleted or overwritten
+    /// entries. 0, the default, never fails.
+    pub fn set_max_skippable_internal_keys(&mut self, n: u64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_max_skippable_internal_keys(self.inner,
+                                                                             n);
+        }
+    }
+
+    /// Abandon reads still running at `deadline` with an `Operation timed
+    /// out` error. It is checked before each file read, so reads served
+    /// from memory always succeed.
+    ///
+    /// The deadline is absolute, options reused across requests need a
+    /// new one for each.
+    pub fn set_deadline(&mut self, deadline: SystemTime) {
+        let micros = match deadline.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() * 1_000_000 + d.subsec_nanos() as u64 / 1000,
+            // Already expired, 0 would disable the deadline.
+            Err(_) => 1,
+        };
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_deadline(self.inner, micros);
+        }
+    }
+
+    /// Fail a single file read taking longer than `timeout` with an
+    /// `Operation timed out` error, if the file system supports it.
+    pub fn set_io_timeout(&mut self, timeout: Duration) {
+        let micros = timeout.as_secs() * 1_000_000 +
+                     timeout.subsec_nanos() as u64 / 1000;
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_io_timeout(self.inner, micros);
+        }
+    }
+
+    /// Read the versions visible at `ts`, the newest written at or before
+    /// it. Only for column families with a timestamp comparator, see
+    /// `Options::add_comparator_with_u64_ts`.
+    pub fn set_timestamp(&mut self, ts: u64) {
+        self.timestamp = encode_u64_ts(ts).to_vec();
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_timestamp(self.inner,
+                                                           self.timestamp.as_ptr(),
+                                                           self.timestamp.len() as size_t);
+        }
+    }
+
+    /// Make iterators return every version written between `ts` and the
+    /// timestamp of `set_timestamp`, instead of only the newest one.
+    pub fn set_iter_start_ts(&mut self, ts: u64) {
+        self.iter_start_ts = encode_u64_ts(ts).to_vec();
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_iter_start_ts(self.inner,
+                                                               self.iter_start_ts.as_ptr(),
+                                                               self.iter_start_ts.len() as size_t);
+        }
+    }
+
+    /// Set the key that iterators created with this option stop before.
+    ///
+    /// The key is copied and kept by the options, rocksdb only holds a
+    /// pointer to it. Iterators own their options, so it stays valid.
+    pub fn set_iterate_upper_bound(&mut self, key: &[u8]) {
+        self.upper_bound = key.to_vec();
+        unsafe {
+            rocksdb_ffi::rocksdb_readoptions_set_iterate_upper_bound(self.inner,
+                                                                     self.upper_bound.as_ptr(),
+                                                                     self.upper_bound.len() as size_t);
+        }
+    }
+}
+
+/// A value returned by rocksdb.
+///
+/// The buffer is allocated by rocksdb and freed when the vector is dropped,
+/// so dereferencing it as `&[u8]` is zero-copy. Use `into_vec` or
+/// `Vec::from` only when an owned copy is really needed.
+pub struct DBVector {
+    base: *mut u8,
+    len: usize,
+}
+
+impl Deref for DBVector {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.base, self.len) }
+    }
+}
+
+impl AsRef<[u8]> for DBVector {
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl Debug for DBVector {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{:?}", self.deref())
+    }
+}
+
+impl<'a> PartialEq<&'a [u8]> for DBVector {
+    fn eq(&self, rhs: &&[u8]) -> bool {
+        self.deref() == *rhs
+    }
+}
+
+impl PartialEq for DBVector {
+    fn eq(&self, rhs: &DBVector) -> bool {
+        self.deref() == rhs.deref()
+    }
+}
+
+impl From<DBVector> for Vec<u8> {
+    fn from(v: DBVector) -> Vec<u8> {
+        v.into_vec()
+    }
+}
+
+impl Drop for DBVector {
+    fn drop(&mut self) {
+        unsafe {
+            libc::free(self.base as *mut c_void);
+        }
+    }
+}
+
+impl DBVector {
+    pub fn from_c(val: *mut u8, val_len: size_t) -> DBVector {
+        DBVector {
+            base: val,
+            len: val_len as usize,
+        }
+    }
+
+    // Copy `v` into a buffer from the C allocator, which `Drop` frees.
+    fn copy_from(v: &[u8]) -> DBVector {
+        unsafe {
+            let len = v.len() as size_t;
+            let base = libc::malloc(cmp::max(len, 1)) as *mut u8;
+            assert!(!base.is_null(), "out of memory");
+            ptr::copy_nonoverlapping(v.as_ptr(), base, v.len());
+            DBVector::from_c(base, len)
+        }
+    }
+
+    pub fn to_utf8(&self) -> Option<&str> {
+        from_utf8(self.deref()).ok()
+    }
+
+    /// Copy the value into a `Vec<u8>` and release the rocksdb buffer.
+    ///
+    /// The memory is owned by rocksdb's allocator, so it can't be handed
+    /// over to a `Vec` without copying.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.deref().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use merge_operator::{MergeOperands, UInt64AddOperator};
+    use rocksdb_options::*;
+    use std::str;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+    use wal::WalReader;
+
+    #[test]
+    fn external() {
+        let path = TempDir::new("_rust_rocksdb_externaltest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        let p = db.put(b"k1", b"v1111");
+        assert!(p.is_ok());
+        let r: Result<Option<DBVector>, String> = db.get(b"k1");
+        assert!(r.unwrap().unwrap().to_utf8().unwrap() == "v1111");
+        assert!(db.delete(b"k1").is_ok());
+        assert!(db.get(b"k1").unwrap().is_none());
+    }
+
+    #[allow(unused_variables)]
+    #[test]
+    fn errors_do_stuff() {
+        let path = TempDir::new("_rust_rocksdb_error").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db = DB::open_default(path_str).unwrap();
+        let opts = Options::new();
+        // The DB will still be open when we try to destroy and the lock should fail
+        match DB::destroy(&opts, path_str) {
+            Err(ref s) => assert!(s.contains("LOCK: No locks available")),
+            Ok(_) => panic!("should fail"),
+        }
+    }
+
+    #[test]
+    fn writebatch_works() {
+        let path = TempDir::new("_rust_rocksdb_writebacktest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+
+        // test put
+        let batch = WriteBatch::new();
+        assert!(db.get(b"k1").unwrap().is_none());
+        assert_eq!(batch.count(), 0);
+        assert!(batch.is_empty());
+        let _ = batch.put(b"k1", b"v1111");
+        assert_eq!(batch.count(), 1);
+        assert!(!batch.is_empty());
+        assert!(db.get(b"k1").unwrap().is_none());
+        let p = db.write(batch);
+        assert!(p.is_ok());
+        let r: Result<Option<DBVector>, String> = db.get(b"k1");
+        assert!(r.unwrap().unwrap().to_utf8().unwrap() == "v1111");
+
+        // test delete
+        let batch = WriteBatch::new();
+        let _ = batch.delete(b"k1");
+        assert_eq!(batch.count(), 1);
+        assert!(!batch.is_empty());
+        let p = db.write(batch);
+        assert!(p.is_ok());
+        assert!(db.get(b"k1").unwrap().is_none());
+    }
+
+    #[test]
+    fn compact_range_test() {
+        let path = TempDir::new("_rust_rocksdb_compactrangetest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        let mut db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = db.create_cf("cf", &opts).unwrap();
+        for i in 0..100 {
+            let key = format!("k{:03}", i);
+            db.put(key.as_bytes(), b"value").unwrap();
+            db.put_cf(cf, key.as_bytes(), b"value").unwrap();
+        }
+        db.flush(true).unwrap();
+        db.delete_range(b"k000", b"k090").unwrap();
+        db.flush(true).unwrap();
+        assert_eq!(db.live_files_cf(db.cfs["default"]).unwrap().len(), 2);
+
+        db.compact_range(None, None);
+        let files = db.live_files_cf(db.cfs["default"]).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].level > 0);
+        assert_eq!(files[0].entries, 10);
+        assert_eq!(files[0].smallest_key, b"k090".to_vec());
+
+        // The other column family is left as it is, its memtable is
+        // flushed by its own compaction.
+        assert!(db.live_files_cf(cf).unwrap().is_empty());
+        db.compact_range_cf(cf, Some(b"k000"), Some(b"k050"));
+        assert!(db.live_files_cf(cf).unwrap()[0].level > 0);
+        assert_eq!(&*db.get_cf(cf, b"k000").unwrap().unwrap(), b"value");
+    }
+
+    #[test]
+    fn writebatch_from_data_test() {
+        assert!(WriteBatch::from_data(&[]).is_err());
+        assert!(WriteBatch::from_data(&[0; 11]).is_err());
+        let batch = WriteBatch::new();
+        batch.put(b"k1", b"v1").unwrap();
+        let copy = WriteBatch::from_data(batch.data()).unwrap();
+        assert_eq!(copy.count(), 1);
+        assert_eq!(copy.data(), batch.data());
+    }
+
+    #[test]
+    fn writebatch_limits_test() {
+        let mut batch = WriteBatch::new();
+        batch.set_max_ops(2);
+        batch.put(b"k1", b"v1").unwrap();
+        batch.delete(b"k2").unwrap();
+        let err = batch.put(b"k3", b"v3").unwrap_err();
+        assert!(err.starts_with("Memory limit"), "{}", err);
+        assert_eq!(batch.count(), 2);
+
+        let mut batch = WriteBatch::new();
+        batch.set_max_bytes(64);
+        batch.put(b"k1", b"v1").unwrap();
+        let size = batch.data().len();
+        let err = batch.put(b"k2", &[0; 64]).unwrap_err();
+        assert!(err.starts_with("Memory limit"), "{}", err);
+        // The batch is left as it was and still takes small writes.
+        assert_eq!((batch.count(), batch.data().len()), (1, size));
+        batch.put(b"k3", b"v3").unwrap();
+        assert_eq!(batch.count(), 2);
+
+        // The size is exact for the default column family: a 200 byte
+        // value has a 2 byte length.
+        let mut exact = WriteBatch::new();
+        exact.set_max_bytes(12 + 1 + 3 + 202);
+        exact.put(b"k1", &[0; 200]).unwrap();
+        assert_eq!(exact.data().len(), 12 + 1 + 3 + 202);
+        assert!(exact.delete(b"k2").is_err());
+
+        let path = TempDir::new("_rust_rocksdb_writebatchlimits").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"v1");
+        assert!(db.get(b"k2").unwrap().is_none());
+    }
+
+    #[test]
+    fn delete_range_test() {
+        let path = TempDir::new("_rust_rocksdb_deleterangetest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        for k in &[b"t1_a", b"t1_b", b"t2_a", b"t2_b"] {
+            db.put(*k, b"v").unwrap();
+        }
+        db.put(b"meta_t1", b"v").unwrap();
+        db.delete_range(b"t2_", b"t3_").unwrap();
+        assert!(db.get(b"t2_a").unwrap().is_none());
+        assert!(db.get(b"t1_b").unwrap().is_some());
+
+        // Drop a tenant and its metadata in one batch.
+        let batch = WriteBatch::new();
+        batch.delete_range(b"t1_", b"t2_").unwrap();
+        batch.delete(b"meta_t1").unwrap();
+        assert_eq!(batch.count(), 2);
+        db.write(batch).unwrap();
+        assert!(db.get(b"t1_a").unwrap().is_none());
+        assert!(db.get(b"meta_t1").unwrap().is_none());
+
+        let cf = *db.cf_handle("default").unwrap();
+        db.put(b"k1", b"v").unwrap();
+        db.delete_range_cf(cf, b"k0", b"k2").unwrap();
+        assert!(db.get(b"k1").unwrap().is_none());
+    }
+
+    #[test]
+    fn iterator_test() {
+        let path = TempDir::new("_rust_rocksdb_iteratortest").expect("");
+
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1111").expect("");
+        db.put(b"k2", b"v2222").expect("");
+        db.put(b"k3", b"v3333").expect("");
+        let mut iter = db.iter();
+        iter.seek(SeekKey::Start);
+        for (k, v) in &mut iter {
+            println!("Hello {}: {}",
+                     str::from_utf8(&*k).unwrap(),
+                     str::from_utf8(&*v).unwrap());
+        }
+    }
+
+    #[test]
+    fn approximate_size_test() {
+        let path = TempDir::new("_rust_rocksdb_iteratortest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        for i in 1..8000 {
+            db.put(format!("{:04}", i).as_bytes(),
+                     format!("{:04}", i).as_bytes())
+                .expect("");
+        }
+        db.flush(true).expect("");
+        assert!(db.get(b"0001").expect("").is_some());
+        db.flush(true).expect("");
+        let sizes = db.get_approximate_sizes(&[Range::new(b"0000", b"2000"),
+                                               Range::new(b"2000", b"4000"),
+                                               Range::new(b"4000", b"6000"),
+                                               Range::new(b"6000", b"8000"),
+                                               Range::new(b"8000", b"9999")])
+            .unwrap();
+        assert_eq!(sizes.len(), 5);
+        for s in &sizes[0..4] {
+            assert!(*s > 0);
+        }
+        assert_eq!(sizes[4], 0);
+
+        let ranges = vec![Range::new(b"0000", b"4000")];
+        let sizes = db.get_approximate_sizes_named_cf("default", ranges.iter())
+            .unwrap();
+        assert!(sizes[0] > 0);
+        assert!(db.get_approximate_sizes_named_cf("nope", &ranges).is_err());
+        assert!(db.get_approximate_sizes(&[Range::new(b"2000", b"1000")])
+            .is_err());
+        assert_eq!(db.get_approximate_sizes(&[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn property_test() {
+        let path = TempDir::new("_rust_rocksdb_propertytest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"a1", b"v1").unwrap();
+        db.flush(true).unwrap();
+        let prop_name = "rocksdb.total-sst-files-size";
+        let st1 = db.get_property_int(prop_name).unwrap();
+        assert!(st1 > 0);
+        db.put(b"a2", b"v2").unwrap();
+        db.flush(true).unwrap();
+        let st2 = db.get_property_int(prop_name).unwrap();
+        assert!(st2 > st1);
+
+        assert_eq!(db.get_property_value("rocksdb.no-such-property"),
+                   Err(Error::UnknownProperty("rocksdb.no-such-property"
+                       .to_owned())));
+        assert_eq!(db.get_property_int("rocksdb.stats"),
+                   Err(Error::InvalidPropertyValue("rocksdb.stats"
+                       .to_owned())));
+        match db.get_property_value("rocksdb.\0stats") {
+            Err(Error::InvalidArgument(e)) => assert!(e.contains("stats")),
+            r => panic!("unexpected {:?}", r),
+        }
+        let cf = *db.cf_handle("default").unwrap();
+        assert!(db.get_property_int_cf(cf, prop_name).is_ok());
+        let bogus = rocksdb_ffi::DBCFHandle(1 as *const c_void);
+        assert_eq!(db.get_property_int_cf(bogus, prop_name),
+                   Err(Error::InvalidColumnFamily(None)));
+    }
+
+    #[test]
+    fn block_cache_usage_test() {
+        let path = TempDir::new("_rust_rocksdb_blockcacheusagetest")
+            .expect("");
+        let cache = Cache::new_lru(8 << 20);
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_block_cache(&cache);
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_block_based_table_factory(&block_opts);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        for i in 0..100 {
+            db.put(format!("k{}", i).as_bytes(), &[0; 1024]).unwrap();
+        }
+        db.flush(true).unwrap();
+        for i in 0..100 {
+            db.get(format!("k{}", i).as_bytes()).unwrap().unwrap();
+        }
+
+        let usage = db.get_block_cache_usage().unwrap();
+        assert!(usage > 0);
+        assert_eq!(usage, cache.get_usage() as u64);
+        let cf = *db.cf_handle("default").unwrap();
+        assert_eq!(db.get_block_cache_usage_cf(cf).unwrap(), usage);
+        assert!(db.get_pinned_usage().unwrap() <= usage);
+        assert!(db.get_pinned_usage_cf(cf).unwrap() <= usage);
+    }
+
+    fn reverse_compare(a: &[u8], b: &[u8]) -> i32 {
+        b.cmp(a) as i32
+    }
+
+    fn concat_merge(_: &[u8],
+                    existing_val: Option<&[u8]>,
+                    operands: &mut MergeOperands)
+                    -> Vec<u8> {
+        let mut result = existing_val.map_or(vec![], |v| v.to_vec());
+        for op in operands {
+            result.extend_from_slice(op);
+        }
+        result
+    }
+
+    #[test]
+    fn check_range_test() {
+        let path = TempDir::new("_rust_rocksdb_checkrangetest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let mut rev_opts = Options::new();
+        rev_opts.add_comparator("reverse", reverse_compare);
+        let mut db = DB::open_cf(&opts, path_str, &["rev"], &[&rev_opts])
+            .unwrap();
+        let rev = *db.cf_handle("rev").unwrap();
+
+        db.check_range(&Range::new(b"a", b"b")).unwrap();
+        db.check_range(&Range::new(b"a", b"a")).unwrap();
+        assert!(db.check_range(&Range::new(b"b", b"a")).is_err());
+        db.check_range_cf(rev, &Range::new(b"b", b"a")).unwrap();
+        assert!(db.check_range_cf(rev, &Range::new(b"a", b"b")).is_err());
+
+        let rev2 = db.create_cf("rev2", &rev_opts).unwrap();
+        db.check_range_cf(rev2, &Range::new(b"b", b"a")).unwrap();
+    }
+
+    #[test]
+    fn delete_matching_test() {
+        let path = TempDir::new("_rust_rocksdb_deletematchingtest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        for i in 0..100 {
+            db.put(format!("k{:03}", i).as_bytes(), format!("{}", i).as_bytes())
+                .unwrap();
+        }
+
+        // Delete the odd values in [k010, k050).
+        let range = Range::new(b"k010", b"k050");
+        let deleted = db.delete_matching(&range, 7, |_, v| {
+                let n: u32 = str::from_utf8(v).unwrap().parse().unwrap();
+                n % 2 == 1
+            })
+            .unwrap();
+        assert_eq!(deleted, 20);
+        assert!(db.get(b"k009").unwrap().is_some());
+        assert!(db.get(b"k010").unwrap().is_some());
+        assert!(db.get(b"k011").unwrap().is_none());
+        assert!(db.get(b"k049").unwrap().is_none());
+        assert!(db.get(b"k051").unwrap().is_some());
+        assert_eq!(db.count_range(b"k", b"l", 1000).unwrap(), 80);
+
+        assert!(db.delete_matching(&range, 0, |_, _| true).is_err());
+        let range = Range::new(b"k050", b"k010");
+        assert!(db.delete_matching(&range, 7, |_, _| true).is_err());
+        assert_eq!(db.count_range(b"k", b"l", 1000).unwrap(), 80);
+    }
+
+    #[test]
+    fn retry_test() {
+        let path = TempDir::new("_rust_rocksdb_retrytest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        let policy = RetryPolicy::default();
+        let batch = WriteBatch::new();
+        batch.put(b"k1", b"v1").unwrap();
+        db.write_with_retry(batch, &WriteOptions::new(), &policy).unwrap();
+        let v = db.get_with_retry(b"k1", &ReadOptions::new(), &policy).unwrap();
+        assert_eq!(&*v.unwrap(), b"v1");
+    }
+
+    #[test]
+    fn write_cf_ops_test() {
+        let path = TempDir::new("_rust_rocksdb_cfopstest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db =
+            DB::open_cf(&opts, path_str, &["default", "index"], &[&opts, &opts])
+                .unwrap();
+        let index = *db.cf_handle("index").unwrap();
+
+        db.write_cf_ops(&[CfOp::Put("default", b"k1", b"v1"),
+                            CfOp::Put("index", b"v1", b"k1"),
+                            CfOp::Put("default", b"k2", b"v2")])
+            .unwrap();
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(&*db.get_cf(index, b"v1").unwrap().unwrap(), b"k1");
+
+        db.write_cf_ops(&[CfOp::Delete("default", b"k1"),
+                            CfOp::DeleteRange("index", b"v0", b"v2")])
+            .unwrap();
+        assert!(db.get(b"k1").unwrap().is_none());
+        assert!(db.get_cf(index, b"v1").unwrap().is_none());
+
+        // A bad op fails the whole group.
+        let err = db.write_cf_ops(&[CfOp::Delete("default", b"k2"),
+                                    CfOp::Put("meta", b"k", b"v")])
+            .unwrap_err();
+        assert_eq!(err, "op 1 (put on meta): Invalid column family: meta");
+        assert!(db.get(b"k2").unwrap().is_some());
+    }
+
+    #[test]
+    fn raw_handle_test() {
+        let path = TempDir::new("_rust_rocksdb_rawhandletest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        unsafe {
+            let raw = rocksdb_ffi::rocksdb_writebatch_create();
+            let batch = WriteBatch::from_raw(raw);
+            rocksdb_ffi::rocksdb_writebatch_put(batch.as_raw(),
+                                                b"k1".as_ptr(),
+                                                2,
+                                                b"v1".as_ptr(),
+                                                2);
+            db.write(batch).unwrap();
+
+            let raw = rocksdb_ffi::rocksdb_readoptions_create();
+            let readopts = ReadOptions::from_raw(raw);
+            rocksdb_ffi::rocksdb_readoptions_set_fill_cache(readopts.as_raw(),
+                                                            false);
+            let mut val_len: size_t = 0;
+            let mut err: *const i8 = 0 as *const i8;
+            let val = rocksdb_ffi::rocksdb_get(db.as_raw(),
+                                               readopts.as_raw(),
+                                               b"k1".as_ptr(),
+                                               2,
+                                               &mut val_len,
+                                               &mut err);
+            assert!(err.is_null());
+            let v = DBVector::from_c(val as *mut u8, val_len);
+            assert_eq!(&*v, b"v1");
+        }
+    }
+
+    #[test]
+    fn open_cf_with_handles_test() {
+        let path = TempDir::new("_rust_rocksdb_opencfhandlestest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let (db, handles) = DB::open_cf_with_handles(&opts,
+                                                     path_str,
+                                                     &["raft", "default",
+                                                       "data"],
+                                                     &[&opts, &opts, &opts])
+            .unwrap();
+        assert_eq!(handles.len(), 3);
+        for (cf, handle) in ["raft", "default", "data"].iter().zip(&handles) {
+            assert_eq!(db.cf_handle(cf).unwrap().0, handle.0);
+        }
+        db.put_cf(handles[2], b"k1", b"v1").unwrap();
+        assert!(db.get(b"k1").unwrap().is_none());
+        drop(db);
+
+        // The default column family is opened anyway, but not returned.
+        let (db, handles) = DB::open_cf_with_handles(&opts,
+                                                     path_str,
+                                                     &["data", "raft"],
+                                                     &[&opts, &opts])
+            .unwrap();
+        assert_eq!(handles.len(), 2);
+        assert_eq!(&*db.get_cf(handles[0], b"k1").unwrap().unwrap(), b"v1");
+    }
+
+    #[test]
+    fn open_for_read_only_test() {
+        let path = TempDir::new("_rust_rocksdb_readonlytest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let missing = path.path().join("missing");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        assert!(DB::open_for_read_only(&opts,
+                                       missing.to_str().unwrap(),
+                                       false)
+            .is_err());
+        assert!(!missing.exists());
+
+        let mut db = DB::open(&opts, path_str).unwrap();
+        db.create_cf("cf", &opts).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.flush(true).unwrap();
+        db.put(b"k2", b"v2").unwrap();
+
+        // The writer keeps the DB open, and its WAL is not empty.
+        assert!(DB::open_for_read_only(&opts, path_str, true).is_err());
+        let ro = DB::open_for_read_only(&opts, path_str, false).unwrap();
+        assert_eq!(&*ro.get(b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(&*ro.get(b"k2").unwrap().unwrap(), b"v2");
+        assert!(ro.put(b"k3", b"v3").is_err());
+        assert!(ro.cf_handle("cf").is_none());
+        db.put(b"k3", b"v3").unwrap();
+        assert!(ro.get(b"k3").unwrap().is_none());
+
+        let ro = DB::open_cf_for_read_only(&opts,
+                                           path_str,
+                                           &["cf"],
+                                           &[&opts],
+                                           false)
+            .unwrap();
+        assert!(ro.cf_handle("cf").is_some());
+    }
+
+    #[test]
+    fn single_delete_test() {
+        let path = TempDir::new("_rust_rocksdb_singledeletetest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.put(b"k2", b"v2").unwrap();
+        db.put(b"k3", b"v3").unwrap();
+        db.flush(true).unwrap();
+
+        db.single_delete(b"k1").unwrap();
+        db.single_delete_cf(cf, b"k2").unwrap();
+        let batch = WriteBatch::new();
+        batch.single_delete(b"k3").unwrap();
+        db.write(batch).unwrap();
+        assert!(db.get(b"k1").unwrap().is_none());
+        assert!(db.get(b"k2").unwrap().is_none());
+        assert!(db.get(b"k3").unwrap().is_none());
+
+        // The tombstones and the puts drop each other.
+        db.flush(true).unwrap();
+        db.compact_range(None, None);
+        assert!(db.live_files().is_empty());
+    }
+
+    #[test]
+    fn key_may_exist_test() {
+        let path = TempDir::new("_rust_rocksdb_keymayexisttest").expect("");
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_bloom_filter(10, false);
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_block_based_table_factory(&block_opts);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+
+        db.put(b"k1", b"v1").unwrap();
+        assert!(db.key_may_exist(b"k1"));
+        assert!(db.key_may_exist_cf(cf, b"k1"));
+        let (may_exist, value) = db.key_may_exist_value(b"k1");
+        assert!(may_exist);
+        assert_eq!(&*value.unwrap(), b"v1");
+        assert!(!db.key_may_exist(b"k2"));
+
+        db.flush(true).unwrap();
+        assert!(db.key_may_exist(b"k1"));
+        assert!(!db.key_may_exist_cf(cf, b"k2"));
+        let (may_exist, value) = db.key_may_exist_value_cf(cf, b"k2");
+        assert!(!may_exist);
+        assert!(value.is_none());
+    }
+
+    #[test]
+    fn multi_get_test() {
+        let path = TempDir::new("_rust_rocksdb_multigettest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let mut db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = db.create_cf("cf", &opts).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.put(b"k3", b"v3").unwrap();
+        db.put_cf(cf, b"k1", b"cf1").unwrap();
+
+        let values: Vec<Option<Vec<u8>>> = db.multi_get(&[b"k3", b"k2", b"k1"])
+            .into_iter()
+            .map(|r| r.unwrap().map(|v| v.into_vec()))
+            .collect();
+        assert_eq!(values,
+                   vec![Some(b"v3".to_vec()), None, Some(b"v1".to_vec())]);
+        assert!(db.multi_get(&[]).is_empty());
+
+        let values = db.multi_get_cf(cf, &[b"k1", b"k3"]);
+        assert_eq!(values[0].as_ref().unwrap().as_ref().unwrap().to_utf8(),
+                   Some("cf1"));
+        assert!(values[1].as_ref().unwrap().is_none());
+
+        let snap = db.snapshot();
+        db.put(b"k2", b"v2").unwrap();
+        let mut readopts = ReadOptions::new();
+        unsafe {
+            readopts.set_snapshot(&snap.snap);
+        }
+        let values = db.multi_get_opt(&[b"k1", b"k2"], &readopts);
+        assert!(values[0].as_ref().unwrap().is_some());
+        assert!(values[1].as_ref().unwrap().is_none());
+
+        // The buffers reused from the longer calls hold nothing of them.
+        let values = db.multi_get(&[b"k2"]);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].as_ref().unwrap().as_ref().unwrap().to_utf8(),
+                   Some("v2"));
+        let values = db.multi_get_sorted_opt(&[b"k1", b"k2"], &readopts);
+        assert_eq!(values[0].as_ref().unwrap().as_ref().unwrap().to_utf8(),
+                   Some("v1"));
+        assert!(values[1].as_ref().unwrap().is_none());
+        let values = db.multi_get_sorted(&[b"k3"]);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].as_ref().unwrap().as_ref().unwrap().to_utf8(),
+                   Some("v3"));
+    }
+
+    #[test]
+    fn get_all_cf_test() {
+        let path = TempDir::new("_rust_rocksdb_getallcftest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let mut db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let meta = db.create_cf("meta", &opts).unwrap();
+        db.create_cf("index", &opts).unwrap();
+        db.put(b"k1", b"data").unwrap();
+        db.put_cf(meta, b"k1", b"meta").unwrap();
+
+        let values = db.get_all_cf(&["default", "meta", "index"], b"k1")
+            .unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["default"].as_ref().unwrap().to_utf8(), Some("data"));
+        assert_eq!(values["meta"].as_ref().unwrap().to_utf8(), Some("meta"));
+        assert!(values["index"].is_none());
+        assert!(db.get_all_cf(&["default", "missing"], b"k1").is_err());
+        assert!(db.get_all_cf(&[], b"k1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn new_iterators_test() {
+        let path = TempDir::new("_rust_rocksdb_newiteratorstest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let mut db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let meta = db.create_cf("meta", &opts).unwrap();
+        let default = *db.cf_handle("default").unwrap();
+        db.put(b"k1", b"data").unwrap();
+        db.put_cf(meta, b"k1", b"meta").unwrap();
+
+        let mut readopts = ReadOptions::new();
+        readopts.set_iterate_upper_bound(b"k3");
+        let mut iters = db.new_iterators(&[default, meta], readopts).unwrap();
+        assert_eq!(iters.len(), 2);
+        db.put(b"k2", b"data").unwrap();
+        db.put_cf(meta, b"k2", b"meta").unwrap();
+
+        // Neither sees the writes made after they were created.
+        let mut meta_iter = iters.pop().unwrap();
+        let mut data_iter = iters.pop().unwrap();
+        data_iter.seek(SeekKey::Start);
+        meta_iter.seek(SeekKey::Start);
+        assert_eq!(data_iter.collect::<Vec<_>>(),
+                   vec![(b"k1".to_vec(), b"data".to_vec())]);
+        drop(data_iter);
+        assert_eq!(meta_iter.collect::<Vec<_>>(),
+                   vec![(b"k1".to_vec(), b"meta".to_vec())]);
+        assert!(db.new_iterators(&[], ReadOptions::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn multi_get_sorted_test() {
+        let path = TempDir::new("_rust_rocksdb_multigetsortedtest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let mut rev_opts = Options::new();
+        rev_opts.add_comparator("reverse", reverse_compare);
+        let db = DB::open_cf(&opts, path_str, &["rev"], &[&rev_opts]).unwrap();
+        let rev = *db.cf_handle("rev").unwrap();
+        for k in &[b"k1", b"k3", b"k5"] {
+            db.put(*k, *k).unwrap();
+            db.put_cf(rev, *k, b"r").unwrap();
+        }
+        db.flush(true).unwrap();
+
+        let keys: Vec<&[u8]> = vec![b"k1", b"k2", b"k3", b"k5"];
+        let values: Vec<_> = db.multi_get_sorted(&keys)
+            .into_iter()
+            .map(|v| v.unwrap().map(|v| v.into_vec()))
+            .collect();
+        assert_eq!(values,
+                   vec![Some(b"k1".to_vec()),
+                        None,
+                        Some(b"k3".to_vec()),
+                        Some(b"k5".to_vec())]);
+        let unsorted: Vec<&[u8]> = vec![b"k3", b"k1"];
+        let values = db.multi_get_sorted(&unsorted);
+        assert_eq!(values.len(), 2);
+        assert!(values.iter().all(|v| v.is_err()));
+        assert!(db.multi_get_sorted(&[]).is_empty());
+
+        // The order is checked with the column family comparator.
+        let rev_keys: Vec<&[u8]> = vec![b"k5", b"k1"];
+        assert!(db.multi_get_sorted_cf(rev, &keys).iter().all(|v| v.is_err()));
+        let default = *db.cf_handle("default").unwrap();
+        let values: Vec<Vec<_>> =
+            db.multi_get_sorted_grouped(&[(default, &keys[..2]),
+                                          (rev, &rev_keys[..])],
+                                        &ReadOptions::new())
+                .into_iter()
+                .map(|group| {
+                    group.into_iter()
+                        .map(|v| v.unwrap().map(|v| v.into_vec()))
+                        .collect()
+                })
+                .collect();
+        assert_eq!(values,
+                   vec![vec![Some(b"k1".to_vec()), None],
+                        vec![Some(b"r".to_vec()), Some(b"r".to_vec())]]);
+    }
+
+    #[test]
+    fn get_value_size_test() {
+        let path = TempDir::new("_rust_rocksdb_valuesizetest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", &[0; 4096]).unwrap();
+        db.put(b"k2", b"").unwrap();
+        assert_eq!(db.get_value_size(b"k1").unwrap(), Some(4096));
+        db.flush(true).unwrap();
+        assert_eq!(db.get_value_size(b"k1").unwrap(), Some(4096));
+        assert_eq!(db.get_value_size(b"k2").unwrap(), Some(0));
+        assert_eq!(db.get_value_size(b"k3").unwrap(), None);
+    }
+
+    #[test]
+    fn get_with_test() {
+        let path = TempDir::new("_rust_rocksdb_getwithtest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        for i in 0..10u8 {
+            db.put(&[b'k', i], &[i; 3]).unwrap();
+        }
+        assert_eq!(db.get_with(&[b'k', 4], |v| v.to_vec()).unwrap(),
+                   Some(vec![4; 3]));
+        assert_eq!(db.get_with(b"missing", |v| v.len()).unwrap(), None);
+
+        let mut sum = 0;
+        db.scan_with(&Range::new(&[b'k', 2], &[b'k', 8]), |k, v| {
+                sum += v[0] as usize;
+                k[1] < 5
+            })
+            .unwrap();
+        assert_eq!(sum, 2 + 3 + 4 + 5);
+    }
+
+    #[test]
+    fn open_cf_checked_test() {
+        let path = TempDir::new("_rust_rocksdb_opencfcheckedtest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.add_merge_operator("concat", concat_merge);
+        {
+            let (db, changes) = DB::open_cf_checked(&opts, path_str, &[], &[])
+                .unwrap();
+            assert!(changes.is_empty());
+            db.merge(b"k1", b"a").unwrap();
+        }
+
+        let mut plain_opts = Options::new();
+        let err = DB::open_cf_checked(&plain_opts, path_str, &[], &[])
+            .err()
+            .unwrap();
+        assert!(err.contains("merge operator concat"), "{}", err);
+        plain_opts.add_comparator("reverse", reverse_compare);
+        plain_opts.add_merge_operator("concat", concat_merge);
+        let err = DB::open_cf_checked(&plain_opts, path_str, &[], &[])
+            .err()
+            .unwrap();
+        assert!(err.contains("comparator"), "{}", err);
+
+        opts.set_write_buffer_size(1 << 20);
+        let (db, changes) = DB::open_cf_checked(&opts, path_str, &[], &[])
+            .unwrap();
+        assert!(changes.iter().any(|c| {
+                    c.cf.as_ref().map(|cf| cf.as_str()) == Some("default") &&
+                    c.name == "write_buffer_size" &&
+                    c.new.as_ref().map(|v| v.as_str()) == Some("1048576")
+                }),
+                "{:?}",
+                changes);
+        assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"a");
+    }
+
+    #[test]
+    fn apply_wal_batch_test() {
+        let path = TempDir::new("_rust_rocksdb_applywaltest").expect("");
+        let primary = Arc::new(DB::open_default(path.path().to_str().unwrap())
+            .unwrap());
+        let replica_path = TempDir::new("_rust_rocksdb_applywaltest_replica")
+            .expect("");
+        let replica = DB::open_default(replica_path.path().to_str().unwrap())
+            .unwrap();
+
+        primary.put(b"k1", b"v1").unwrap();
+        let batch = WriteBatch::new();
+        batch.put(b"k2", b"v2").unwrap();
+        batch.delete(b"k1").unwrap();
+        primary.write(batch).unwrap();
+        primary.put(b"k3", b"v3").unwrap();
+
+        let reader = WalReader::open(primary.clone(), "replica").unwrap();
+        let batches: Vec<Vec<u8>> = reader.poll(10)
+            .unwrap()
+            .iter()
+            .map(|b| b.1.data().to_vec())
+            .collect();
+        assert_eq!(batches.len(), 3);
+
+        assert_eq!(replica.apply_wal_batch(&batches[0], 1).unwrap(), 2);
+        // A gap or a batch applied twice is rejected.
+        assert!(replica.apply_wal_batch(&batches[2], 2).is_err());
+        assert!(replica.apply_wal_batch(&batches[0], 2).is_err());
+        assert!(replica.apply_wal_batch(&batches[1], 3).is_err());
+
+        let mut writeopts = WriteOptions::new();
+        writeopts.disable_wal(true);
+        let rest: Vec<&[u8]> = batches[1..].iter().map(|b| &b[..]).collect();
+        assert_eq!(replica.apply_wal_batches_opt(&rest, 2, &writeopts)
+                       .unwrap(),
+                   5);
+        assert!(replica.get(b"k1").unwrap().is_none());
+        assert_eq!(&*replica.get(b"k2").unwrap().unwrap(), b"v2");
+        assert_eq!(&*replica.get(b"k3").unwrap().unwrap(), b"v3");
+    }
+
+    #[test]
+    fn space_amp_report_test() {
+        let path = TempDir::new("_rust_rocksdb_spaceamptest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        let report = db.space_amp_report(cf).unwrap();
+        assert!(report.levels.is_empty());
+        assert!(report.hints.is_empty());
+
+        for i in 0..100 {
+            db.put(format!("k{:03}", i).as_bytes(), b"value").unwrap();
+        }
+        db.flush(true).unwrap();
+        for i in 20..60 {
+            db.delete(format!("k{:03}", i).as_bytes()).unwrap();
+        }
+        db.flush(true).unwrap();
+
+        let files = db.live_files();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| f.cf == "default" && f.level == 0));
+        assert_eq!(files.iter().map(|f| f.deletions).sum::<u64>(), 40);
+
+        let report = db.space_amp_report(cf).unwrap();
+        assert_eq!(report.levels.len(), 1);
+        let level = &report.levels[0];
+        assert_eq!((level.level, level.files), (0, 2));
+        assert_eq!((level.entries, level.deletions), (140, 40));
+        assert_eq!(level.size_amp, 1.0);
+        assert_eq!(report.sst_files_size, level.size);
+        // Only the file of deletions is worth compacting.
+        assert!(report.hints.len() >= 1);
+        let hint = report.hints
+            .iter()
+            .find(|h| h.start == b"k020".to_vec())
+            .unwrap();
+        assert_eq!(hint.end, b"k059".to_vec());
+        assert!(hint.reclaimable > 0);
+    }
+
+    #[test]
+    fn range_tombstones_test() {
+        let path = TempDir::new("_rust_rocksdb_rangetombstonestest")
+            .expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        for i in 0..100 {
+            db.put(format!("k{:03}", i).as_bytes(), b"value").unwrap();
         }
+        db.flush(true).unwrap();
+        assert_eq!(db.range_tombstones(cf).unwrap(),
+                   RangeTombstones::default());
+
+        db.delete_range(b"k010", b"k020").unwrap();
+        db.delete_range(b"k050", b"k060").unwrap();
+        // Not flushed yet.
+        assert_eq!(db.range_tombstones(cf).unwrap().count, 0);
+        db.flush(true).unwrap();
+
+        let tombstones = db.range_tombstones(cf).unwrap();
+        assert_eq!(tombstones.count, 2);
+        assert_eq!(tombstones.levels, vec![(0, 2)]);
+        assert_eq!(tombstones.spans.len(), 1);
+        assert_eq!(tombstones.spans[0].start, b"k000".to_vec());
+        assert_eq!(tombstones.spans[0].end, b"k099".to_vec());
     }
 
-    fn delete(&self, key: &[u8]) -> Result<(), String> {
+    #[test]
+    fn table_property_test() {
+        let props = "# entries=2; # range deletions=1; raw key size=20";
+        assert_eq!(table_property(props, "# entries"), 2);
+        assert_eq!(table_property(props, "# range deletions"), 1);
+        assert_eq!(table_property(props, "raw key size"), 20);
+        assert_eq!(table_property(props, "# deletions"), 0);
+    }
+
+    #[test]
+    fn snapshot_handle_test() {
+        let path = TempDir::new("_rust_rocksdb_snapshothandletest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        let handle = db.snapshot_handle();
+        db.put(b"k1", b"v2").unwrap();
+        assert_eq!(db.live_snapshot_handles(), 1);
+
+        // Kept by a registry as a raw pointer.
+        let raw = handle.into_raw();
+        let handle = unsafe { SnapshotHandle::from_raw(raw) };
+        let mut readopts = ReadOptions::new();
         unsafe {
-            rocksdb_ffi::rocksdb_writebatch_delete(self.inner,
-                                                   key.as_ptr(),
-                                                   key.len() as size_t);
-            Ok(())
+            readopts.set_snapshot(handle.as_unsafe_snap());
         }
-    }
+        assert_eq!(&*db.get_opt(b"k1", &readopts).unwrap().unwrap(), b"v1");
+        drop(readopts);
 
-    fn delete_cf(&self, cf: DBCFHandle, key: &[u8]) -> Result<(), String> {
         unsafe {
-            rocksdb_ffi::rocksdb_writebatch_delete_cf(self.inner,
-                                                      cf,
-                                                      key.as_ptr(),
-                                                      key.len() as size_t);
-            Ok(())
+            db.release_snapshot_handle(handle);
         }
+        assert_eq!(db.live_snapshot_handles(), 0);
     }
-}
 
-impl Drop for ReadOptions {
-    fn drop(&mut self) {
-        unsafe { rocksdb_ffi::rocksdb_readoptions_destroy(self.inner) }
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "snapshot handles")]
+    fn snapshot_handle_leak_test() {
+        let path = TempDir::new("_rust_rocksdb_snapshotleaktest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.snapshot_handle().into_raw();
     }
-}
 
-impl Default for ReadOptions {
-    fn default() -> ReadOptions {
-        unsafe {
-            ReadOptions { inner: rocksdb_ffi::rocksdb_readoptions_create() }
+    #[test]
+    fn try_write_test() {
+        let path = TempDir::new("_rust_rocksdb_trywritetest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        opts.set_level_zero_slowdown_writes_trigger(2);
+        opts.set_level_zero_stop_writes_trigger(2);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+
+        let batch = WriteBatch::new();
+        batch.put(b"k1", b"v1").unwrap();
+        db.try_write(batch).unwrap();
+        db.flush(true).unwrap();
+        assert_eq!(db.write_stall_state(), None);
+
+        // A second L0 file reaches the stop trigger.
+        db.put(b"k2", b"v2").unwrap();
+        db.flush(true).unwrap();
+        assert_eq!(db.write_stall_state(), Some(StallReason::Stopped));
+        let batch = WriteBatch::new();
+        batch.put(b"k3", b"v3").unwrap();
+        match db.try_write(batch) {
+            Err(TryWriteError { error, batch }) => {
+                assert_eq!(error, Error::WriteStall(StallReason::Stopped));
+                assert_eq!(batch.count(), 1)
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(db.get(b"k3").unwrap().is_none());
+
+        let res = db.try_write_opt(WriteBatch::new(), &WriteOptions::new());
+        match res {
+            Err(TryWriteError { error: Error::InvalidArgument(e), .. }) => {
+                assert!(e.contains("no_slowdown"), "{}", e)
+            }
+            res => panic!("unexpected result {:?}", res),
         }
     }
-}
 
-impl ReadOptions {
-    pub fn new() -> ReadOptions {
-        ReadOptions::default()
+    #[test]
+    fn get_into_test() {
+        let path = TempDir::new("_rust_rocksdb_getintotest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", &[1; 100]).unwrap();
+        db.put(b"k2", b"v2").unwrap();
+
+        let mut buf = Vec::with_capacity(256);
+        assert!(db.get_into(b"k1", &mut buf).unwrap());
+        assert_eq!(buf, vec![1; 100]);
+        assert!(db.get_into(b"k2", &mut buf).unwrap());
+        assert_eq!(&buf[..], b"v2");
+        assert!(buf.capacity() >= 256);
+        assert!(!db.get_into(b"k3", &mut buf).unwrap());
+        assert!(buf.is_empty());
     }
-    // TODO add snapshot setting here
-    // TODO add snapshot wrapper structs with proper destructors;
-    // that struct needs an "iterator" impl too.
-    #[allow(dead_code)]
-    pub fn fill_cache(&mut self, v: bool) {
-        unsafe {
-            rocksdb_ffi::rocksdb_readoptions_set_fill_cache(self.inner, v);
-        }
+
+    #[test]
+    fn read_deadline_test() {
+        let path = TempDir::new("_rust_rocksdb_deadlinetest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.flush(true).unwrap();
+
+        let mut readopts = ReadOptions::new();
+        readopts.fill_cache(false);
+        readopts.set_deadline(UNIX_EPOCH);
+        let err = db.get_opt(b"k1", &readopts).unwrap_err();
+        assert!(err.starts_with("Operation timed out"), "{}", err);
+
+        let mut readopts = ReadOptions::new();
+        readopts.set_deadline(SystemTime::now() + Duration::from_secs(60));
+        readopts.set_io_timeout(Duration::from_secs(10));
+        assert!(db.get_opt(b"k1", &readopts).unwrap().is_some());
     }
 
-    pub unsafe fn set_snapshot(&mut self, snapshot: &UnsafeSnap) {
-        rocksdb_ffi::rocksdb_readoptions_set_snapshot(self.inner,
-                                                      snapshot.inner);
+    #[test]
+    fn try_flush_test() {
+        let path = TempDir::new("_rust_rocksdb_tryflushtest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.try_flush().unwrap();
+        assert!(db.wait_for_flush(Duration::from_secs(10)).unwrap());
+        assert_eq!(db.get_property_int("rocksdb.num-files-at-level0"), Ok(1));
+        let cf = *db.cf_handle("default").unwrap();
+        assert!(db.wait_for_flush_cf(cf, Duration::from_millis(0)).unwrap());
     }
-}
 
-pub struct DBVector {
-    base: *mut u8,
-    len: usize,
-}
+    #[test]
+    fn entry_overhead_test() {
+        let path = TempDir::new("_rust_rocksdb_overheadtest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_arena_block_size(64 << 10);
+        opts.set_memtable_prefix_bloom_size_ratio(0.1);
+        opts.set_memtable_whole_key_filtering(true);
+        opts.set_max_successive_merges(8);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        for i in 0..100 {
+            db.put(format!("k{:04}", i).as_bytes(), b"v").unwrap();
+        }
+        db.flush(true).unwrap();
+        for i in 0..50 {
+            db.put(format!("k{:04}", i).as_bytes(), b"w").unwrap();
+        }
 
-impl Deref for DBVector {
-    type Target = [u8];
-    fn deref(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self.base, self.len) }
+        let report = db.entry_overhead_cf(cf).unwrap();
+        assert_eq!(report.memtable_entries, 50);
+        assert_eq!(report.sst_entries, 100);
+        assert_eq!(report.raw_key_bytes, 100 * (5 + 8));
+        assert_eq!(report.raw_value_bytes, 100);
+        assert!(report.sst_file_bytes > 0);
+        assert!(report.memtable_bytes_per_entry() > 0.0);
+        assert!(report.raw_bytes_per_entry() > 0.0);
+        assert!(report.memtable_overhead_per_entry() > 0.0);
+        assert_eq!(EntryOverheadReport::default().sst_bytes_per_entry(), 0.0);
     }
-}
 
-impl Drop for DBVector {
-    fn drop(&mut self) {
-        unsafe {
-            libc::free(self.base as *mut c_void);
+    #[test]
+    fn counter_test() {
+        let path = TempDir::new("_rust_rocksdb_countertest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_merge_operator(UInt64AddOperator);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        assert_eq!(db.get_counter(b"c1").unwrap(), None);
+        db.increment(b"c1", 5).unwrap();
+        db.increment(b"c1", 10).unwrap();
+        assert_eq!(db.get_counter(b"c1").unwrap(), Some(15));
+        db.flush(true).unwrap();
+        db.increment(b"c1", 1u64.wrapping_neg()).unwrap();
+        db.compact_range(None, None);
+        assert_eq!(db.get_counter(b"c1").unwrap(), Some(14));
+
+        // Values of the wrong size count as 0, but don't read as counters.
+        db.put(b"c2", b"abc").unwrap();
+        assert!(db.get_counter(b"c2").is_err());
+        db.increment(b"c2", 3).unwrap();
+        assert_eq!(db.get_counter(b"c2").unwrap(), Some(3));
+        let cf = *db.cf_handle("default").unwrap();
+        db.increment_cf(cf, b"c3", 7).unwrap();
+        assert_eq!(db.get_counter_cf(cf, b"c3").unwrap(), Some(7));
+    }
+
+    #[test]
+    fn default_options_across_threads_test() {
+        let path = TempDir::new("_rust_rocksdb_defaultoptstest").expect("");
+        let db = Arc::new(DB::open_default(path.path().to_str().unwrap())
+            .unwrap());
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let key = format!("k{}-{}", t, i);
+                        db.put(key.as_bytes(), b"v").unwrap();
+                        assert!(db.get(key.as_bytes()).unwrap().is_some());
+                        db.delete(key.as_bytes()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
         }
+        assert!(db.get(b"k0-0").unwrap().is_none());
     }
-}
 
-impl DBVector {
-    pub fn from_c(val: *mut u8, val_len: size_t) -> DBVector {
-        DBVector {
-            base: val,
-            len: val_len as usize,
+    #[test]
+    fn level_sizes_test() {
+        let path = TempDir::new("_rust_rocksdb_levelsizestest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_disable_auto_compactions(true);
+        opts.set_level_zero_file_num_compaction_trigger(4);
+        opts.set_max_bytes_for_level_base(1 << 20);
+        opts.set_max_bytes_for_level_multiplier(10.0);
+        opts.set_level_compaction_dynamic_level_bytes(false);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        for i in 0..2 {
+            db.put(format!("k{}", i).as_bytes(), b"v").unwrap();
+            db.flush(true).unwrap();
         }
+
+        let sizes = db.level_sizes_cf(cf, &opts).unwrap();
+        assert_eq!(sizes.levels.len(), opts.get_num_levels() as usize);
+        let l0 = &sizes.levels[0];
+        assert_eq!((l0.files, l0.target_size, l0.fill_ratio), (2, None, 0.5));
+        let targets: Vec<_> =
+            sizes.levels[1..3].iter().map(|l| l.target_size).collect();
+        assert_eq!(targets, vec![Some(1 << 20), Some(10 << 20)]);
+        assert!(sizes.levels[1..].iter().all(|l| l.fill_ratio == 0.0));
+
+        db.compact_range(None, None);
+        let sizes = db.level_sizes_cf(cf, &opts).unwrap();
+        assert_eq!(sizes.levels[0].files, 0);
+        let filled: Vec<_> =
+            sizes.levels.iter().filter(|l| l.fill_ratio > 0.0).collect();
+        assert_eq!(filled.len(), 1);
+        assert_eq!(filled[0].files, 1);
     }
 
-    pub fn to_utf8(&self) -> Option<&str> {
-        from_utf8(self.deref()).ok()
+    #[test]
+    fn toggle_auto_compactions_test() {
+        let path = TempDir::new("_rust_rocksdb_autocompacttest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_level_zero_file_num_compaction_trigger(2);
+        let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        let l0 = || db.get_property_int("rocksdb.num-files-at-level0").unwrap();
+
+        db.disable_auto_compactions(&[cf]).unwrap();
+        for i in 0..4 {
+            db.put(format!("k{}", i).as_bytes(), b"v").unwrap();
+            db.flush(true).unwrap();
+        }
+        assert_eq!(l0(), 4);
+
+        db.enable_auto_compactions(&[cf]).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while l0() >= 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(l0() < 2);
+        assert!(db.set_options_cf(cf, &[("no_such_option", "1")]).is_err());
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use rocksdb_options::*;
-    use std::str;
-    use tempdir::TempDir;
+    #[test]
+    fn health_snapshot_test() {
+        let path = TempDir::new("_rust_rocksdb_healthtest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.put(b"k2", b"v2").unwrap();
+        let h = db.get_health_snapshot();
+        assert_eq!(h.num_files_at_level0, Some(0));
+        assert!(h.cur_size_all_memtables.unwrap() > 0);
+        assert_eq!(h.estimate_num_keys, Some(2));
+
+        db.flush(true).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        let h = db.get_health_snapshot_cf(cf);
+        assert_eq!(h.num_files_at_level0, Some(1));
+        assert_eq!(h.num_immutable_memtables, Some(0));
+    }
 
     #[test]
-    fn external() {
-        let path = TempDir::new("_rust_rocksdb_externaltest").expect("");
+    fn dbvector_test() {
+        let path = TempDir::new("_rust_rocksdb_dbvectortest").expect("");
         let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
-        let p = db.put(b"k1", b"v1111");
-        assert!(p.is_ok());
-        let r: Result<Option<DBVector>, String> = db.get(b"k1");
-        assert!(r.unwrap().unwrap().to_utf8().unwrap() == "v1111");
-        assert!(db.delete(b"k1").is_ok());
-        assert!(db.get(b"k1").unwrap().is_none());
+        db.put(b"k1", b"v1111").unwrap();
+        let v = db.get(b"k1").unwrap().unwrap();
+        assert_eq!(v, &b"v1111"[..]);
+        assert_eq!(v.as_ref(), b"v1111");
+        assert_eq!(format!("{:?}", v), format!("{:?}", &b"v1111"[..]));
+        assert_eq!(v, db.get(b"k1").unwrap().unwrap());
+        assert_eq!(v.into_vec(), b"v1111".to_vec());
+        let v: Vec<u8> = db.get(b"k1").unwrap().unwrap().into();
+        assert_eq!(v, b"v1111".to_vec());
     }
 
-    #[allow(unused_variables)]
     #[test]
-    fn errors_do_stuff() {
-        let path = TempDir::new("_rust_rocksdb_error").expect("");
+    fn disk_usage_test() {
+        let path = TempDir::new("_rust_rocksdb_diskusagetest").expect("");
         let path_str = path.path().to_str().unwrap();
         let db = DB::open_default(path_str).unwrap();
-        let opts = Options::new();
-        // The DB will still be open when we try to destroy and the lock should fail
-        match DB::destroy(&opts, path_str) {
-            Err(ref s) => assert!(s.contains("LOCK: No locks available")),
-            Ok(_) => panic!("should fail"),
-        }
+        db.put(b"k1", b"v1").unwrap();
+        let usage = db.get_disk_usage().unwrap();
+        assert_eq!(usage.sst_files_size[path_str], 0);
+        assert!(usage.wal_files_size[path_str] > 0);
+
+        db.flush(true).unwrap();
+        let usage = db.get_disk_usage().unwrap();
+        assert!(usage.sst_files_size[path_str] > 0);
+        assert_eq!(usage.blob_files_size[path_str], 0);
+        assert!(usage.total() >= usage.sst_files_size[path_str]);
+        drop(db);
+
+        // WAL files in a directory of their own are counted too.
+        let path = TempDir::new("_rust_rocksdb_diskusagewaltest").expect("");
+        let db_path = path.path().join("db");
+        let wal_path = path.path().join("wal");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_wal_dir(wal_path.to_str().unwrap());
+        let db = DB::open(&opts, db_path.to_str().unwrap()).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        let usage = db.get_disk_usage().unwrap();
+        assert!(usage.wal_files_size[wal_path.to_str().unwrap()] > 0);
+        assert!(!usage.wal_files_size.contains_key(db_path.to_str().unwrap()));
     }
 
     #[test]
-    fn writebatch_works() {
-        let path = TempDir::new("_rust_rocksdb_writebacktest").expect("");
+    fn estimate_range_size_test() {
+        let path = TempDir::new("_rust_rocksdb_estimatesizetest").expect("");
         let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        for i in 0..1000 {
+            let v = vec![b'v'; 10 + i % 100];
+            db.put(format!("{:04}", i).as_bytes(), &v).unwrap();
+        }
+        let range = Range::new(b"0000", b"1000");
+        let e = db.estimate_range_size(&range, 50).unwrap();
+        assert_eq!(e.estimated_num_keys, 1000);
+        assert!(e.value_sizes.len() > 1 && e.value_sizes.len() <= 50);
+        assert_eq!(e.avg_key_size, 4.0);
+        assert!(e.value_size_percentile(0.0) >= 10);
+        assert!(e.value_size_percentile(100.0) < 110);
 
-        // test put
-        let batch = WriteBatch::new();
-        assert!(db.get(b"k1").unwrap().is_none());
-        assert_eq!(batch.count(), 0);
-        assert!(batch.is_empty());
-        let _ = batch.put(b"k1", b"v1111");
-        assert_eq!(batch.count(), 1);
-        assert!(!batch.is_empty());
-        assert!(db.get(b"k1").unwrap().is_none());
-        let p = db.write(batch);
-        assert!(p.is_ok());
-        let r: Result<Option<DBVector>, String> = db.get(b"k1");
-        assert!(r.unwrap().unwrap().to_utf8().unwrap() == "v1111");
+        db.flush(true).unwrap();
+        let e = db.estimate_range_size(&range, 50).unwrap();
+        assert!(e.estimated_num_keys > 0);
+        assert!(e.estimated_logical_size > 0);
+
+        let e = db.estimate_range_size(&Range::new(b"2000", b"3000"), 10)
+            .unwrap();
+        assert!(e.value_sizes.is_empty());
+        assert_eq!(e.estimated_logical_size, 0);
+        assert_eq!(e.value_size_percentile(50.0), 0);
+        assert!(db.estimate_range_size(&Range::new(b"3000", b"2000"), 10)
+            .is_err());
 
-        // test delete
-        let batch = WriteBatch::new();
-        let _ = batch.delete(b"k1");
-        assert_eq!(batch.count(), 1);
-        assert!(!batch.is_empty());
-        let p = db.write(batch);
-        assert!(p.is_ok());
-        assert!(db.get(b"k1").unwrap().is_none());
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let mut rev_opts = Options::new();
+        rev_opts.add_comparator("reverse", reverse_compare);
+        drop(db);
+        let db = DB::open_cf(&opts,
+                             path.path().to_str().unwrap(),
+                             &["rev"],
+                             &[&rev_opts])
+            .unwrap();
+        let rev = *db.cf_handle("rev").unwrap();
+        for i in 0..100 {
+            db.put_cf(rev, format!("{:04}", i).as_bytes(), b"value")
+                .unwrap();
+        }
+        let range = Range::new(b"0099", b"0000");
+        let e = db.estimate_range_size_cf(rev, &range, 10).unwrap();
+        assert_eq!(e.value_sizes, vec![5; 10]);
+        let range = Range::new(b"0000", b"0099");
+        assert!(db.estimate_range_size_cf(rev, &range, 10).is_err());
     }
 
     #[test]
-    fn iterator_test() {
-        let path = TempDir::new("_rust_rocksdb_iteratortest").expect("");
-
-        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
-        db.put(b"k1", b"v1111").expect("");
-        db.put(b"k2", b"v2222").expect("");
-        db.put(b"k3", b"v3333").expect("");
-        let mut iter = db.iter();
-        iter.seek(SeekKey::Start);
-        for (k, v) in &mut iter {
-            println!("Hello {}: {}",
-                     str::from_utf8(&*k).unwrap(),
-                     str::from_utf8(&*v).unwrap());
+    fn interpolate_key_test() {
+        assert_eq!(interpolate_key(b"a", b"b", 0, 2), b"a".to_vec());
+        let mid = interpolate_key(b"a", b"b", 1, 2);
+        assert!(&mid[..] > &b"a"[..] && &mid[..] < &b"b"[..]);
+        assert_eq!(interpolate_key(b"b", b"a", 1, 2), b"b".to_vec());
+        let keys: Vec<_> = (0..10)
+            .map(|i| interpolate_key(b"k0000", b"k9999", i, 10))
+            .collect();
+        for w in keys.windows(2) {
+            assert!(w[0] < w[1]);
         }
     }
 
     #[test]
-    fn approximate_size_test() {
-        let path = TempDir::new("_rust_rocksdb_iteratortest").expect("");
-        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
-        for i in 1..8000 {
-            db.put(format!("{:04}", i).as_bytes(),
-                     format!("{:04}", i).as_bytes())
-                .expect("");
-        }
-        db.flush(true).expect("");
-        assert!(db.get(b"0001").expect("").is_some());
-        db.flush(true).expect("");
-        let sizes = db.get_approximate_sizes(&[Range::new(b"0000", b"2000"),
-                                               Range::new(b"2000", b"4000"),
-                                               Range::new(b"4000", b"6000"),
-                                               Range::new(b"6000", b"8000"),
-                                               Range::new(b"8000", b"9999")]);
-        assert_eq!(sizes.len(), 5);
-        for s in &sizes[0..4] {
-            assert!(*s > 0);
+    fn flush_cf_test() {
+        let path = TempDir::new("_rust_rocksdb_flushcftest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let mut db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = db.create_cf("cf", &opts).unwrap();
+        db.put(b"k1", b"v1").unwrap();
+        db.put_cf(cf, b"k1", b"v1").unwrap();
+
+        let mut flush_opts = FlushOptions::new();
+        flush_opts.set_wait(true);
+        flush_opts.set_allow_write_stall(true);
+        db.flush_cf(cf, &flush_opts).unwrap();
+        assert_eq!(db.live_files_cf(cf).unwrap().len(), 1);
+        assert!(db.live_files_cf(db.cfs["default"]).unwrap().is_empty());
+        assert_eq!(&*db.get_cf(cf, b"k1").unwrap().unwrap(), b"v1");
+    }
+
+    #[test]
+    fn prewarm_test() {
+        let path = TempDir::new("_rust_rocksdb_prewarmtest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let cache = Cache::new_lru(8 << 20);
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_block_cache(&cache);
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_block_based_table_factory(&block_opts);
+        {
+            let db = DB::open(&opts, path_str).unwrap();
+            for i in 0..100 {
+                db.put(format!("k{:03}", i).as_bytes(), &[0; 100]).unwrap();
+            }
+            db.flush(true).unwrap();
         }
-        assert_eq!(sizes[4], 0);
+
+        let db = DB::open(&opts, path_str).unwrap();
+        let cf = *db.cf_handle("default").unwrap();
+        let before = cache.get_usage();
+        let read = db.prewarm(cf,
+                              &[Range::new(b"k000", b"k010"),
+                                Range::new(b"k050", b"k100")])
+            .unwrap();
+        assert_eq!(read, 60 * 104);
+        assert!(cache.get_usage() > before);
+        assert!(db.prewarm(cf, &[Range::new(b"k2", b"k1")]).is_err());
+        assert_eq!(db.prewarm(cf, &[]).unwrap(), 0);
     }
 
     #[test]
-    fn property_test() {
-        let path = TempDir::new("_rust_rocksdb_propertytest").expect("");
+    fn count_range_test() {
+        let path = TempDir::new("_rust_rocksdb_countrangetest").expect("");
         let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
-        db.put(b"a1", b"v1").unwrap();
-        db.flush(true).unwrap();
-        let prop_name = "rocksdb.total-sst-files-size";
-        let st1 = db.get_property_int(prop_name).unwrap();
-        assert!(st1 > 0);
-        db.put(b"a2", b"v2").unwrap();
-        db.flush(true).unwrap();
-        let st2 = db.get_property_int(prop_name).unwrap();
-        assert!(st2 > st1);
+        for i in 0..100 {
+            db.put(format!("{:03}", i).as_bytes(), b"v").unwrap();
+        }
+        assert_eq!(db.count_range(b"000", b"100", 1000).unwrap(), 100);
+        assert_eq!(db.count_range(b"010", b"020", 1000).unwrap(), 10);
+        assert_eq!(db.count_range(b"010", b"020", 5).unwrap(), 5);
+        assert_eq!(db.count_range(b"200", b"300", 1000).unwrap(), 0);
+        let cf = *db.cf_handle("default").unwrap();
+        assert_eq!(db.count_range_cf(cf, b"050", b"999", 1000).unwrap(), 50);
     }
 }
 
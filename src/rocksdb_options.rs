@@ -12,26 +12,94 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
-use libc::{c_int, size_t};
-use std::ffi::CString;
+use libc::{self, c_char, c_int, c_uint, c_void, size_t};
+use std::ffi::{CStr, CString};
 use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
 
-use rocksdb_ffi::{self, DBCompressionType};
+use rocksdb_ffi::{self, DBChecksumType, DBCompressionType,
+                  DBDataBlockIndexType};
+#[cfg(feature = "cache-dump")]
+use rocksdb_ffi::error_message;
+#[cfg(feature = "cache-dump")]
+use rocksdb::DB;
 use merge_operator::{self, MergeOperatorCallback, full_merge_callback,
                      partial_merge_callback};
 use comparator::{self, ComparatorCallback, compare_callback};
-use merge_operator::MergeFn;
+use compaction_filter::{self, CompactionFilter, CompactionFilterCallback};
+use env::Env;
+use merge_operator::{FnMergeOperator, MergeFn, MergeOperator};
+use metrics;
+
+const DEFAULT_COMPARATOR_NAME: &'static str = "leveldb.BytewiseComparator";
 
 pub struct BlockBasedOptions {
     inner: rocksdb_ffi::DBBlockBasedTableOptions,
 }
 
+/// Options to open a DB or create a column family with.
+///
+/// Rocksdb copies the options it is given, so the same `Options` can be
+/// used to open any number of DBs, from any thread. Cloning makes a deep
+/// copy, merge operators, comparators and table factories are shared by
+/// the copies.
+///
+/// Each column family can be given its own merge operator and comparator
+/// through the options passed to `DB::open_cf` or `DB::create_cf`, they
+/// are kept alive until the DB is dropped.
 pub struct Options {
     pub inner: rocksdb_ffi::DBOptions,
+    // Rocksdb only keeps a raw pointer to the comparator, so it is owned
+    // here and by the DBs opened with these options.
+    comparator: Option<Arc<ComparatorHandle>>,
+    // Same for the compaction filter.
+    compaction_filter: Option<Arc<CompactionFilterHandle>>,
+    merge_operator_name: Option<String>,
+    db_paths: Vec<String>,
+    wal_dir: Option<String>,
+}
+
+struct ComparatorHandle {
+    inner: rocksdb_ffi::DBComparator,
+    name: String,
+    compare_fn: fn(&[u8], &[u8]) -> i32,
+}
+
+unsafe impl Send for ComparatorHandle {}
+unsafe impl Sync for ComparatorHandle {}
+
+impl Drop for ComparatorHandle {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_comparator_destroy(self.inner);
+        }
+    }
+}
+
+struct CompactionFilterHandle {
+    inner: rocksdb_ffi::DBCompactionFilter,
+    name: String,
 }
 
+unsafe impl Send for CompactionFilterHandle {}
+unsafe impl Sync for CompactionFilterHandle {}
+
+impl Drop for CompactionFilterHandle {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_compactionfilter_destroy(self.inner);
+        }
+    }
+}
+
+// Options are only ever read by rocksdb, and changed through `&mut self`.
+unsafe impl Send for Options {}
+unsafe impl Sync for Options {}
+
 pub struct WriteOptions {
     pub inner: rocksdb_ffi::DBWriteOptions,
+    no_slowdown: bool,
 }
 
 impl Drop for Options {
@@ -58,6 +126,249 @@ impl Drop for WriteOptions {
     }
 }
 
+/// Limits the rate of flush and compaction writes.
+///
+/// The limiter is shared with the DBs it is set on, so changes made
+/// through it after they are opened still take effect.
+pub struct RateLimiter {
+    inner: rocksdb_ffi::DBRateLimiter,
+}
+
+unsafe impl Send for RateLimiter {}
+unsafe impl Sync for RateLimiter {}
+
+impl Drop for RateLimiter {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ratelimiter_destroy(self.inner);
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing `rate_bytes_per_sec`, refilled every
+    /// `refill_period_us`. Low priority requests are let through ahead of
+    /// high priority ones once every `fairness` times.
+    pub fn new(rate_bytes_per_sec: i64,
+               refill_period_us: i64,
+               fairness: i32)
+               -> RateLimiter {
+        let limiter = unsafe {
+            rocksdb_ffi::rocksdb_ratelimiter_create(rate_bytes_per_sec,
+                                                    refill_period_us,
+                                                    fairness)
+        };
+        RateLimiter { inner: limiter }
+    }
+
+    /// Create a limiter that tunes its rate between 1/20 of
+    /// `rate_bytes_per_sec` and `rate_bytes_per_sec` by how often it
+    /// throttles, so compactions speed up when the disk is idle.
+    pub fn new_auto_tuned(rate_bytes_per_sec: i64,
+                          refill_period_us: i64,
+                          fairness: i32)
+                          -> RateLimiter {
+        let limiter = unsafe {
+            rocksdb_ffi::rocksdb_ratelimiter_create_auto_tuned(rate_bytes_per_sec,
+                                                               refill_period_us,
+                                                               fairness)
+        };
+        RateLimiter { inner: limiter }
+    }
+
+    /// Change the rate, for an auto tuned limiter this is the upper bound.
+    pub fn set_bytes_per_second(&self, rate_bytes_per_sec: i64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ratelimiter_set_bytes_per_second(self.inner,
+                                                                  rate_bytes_per_sec);
+        }
+    }
+}
+
+/// A block cache, which can be shared by several DBs and column families
+/// to bound their total memory use.
+pub struct Cache {
+    inner: rocksdb_ffi::DBCache,
+    // Copies of the options whose statistics count the blocks of this
+    // cache, sharing their statistics.
+    stats_opts: Mutex<Vec<Options>>,
+}
+
+/// Block cache counters, see `Cache::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Blocks added to the cache.
+    pub inserts: u64,
+    /// Blocks that could not be added, the cache being full of pinned
+    /// blocks.
+    pub insert_failures: u64,
+    pub data_hits: u64,
+    pub data_misses: u64,
+    pub index_hits: u64,
+    pub index_misses: u64,
+    pub filter_hits: u64,
+    pub filter_misses: u64,
+}
+
+impl CacheStats {
+    /// The ratio of lookups that hit, 0 if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        if self.hits + self.misses == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / (self.hits + self.misses) as f64
+    }
+}
+
+unsafe impl Send for Cache {}
+unsafe impl Sync for Cache {}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        // The tables using the cache keep their own reference to it.
+        unsafe {
+            rocksdb_ffi::rocksdb_cache_destroy(self.inner);
+        }
+    }
+}
+
+impl Cache {
+    pub fn new_lru(capacity: size_t) -> Cache {
+        Cache::from_raw(rocksdb_ffi::new_cache(capacity))
+    }
+
+    fn from_raw(inner: rocksdb_ffi::DBCache) -> Cache {
+        Cache {
+            inner: inner,
+            stats_opts: Mutex::new(vec![]),
+        }
+    }
+
+    /// Create a LRU cache which keeps `high_pri_pool_ratio` of its
+    /// capacity for high priority blocks, see
+    /// `BlockBasedOptions::set_cache_index_and_filter_blocks_with_high_priority`.
+    ///
+    /// Blocks read by scans can't evict the high priority ones, only other
+    /// low priority blocks.
+    pub fn new_lru_with_high_pri_pool(capacity: size_t,
+                                      high_pri_pool_ratio: f64)
+                                      -> Cache {
+        assert!(high_pri_pool_ratio >= 0.0 && high_pri_pool_ratio <= 1.0);
+        unsafe {
+            let opts = rocksdb_ffi::rocksdb_lru_cache_options_create();
+            rocksdb_ffi::rocksdb_lru_cache_options_set_capacity(opts, capacity);
+            rocksdb_ffi::rocksdb_lru_cache_options_set_high_pri_pool_ratio(opts,
+                                                                           high_pri_pool_ratio);
+            let cache = rocksdb_ffi::rocksdb_cache_create_lru_opts(opts);
+            rocksdb_ffi::rocksdb_lru_cache_options_destroy(opts);
+            Cache::from_raw(cache)
+        }
+    }
+
+    /// Memory used by the blocks in the cache.
+    pub fn get_usage(&self) -> size_t {
+        unsafe { rocksdb_ffi::rocksdb_cache_get_usage(self.inner) }
+    }
+
+    /// Memory used by the blocks pinned in the cache.
+    pub fn get_pinned_usage(&self) -> size_t {
+        unsafe { rocksdb_ffi::rocksdb_cache_get_pinned_usage(self.inner) }
+    }
+
+    /// Count the block cache tickers of the statistics of `opts` in the
+    /// `stats` of this cache.
+    ///
+    /// Rocksdb counts cache hits and misses per statistics, not per cache,
+    /// so `opts` must have been given `enable_statistics` and be used only
+    /// by the DBs whose block based tables use this cache, or the lookups
+    /// of the other caches are counted too.
+    pub fn add_statistics(&self, opts: &Options) {
+        self.stats_opts.lock().unwrap().push(opts.clone());
+    }
+
+    /// The counters of the lookups in the cache, summed over the
+    /// statistics given to `add_statistics`.
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+        for opts in self.stats_opts.lock().unwrap().iter() {
+            let dump = match opts.get_statistics() {
+                Some(dump) => dump,
+                None => continue,
+            };
+            let ticker = |name| metrics::ticker(&dump, name).unwrap_or(0);
+            stats.hits += ticker("rocksdb.block.cache.hit");
+            stats.misses += ticker("rocksdb.block.cache.miss");
+            stats.inserts += ticker("rocksdb.block.cache.add");
+            stats.insert_failures += ticker("rocksdb.block.cache.add.failures");
+            stats.data_hits += ticker("rocksdb.block.cache.data.hit");
+            stats.data_misses += ticker("rocksdb.block.cache.data.miss");
+            stats.index_hits += ticker("rocksdb.block.cache.index.hit");
+            stats.index_misses += ticker("rocksdb.block.cache.index.miss");
+            stats.filter_hits += ticker("rocksdb.block.cache.filter.hit");
+            stats.filter_misses += ticker("rocksdb.block.cache.filter.miss");
+        }
+        stats
+    }
+}
+
+#[cfg(feature = "cache-dump")]
+impl Cache {
+    /// Save the blocks of `db` in the cache to the file at `path`, to load
+    /// them back with `new_lru_from_dump` once `db` is reopened.
+    pub fn dump(&self, db: &DB, path: &str) -> Result<(), String> {
+        let cpath = match CString::new(path.as_bytes()) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err("Failed to convert path to CString when dumping \
+                            the block cache"
+                    .to_owned())
+            }
+        };
+        let mut err: *const i8 = 0 as *const i8;
+        unsafe {
+            rocksdb_ffi::rocksdb_shim_cache_dump(self.inner,
+                                                 db.as_raw(),
+                                                 cpath.as_ptr(),
+                                                 &mut err);
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(())
+    }
+
+    /// Create a LRU cache of `capacity` bytes holding the blocks saved to
+    /// `path` by `dump`.
+    ///
+    /// Rocksdb only loads dumps into a secondary cache, so the blocks are
+    /// kept compressed in one of `secondary_capacity` bytes, and move up
+    /// into the cache the first time they are read.
+    pub fn new_lru_from_dump(capacity: size_t,
+                             secondary_capacity: size_t,
+                             path: &str)
+                             -> Result<Cache, String> {
+        let cpath = match CString::new(path.as_bytes()) {
+            Ok(c) => c,
+            Err(_) => {
+                return Err("Failed to convert path to CString when loading \
+                            the block cache"
+                    .to_owned())
+            }
+        };
+        let mut err: *const i8 = 0 as *const i8;
+        let cache = unsafe {
+            rocksdb_ffi::rocksdb_shim_cache_create_lru_from_dump(
+                capacity, secondary_capacity, cpath.as_ptr(), &mut err)
+        };
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        Ok(Cache::from_raw(cache))
+    }
+}
+
 impl Default for BlockBasedOptions {
     fn default() -> BlockBasedOptions {
         let block_opts =
@@ -91,6 +402,13 @@ impl BlockBasedOptions {
         }
     }
 
+    pub fn set_block_cache(&mut self, cache: &Cache) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_block_cache(self.inner,
+                                                                     cache.inner);
+        }
+    }
+
     pub fn set_bloom_filter(&mut self,
                             bits_per_key: c_int,
                             block_based: bool) {
@@ -112,6 +430,66 @@ impl BlockBasedOptions {
                                                                                        v as u8);
         }
     }
+
+    /// Insert the index and filter blocks into the high priority pool of
+    /// the block cache. Only used with `set_cache_index_and_filter_blocks`.
+    pub fn set_cache_index_and_filter_blocks_with_high_priority(&mut self,
+                                                                v: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_cache_index_and_filter_blocks_with_high_priority(self.inner, v as u8);
+        }
+    }
+
+    /// Keep the top level index of partitioned indexes and filters pinned
+    /// in the block cache, so a lookup never has to read it from disk.
+    pub fn set_pin_top_level_index_and_filter(&mut self, v: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_pin_top_level_index_and_filter(self.inner,
+                                                                                        v as u8);
+        }
+    }
+
+    /// Checksum used to verify the blocks of the sst files written from
+    /// now on, `DBxxHash` is faster than the default `DBCRC32c` on CPUs
+    /// without a crc32 instruction.
+    pub fn set_checksum(&mut self, checksum: DBChecksumType) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_checksum(
+                self.inner, checksum as c_char);
+        }
+    }
+
+    /// Version of the sst file format to write.
+    ///
+    /// Newer versions enable newer features but can't be read by older
+    /// rocksdb releases, so only bump it once every reader is upgraded.
+    pub fn set_format_version(&mut self, version: c_int) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_format_version(self.inner,
+                                                                        version);
+        }
+    }
+
+    /// With `DBBinarySearchAndHash` each data block gets a hash index, so
+    /// point lookups find their key without a binary search over the
+    /// restart points. It costs some space per block and doesn't help
+    /// range scans.
+    pub fn set_data_block_index_type(&mut self,
+                                     index_type: DBDataBlockIndexType) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_data_block_index_type(self.inner,
+                                                                               index_type);
+        }
+    }
+
+    /// Ratio of keys to buckets of the data block hash index, a lower
+    /// ratio means less collisions and more space. Defaults to 0.75.
+    pub fn set_data_block_hash_ratio(&mut self, ratio: f64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_block_based_options_set_data_block_hash_ratio(self.inner,
+                                                                               ratio);
+        }
+    }
 }
 
 // TODO figure out how to create these in a Rusty way
@@ -146,7 +524,33 @@ impl Default for Options {
             if opt_ptr.is_null() {
                 panic!("Could not create rocksdb options".to_string());
             }
-            Options { inner: opts }
+            Options {
+                inner: opts,
+                comparator: None,
+                compaction_filter: None,
+                merge_operator_name: None,
+                db_paths: vec![],
+                wal_dir: None,
+            }
+        }
+    }
+}
+
+impl Clone for Options {
+    fn clone(&self) -> Options {
+        unsafe {
+            let opts = rocksdb_ffi::rocksdb_options_create_copy(self.inner);
+            if opts.0.is_null() {
+                panic!("Could not copy rocksdb options".to_string());
+            }
+            Options {
+                inner: opts,
+                comparator: self.comparator.clone(),
+                compaction_filter: self.compaction_filter.clone(),
+                merge_operator_name: self.merge_operator_name.clone(),
+                db_paths: self.db_paths.clone(),
+                wal_dir: self.wal_dir.clone(),
+            }
         }
     }
 }
@@ -156,6 +560,66 @@ impl Options {
         Options::default()
     }
 
+    /// Options for a write-heavy column family read back sequentially
+    /// and soon deleted, like a raft log.
+    ///
+    /// Large memtables absorb the writes and most entries are deleted
+    /// before they are compacted far, so compactions start late and
+    /// there is no bloom filter.
+    pub fn new_log_cf() -> Options {
+        let mut opts = Options::new();
+        opts.set_write_buffer_size(128 << 20);
+        opts.set_max_write_buffer_number(5);
+        opts.set_min_write_buffer_number_to_merge(1);
+        opts.set_level_zero_file_num_compaction_trigger(8);
+        opts.set_level_zero_slowdown_writes_trigger(20);
+        opts.set_level_zero_stop_writes_trigger(36);
+        opts.set_target_file_size_base(32 << 20);
+        opts.set_max_bytes_for_level_base(256 << 20);
+        opts.compression(DBCompressionType::DBLz4);
+        opts
+    }
+
+    /// Options for a column family mostly read by key, its blocks cached
+    /// in `cache`.
+    ///
+    /// Small blocks with a hash index, a bloom filter and the index and
+    /// filter blocks kept in the cache make each get read little.
+    pub fn new_point_lookup_cf(cache: &Cache) -> Options {
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_block_cache(cache);
+        block_opts.set_block_size(4 << 10);
+        block_opts.set_bloom_filter(10, false);
+        block_opts.set_cache_index_and_filter_blocks(true);
+        block_opts.set_pin_top_level_index_and_filter(true);
+        block_opts.set_data_block_index_type(
+            DBDataBlockIndexType::DBBinarySearchAndHash);
+        let mut opts = Options::new();
+        opts.set_block_based_table_factory(&block_opts);
+        opts.set_write_buffer_size(64 << 20);
+        opts.compression(DBCompressionType::DBLz4);
+        opts
+    }
+
+    /// Options for a column family mostly read by range scans, its blocks
+    /// cached in `cache`.
+    ///
+    /// Large blocks compress better and cost fewer reads per scanned key,
+    /// a bloom filter wouldn't help scans.
+    pub fn new_scan_cf(cache: &Cache) -> Options {
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_block_cache(cache);
+        block_opts.set_block_size(64 << 10);
+        block_opts.set_cache_index_and_filter_blocks(true);
+        let mut opts = Options::new();
+        opts.set_block_based_table_factory(&block_opts);
+        opts.set_write_buffer_size(64 << 20);
+        opts.set_target_file_size_base(64 << 20);
+        opts.set_max_bytes_for_level_base(512 << 20);
+        opts.compression(DBCompressionType::DBLz4);
+        opts
+    }
+
     pub fn increase_parallelism(&mut self, parallelism: i32) {
         unsafe {
             rocksdb_ffi::rocksdb_options_increase_parallelism(self.inner,
@@ -178,6 +642,15 @@ impl Options {
         }
     }
 
+    /// Create the column families passed to `DB::open_cf` that don't
+    /// exist yet instead of failing.
+    pub fn create_missing_column_families(&mut self, create_missing: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_create_missing_column_families(
+                self.inner, create_missing);
+        }
+    }
+
     pub fn compression(&mut self, t: DBCompressionType) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_compression(self.inner, t);
@@ -193,10 +666,24 @@ impl Options {
         }
     }
 
+    /// Merge the operands of `DB::merge` by calling `merge_fn`, which also
+    /// combines operands without their existing value.
     pub fn add_merge_operator(&mut self, name: &str, merge_fn: MergeFn) {
+        self.set_merge_operator(FnMergeOperator {
+            name: name.to_owned(),
+            merge_fn: merge_fn,
+        });
+    }
+
+    /// Merge the operands of `DB::merge` with `op`.
+    pub fn set_merge_operator<M>(&mut self, op: M)
+        where M: MergeOperator + 'static
+    {
+        let name = op.name().to_owned();
         let cb = Box::new(MergeOperatorCallback {
             name: CString::new(name.as_bytes()).unwrap(),
-            merge_fn: merge_fn,
+            op: Box::new(op),
+            poisoned: AtomicBool::new(false),
         });
 
         unsafe {
@@ -209,6 +696,38 @@ impl Options {
                 merge_operator::name_callback);
             rocksdb_ffi::rocksdb_options_set_merge_operator(self.inner, mo);
         }
+        self.merge_operator_name = Some(name);
+    }
+
+    /// Pass the entries compactions write to `filter`, which can drop
+    /// them or change their value, e.g. to remove expired records.
+    pub fn set_compaction_filter<F>(&mut self, filter: F)
+        where F: CompactionFilter + 'static
+    {
+        let name = filter.name().to_owned();
+        let cb = Box::new(CompactionFilterCallback {
+            name: CString::new(name.as_bytes()).unwrap(),
+            filter: Box::new(filter),
+            poisoned: AtomicBool::new(false),
+        });
+
+        unsafe {
+            let cf = rocksdb_ffi::rocksdb_compactionfilter_create(
+                mem::transmute(cb),
+                compaction_filter::destructor_callback,
+                compaction_filter::filter_callback,
+                compaction_filter::name_callback);
+            rocksdb_ffi::rocksdb_options_set_compaction_filter(self.inner, cf);
+            self.compaction_filter = Some(Arc::new(CompactionFilterHandle {
+                inner: cf,
+                name: name,
+            }));
+        }
+    }
+
+    /// Name of the filter set with `set_compaction_filter`.
+    pub fn compaction_filter_name(&self) -> Option<&str> {
+        self.compaction_filter.as_ref().map(|f| f.name.as_str())
     }
 
     pub fn add_comparator(&mut self,
@@ -226,6 +745,60 @@ impl Options {
                 compare_callback,
                 comparator::name_callback);
             rocksdb_ffi::rocksdb_options_set_comparator(self.inner, cmp);
+            self.comparator = Some(Arc::new(ComparatorHandle {
+                inner: cmp,
+                name: name.to_owned(),
+                compare_fn: compare_fn,
+            }));
+        }
+    }
+
+    /// The function of the comparator added with `add_comparator` or
+    /// `add_comparator_with_u64_ts`, `None` for the default bytewise order.
+    pub fn get_compare_fn(&self) -> Option<fn(&[u8], &[u8]) -> i32> {
+        self.comparator.as_ref().map(|c| c.compare_fn)
+    }
+
+    /// Name of the comparator, as rocksdb records it in the OPTIONS file.
+    pub fn comparator_name(&self) -> &str {
+        self.comparator.as_ref().map_or(DEFAULT_COMPARATOR_NAME, |c| &c.name)
+    }
+
+    /// Name of the merge operator added with `add_merge_operator`.
+    pub fn merge_operator_name(&self) -> Option<&str> {
+        self.merge_operator_name.as_ref().map(|n| n.as_str())
+    }
+
+    /// Like `add_comparator`, for keys carrying a u64 timestamp.
+    ///
+    /// `compare_fn` compares the user keys, and versions of the same key are
+    /// ordered newest first. Timestamps are encoded by
+    /// `comparator::encode_u64_ts`, the same as rocksdb's built-in
+    /// timestamp comparator. Write with `DB::put_with_ts` and read past
+    /// versions with `ReadOptions::set_timestamp`.
+    pub fn add_comparator_with_u64_ts(&mut self,
+                                      name: &str,
+                                      compare_fn: fn(&[u8], &[u8]) -> i32) {
+        let cb = Box::new(ComparatorCallback {
+            name: CString::new(name.as_bytes()).unwrap(),
+            f: compare_fn,
+        });
+
+        unsafe {
+            let cmp = rocksdb_ffi::rocksdb_comparator_with_ts_create(
+                mem::transmute(cb),
+                comparator::destructor_callback,
+                comparator::compare_with_ts_callback,
+                comparator::compare_ts_callback,
+                comparator::compare_without_ts_callback,
+                comparator::name_callback,
+                comparator::TIMESTAMP_SIZE as size_t);
+            rocksdb_ffi::rocksdb_options_set_comparator(self.inner, cmp);
+            self.comparator = Some(Arc::new(ComparatorHandle {
+                inner: cmp,
+                name: name.to_owned(),
+                compare_fn: compare_fn,
+            }));
         }
     }
 
@@ -237,12 +810,95 @@ impl Options {
         }
     }
 
+    /// Set the directories sst files are placed in, with the target size
+    /// of each one.
+    ///
+    /// Newer data is placed in the earlier paths and moved to the later
+    /// ones as they fill up, so listing a fast device first and a large,
+    /// slow one last keeps the bottommost levels on the slow device.
+    /// The last path's target size is ignored when it is exceeded.
+    pub fn set_db_paths(&mut self, paths: &[(&str, u64)]) {
+        self.db_paths = paths.iter().map(|&(p, _)| p.to_owned()).collect();
+        let cpaths: Vec<CString> = paths.iter()
+            .map(|&(p, _)| CString::new(p.as_bytes()).unwrap())
+            .collect();
+        unsafe {
+            let dbpaths: Vec<rocksdb_ffi::DBPath> = cpaths.iter()
+                .zip(paths)
+                .map(|(p, &(_, size))| {
+                    rocksdb_ffi::rocksdb_dbpath_create(p.as_ptr(), size)
+                })
+                .collect();
+            rocksdb_ffi::rocksdb_options_set_db_paths(self.inner,
+                                                      dbpaths.as_ptr(),
+                                                      dbpaths.len() as size_t);
+            for p in dbpaths {
+                rocksdb_ffi::rocksdb_dbpath_destroy(p);
+            }
+        }
+    }
+
+    /// Return the paths set by `set_db_paths`.
+    pub fn get_db_paths(&self) -> &[String] {
+        &self.db_paths
+    }
+
+    /// Set the directory of the WAL files, the DB directory by default.
+    ///
+    /// Putting them on another device than the sst files keeps the writes
+    /// of the WAL from competing with the compactions.
+    pub fn set_wal_dir(&mut self, path: &str) {
+        self.wal_dir = Some(path.to_owned());
+        let cpath = CString::new(path.as_bytes()).unwrap();
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_wal_dir(self.inner,
+                                                     cpath.as_ptr());
+        }
+    }
+
+    /// Return the directory set by `set_wal_dir`.
+    pub fn get_wal_dir(&self) -> Option<&str> {
+        self.wal_dir.as_ref().map(|p| p.as_str())
+    }
+
     pub fn set_max_open_files(&mut self, nfiles: c_int) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_max_open_files(self.inner, nfiles);
         }
     }
 
+    /// Set the number of threads used to open sst files when the DB is
+    /// opened, only effective when `max_open_files` is -1.
+    ///
+    /// Raising this speeds up opening DBs with many files or column
+    /// families, which otherwise opens them one at a time.
+    pub fn set_max_file_opening_threads(&mut self, n: c_int) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_max_file_opening_threads(self.inner,
+                                                                      n);
+        }
+    }
+
+    /// Don't load the stats of every sst file when the DB is opened.
+    ///
+    /// Saves reading a block from each file on slow disks, at the cost of
+    /// less accurate compaction decisions until the stats are loaded.
+    pub fn set_skip_stats_update_on_db_open(&mut self, skip: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_skip_stats_update_on_db_open(
+                self.inner, skip);
+        }
+    }
+
+    /// Don't check the size of every sst file against the MANIFEST when
+    /// the DB is opened.
+    pub fn set_skip_checking_sst_file_sizes_on_db_open(&mut self, skip: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_skip_checking_sst_file_sizes_on_db_open(
+                self.inner, skip);
+        }
+    }
+
     pub fn set_use_fsync(&mut self, useit: bool) {
         unsafe {
             if useit {
@@ -259,22 +915,92 @@ impl Options {
         }
     }
 
-    pub fn set_disable_data_sync(&mut self, disable: bool) {
+    /// Sync the WAL incrementally every `nbytes` written, instead of all
+    /// at once when it is closed. 0 turns it off.
+    pub fn set_wal_bytes_per_sync(&mut self, nbytes: u64) {
         unsafe {
-            if disable {
-                rocksdb_ffi::rocksdb_options_set_disable_data_sync(self.inner,
-                                                                   1);
-            } else {
-                rocksdb_ffi::rocksdb_options_set_disable_data_sync(self.inner,
-                                                                   0);
-            }
+            rocksdb_ffi::rocksdb_options_set_wal_bytes_per_sync(self.inner,
+                                                                nbytes);
+        }
+    }
+
+    /// Roll over to a new MANIFEST once it reaches `size` bytes, so that a
+    /// long-lived DB doesn't keep growing the same file.
+    pub fn set_max_manifest_file_size(&mut self, size: u64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_max_manifest_file_size(self.inner,
+                                                                    size);
+        }
+    }
+
+    /// Preallocate MANIFEST files in chunks of `size` bytes.
+    pub fn set_manifest_preallocation_size(&mut self, size: size_t) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_manifest_preallocation_size(
+                self.inner, size);
+        }
+    }
+
+    /// Force flushing the column families backed by the oldest WAL once
+    /// all WALs add up to `size` bytes. 0 lets rocksdb pick a limit.
+    pub fn set_max_total_wal_size(&mut self, size: u64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_max_total_wal_size(self.inner,
+                                                                size);
+        }
+    }
+
+    /// Archive obsolete WAL files instead of deleting them, and delete
+    /// the archived ones after `ttl` seconds, so that they can still be
+    /// read with `WalReader`. 0 deletes them at once unless
+    /// `set_wal_size_limit_mb` is set.
+    pub fn set_wal_ttl_seconds(&mut self, ttl: u64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_WAL_ttl_seconds(self.inner, ttl);
+        }
+    }
+
+    /// Like `set_wal_ttl_seconds`, deleting the oldest archived WAL files
+    /// once they take more than `limit` MB.
+    pub fn set_wal_size_limit_mb(&mut self, limit: u64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_WAL_size_limit_MB(self.inner,
+                                                               limit);
         }
     }
 
-    pub fn allow_os_buffer(&mut self, is_allow: bool) {
+    /// Read sst files with O_DIRECT, bypassing the OS page cache, which
+    /// replaced `allow_os_buffer(false)`.
+    pub fn set_use_direct_reads(&mut self, v: bool) {
         unsafe {
-            rocksdb_ffi::rocksdb_options_set_allow_os_buffer(self.inner,
-                                                             is_allow);
+            rocksdb_ffi::rocksdb_options_set_use_direct_reads(self.inner,
+                                                              v as u8);
+        }
+    }
+
+    /// Like `set_use_direct_reads`, for the reads and writes of flushes
+    /// and compactions.
+    pub fn set_use_direct_io_for_flush_and_compaction(&mut self, v: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_use_direct_io_for_flush_and_compaction(
+                self.inner, v as u8);
+        }
+    }
+
+    /// Read sst files through mmap instead of `read`, which avoids copying
+    /// blocks for DBs that fit in memory.
+    pub fn allow_mmap_reads(&mut self, is_allow: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_allow_mmap_reads(self.inner,
+                                                              is_allow);
+        }
+    }
+
+    /// Write sst files through mmap.
+    pub fn allow_mmap_writes(&mut self, is_allow: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_allow_mmap_writes(self.inner,
+                                                               is_allow);
         }
     }
 
@@ -306,18 +1032,124 @@ impl Options {
         }
     }
 
+    /// Sets the size of the blocks memtables allocate their entries from,
+    /// by default an eighth of the write buffer size.
+    ///
+    /// Smaller blocks waste less of the last one when memtables hold few
+    /// entries, larger ones mean fewer allocations.
+    pub fn set_arena_block_size(&mut self, size: size_t) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_arena_block_size(self.inner, size);
+        }
+    }
+
+    /// Allocate the memtable blocks from huge pages of this size, which
+    /// must be reserved beforehand, to save TLB misses on large
+    /// memtables. 0, the default, turns it off.
+    pub fn set_memtable_huge_page_size(&mut self, size: size_t) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_memtable_huge_page_size(self.inner,
+                                                                     size);
+        }
+    }
+
+    /// Give the memtables a bloom filter of `ratio` times the write buffer
+    /// size, over the key prefixes or, see
+    /// `set_memtable_whole_key_filtering`, the whole keys. 0, the default,
+    /// turns it off.
+    pub fn set_memtable_prefix_bloom_size_ratio(&mut self, ratio: f64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_memtable_prefix_bloom_size_ratio(
+                self.inner, ratio);
+        }
+    }
+
+    pub fn set_memtable_whole_key_filtering(&mut self, whole_key: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_memtable_whole_key_filtering(
+                self.inner, whole_key as u8);
+        }
+    }
+
+    /// Overwrite the value of a key in place in the memtable when the new
+    /// one is not larger, instead of adding an entry. Snapshots then don't
+    /// see the old value, and concurrent memtable writes are not allowed.
+    pub fn set_inplace_update_support(&mut self, inplace: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_inplace_update_support(
+                self.inner, inplace as u8);
+        }
+    }
+
+    /// Merge the operands of a key into a value in the memtable once it has
+    /// `n` of them, rather than keeping them all. 0, the default, never
+    /// does.
+    pub fn set_max_successive_merges(&mut self, n: size_t) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_max_successive_merges(self.inner,
+                                                                   n);
+        }
+    }
+
     pub fn set_max_bytes_for_level_base(&mut self, size: u64) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_max_bytes_for_level_base(self.inner, size);
         }
     }
 
-    pub fn set_max_bytes_for_level_multiplier(&mut self, mul: i32) {
+    pub fn set_max_bytes_for_level_multiplier(&mut self, mul: f64) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_max_bytes_for_level_multiplier(self.inner, mul);
         }
     }
 
+    pub fn get_num_levels(&self) -> i32 {
+        unsafe { rocksdb_ffi::rocksdb_options_get_num_levels(self.inner) }
+    }
+
+    pub fn get_level_zero_file_num_compaction_trigger(&self) -> i32 {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_get_level0_file_num_compaction_trigger(
+                self.inner)
+        }
+    }
+
+    pub fn get_max_bytes_for_level_base(&self) -> u64 {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_get_max_bytes_for_level_base(
+                self.inner)
+        }
+    }
+
+    pub fn get_max_bytes_for_level_multiplier(&self) -> f64 {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_get_max_bytes_for_level_multiplier(
+                self.inner)
+        }
+    }
+
+    pub fn set_level_compaction_dynamic_level_bytes(&mut self, v: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_level_compaction_dynamic_level_bytes(
+                self.inner, v as u8);
+        }
+    }
+
+    /// Whether the level sizes are derived from the size of the last level
+    /// instead of growing from `max_bytes_for_level_base`.
+    pub fn get_level_compaction_dynamic_level_bytes(&self) -> bool {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_get_level_compaction_dynamic_level_bytes(
+                self.inner) != 0
+        }
+    }
+
+    /// Sets the size of the sst files written to level 1, 64MB by
+    /// default. Those of level n+1 are `set_target_file_size_multiplier`
+    /// times larger than those of level n.
+    ///
+    /// Column families of large values usually want larger files, so they
+    /// don't end up with many small ones.
     pub fn set_target_file_size_base(&mut self, size: u64) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_target_file_size_base(self.inner,
@@ -325,6 +1157,24 @@ impl Options {
         }
     }
 
+    /// Default: 1, all the levels have files of the same size.
+    pub fn set_target_file_size_multiplier(&mut self, mul: c_int) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_target_file_size_multiplier(
+                self.inner, mul);
+        }
+    }
+
+    /// Sets the maximum size of the input of a compaction, so it doesn't
+    /// pick up too many files of the next level. 0, the default, means 25
+    /// times the target file size.
+    pub fn set_max_compaction_bytes(&mut self, size: u64) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_max_compaction_bytes(self.inner,
+                                                                  size);
+        }
+    }
+
     pub fn set_min_write_buffer_number_to_merge(&mut self, to_merge: c_int) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_min_write_buffer_number_to_merge(
@@ -361,6 +1211,19 @@ impl Options {
         }
     }
 
+    /// With FIFO compaction, drop the oldest sst files once they take more
+    /// than `size` bytes in total.
+    pub fn set_fifo_max_table_files_size(&mut self, size: u64) {
+        unsafe {
+            let fifo_opts = rocksdb_ffi::rocksdb_fifo_compaction_options_create();
+            rocksdb_ffi::rocksdb_fifo_compaction_options_set_max_table_files_size(
+                fifo_opts, size);
+            rocksdb_ffi::rocksdb_options_set_fifo_compaction_options(self.inner,
+                                                                     fifo_opts);
+            rocksdb_ffi::rocksdb_fifo_compaction_options_destroy(fifo_opts);
+        }
+    }
+
     pub fn set_max_background_compactions(&mut self, n: c_int) {
         unsafe {
             rocksdb_ffi::rocksdb_options_set_max_background_compactions(
@@ -375,9 +1238,14 @@ impl Options {
         }
     }
 
-    pub fn set_filter_deletes(&mut self, filter: bool) {
+    /// Set the access pattern advised to the OS for the input files of a
+    /// compaction. `DBAccessNone` keeps long compactions from evicting
+    /// the hot working set out of the page cache.
+    pub fn set_access_hint_on_compaction_start(&mut self,
+                                               hint: rocksdb_ffi::DBAccessHint) {
         unsafe {
-            rocksdb_ffi::rocksdb_options_set_filter_deletes(self.inner, filter);
+            rocksdb_ffi::rocksdb_options_set_access_hint_on_compaction_start(
+                self.inner, hint);
         }
     }
 
@@ -398,6 +1266,53 @@ impl Options {
         }
     }
 
+    pub fn set_ratelimiter(&mut self, limiter: &RateLimiter) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_ratelimiter(self.inner,
+                                                         limiter.inner);
+        }
+    }
+
+    pub fn set_env(&mut self, env: &Env) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_env(self.inner, env.inner);
+        }
+    }
+
+    /// Collect statistics, for all the DBs opened with these options,
+    /// readable with `get_statistics`. It costs 5%-10% of throughput.
+    pub fn enable_statistics(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_enable_statistics(self.inner);
+        }
+    }
+
+    /// Dump the collected statistics, `None` if they are not enabled.
+    pub fn get_statistics(&self) -> Option<String> {
+        unsafe {
+            let value =
+                rocksdb_ffi::rocksdb_options_statistics_get_string(self.inner);
+            if value.is_null() {
+                return None;
+            }
+            let s = CStr::from_ptr(value).to_string_lossy().into_owned();
+            libc::free(value as *mut c_void);
+            Some(s)
+        }
+    }
+
+    /// Write the statistics to the info log every `period` seconds, 0
+    /// disables it.
+    pub fn set_stats_dump_period_sec(&mut self, period: c_uint) {
+        unsafe {
+            rocksdb_ffi::rocksdb_options_set_stats_dump_period_sec(self.inner,
+                                                                   period);
+        }
+    }
+
+    /// Measure the time and bytes of the IO done by flushes and
+    /// compactions, so that they are counted in the statistics, e.g.
+    /// `rocksdb.compact.read.bytes` and `rocksdb.compact.write.bytes`.
     pub fn set_report_bg_io_stats(&mut self, enable: bool) {
         unsafe {
             if enable {
@@ -418,7 +1333,10 @@ impl Default for WriteOptions {
         if opt_ptr.is_null() {
             panic!("Could not create rocksdb write options".to_string());
         }
-        WriteOptions { inner: write_opts }
+        WriteOptions {
+            inner: write_opts,
+            no_slowdown: false,
+        }
     }
 }
 
@@ -442,4 +1360,133 @@ impl WriteOptions {
             }
         }
     }
+
+    /// Fail the write with a `Result incomplete: Write stall` error instead
+    /// of waiting when writes are being stalled.
+    pub fn set_no_slowdown(&mut self, no_slowdown: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_writeoptions_set_no_slowdown(self.inner,
+                                                              no_slowdown as u8);
+        }
+        self.no_slowdown = no_slowdown;
+    }
+
+    pub fn get_no_slowdown(&self) -> bool {
+        self.no_slowdown
+    }
+}
+
+/// Options of `DB::flush_cf`.
+pub struct FlushOptions {
+    pub inner: rocksdb_ffi::DBFlushOptions,
+}
+
+impl Drop for FlushOptions {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_flushoptions_destroy(self.inner);
+        }
+    }
+}
+
+impl Default for FlushOptions {
+    fn default() -> FlushOptions {
+        let opts = unsafe { rocksdb_ffi::rocksdb_flushoptions_create() };
+        if opts.0.is_null() {
+            panic!("Could not create rocksdb flush options".to_string());
+        }
+        FlushOptions { inner: opts }
+    }
+}
+
+impl FlushOptions {
+    pub fn new() -> FlushOptions {
+        FlushOptions::default()
+    }
+
+    /// Wait until the flush is done, the default, instead of returning
+    /// once it is scheduled.
+    pub fn set_wait(&mut self, wait: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_flushoptions_set_wait(self.inner, wait);
+        }
+    }
+
+    /// Flush right away even if that stalls writes, instead of waiting
+    /// for the flushes and compactions that would cause the stall.
+    pub fn set_allow_write_stall(&mut self, allow: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_flushoptions_set_allow_write_stall(self.inner,
+                                                                    allow as u8);
+        }
+    }
+}
+
+/// Options of `DB::ingest_external_file`.
+pub struct IngestExternalFileOptions {
+    pub inner: rocksdb_ffi::DBIngestExternalFileOptions,
+}
+
+impl Drop for IngestExternalFileOptions {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ingestexternalfileoptions_destroy(self.inner);
+        }
+    }
+}
+
+impl Default for IngestExternalFileOptions {
+    fn default() -> IngestExternalFileOptions {
+        let opts =
+            unsafe { rocksdb_ffi::rocksdb_ingestexternalfileoptions_create() };
+        if opts.0.is_null() {
+            panic!("Could not create rocksdb ingest options".to_string());
+        }
+        IngestExternalFileOptions { inner: opts }
+    }
+}
+
+impl IngestExternalFileOptions {
+    pub fn new() -> IngestExternalFileOptions {
+        IngestExternalFileOptions::default()
+    }
+
+    /// Move the files into the DB instead of copying them.
+    pub fn set_move_files(&mut self, move_files: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ingestexternalfileoptions_set_move_files(
+                self.inner,
+                move_files as u8);
+        }
+    }
+
+    /// Whether snapshots taken before the ingestion keep not seeing the
+    /// ingested keys.
+    pub fn set_snapshot_consistency(&mut self, consistency: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ingestexternalfileoptions_set_snapshot_consistency(
+                self.inner,
+                consistency as u8);
+        }
+    }
+
+    /// Allow assigning a sequence number to the files when their keys
+    /// overlap keys already in the DB, instead of failing the ingestion.
+    pub fn set_allow_global_seqno(&mut self, allow: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ingestexternalfileoptions_set_allow_global_seqno(
+                self.inner,
+                allow as u8);
+        }
+    }
+
+    /// Allow flushing the memtable when it overlaps the files, instead of
+    /// failing the ingestion.
+    pub fn set_allow_blocking_flush(&mut self, allow: bool) {
+        unsafe {
+            rocksdb_ffi::rocksdb_ingestexternalfileoptions_set_allow_blocking_flush(
+                self.inner,
+                allow as u8);
+        }
+    }
 }
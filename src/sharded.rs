@@ -0,0 +1,415 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::mem;
+use std::path::Path;
+
+use rocksdb::{DB, DBIterator, ReadOptions, SeekKey, Writable, WriteBatch};
+use rocksdb_ffi::DBCFHandle;
+use rocksdb_options::Options;
+
+const META_PREFIX: &'static str = "sharded-db:";
+const RESHARD_BATCH_SIZE: usize = 1000;
+
+/// A single keyspace spread over several column families, so that each
+/// one stays small enough for its compactions to keep up.
+///
+/// A key goes to the shard picked by a stable hash of it. The shards of a
+/// keyspace named `prefix` are the column families `<prefix>-<n>-<i>` for
+/// `n` shards, and the shard count is recorded in the default column
+/// family.
+///
+/// rocksdb's C API doesn't expose column family export and import, so
+/// `reshard` moves the keys with regular writes.
+pub struct ShardedDb {
+    db: DB,
+    prefix: String,
+    shards: Vec<String>,
+    compare: fn(&[u8], &[u8]) -> i32,
+}
+
+impl ShardedDb {
+    /// Open the DB at `path` with the keyspace `prefix` in `num_shards`
+    /// shards, creating the missing ones with `shard_opts`.
+    ///
+    /// An existing keyspace must have `num_shards` shards, use `reshard` to
+    /// change it. The shards left by an interrupted `reshard` are dropped,
+    /// other column families are opened with `opts`.
+    pub fn open(opts: &Options,
+                path: &str,
+                prefix: &str,
+                num_shards: usize,
+                shard_opts: &Options)
+                -> Result<ShardedDb, String> {
+        if num_shards == 0 {
+            return Err("Invalid argument: a sharded db needs at least one \
+                        shard"
+                .to_owned());
+        }
+        // A DB yet to be created has none but the default one.
+        let existing = if Path::new(path).join("CURRENT").exists() {
+            try!(DB::list_column_families(opts, path))
+        } else {
+            vec!["default".to_owned()]
+        };
+        let names: Vec<&str> = existing.iter().map(|n| n.as_str()).collect();
+        let cf_opts: Vec<&Options> = names.iter()
+            .map(|n| if is_shard(prefix, n) { shard_opts } else { opts })
+            .collect();
+        let mut db = try!(DB::open_cf(opts, path, &names, &cf_opts));
+
+        let meta_key = meta_key(prefix);
+        let count = match try!(db.get(&meta_key)) {
+            Some(v) => try!(parse_count(&v)),
+            None => {
+                try!(db.put(&meta_key, num_shards.to_string().as_bytes()));
+                num_shards
+            }
+        };
+        if count != num_shards {
+            return Err(format!("Invalid argument: {} has {} shards, not {}",
+                               prefix,
+                               count,
+                               num_shards));
+        }
+
+        let shards = shard_names(prefix, count);
+        for name in &existing {
+            if is_shard(prefix, name) && !shards.contains(name) {
+                try!(db.drop_cf(name));
+            }
+        }
+        for name in &shards {
+            if db.cf_handle(name).is_none() {
+                try!(db.create_cf(name, shard_opts));
+            }
+        }
+        Ok(ShardedDb {
+            db: db,
+            prefix: prefix.to_owned(),
+            shards: shards,
+            compare: shard_opts.get_compare_fn().unwrap_or(bytewise_compare),
+        })
+    }
+
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Names of the shard column families, in shard order.
+    pub fn shard_names(&self) -> &[String] {
+        &self.shards
+    }
+
+    /// Index of the shard holding `key`.
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        (hash(key) % self.shards.len() as u64) as usize
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), String> {
+        let cf = try!(self.handle(self.shard_of(key)));
+        self.db.put_cf(cf, key, value)
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<(), String> {
+        let cf = try!(self.handle(self.shard_of(key)));
+        self.db.delete_cf(cf, key)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let cf = try!(self.handle(self.shard_of(key)));
+        let value = try!(self.db.get_cf(cf, key));
+        Ok(value.map(|v| v.to_vec()))
+    }
+
+    /// Read `keys` from the same snapshot, with one batched read per shard.
+    pub fn multi_get(&self,
+                     keys: &[&[u8]])
+                     -> Result<Vec<Option<Vec<u8>>>, String> {
+        let mut readopts = ReadOptions::new();
+        unsafe {
+            let snap = self.db.unsafe_snap();
+            readopts.set_snapshot(&snap);
+            let res = self.multi_get_opt(keys, &readopts);
+            self.db.release_snap(&snap);
+            res
+        }
+    }
+
+    fn multi_get_opt(&self,
+                     keys: &[&[u8]],
+                     readopts: &ReadOptions)
+                     -> Result<Vec<Option<Vec<u8>>>, String> {
+        let mut groups = vec![vec![]; self.shards.len()];
+        for (i, key) in keys.iter().enumerate() {
+            groups[self.shard_of(key)].push(i);
+        }
+        let mut values = vec![None; keys.len()];
+        for (shard, mut group) in groups.into_iter().enumerate() {
+            if group.is_empty() {
+                continue;
+            }
+            let compare = self.compare;
+            group.sort_by(|a, b| compare(keys[*a], keys[*b]).cmp(&0));
+            let sorted: Vec<&[u8]> = group.iter().map(|i| keys[*i]).collect();
+            let cf = try!(self.handle(shard));
+            let found = self.db.multi_get_sorted_cf_opt(cf, &sorted, readopts);
+            for (i, value) in group.into_iter().zip(found) {
+                values[i] = try!(value).map(|v| v.into_vec());
+            }
+        }
+        Ok(values)
+    }
+
+    /// Call `f` on the keys in [`start_key`, `end_key`) of every shard, in
+    /// key order and from the same snapshot, until it returns false.
+    ///
+    /// `None` leaves that side of the range unbounded.
+    pub fn scan<F>(&self,
+                   start_key: Option<&[u8]>,
+                   end_key: Option<&[u8]>,
+                   mut f: F)
+                   -> Result<(), String>
+        where F: FnMut(&[u8], &[u8]) -> bool
+    {
+        let snap = self.db.snapshot();
+        let mut iters: Vec<DBIterator> = vec![];
+        for shard in 0..self.shards.len() {
+            let cf = try!(self.handle(shard));
+            iters.push(self.db.iter_cf_at(cf, &snap, start_key, end_key));
+        }
+        // Shards hold distinct keys, so the smallest current key is the
+        // next one.
+        loop {
+            let mut next: Option<(usize, &[u8], &[u8])> = None;
+            for (i, iter) in iters.iter().enumerate() {
+                let (key, value) = match iter.entry() {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                let smaller = match next {
+                    Some((_, next_key, _)) => {
+                        (self.compare)(key, next_key) < 0
+                    }
+                    None => true,
+                };
+                if smaller {
+                    next = Some((i, key, value));
+                }
+            }
+            let i = match next {
+                Some((i, key, value)) => {
+                    if !f(key, value) {
+                        break;
+                    }
+                    i
+                }
+                None => break,
+            };
+            iters[i].next();
+        }
+        for iter in &iters {
+            try!(iter.status());
+        }
+        Ok(())
+    }
+
+    /// Move every key to a layout of `num_shards` shards created with
+    /// `shard_opts`, then drop the old shards.
+    ///
+    /// The new layout takes over once all the keys are copied, if it fails
+    /// before that the old shards are still used and the partial new ones
+    /// are dropped by the next `open` or `reshard`.
+    pub fn reshard(&mut self,
+                   num_shards: usize,
+                   shard_opts: &Options)
+                   -> Result<(), String> {
+        if num_shards == 0 {
+            return Err("Invalid argument: a sharded db needs at least one \
+                        shard"
+                .to_owned());
+        }
+        if num_shards == self.shards.len() {
+            return Ok(());
+        }
+        let new_shards = shard_names(&self.prefix, num_shards);
+        let mut handles = vec![];
+        for name in &new_shards {
+            if self.db.cf_handle(name).is_some() {
+                try!(self.db.drop_cf(name));
+            }
+            handles.push(try!(self.db.create_cf(name, shard_opts)));
+        }
+
+        for shard in 0..self.shards.len() {
+            let cf = try!(self.handle(shard));
+            let mut iter = self.db.iter_cf(cf);
+            let mut batch = WriteBatch::new();
+            iter.seek(SeekKey::Start);
+            while let Some((key, value)) = iter.entry() {
+                let target = (hash(key) % num_shards as u64) as usize;
+                try!(batch.put_cf(handles[target], key, value));
+                if batch.count() >= RESHARD_BATCH_SIZE {
+                    try!(self.db.write(mem::replace(&mut batch,
+                                                    WriteBatch::new())));
+                }
+                iter.next();
+            }
+            try!(iter.status());
+            try!(self.db.write(batch));
+        }
+
+        try!(self.db.put(&meta_key(&self.prefix),
+                         num_shards.to_string().as_bytes()));
+        let old_shards = mem::replace(&mut self.shards, new_shards);
+        self.compare = shard_opts.get_compare_fn().unwrap_or(bytewise_compare);
+        for name in &old_shards {
+            try!(self.db.drop_cf(name));
+        }
+        Ok(())
+    }
+
+    fn handle(&self, shard: usize) -> Result<DBCFHandle, String> {
+        Ok(try!(self.db.cf_handle_or_err(&self.shards[shard])))
+    }
+}
+
+fn meta_key(prefix: &str) -> Vec<u8> {
+    format!("{}{}", META_PREFIX, prefix).into_bytes()
+}
+
+fn parse_count(value: &[u8]) -> Result<usize, String> {
+    String::from_utf8_lossy(value)
+        .parse()
+        .map_err(|e| format!("Invalid shard count {:?}: {}", value, e))
+}
+
+fn shard_names(prefix: &str, num_shards: usize) -> Vec<String> {
+    (0..num_shards)
+        .map(|i| format!("{}-{}-{}", prefix, num_shards, i))
+        .collect()
+}
+
+// Whether `name` is the name of a shard of `prefix`, of any layout.
+fn is_shard(prefix: &str, name: &str) -> bool {
+    if !name.starts_with(prefix) {
+        return false;
+    }
+    let parts: Vec<&str> = name[prefix.len()..].split('-').collect();
+    let is_number = |p: &&str| {
+        !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit())
+    };
+    parts.len() == 3 && parts[0].is_empty() && parts[1..].iter().all(is_number)
+}
+
+// FNV-1a, the shard of a key must not change between runs or versions.
+fn hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn bytewise_compare(a: &[u8], b: &[u8]) -> i32 {
+    a.cmp(b) as i32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::DB;
+    use rocksdb_options::Options;
+    use tempdir::TempDir;
+
+    #[test]
+    fn sharded_db_test() {
+        let path = TempDir::new("_rust_rocksdb_shardeddbtest").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let keys: Vec<Vec<u8>> =
+            (0..200).map(|i| format!("key{:03}", i).into_bytes()).collect();
+        {
+            let db = ShardedDb::open(&opts, path_str, "users", 4, &opts)
+                .unwrap();
+            assert_eq!(db.shard_names()[3], "users-4-3");
+            for k in &keys {
+                db.put(k, k).unwrap();
+            }
+            for shard in 0..4 {
+                let cf = *db.db().cf_handle(&db.shard_names()[shard]).unwrap();
+                assert!(db.db().iter_cf(cf).seek(SeekKey::Start));
+            }
+            db.delete(b"key007").unwrap();
+            assert_eq!(db.get(b"key008").unwrap(), Some(b"key008".to_vec()));
+
+            let wanted: Vec<&[u8]> = vec![b"key150", b"key007", b"key003"];
+            assert_eq!(db.multi_get(&wanted).unwrap(),
+                       vec![Some(b"key150".to_vec()),
+                            None,
+                            Some(b"key003".to_vec())]);
+
+            let mut scanned = vec![];
+            db.scan(Some(b"key005"), Some(b"key010"), |k, _| {
+                    scanned.push(k.to_vec());
+                    true
+                })
+                .unwrap();
+            assert_eq!(scanned,
+                       vec![b"key005".to_vec(),
+                            b"key006".to_vec(),
+                            b"key008".to_vec(),
+                            b"key009".to_vec()]);
+            let mut count = 0;
+            db.scan(None, None, |_, _| {
+                    count += 1;
+                    count < 10
+                })
+                .unwrap();
+            assert_eq!(count, 10);
+        }
+
+        {
+            assert!(ShardedDb::open(&opts, path_str, "users", 3, &opts)
+                .is_err());
+            let mut db = ShardedDb::open(&opts, path_str, "users", 4, &opts)
+                .unwrap();
+            db.reshard(3, &opts).unwrap();
+            assert_eq!(db.num_shards(), 3);
+            for k in &keys {
+                let expected = if &k[..] == b"key007" {
+                    None
+                } else {
+                    Some(k.clone())
+                };
+                assert_eq!(db.get(k).unwrap(), expected);
+            }
+        }
+
+        let mut cfs = DB::list_column_families(&opts, path_str).unwrap();
+        cfs.sort();
+        assert_eq!(cfs, vec!["default", "users-3-0", "users-3-1", "users-3-2"]);
+        let db = ShardedDb::open(&opts, path_str, "users", 3, &opts).unwrap();
+        let mut count = 0;
+        db.scan(None, None, |_, _| {
+                count += 1;
+                true
+            })
+            .unwrap();
+        assert_eq!(count, 199);
+    }
+}
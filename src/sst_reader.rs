@@ -0,0 +1,790 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Offline inspection of a standalone sst file, such as one written by an
+//! `SstFileWriter` before it is ingested.
+//!
+//! Like `manifest`, this reads the file directly instead of going through
+//! rocksdb, whose C API can't open a table without a DB. Only the block
+//! based tables rocksdb writes by default are supported: binary search
+//! indexes, and blocks that are uncompressed or compressed with snappy.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::vec;
+
+use coding::{decode_fixed32, decode_fixed64, get_varint32, get_varint64,
+             unmask_crc};
+use manifest::crc32c_extend;
+
+const LEGACY_MAGIC: u64 = 0xdb4775248b80fb57;
+const MAGIC: u64 = 0x88e241b785f4cff7;
+const LEGACY_FOOTER_SIZE: usize = 48;
+const FOOTER_SIZE: usize = 53;
+// Two block handles of at most 10 bytes per varint, after the checksum
+// type in the newer footers.
+const FOOTER_HANDLES_SIZE: usize = 40;
+// From this format version on, the footer holds the size of the
+// metaindex block instead of the handles, and the checksums of the blocks
+// depend on their offset.
+const EXTENDED_FORMAT_VERSION: u32 = 6;
+const EXTENDED_MAGIC: [u8; 4] = [0x3e, 0x00, 0x7a, 0x00];
+
+// Compression type and checksum after each block.
+const BLOCK_TRAILER_SIZE: u64 = 5;
+const NO_COMPRESSION: u8 = 0;
+const SNAPPY_COMPRESSION: u8 = 1;
+const CRC32C_CHECKSUM: u8 = 1;
+
+// Meta blocks.
+const PROPERTIES_BLOCK: &'static str = "rocksdb.properties";
+const INDEX_BLOCK: &'static str = "rocksdb.index";
+
+// Set in the last word of data blocks followed by a hash index.
+const HASH_INDEX_FLAG: u32 = 1 << 31;
+const TWO_LEVEL_INDEX_SEARCH: u32 = 2;
+
+// The most a snappy block expands: a 3 bytes copy of 64 bytes.
+const MAX_SNAPPY_RATIO: usize = 22;
+
+/// The properties rocksdb stores in an sst file, see
+/// `SstFileReader::table_properties`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableProperties {
+    pub data_size: u64,
+    pub index_size: u64,
+    pub filter_size: u64,
+    pub raw_key_size: u64,
+    pub raw_value_size: u64,
+    pub num_data_blocks: u64,
+    pub num_entries: u64,
+    /// Number of deletions among the entries.
+    pub num_deletions: u64,
+    pub num_merge_operands: u64,
+    /// Number of range tombstones, not counted in the entries.
+    pub num_range_deletions: u64,
+    pub format_version: u64,
+    pub column_family_name: String,
+    pub comparator_name: String,
+    pub merge_operator_name: String,
+    pub compression_name: String,
+    pub filter_policy_name: String,
+    /// All the properties, those of the property collectors included, as
+    /// stored.
+    pub all: BTreeMap<String, Vec<u8>>,
+}
+
+/// The kind of an entry of an sst file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    Delete,
+    Put,
+    Merge,
+    SingleDelete,
+    /// A type this reader doesn't know, e.g. a blob index.
+    Other(u8),
+}
+
+/// An entry of an sst file, see `SstFileReader::iter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SstEntry {
+    pub key: Vec<u8>,
+    pub seqno: u64,
+    pub entry_type: EntryType,
+    pub value: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+struct Footer {
+    format_version: u32,
+    checksum_type: u8,
+    metaindex: BlockHandle,
+    // Stored in the metaindex block from `EXTENDED_FORMAT_VERSION` on.
+    index: Option<BlockHandle>,
+}
+
+/// Reads the entries and properties of an sst file without opening a DB.
+///
+/// Blocks are read from the file as they are needed, checking their
+/// checksums when they are crc32c, the only kind verified, and the file
+/// has a format version before 6.
+pub struct SstFileReader {
+    file: File,
+    file_len: u64,
+    path: String,
+    format_version: u32,
+    checksum_type: u8,
+    properties: TableProperties,
+    data_blocks: Vec<BlockHandle>,
+}
+
+impl SstFileReader {
+    /// Open the sst file at `path` and read its properties and index.
+    pub fn open(path: &str) -> Result<SstFileReader, String> {
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("failed to open {}: {}", path, e)),
+        };
+        let file_len = match file.metadata() {
+            Ok(m) => m.len(),
+            Err(e) => return Err(format!("failed to stat {}: {}", path, e)),
+        };
+        let footer = try!(read_footer(&file, file_len, path));
+        let mut reader = SstFileReader {
+            file: file,
+            file_len: file_len,
+            path: path.to_owned(),
+            format_version: footer.format_version,
+            checksum_type: footer.checksum_type,
+            properties: TableProperties::default(),
+            data_blocks: vec![],
+        };
+
+        let metaindex = try!(reader.read_block(footer.metaindex));
+        let mut meta_blocks = BTreeMap::new();
+        for (name, value) in try!(decode_entries(&metaindex)) {
+            let mut value = &value[..];
+            meta_blocks.insert(name, try!(get_block_handle(&mut value)));
+        }
+        if let Some(&handle) = meta_blocks.get(PROPERTIES_BLOCK.as_bytes()) {
+            let block = try!(reader.read_block(handle));
+            reader.properties = try!(decode_properties(&block));
+        }
+
+        let index = match footer.index {
+            Some(handle) => handle,
+            None => {
+                match meta_blocks.get(INDEX_BLOCK.as_bytes()) {
+                    Some(&handle) => handle,
+                    None => return Err(format!("no index block in {}", path)),
+                }
+            }
+        };
+        let index_type = reader.properties
+            .all
+            .get("rocksdb.block.based.table.index.type")
+            .and_then(|v| if v.len() == 4 {
+                Some(decode_fixed32(v))
+            } else {
+                None
+            });
+        if index_type == Some(TWO_LEVEL_INDEX_SEARCH) {
+            return Err(format!("partitioned index of {} is not supported",
+                               path));
+        }
+        let delta_encoded = reader.properties
+            .all
+            .get("rocksdb.index.value.is.delta.encoded")
+            .map_or(false, |v| v.first().map_or(false, |b| *b != 0));
+        let block = try!(reader.read_block(index));
+        reader.data_blocks = if delta_encoded {
+            try!(decode_delta_index(&block))
+        } else {
+            let mut handles = vec![];
+            for (_, value) in try!(decode_entries(&block)) {
+                handles.push(try!(get_block_handle(&mut &value[..])));
+            }
+            handles
+        };
+        Ok(reader)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn table_properties(&self) -> &TableProperties {
+        &self.properties
+    }
+
+    /// Iterate over the entries of the file, in the order they are stored.
+    ///
+    /// The iterator stops after returning an error.
+    pub fn iter(&self) -> SstFileIterator {
+        SstFileIterator {
+            reader: self,
+            next_block: 0,
+            entries: vec![].into_iter(),
+            failed: false,
+        }
+    }
+
+    /// Read all the data blocks, checking they decode and that their
+    /// checksums match.
+    ///
+    /// Fails without reading them if their checksums can't be verified:
+    /// they are not crc32c, or the file has format version 6 or later.
+    pub fn verify_checksums(&self) -> Result<(), String> {
+        if self.checksum_type != CRC32C_CHECKSUM {
+            return Err(format!("checksum type {} not verified in {}",
+                               self.checksum_type,
+                               self.path));
+        }
+        if self.format_version >= EXTENDED_FORMAT_VERSION {
+            return Err(format!("checksum type {} not verified in format \
+                                version {} of {}",
+                               self.checksum_type,
+                               self.format_version,
+                               self.path));
+        }
+        for &handle in &self.data_blocks {
+            try!(self.read_block(handle));
+        }
+        Ok(())
+    }
+
+    fn read_block(&self, handle: BlockHandle) -> Result<Vec<u8>, String> {
+        let end = handle.offset
+            .checked_add(handle.size)
+            .and_then(|end| end.checked_add(BLOCK_TRAILER_SIZE));
+        if end.map_or(true, |end| end > self.file_len) {
+            return Err(format!("block at offset {} of size {} is past the \
+                                end of {}",
+                               handle.offset,
+                               handle.size,
+                               self.path));
+        }
+        let size = handle.size as usize;
+        let mut buf = vec![0; size + BLOCK_TRAILER_SIZE as usize];
+        let mut file = &self.file;
+        if let Err(e) = file.seek(SeekFrom::Start(handle.offset))
+            .and_then(|_| file.read_exact(&mut buf)) {
+            return Err(format!("failed to read block at offset {} of {}: {}",
+                               handle.offset,
+                               self.path,
+                               e));
+        }
+        let compression = buf[size];
+        if self.checksum_type == CRC32C_CHECKSUM &&
+           self.format_version < EXTENDED_FORMAT_VERSION {
+            // The checksum covers the block and its compression type.
+            let expected = unmask_crc(decode_fixed32(&buf[size + 1..]));
+            if crc32c_extend(0, &buf[..size + 1]) != expected {
+                return Err(format!("checksum mismatch in block at offset {} \
+                                    of {}",
+                                   handle.offset,
+                                   self.path));
+            }
+        }
+        buf.truncate(size);
+        match compression {
+            NO_COMPRESSION => Ok(buf),
+            SNAPPY_COMPRESSION => snappy_decompress(&buf),
+            t => {
+                Err(format!("unsupported compression type {} in {}",
+                            t,
+                            self.path))
+            }
+        }
+    }
+}
+
+/// Iterator over the entries of an sst file, see `SstFileReader::iter`.
+pub struct SstFileIterator<'a> {
+    reader: &'a SstFileReader,
+    next_block: usize,
+    entries: vec::IntoIter<SstEntry>,
+    failed: bool,
+}
+
+impl<'a> Iterator for SstFileIterator<'a> {
+    type Item = Result<SstEntry, String>;
+
+    fn next(&mut self) -> Option<Result<SstEntry, String>> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            let handle = match self.reader.data_blocks.get(self.next_block) {
+                Some(&handle) => handle,
+                None => return None,
+            };
+            self.next_block += 1;
+            match self.reader.read_block(handle).and_then(|b| decode_data(&b)) {
+                Ok(entries) => self.entries = entries.into_iter(),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+fn read_footer(mut file: &File,
+               len: u64,
+               path: &str)
+               -> Result<Footer, String> {
+    if len < LEGACY_FOOTER_SIZE as u64 {
+        return Err(format!("{} is too short to be an sst file", path));
+    }
+    let footer_size = if len < FOOTER_SIZE as u64 {
+        LEGACY_FOOTER_SIZE
+    } else {
+        FOOTER_SIZE
+    };
+    let mut buf = vec![0; footer_size];
+    if let Err(e) = file.seek(SeekFrom::End(-(footer_size as i64)))
+        .and_then(|_| file.read_exact(&mut buf)) {
+        return Err(format!("failed to read footer of {}: {}", path, e));
+    }
+
+    let magic = decode_fixed64(&buf[footer_size - 8..]);
+    if magic == LEGACY_MAGIC {
+        let mut input = &buf[footer_size - LEGACY_FOOTER_SIZE..];
+        return Ok(Footer {
+            format_version: 0,
+            checksum_type: CRC32C_CHECKSUM,
+            metaindex: try!(get_block_handle(&mut input)),
+            index: Some(try!(get_block_handle(&mut input))),
+        });
+    }
+    if magic != MAGIC || footer_size != FOOTER_SIZE {
+        return Err(format!("{} is not a block based sst file", path));
+    }
+    let format_version = decode_fixed32(&buf[FOOTER_SIZE - 12..]);
+    let checksum_type = buf[0];
+    if format_version < EXTENDED_FORMAT_VERSION {
+        let mut input = &buf[1..1 + FOOTER_HANDLES_SIZE];
+        return Ok(Footer {
+            format_version: format_version,
+            checksum_type: checksum_type,
+            metaindex: try!(get_block_handle(&mut input)),
+            index: Some(try!(get_block_handle(&mut input))),
+        });
+    }
+    if buf[1..5] != EXTENDED_MAGIC {
+        return Err(format!("invalid footer in {}", path));
+    }
+    // The metaindex block comes right before the footer.
+    let size = decode_fixed32(&buf[13..17]) as u64;
+    let end = len - FOOTER_SIZE as u64;
+    if size + BLOCK_TRAILER_SIZE > end {
+        return Err(format!("invalid metaindex size in {}", path));
+    }
+    Ok(Footer {
+        format_version: format_version,
+        checksum_type: checksum_type,
+        metaindex: BlockHandle {
+            offset: end - BLOCK_TRAILER_SIZE - size,
+            size: size,
+        },
+        index: None,
+    })
+}
+
+fn decode_properties(block: &[u8]) -> Result<TableProperties, String> {
+    let mut props = TableProperties::default();
+    for (name, value) in try!(decode_entries(block)) {
+        let name = String::from_utf8_lossy(&name).into_owned();
+        {
+            let int = || get_varint64(&mut &value[..]).unwrap_or(0);
+            let string = || String::from_utf8_lossy(&value).into_owned();
+            match &name[..] {
+                "rocksdb.data.size" => props.data_size = int(),
+                "rocksdb.index.size" => props.index_size = int(),
+                "rocksdb.filter.size" => props.filter_size = int(),
+                "rocksdb.raw.key.size" => props.raw_key_size = int(),
+                "rocksdb.raw.value.size" => props.raw_value_size = int(),
+                "rocksdb.num.data.blocks" => props.num_data_blocks = int(),
+                "rocksdb.num.entries" => props.num_entries = int(),
+                "rocksdb.deleted.keys" => props.num_deletions = int(),
+                "rocksdb.merge.operands" => props.num_merge_operands = int(),
+                "rocksdb.num.range-deletions" => {
+                    props.num_range_deletions = int()
+                }
+                "rocksdb.format.version" => props.format_version = int(),
+                "rocksdb.column.family.name" => {
+                    props.column_family_name = string()
+                }
+                "rocksdb.comparator" => props.comparator_name = string(),
+                "rocksdb.merge.operator" => {
+                    props.merge_operator_name = string()
+                }
+                "rocksdb.compression" => props.compression_name = string(),
+                "rocksdb.filter.policy" => props.filter_policy_name = string(),
+                _ => {}
+            }
+        }
+        props.all.insert(name, value);
+    }
+    Ok(props)
+}
+
+fn decode_data(block: &[u8]) -> Result<Vec<SstEntry>, String> {
+    let mut entries = vec![];
+    for (mut key, value) in try!(decode_entries(block)) {
+        if key.len() < 8 {
+            return Err("invalid internal key in sst file".to_owned());
+        }
+        let trailer = decode_fixed64(&key[key.len() - 8..]);
+        let len = key.len() - 8;
+        key.truncate(len);
+        let entry_type = match (trailer & 0xff) as u8 {
+            0 => EntryType::Delete,
+            1 => EntryType::Put,
+            2 => EntryType::Merge,
+            7 => EntryType::SingleDelete,
+            t => EntryType::Other(t),
+        };
+        entries.push(SstEntry {
+            key: key,
+            seqno: trailer >> 8,
+            entry_type: entry_type,
+            value: value,
+        });
+    }
+    Ok(entries)
+}
+
+/// Return the part of `block` holding its entries, before the restart
+/// points.
+fn block_entries(block: &[u8]) -> Result<&[u8], String> {
+    block_restarts(block).map(|(entries, _)| entries)
+}
+
+/// Split `block` into the part holding its entries and the offsets of
+/// its restart points in that part, the entries whose key is not prefix
+/// compressed.
+fn block_restarts(block: &[u8]) -> Result<(&[u8], Vec<usize>), String> {
+    if block.len() < 4 {
+        return Err("truncated block in sst file".to_owned());
+    }
+    let mut end = block.len() - 4;
+    let footer = decode_fixed32(&block[end..]);
+    if footer & HASH_INDEX_FLAG != 0 {
+        if end < 2 {
+            return Err("truncated block hash index in sst file".to_owned());
+        }
+        end -= 2;
+        let buckets = (block[end] as usize) | (block[end + 1] as usize) << 8;
+        if end < buckets {
+            return Err("truncated block hash index in sst file".to_owned());
+        }
+        end -= buckets;
+    }
+    let restarts = (footer & !HASH_INDEX_FLAG) as usize * 4;
+    if end < restarts {
+        return Err("truncated block restarts in sst file".to_owned());
+    }
+    let entries = &block[..end - restarts];
+    let offsets = block[end - restarts..end]
+        .chunks(4)
+        .map(|b| decode_fixed32(b) as usize)
+        .collect();
+    Ok((entries, offsets))
+}
+
+/// Decode the prefix compressed keys and the values of `block`.
+fn decode_entries(block: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
+    let mut input = try!(block_entries(block));
+    let mut entries = vec![];
+    let mut key: Vec<u8> = vec![];
+    while !input.is_empty() {
+        let shared = try!(get_varint32(&mut input)) as usize;
+        let non_shared = try!(get_varint32(&mut input)) as usize;
+        let value_len = try!(get_varint32(&mut input)) as usize;
+        if shared > key.len() || input.len() < non_shared + value_len {
+            return Err("invalid block entry in sst file".to_owned());
+        }
+        key.truncate(shared);
+        key.extend_from_slice(&input[..non_shared]);
+        let value = input[non_shared..non_shared + value_len].to_vec();
+        input = &input[non_shared + value_len..];
+        entries.push((key.clone(), value));
+    }
+    Ok(entries)
+}
+
+/// Decode the data block handles of an index block whose entries only
+/// hold the size of a block when it follows the previous one, all but
+/// those at restart points, which hold the whole handle.
+fn decode_delta_index(block: &[u8]) -> Result<Vec<BlockHandle>, String> {
+    let (entries, restarts) = try!(block_restarts(block));
+    let mut input = entries;
+    let mut handles: Vec<BlockHandle> = vec![];
+    while !input.is_empty() {
+        let restart = restarts.contains(&(entries.len() - input.len()));
+        try!(get_varint32(&mut input));
+        let non_shared = try!(get_varint32(&mut input)) as usize;
+        if input.len() < non_shared {
+            return Err("invalid index entry in sst file".to_owned());
+        }
+        input = &input[non_shared..];
+        let handle = match handles.last() {
+            Some(prev) if !restart => {
+                let delta = try!(get_varsigned64(&mut input));
+                let corrupted = || {
+                    "Corruption: invalid block handle delta in sst index"
+                        .to_owned()
+                };
+                let offset = try!(prev.offset
+                    .checked_add(prev.size)
+                    .and_then(|o| o.checked_add(BLOCK_TRAILER_SIZE))
+                    .ok_or_else(&corrupted));
+                let size = match (prev.size as i64).checked_add(delta) {
+                    Some(size) if prev.size <= i64::max_value() as u64 &&
+                                  size >= 0 => size as u64,
+                    _ => return Err(corrupted()),
+                };
+                BlockHandle {
+                    offset: offset,
+                    size: size,
+                }
+            }
+            None if !restart => {
+                return Err("invalid index entry in sst file".to_owned());
+            }
+            _ => try!(get_block_handle(&mut input)),
+        };
+        handles.push(handle);
+    }
+    Ok(handles)
+}
+
+fn snappy_decompress(mut input: &[u8]) -> Result<Vec<u8>, String> {
+    let corrupted = || Err("corrupted snappy block in sst file".to_owned());
+    let len = try!(get_varint32(&mut input)) as usize;
+    if len > input.len() * MAX_SNAPPY_RATIO {
+        return corrupted();
+    }
+    let mut out = Vec::with_capacity(len);
+    while !input.is_empty() {
+        let tag = input[0];
+        input = &input[1..];
+        let (copy_len, offset_len) = match tag & 3 {
+            0 => {
+                let mut literal_len = (tag >> 2) as usize;
+                if literal_len >= 60 {
+                    // The length follows in 1 to 4 bytes.
+                    let n = literal_len - 59;
+                    if input.len() < n {
+                        return corrupted();
+                    }
+                    literal_len = input[..n]
+                        .iter()
+                        .rev()
+                        .fold(0, |v, b| v << 8 | *b as usize);
+                    input = &input[n..];
+                }
+                let literal_len = literal_len + 1;
+                if input.len() < literal_len || out.len() + literal_len > len {
+                    return corrupted();
+                }
+                out.extend_from_slice(&input[..literal_len]);
+                input = &input[literal_len..];
+                continue;
+            }
+            1 => (4 + ((tag >> 2) & 7) as usize, 1),
+            2 => (1 + (tag >> 2) as usize, 2),
+            _ => (1 + (tag >> 2) as usize, 4),
+        };
+        if input.len() < offset_len {
+            return corrupted();
+        }
+        let mut offset = input[..offset_len]
+            .iter()
+            .rev()
+            .fold(0, |v, b| v << 8 | *b as usize);
+        if tag & 3 == 1 {
+            offset |= ((tag >> 5) as usize) << 8;
+        }
+        input = &input[offset_len..];
+        if offset == 0 || offset > out.len() || out.len() + copy_len > len {
+            return corrupted();
+        }
+        // The copy may overlap what it appends.
+        let start = out.len() - offset;
+        for i in start..start + copy_len {
+            let b = out[i];
+            out.push(b);
+        }
+    }
+    if out.len() != len {
+        return corrupted();
+    }
+    Ok(out)
+}
+
+fn get_block_handle(input: &mut &[u8]) -> Result<BlockHandle, String> {
+    Ok(BlockHandle {
+        offset: try!(get_varint64(input)),
+        size: try!(get_varint64(input)),
+    })
+}
+
+// Zigzag encoded.
+fn get_varsigned64(input: &mut &[u8]) -> Result<i64, String> {
+    let v = try!(get_varint64(input));
+    Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bulk_load::SstFileWriter;
+    use rocksdb_ffi::{DBChecksumType, DBCompressionType};
+    use rocksdb_options::{BlockBasedOptions, Options};
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_snappy_decompress() {
+        // "abc" then a copy of 9 bytes at offset 3, overlapping itself.
+        let block = [12, 0x08, b'a', b'b', b'c', 0x15, 3];
+        assert_eq!(snappy_decompress(&block).unwrap(), b"abcabcabcabc");
+        assert!(snappy_decompress(&[12, 0x08, b'a', b'b', b'c']).is_err());
+        assert!(snappy_decompress(&[3, 0x15, 3]).is_err());
+        // More output than the input can expand to, or than announced.
+        assert!(snappy_decompress(&[0xff, 0xff, 0xff, 0x7f, 0]).is_err());
+        assert!(snappy_decompress(&[2, 0x08, b'a', b'b', b'c']).is_err());
+        let mut input: &[u8] = &[0x03, 0x04];
+        assert_eq!(get_varsigned64(&mut input).unwrap(), -2);
+        assert_eq!(get_varsigned64(&mut input).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_decode_delta_index() {
+        // Restarts every 2 entries: the second entry holds the size delta
+        // of its block although its key shares nothing with the first.
+        let block = [0, 1, b'a', 0, 100,
+                     0, 1, b'b', 19,
+                     0, 1, b'c', 0xf4, 0x03, 80,
+                     0, 0, 0, 0, 9, 0, 0, 0, 2, 0, 0, 0];
+        let handles = decode_delta_index(&block).unwrap();
+        assert_eq!(handles,
+                   vec![BlockHandle {
+                            offset: 0,
+                            size: 100,
+                        },
+                        BlockHandle {
+                            offset: 105,
+                            size: 90,
+                        },
+                        BlockHandle {
+                            offset: 500,
+                            size: 80,
+                        }]);
+        // The first entry must be a restart point.
+        let block = [0, 1, b'a', 19, 0, 0, 0, 0];
+        assert!(decode_delta_index(&block).is_err());
+        // A delta that makes the size negative.
+        let block = [0, 1, b'a', 0, 100, 0, 1, b'b', 0xc9, 0x01, 0, 0, 0, 0, 1,
+                     0, 0, 0];
+        assert!(decode_delta_index(&block).is_err());
+        // A block that ends past the largest offset.
+        let mut block = vec![0, 1, b'a'];
+        block.extend_from_slice(&[0xff; 9]);
+        block.extend_from_slice(&[0x01, 1, 0, 1, b'b', 0]);
+        block.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0]);
+        assert!(decode_delta_index(&block).is_err());
+    }
+
+    #[test]
+    fn sst_file_reader_test() {
+        let dir = TempDir::new("_rust_rocksdb_sstreadertest").expect("");
+        for (i, compression) in vec![DBCompressionType::DBNo,
+                                     DBCompressionType::DBSnappy]
+            .into_iter()
+            .enumerate() {
+            let path = dir.path().join(format!("{}.sst", i));
+            let path = path.to_str().unwrap();
+            let mut opts = Options::new();
+            opts.compression(compression);
+            let mut writer = SstFileWriter::new(&opts);
+            writer.open(path).unwrap();
+            let entries: Vec<(String, String)> = (0..1000)
+                .map(|i| (format!("k{:04}", i), format!("value-{}", i % 7)))
+                .collect();
+            for &(ref k, ref v) in &entries {
+                writer.put(k.as_bytes(), v.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let reader = SstFileReader::open(path).unwrap();
+            let props = reader.table_properties().clone();
+            assert_eq!(props.num_entries, 1000);
+            assert_eq!(props.num_deletions, 0);
+            assert!(props.num_data_blocks > 0);
+            assert_eq!(props.comparator_name, "leveldb.BytewiseComparator");
+            reader.verify_checksums().unwrap();
+            let read: Vec<_> = reader.iter().map(|e| e.unwrap()).collect();
+            assert_eq!(read.len(), entries.len());
+            for (e, &(ref k, ref v)) in read.iter().zip(&entries) {
+                assert_eq!(e.key, k.as_bytes());
+                assert_eq!(e.value, v.as_bytes());
+                assert_eq!(e.entry_type, EntryType::Put);
+            }
+        }
+
+        // Blocks past the end of the file are not read.
+        let reader = SstFileReader::open(dir.path()
+                .join("0.sst")
+                .to_str()
+                .unwrap())
+            .unwrap();
+        let len = reader.file_len;
+        let past_end = [(len - 5, 1), (len, 0), (u64::max_value(), 1)];
+        for &(offset, size) in &past_end {
+            let handle = BlockHandle {
+                offset: offset,
+                size: size,
+            };
+            assert!(reader.read_block(handle).is_err());
+        }
+
+        // Checksums other than crc32c, or in format version 6, are not
+        // verified.
+        for &(checksum, version) in &[(DBChecksumType::DBxxHash, 5),
+                                      (DBChecksumType::DBCRC32c, 6)] {
+            let path = dir.path().join(format!("{}.sst", version));
+            let path = path.to_str().unwrap();
+            let mut block_opts = BlockBasedOptions::new();
+            block_opts.set_checksum(checksum);
+            block_opts.set_format_version(version);
+            let mut opts = Options::new();
+            opts.set_block_based_table_factory(&block_opts);
+            let mut writer = SstFileWriter::new(&opts);
+            writer.open(path).unwrap();
+            writer.put(b"k1", b"v1").unwrap();
+            writer.finish().unwrap();
+            let reader = SstFileReader::open(path).unwrap();
+            let e = reader.verify_checksums().unwrap_err();
+            assert!(e.starts_with(&format!("checksum type {} not verified",
+                                           checksum as u8)),
+                    "{}",
+                    e);
+            assert_eq!(reader.iter().count(), 1);
+        }
+
+        let path = dir.path().join("0.sst");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"garbage").unwrap();
+        drop(file);
+        assert!(SstFileReader::open(path.to_str().unwrap()).is_err());
+        let missing = dir.path().join("missing.sst");
+        assert!(SstFileReader::open(missing.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::sync::{RwLock, RwLockReadGuard};
+
+use error::Error;
+use rocksdb::{DB, DBVector, Writable};
+use rocksdb_options::Options;
+
+/// A DB shared between threads that can also create and drop column
+/// families.
+///
+/// Reads and writes go through `&self` and run concurrently, as on a
+/// `DB`. Creating or dropping a column family, which needs `&mut DB`,
+/// waits for them to finish and holds new ones off until it is done, so
+/// no read or write ever uses the handle of a dropped column family.
+pub struct SyncDb {
+    db: RwLock<DB>,
+}
+
+impl SyncDb {
+    pub fn new(db: DB) -> SyncDb {
+        SyncDb { db: RwLock::new(db) }
+    }
+
+    /// Borrow the DB for the `&self` methods.
+    ///
+    /// Column families can't be created or dropped while the guard is
+    /// held. Handles looked up through it and kept after it is released
+    /// stay valid until the `SyncDb` is dropped, but may belong to a column
+    /// family dropped since, see `DB::drop_cf`.
+    pub fn read(&self) -> RwLockReadGuard<DB> {
+        self.db.read().unwrap()
+    }
+
+    pub fn create_cf(&self, name: &str, opts: &Options) -> Result<(), String> {
+        let mut db = self.db.write().unwrap();
+        try!(db.create_cf(name, opts));
+        Ok(())
+    }
+
+    pub fn drop_cf(&self, name: &str) -> Result<(), Error> {
+        self.db.write().unwrap().drop_cf(name)
+    }
+
+    /// The names of the column families, `default` included.
+    pub fn cf_names(&self) -> Vec<String> {
+        self.read().cf_names().iter().map(|n| (*n).to_owned()).collect()
+    }
+
+    pub fn get_cf(&self,
+                  cf: &str,
+                  key: &[u8])
+                  -> Result<Option<DBVector>, String> {
+        let db = self.read();
+        let handle = try!(db.cf_handle_or_err(cf));
+        db.get_cf(handle, key)
+    }
+
+    pub fn put_cf(&self,
+                  cf: &str,
+                  key: &[u8],
+                  value: &[u8])
+                  -> Result<(), String> {
+        let db = self.read();
+        let handle = try!(db.cf_handle_or_err(cf));
+        db.put_cf(handle, key, value)
+    }
+
+    pub fn delete_cf(&self, cf: &str, key: &[u8]) -> Result<(), String> {
+        let db = self.read();
+        let handle = try!(db.cf_handle_or_err(cf));
+        db.delete_cf(handle, key)
+    }
+
+    pub fn into_inner(self) -> DB {
+        self.db.into_inner().unwrap()
+    }
+}
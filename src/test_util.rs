@@ -0,0 +1,115 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Helpers for tests that need a throwaway DB, enabled by the `test-util`
+//! feature.
+
+use std::ops::Deref;
+use std::path::Path;
+
+use tempdir::TempDir;
+
+use rocksdb::DB;
+use rocksdb_options::Options;
+
+/// Options sized for tests: small memtables and files, so flushes and
+/// compactions happen after a few keys instead of a few megabytes.
+pub fn small_options() -> Options {
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_write_buffer_size(64 << 10);
+    opts.set_target_file_size_base(64 << 10);
+    opts.set_max_bytes_for_level_base(256 << 10);
+    opts.set_max_open_files(64);
+    opts
+}
+
+/// A DB in a temporary directory.
+///
+/// Dropping it closes the DB, destroys it and removes the directory, lock
+/// file included.
+pub struct TempDb {
+    db: Option<DB>,
+    opts: Options,
+    dir: TempDir,
+}
+
+impl TempDb {
+    /// Open a DB with `small_options`, `prefix` names the directory.
+    pub fn new(prefix: &str) -> TempDb {
+        TempDb::with_options(prefix, small_options())
+    }
+
+    pub fn with_options(prefix: &str, opts: Options) -> TempDb {
+        let dir = TempDir::new(prefix).expect("failed to create temp dir");
+        let db = DB::open(&opts, dir.path().to_str().unwrap())
+            .expect("failed to open temp db");
+        TempDb {
+            db: Some(db),
+            opts: opts,
+            dir: dir,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.opts
+    }
+
+    /// Close and open the DB again, to check what survives a restart.
+    pub fn reopen(&mut self) {
+        drop(self.db.take());
+        let db = DB::open(&self.opts, self.dir.path().to_str().unwrap())
+            .expect("failed to reopen temp db");
+        self.db = Some(db);
+    }
+}
+
+impl Deref for TempDb {
+    type Target = DB;
+
+    fn deref(&self) -> &DB {
+        self.db.as_ref().unwrap()
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        drop(self.db.take());
+        let _ = DB::destroy(&self.opts, self.dir.path().to_str().unwrap());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::Writable;
+
+    #[test]
+    fn temp_db_test() {
+        let path;
+        {
+            let mut db = TempDb::new("_rust_rocksdb_tempdbtest");
+            path = db.path().to_owned();
+            db.put(b"k1", b"v1").unwrap();
+            db.reopen();
+            assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"v1");
+        }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Comparing the content of two DBs, like a replica and its primary, or a
+//! DB and a checkpoint of it.
+
+use std::cmp::Ordering;
+
+use rocksdb::{DB, Snapshot};
+
+/// A key whose value differs between two DBs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyDiff {
+    pub cf: String,
+    pub key: Vec<u8>,
+    /// The value in the left DB, `None` if the key is missing there.
+    pub left: Option<Vec<u8>>,
+    /// The value in the right DB, `None` if the key is missing there.
+    pub right: Option<Vec<u8>>,
+}
+
+/// The result of `diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    /// Column families only in one of the DBs, which aren't compared.
+    pub cfs_only_left: Vec<String>,
+    pub cfs_only_right: Vec<String>,
+    /// Number of distinct keys visited in the compared column families.
+    pub keys_compared: u64,
+    /// Number of differing keys found.
+    pub diff_count: u64,
+    /// The first differing keys found, at most `max_diffs` of them.
+    pub diffs: Vec<KeyDiff>,
+}
+
+impl DiffReport {
+    pub fn is_consistent(&self) -> bool {
+        self.cfs_only_left.is_empty() && self.cfs_only_right.is_empty() &&
+        self.diff_count == 0
+    }
+}
+
+/// Compare every column family of `left` and `right`, as of snapshots of
+/// both taken first, keeping at most `max_diffs` differing keys.
+///
+/// Both DBs are walked in key order side by side, so memory use only
+/// depends on `max_diffs`. A column family must use the same comparator
+/// in both DBs.
+pub fn diff(left: &DB,
+            right: &DB,
+            max_diffs: usize)
+            -> Result<DiffReport, String> {
+    let (left_snap, right_snap) = (left.snapshot(), right.snapshot());
+    let (left_cfs, right_cfs) = (left.cf_names(), right.cf_names());
+    let mut report = DiffReport::default();
+    let (mut keys, mut diff_count, mut diffs) = (0, 0, vec![]);
+    for cf in &left_cfs {
+        if !right_cfs.contains(cf) {
+            report.cfs_only_left.push(cf.to_string());
+            continue;
+        }
+        keys += try!(diff_cf_at(left, &left_snap, right, &right_snap, cf, |d| {
+            diff_count += 1;
+            if diffs.len() < max_diffs {
+                diffs.push(d);
+            }
+            true
+        }));
+    }
+    report.cfs_only_right = right_cfs.iter()
+        .filter(|cf| !left_cfs.contains(cf))
+        .map(|cf| cf.to_string())
+        .collect();
+    report.keys_compared = keys;
+    report.diff_count = diff_count;
+    report.diffs = diffs;
+    Ok(report)
+}
+
+/// Compare the column family `cf` of `left` and `right`, calling `f` with
+/// each differing key until it returns false, and return the number of
+/// keys visited.
+pub fn diff_cf<F>(left: &DB,
+                  right: &DB,
+                  cf: &str,
+                  f: F)
+                  -> Result<u64, String>
+    where F: FnMut(KeyDiff) -> bool
+{
+    diff_cf_at(left, &left.snapshot(), right, &right.snapshot(), cf, f)
+}
+
+fn diff_cf_at<F>(left: &DB,
+                 left_snap: &Snapshot,
+                 right: &DB,
+                 right_snap: &Snapshot,
+                 cf: &str,
+                 mut f: F)
+                 -> Result<u64, String>
+    where F: FnMut(KeyDiff) -> bool
+{
+    let left_cf = try!(left.cf_handle_or_err(cf));
+    let right_cf = try!(right.cf_handle_or_err(cf));
+    let compare = try!(left.compare_fn_cf(left_cf));
+    let mut left_iter = left.iter_cf_at(left_cf, left_snap, None, None);
+    let mut right_iter = right.iter_cf_at(right_cf, right_snap, None, None);
+    let mut keys = 0;
+    loop {
+        let order = match (left_iter.key(), right_iter.key()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(l), Some(r)) => compare(l, r).cmp(&0),
+        };
+        keys += 1;
+        let diff = match order {
+            Ordering::Less => {
+                let d = left_iter.entry()
+                    .map(|(key, value)| key_diff(cf, key, Some(value), None));
+                left_iter.next();
+                d
+            }
+            Ordering::Greater => {
+                let d = right_iter.entry()
+                    .map(|(key, value)| key_diff(cf, key, None, Some(value)));
+                right_iter.next();
+                d
+            }
+            Ordering::Equal => {
+                let d = match (left_iter.entry(), right_iter.entry()) {
+                    (Some((key, l)), Some((_, r))) if l != r => {
+                        Some(key_diff(cf, key, Some(l), Some(r)))
+                    }
+                    _ => None,
+                };
+                left_iter.next();
+                right_iter.next();
+                d
+            }
+        };
+        if let Some(d) = diff {
+            if !f(d) {
+                break;
+            }
+        }
+    }
+    try!(left_iter.status());
+    try!(right_iter.status());
+    Ok(keys)
+}
+
+fn key_diff(cf: &str,
+            key: &[u8],
+            left: Option<&[u8]>,
+            right: Option<&[u8]>)
+            -> KeyDiff {
+    KeyDiff {
+        cf: cf.to_owned(),
+        key: key.to_vec(),
+        left: left.map(|v| v.to_vec()),
+        right: right.map(|v| v.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable};
+    use rocksdb_options::Options;
+    use tempdir::TempDir;
+
+    #[test]
+    fn diff_test() {
+        let left_path = TempDir::new("_rust_rocksdb_difftest_left").expect("");
+        let right_path = TempDir::new("_rust_rocksdb_difftest_right")
+            .expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let mut left = DB::open(&opts, left_path.path().to_str().unwrap())
+            .unwrap();
+        let right = DB::open(&opts, right_path.path().to_str().unwrap())
+            .unwrap();
+        for db in &[&left, &right] {
+            db.put(b"k1", b"v1").unwrap();
+            db.put(b"k3", b"v3").unwrap();
+        }
+        assert!(diff(&left, &right, 10).unwrap().is_consistent());
+
+        // A checkpoint is consistent with its DB.
+        let checkpoint = TempDir::new("_rust_rocksdb_difftest_cp").expect("");
+        let checkpoint_path = checkpoint.path().join("cp");
+        left.create_checkpoint(checkpoint_path.to_str().unwrap(), 0).unwrap();
+        let copy = DB::open(&opts, checkpoint_path.to_str().unwrap()).unwrap();
+        assert!(diff(&left, &copy, 10).unwrap().is_consistent());
+
+        left.put(b"k0", b"v0").unwrap();
+        left.put(b"k3", b"v3-left").unwrap();
+        right.put(b"k4", b"v4").unwrap();
+        left.create_cf("cf1", &opts).unwrap();
+
+        let report = diff(&left, &right, 2).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.cfs_only_left, vec!["cf1".to_owned()]);
+        assert!(report.cfs_only_right.is_empty());
+        assert_eq!(report.keys_compared, 4);
+        assert_eq!(report.diff_count, 3);
+        assert_eq!(report.diffs,
+                   vec![KeyDiff {
+                            cf: "default".to_owned(),
+                            key: b"k0".to_vec(),
+                            left: Some(b"v0".to_vec()),
+                            right: None,
+                        },
+                        KeyDiff {
+                            cf: "default".to_owned(),
+                            key: b"k3".to_vec(),
+                            left: Some(b"v3-left".to_vec()),
+                            right: Some(b"v3".to_vec()),
+                        }]);
+
+        let mut diffs = vec![];
+        diff_cf(&left, &right, "default", |d| {
+                diffs.push(d.key);
+                false
+            })
+            .unwrap();
+        assert_eq!(diffs, vec![b"k0".to_vec()]);
+        assert!(diff_cf(&left, &right, "cf1", |_| true).is_err());
+    }
+}
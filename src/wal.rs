@@ -0,0 +1,335 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Arc;
+
+use rocksdb_ffi::{self, error_message};
+use rocksdb::{DB, WriteBatch};
+
+const STATE_PREFIX: &'static str = "WAL-CONSUMER-";
+
+/// Reads the writes made to a DB from its WAL, for change capture.
+///
+/// Each reader has a name, and the sequence number of the last batch it
+/// acknowledged is saved in the DB directory under that name, so a
+/// restarted consumer resumes where it left off.
+///
+/// The WAL files a consumer still needs must not be deleted. Open the DB
+/// with `Options::set_wal_ttl_seconds` or `set_wal_size_limit_mb` so that
+/// they are archived for long enough, or have the reader hold them with
+/// `set_hold_files`. If they are deleted anyway `poll` fails instead of
+/// skipping the lost writes.
+///
+/// Batches are only read when `poll` is called, so a slow consumer never
+/// has more than one poll's worth of batches in memory.
+pub struct WalReader {
+    db: Arc<DB>,
+    state_path: PathBuf,
+    applied: u64,
+    holding: bool,
+}
+
+impl WalReader {
+    /// Open the consumer `name` of `db`, starting after the last batch it
+    /// acknowledged, or from the start of the WAL for a new consumer.
+    ///
+    /// The name is part of a file name, so it may only contain ASCII
+    /// letters, digits, `_` and `-`.
+    pub fn open(db: Arc<DB>, name: &str) -> Result<WalReader, String> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if name.is_empty() || !name.chars().all(valid) {
+            return Err(format!("Invalid argument: invalid consumer name {:?}",
+                               name));
+        }
+        let state_path = PathBuf::from(db.path())
+            .join(format!("{}{}", STATE_PREFIX, name));
+        let applied = try!(read_state(&state_path));
+        Ok(WalReader {
+            db: db,
+            state_path: state_path,
+            applied: applied,
+            holding: false,
+        })
+    }
+
+    /// Sequence number of the last write acknowledged.
+    pub fn applied_sequence(&self) -> u64 {
+        self.applied
+    }
+
+    /// Number of writes made after the last acknowledged one.
+    pub fn lag(&self) -> u64 {
+        self.db.get_latest_sequence_number().saturating_sub(self.applied)
+    }
+
+    /// Stop the DB from deleting any obsolete file, WAL and sst files
+    /// alike, while `hold` is set. It bounds how far behind the consumer
+    /// can fall at the cost of disk space, so only hold files for short
+    /// outages of the consumer.
+    pub fn set_hold_files(&mut self, hold: bool) -> Result<(), String> {
+        if hold == self.holding {
+            return Ok(());
+        }
+        let mut err: *const i8 = ptr::null();
+        unsafe {
+            if hold {
+                rocksdb_ffi::rocksdb_disable_file_deletions(self.db.as_raw(),
+                                                            &mut err);
+            } else {
+                rocksdb_ffi::rocksdb_enable_file_deletions(self.db.as_raw(),
+                                                           0,
+                                                           &mut err);
+            }
+        }
+        if !err.is_null() {
+            return Err(error_message(err));
+        }
+        self.holding = hold;
+        Ok(())
+    }
+
+    /// Read at most `max_batches` batches written after the last
+    /// acknowledged one, with the sequence number of their first write.
+    ///
+    /// Polling again without acknowledging returns the same batches.
+    pub fn poll(&self,
+                max_batches: usize)
+                -> Result<Vec<(u64, WriteBatch)>, String> {
+        let next = self.applied + 1;
+        let mut batches = vec![];
+        if next > self.db.get_latest_sequence_number() {
+            return Ok(batches);
+        }
+
+        let iter = try!(self.db.get_updates_since(next));
+        let mut expected = next;
+        for item in iter.take(max_batches) {
+            let (seq, batch) = try!(item);
+            let last = seq + batch.count() as u64 - 1;
+            if seq > expected {
+                return Err(format!("WAL from sequence {} to {} was deleted \
+                                    before being read",
+                                   expected,
+                                   seq - 1));
+            }
+            // The first batch may have been acknowledged already.
+            if last >= next {
+                batches.push((seq, batch));
+            }
+            expected = last + 1;
+        }
+        Ok(batches)
+    }
+
+    /// Acknowledge the batch `(seq, batch)` returned by `poll` and all the
+    /// ones before it, they won't be returned again, even after a restart.
+    pub fn ack(&mut self, seq: u64, batch: &WriteBatch) -> Result<(), String> {
+        let last = seq + batch.count() as u64 - 1;
+        if last <= self.applied {
+            return Ok(());
+        }
+        try!(write_state(&self.state_path, last));
+        self.applied = last;
+        Ok(())
+    }
+}
+
+impl Drop for WalReader {
+    fn drop(&mut self) {
+        let _ = self.set_hold_files(false);
+    }
+}
+
+/// The batches written to a DB from a sequence number on, with the
+/// sequence number of their first write, see `DB::get_updates_since`.
+///
+/// It ends at the last batch written when it was created, or with an
+/// error if the WAL files it reads were deleted.
+pub struct WalIterator<'a> {
+    inner: rocksdb_ffi::DBWalIterator,
+    done: bool,
+    _db: PhantomData<&'a DB>,
+}
+
+impl<'a> WalIterator<'a> {
+    /// Take ownership of a WAL iterator created through the C API, for
+    /// `db`.
+    pub unsafe fn from_raw(_db: &'a DB,
+                           inner: rocksdb_ffi::DBWalIterator)
+                           -> WalIterator<'a> {
+        WalIterator {
+            inner: inner,
+            done: false,
+            _db: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for WalIterator<'a> {
+    type Item = Result<(u64, WriteBatch), String>;
+
+    fn next(&mut self) -> Option<Result<(u64, WriteBatch), String>> {
+        if self.done {
+            return None;
+        }
+        unsafe {
+            if rocksdb_ffi::rocksdb_wal_iter_valid(self.inner) == 0 {
+                self.done = true;
+                let mut err: *const i8 = ptr::null();
+                rocksdb_ffi::rocksdb_wal_iter_status(self.inner, &mut err);
+                if !err.is_null() {
+                    return Some(Err(error_message(err)));
+                }
+                return None;
+            }
+            let mut seq = 0;
+            let raw = rocksdb_ffi::rocksdb_wal_iter_get_batch(self.inner,
+                                                              &mut seq);
+            rocksdb_ffi::rocksdb_wal_iter_next(self.inner);
+            Some(Ok((seq, WriteBatch::from_raw(raw))))
+        }
+    }
+}
+
+impl<'a> Drop for WalIterator<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            rocksdb_ffi::rocksdb_wal_iter_destroy(self.inner);
+        }
+    }
+}
+
+fn read_state(path: &PathBuf) -> Result<u64, String> {
+    let mut buf = String::new();
+    match File::open(path) {
+        Ok(mut f) => {
+            if let Err(e) = f.read_to_string(&mut buf) {
+                return Err(format!("Failed to read {}: {}",
+                                   path.display(),
+                                   e));
+            }
+        }
+        Err(_) => return Ok(0),
+    }
+    buf.trim()
+        .parse()
+        .map_err(|e| {
+            format!("Invalid consumer state in {}: {}", path.display(), e)
+        })
+}
+
+// Write to a temporary file and rename it over the old one, so a crash
+// leaves either the old or the new sequence.
+fn write_state(path: &PathBuf, applied: u64) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    let res = File::create(&tmp)
+        .and_then(|mut f| {
+            try!(write!(f, "{}\n", applied));
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    res.map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable, WriteBatch};
+    use rocksdb_options::Options;
+    use std::sync::Arc;
+    use tempdir::TempDir;
+
+    #[test]
+    fn get_updates_since_test() {
+        let path = TempDir::new("_rust_rocksdb_updatessincetest").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        assert_eq!(db.get_latest_sequence_number(), 0);
+        db.put(b"k1", b"v1").unwrap();
+        let batch = WriteBatch::new();
+        batch.put(b"k2", b"v2").unwrap();
+        batch.delete(b"k1").unwrap();
+        db.write(batch).unwrap();
+        assert_eq!(db.get_latest_sequence_number(), 3);
+
+        let updates: Vec<_> = db.get_updates_since(1)
+            .unwrap()
+            .map(|u| u.unwrap())
+            .collect();
+        assert_eq!(updates.len(), 2);
+        assert_eq!((updates[0].0, updates[0].1.count()), (1, 1));
+        assert_eq!((updates[1].0, updates[1].1.count()), (2, 2));
+
+        // Starting in the middle of a batch returns the whole batch.
+        let mut updates = db.get_updates_since(3).unwrap();
+        assert_eq!(updates.next().unwrap().unwrap().0, 2);
+        assert!(updates.next().is_none());
+        assert!(updates.next().is_none());
+    }
+
+    #[test]
+    fn wal_reader_test() {
+        let path = TempDir::new("_rust_rocksdb_walreadertest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_wal_ttl_seconds(3600);
+        let db = Arc::new(DB::open(&opts, path.path().to_str().unwrap())
+            .unwrap());
+        db.put(b"k1", b"v1").unwrap();
+        let batch = WriteBatch::new();
+        batch.put(b"k2", b"v2").unwrap();
+        batch.delete(b"k1").unwrap();
+        db.write(batch).unwrap();
+        db.put(b"k3", b"v3").unwrap();
+
+        let mut reader = WalReader::open(db.clone(), "cdc").unwrap();
+        assert_eq!(reader.applied_sequence(), 0);
+        assert_eq!(reader.lag(), 4);
+        let batches = reader.poll(2).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].0, 1);
+        assert_eq!((batches[1].0, batches[1].1.count()), (2, 2));
+
+        // Nothing is consumed until it is acknowledged.
+        assert_eq!(reader.poll(10).unwrap().len(), 3);
+        reader.ack(batches[1].0, &batches[1].1).unwrap();
+        assert_eq!(reader.applied_sequence(), 3);
+        assert_eq!(reader.lag(), 1);
+        drop(reader);
+
+        // A reopened consumer resumes after the acknowledged batch.
+        let mut reader = WalReader::open(db.clone(), "cdc").unwrap();
+        assert_eq!(reader.applied_sequence(), 3);
+        reader.set_hold_files(true).unwrap();
+        db.flush(true).unwrap();
+        let batches = reader.poll(10).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].0, 4);
+        reader.ack(batches[0].0, &batches[0].1).unwrap();
+        assert!(reader.poll(10).unwrap().is_empty());
+        reader.set_hold_files(false).unwrap();
+
+        // Other consumers are tracked separately.
+        let reader = WalReader::open(db.clone(), "other").unwrap();
+        assert_eq!(reader.poll(10).unwrap().len(), 3);
+
+        for name in &["", "../cdc", "a/b", "a.b"] {
+            assert!(WalReader::open(db.clone(), name).is_err());
+        }
+    }
+}
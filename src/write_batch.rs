@@ -0,0 +1,220 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use coding::{WRITE_BATCH_HEADER_SIZE, decode_fixed32, get_length_prefixed,
+             get_varint32};
+use rocksdb::WriteBatch;
+
+// Record tags, from rocksdb's db/dbformat.h.
+const TYPE_DELETION: u8 = 0x0;
+const TYPE_VALUE: u8 = 0x1;
+const TYPE_MERGE: u8 = 0x2;
+const TYPE_LOG_DATA: u8 = 0x3;
+const TYPE_CF_DELETION: u8 = 0x4;
+const TYPE_CF_VALUE: u8 = 0x5;
+const TYPE_CF_MERGE: u8 = 0x6;
+const TYPE_SINGLE_DELETION: u8 = 0x7;
+const TYPE_CF_SINGLE_DELETION: u8 = 0x8;
+const TYPE_BEGIN_PREPARE_XID: u8 = 0x9;
+const TYPE_END_PREPARE_XID: u8 = 0xA;
+const TYPE_COMMIT_XID: u8 = 0xB;
+const TYPE_ROLLBACK_XID: u8 = 0xC;
+const TYPE_NOOP: u8 = 0xD;
+const TYPE_CF_RANGE_DELETION: u8 = 0xE;
+const TYPE_RANGE_DELETION: u8 = 0xF;
+const TYPE_BEGIN_PERSISTED_PREPARE_XID: u8 = 0x12;
+const TYPE_BEGIN_UNPREPARE_XID: u8 = 0x13;
+
+/// Receives the operations of a write batch, see `WriteBatch::iterate`.
+///
+/// `cf` is the id of the column family, 0 for the default one. Single
+/// deletes are passed to `delete` unless `single_delete` is overridden,
+/// and the blobs added with log data are ignored unless `log_data` is.
+pub trait WriteBatchHandler {
+    fn put(&mut self, cf: u32, key: &[u8], value: &[u8]);
+    fn delete(&mut self, cf: u32, key: &[u8]);
+    fn merge(&mut self, cf: u32, key: &[u8], value: &[u8]);
+    /// Delete the keys in [`begin_key`, `end_key`).
+    fn delete_range(&mut self, cf: u32, begin_key: &[u8], end_key: &[u8]);
+
+    fn single_delete(&mut self, cf: u32, key: &[u8]) {
+        self.delete(cf, key)
+    }
+
+    fn log_data(&mut self, _: &[u8]) {}
+}
+
+impl WriteBatch {
+    /// Pass the operations of the batch to `handler`, in the order they
+    /// were added.
+    pub fn iterate<H: WriteBatchHandler>(&self,
+                                         handler: &mut H)
+                                         -> Result<(), String> {
+        iterate_write_batch(self.data(), handler)
+    }
+}
+
+/// Pass the operations of the serialized write batch `data`, e.g. from
+/// `WriteBatch::data` or a replication stream, to `handler`.
+///
+/// The batch is checked as it is decoded, so an error may come after
+/// some operations were passed on.
+pub fn iterate_write_batch<H: WriteBatchHandler>(data: &[u8],
+                                                 handler: &mut H)
+                                                 -> Result<(), String> {
+    if data.len() < WRITE_BATCH_HEADER_SIZE {
+        return Err("Corruption: malformed WriteBatch (too small)".to_owned());
+    }
+    let count = decode_fixed32(&data[8..]);
+    let mut input = &data[WRITE_BATCH_HEADER_SIZE..];
+    let mut found = 0;
+    while !input.is_empty() {
+        let tag = input[0];
+        input = &input[1..];
+        let cf = match tag {
+            TYPE_CF_DELETION |
+            TYPE_CF_VALUE |
+            TYPE_CF_MERGE |
+            TYPE_CF_SINGLE_DELETION |
+            TYPE_CF_RANGE_DELETION => try!(get_varint32(&mut input)),
+            _ => 0,
+        };
+        match tag {
+            TYPE_VALUE | TYPE_CF_VALUE => {
+                let key = try!(get_length_prefixed(&mut input));
+                let value = try!(get_length_prefixed(&mut input));
+                handler.put(cf, key, value);
+            }
+            TYPE_DELETION | TYPE_CF_DELETION => {
+                handler.delete(cf, try!(get_length_prefixed(&mut input)));
+            }
+            TYPE_SINGLE_DELETION | TYPE_CF_SINGLE_DELETION => {
+                handler.single_delete(cf,
+                                      try!(get_length_prefixed(&mut input)));
+            }
+            TYPE_MERGE | TYPE_CF_MERGE => {
+                let key = try!(get_length_prefixed(&mut input));
+                let value = try!(get_length_prefixed(&mut input));
+                handler.merge(cf, key, value);
+            }
+            TYPE_RANGE_DELETION | TYPE_CF_RANGE_DELETION => {
+                let begin = try!(get_length_prefixed(&mut input));
+                let end = try!(get_length_prefixed(&mut input));
+                handler.delete_range(cf, begin, end);
+            }
+            TYPE_LOG_DATA => {
+                handler.log_data(try!(get_length_prefixed(&mut input)));
+                continue;
+            }
+            // Transaction markers are not operations.
+            TYPE_BEGIN_PREPARE_XID |
+            TYPE_BEGIN_PERSISTED_PREPARE_XID |
+            TYPE_BEGIN_UNPREPARE_XID |
+            TYPE_NOOP => continue,
+            TYPE_END_PREPARE_XID | TYPE_COMMIT_XID | TYPE_ROLLBACK_XID => {
+                try!(get_length_prefixed(&mut input));
+                continue;
+            }
+            _ => {
+                return Err(format!("Not implemented: WriteBatch record type \
+                                    {:#x}",
+                                   tag))
+            }
+        }
+        found += 1;
+    }
+    if found != count {
+        return Err(format!("Corruption: WriteBatch has wrong count, {} \
+                            instead of {}",
+                           found,
+                           count));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, Writable, WriteBatch};
+    use rocksdb_options::Options;
+    use tempdir::TempDir;
+
+    #[derive(Default)]
+    struct Recorder {
+        ops: Vec<String>,
+    }
+
+    impl WriteBatchHandler for Recorder {
+        fn put(&mut self, cf: u32, key: &[u8], value: &[u8]) {
+            self.ops.push(format!("put {} {:?} {:?}", cf, key, value));
+        }
+
+        fn delete(&mut self, cf: u32, key: &[u8]) {
+            self.ops.push(format!("delete {} {:?}", cf, key));
+        }
+
+        fn merge(&mut self, cf: u32, key: &[u8], value: &[u8]) {
+            self.ops.push(format!("merge {} {:?} {:?}", cf, key, value));
+        }
+
+        fn delete_range(&mut self, cf: u32, begin_key: &[u8], end_key: &[u8]) {
+            self.ops.push(format!("delete_range {} {:?} {:?}",
+                                  cf,
+                                  begin_key,
+                                  end_key));
+        }
+    }
+
+    #[test]
+    fn iterate_write_batch_test() {
+        let path = TempDir::new("_rust_rocksdb_batchiteratetest").expect("");
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        let mut db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+        let cf = db.create_cf("cf", &opts).unwrap();
+
+        let batch = WriteBatch::new();
+        batch.put(b"k1", b"v1").unwrap();
+        batch.put_cf(cf, b"k2", b"v2").unwrap();
+        batch.delete(b"k1").unwrap();
+        batch.single_delete_cf(cf, b"k2").unwrap();
+        batch.merge_cf(cf, b"k3", b"m").unwrap();
+        batch.delete_range(b"a", b"b").unwrap();
+        let mut recorder = Recorder::default();
+        batch.iterate(&mut recorder).unwrap();
+        assert_eq!(recorder.ops,
+                   vec!["put 0 [107, 49] [118, 49]",
+                        "put 1 [107, 50] [118, 50]",
+                        "delete 0 [107, 49]",
+                        "delete 1 [107, 50]",
+                        "merge 1 [107, 51] [109]",
+                        "delete_range 0 [97] [98]"]);
+
+        // Batches read back from the WAL decode the same way.
+        db.write(batch).unwrap();
+        let mut updates = db.get_updates_since(1).unwrap();
+        let (_, batch) = updates.next().unwrap().unwrap();
+        let mut from_wal = Recorder::default();
+        batch.iterate(&mut from_wal).unwrap();
+        assert_eq!(from_wal.ops, recorder.ops);
+
+        let batch = WriteBatch::new();
+        batch.put(b"k1", b"v1").unwrap();
+        let mut data = batch.data().to_vec();
+        data.pop();
+        assert!(iterate_write_batch(&data, &mut Recorder::default()).is_err());
+        assert!(iterate_write_batch(b"short", &mut Recorder::default())
+            .is_err());
+    }
+}
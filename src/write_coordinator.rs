@@ -0,0 +1,261 @@
+// Copyright 2016 Tyler Neely
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use coding::WRITE_BATCH_HEADER_SIZE;
+use rocksdb::{DB, WriteBatch};
+use rocksdb_options::WriteOptions;
+
+struct State {
+    records: Vec<u8>,
+    count: u32,
+    members: usize,
+    group: u64,
+    has_leader: bool,
+    // Results of finished groups, with the number of members that haven't
+    // picked it up yet.
+    results: HashMap<u64, (Result<(), String>, usize)>,
+}
+
+/// Commits the batches written from many threads together.
+///
+/// The first writer of a group waits for `window` and then commits every
+/// batch written in the meantime as a single synced write batch, so the
+/// whole group pays for one WAL sync. Each writer gets the result of the
+/// write its batch was part of.
+pub struct WriteCoordinator {
+    db: Arc<DB>,
+    window: Duration,
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+impl WriteCoordinator {
+    pub fn new(db: Arc<DB>, window: Duration) -> WriteCoordinator {
+        WriteCoordinator {
+            db: db,
+            window: window,
+            state: Mutex::new(State {
+                records: vec![],
+                count: 0,
+                members: 0,
+                group: 0,
+                has_leader: false,
+                results: HashMap::new(),
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub fn db(&self) -> &Arc<DB> {
+        &self.db
+    }
+
+    /// Write `batch` durably, together with the batches written by other
+    /// threads around the same time.
+    pub fn write(&self, batch: WriteBatch) -> Result<(), String> {
+        let data = batch.data();
+        if data.len() < WRITE_BATCH_HEADER_SIZE {
+            return Err("Invalid write batch".to_owned());
+        }
+
+        let mut state = self.lock_state();
+        state.records.extend_from_slice(&data[WRITE_BATCH_HEADER_SIZE..]);
+        state.count += batch.count() as u32;
+        state.members += 1;
+        let group = state.group;
+
+        if state.has_leader {
+            loop {
+                let done = match state.results.get_mut(&group) {
+                    Some(&mut (ref res, ref mut left)) => {
+                        *left -= 1;
+                        Some((res.clone(), *left))
+                    }
+                    None => None,
+                };
+                if let Some((res, left)) = done {
+                    if left == 0 {
+                        state.results.remove(&group);
+                    }
+                    return res;
+                }
+                state = self.cond
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        }
+
+        state.has_leader = true;
+        drop(state);
+        let mut guard = LeaderGuard {
+            wc: self,
+            group: group,
+            followers: None,
+            result: None,
+        };
+        thread::sleep(self.window);
+
+        let (data, followers) = close_group(&mut self.lock_state());
+        guard.followers = Some(followers);
+
+        let mut wo = WriteOptions::new();
+        wo.set_sync(true);
+        let res = WriteBatch::from_data(&data)
+            .and_then(|batch| self.db.write_opt(batch, &wo));
+        guard.result = Some(res.clone());
+        res
+    }
+
+    // A panic never holds the lock while the state is inconsistent.
+    fn lock_state(&self) -> MutexGuard<State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Take the batches of the current group, return them as one batch with
+// the number of followers waiting for its result.
+fn close_group(state: &mut State) -> (Vec<u8>, usize) {
+    let mut data = vec![0; WRITE_BATCH_HEADER_SIZE];
+    let count = state.count;
+    for i in 0..4 {
+        data[8 + i] = (count >> (8 * i)) as u8;
+    }
+    data.append(&mut state.records);
+    let followers = state.members - 1;
+    state.count = 0;
+    state.members = 0;
+    state.group += 1;
+    state.has_leader = false;
+    (data, followers)
+}
+
+// Hands the result of a group to its followers once the leader is done
+// with it, failing the group if the leader panicked so they don't wait
+// for it forever.
+struct LeaderGuard<'a> {
+    wc: &'a WriteCoordinator,
+    group: u64,
+    // Known once the group is closed.
+    followers: Option<usize>,
+    result: Option<Result<(), String>>,
+}
+
+impl<'a> Drop for LeaderGuard<'a> {
+    fn drop(&mut self) {
+        let res = self.result.take().unwrap_or_else(|| {
+            Err("Write group failed, its leader panicked".to_owned())
+        });
+        let mut state = self.wc.lock_state();
+        let followers = match self.followers {
+            Some(followers) => followers,
+            None => close_group(&mut state).1,
+        };
+        if followers > 0 {
+            state.results.insert(self.group, (res, followers));
+            self.wc.cond.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocksdb::{DB, WriteBatch, Writable};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    #[test]
+    fn write_coordinator_test() {
+        let path = TempDir::new("_rust_rocksdb_writecoordinator").expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        let wc = Arc::new(WriteCoordinator::new(Arc::new(db),
+                                                Duration::from_millis(5)));
+
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let wc = wc.clone();
+                thread::spawn(move || {
+                    for j in 0..10 {
+                        let batch = WriteBatch::new();
+                        let key = format!("k{}-{}", i, j);
+                        batch.put(key.as_bytes(), b"v").unwrap();
+                        batch.delete(b"nonexist").unwrap();
+                        wc.write(batch).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        for i in 0..16 {
+            for j in 0..10 {
+                let key = format!("k{}-{}", i, j);
+                assert!(wc.db().get(key.as_bytes()).unwrap().is_some());
+            }
+        }
+        assert!(wc.state.lock().unwrap().results.is_empty());
+    }
+
+    #[test]
+    fn leader_panic_test() {
+        let path = TempDir::new("_rust_rocksdb_writecoordinatorpanic")
+            .expect("");
+        let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+        let wc = Arc::new(WriteCoordinator::new(Arc::new(db),
+                                                Duration::from_millis(5)));
+
+        // Stand in for a leader that panics before closing its group.
+        {
+            let mut state = wc.lock_state();
+            state.members = 1;
+            state.has_leader = true;
+        }
+        let guard = LeaderGuard {
+            wc: &wc,
+            group: 0,
+            followers: None,
+            result: None,
+        };
+        let follower = {
+            let wc = wc.clone();
+            thread::spawn(move || {
+                let batch = WriteBatch::new();
+                batch.put(b"k1", b"v1").unwrap();
+                wc.write(batch)
+            })
+        };
+        while wc.lock_state().members < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(guard);
+        let err = follower.join().unwrap().unwrap_err();
+        assert!(err.contains("panicked"), "{}", err);
+        assert!(wc.db().get(b"k1").unwrap().is_none());
+
+        // The next group gets a leader of its own.
+        let batch = WriteBatch::new();
+        batch.put(b"k2", b"v2").unwrap();
+        wc.write(batch).unwrap();
+        assert!(wc.lock_state().results.is_empty());
+    }
+}
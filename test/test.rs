@@ -4,3 +4,8 @@ extern crate tempdir;
 mod test_iterator;
 mod test_multithreaded;
 mod test_column_family;
+mod test_rocksdb_options;
+mod test_manifest;
+mod test_callback;
+mod test_model;
+mod test_timestamp;
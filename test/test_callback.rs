@@ -0,0 +1,44 @@
+use rocksdb::{DB, MergeOperands, Options, PanicPolicy, Writable,
+              set_panic_policy};
+use tempdir::TempDir;
+
+fn panicking_merge(_: &[u8], _: Option<&[u8]>, _: &mut MergeOperands) -> Vec<u8> {
+    panic!("merge failed");
+}
+
+// Restores the default panic policy when dropped, even if the test fails.
+struct PoisonPolicy;
+
+impl PoisonPolicy {
+    fn set() -> PoisonPolicy {
+        set_panic_policy(PanicPolicy::Poison);
+        PoisonPolicy
+    }
+}
+
+impl Drop for PoisonPolicy {
+    fn drop(&mut self) {
+        set_panic_policy(PanicPolicy::Abort);
+    }
+}
+
+#[test]
+pub fn test_merge_operator_panic_poisons() {
+    let path = TempDir::new("_rust_rocksdb_callbackpanic").expect("");
+    let _policy = PoisonPolicy::set();
+
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.add_merge_operator("panicking operator", panicking_merge);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"a").unwrap();
+    db.merge(b"k1", b"b").unwrap();
+    assert!(db.get(b"k1").is_err());
+    // Later merges fail without calling the operator again.
+    db.put(b"k2", b"a").unwrap();
+    db.merge(b"k2", b"b").unwrap();
+    assert!(db.get(b"k2").is_err());
+    // Keys that don't need merging are still readable.
+    db.put(b"k3", b"a").unwrap();
+    assert!(db.get(b"k3").unwrap().is_some());
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
-use rocksdb::{DB, MergeOperands, Options, Writable};
+use rocksdb::{DB, Error, MergeOperands, Options, SeekKey, Writable};
 use tempdir::TempDir;
 
 #[test]
@@ -48,11 +48,31 @@ pub fn test_column_family() {
             }
             Err(e) => {
                 assert!(e.starts_with("Invalid argument: You have to open \
-                                       all column families."))
+                                       all column families."));
+                assert!(e.contains("\"cf1\""));
             }
         }
     }
 
+    // should name the column families that don't exist
+    {
+        let opts = Options::new();
+        match DB::open_cf(&opts, path_str, &["cf1", "cf2"], &[&opts, &opts]) {
+            Ok(_) => panic!("should not have opened DB with unknown cf"),
+            Err(e) => assert!(e.contains("\"cf2\"")),
+        }
+    }
+
+    // should create missing column families when asked to
+    {
+        let mut opts = Options::new();
+        opts.create_missing_column_families(true);
+        let mut db = DB::open_cf(&opts, path_str, &["cf1", "cf2"], &[&opts, &opts])
+            .unwrap();
+        assert_eq!(db.cf_names(), vec!["cf1", "cf2", "default"]);
+        db.drop_cf("cf2").unwrap();
+    }
+
     // should properly open db when specyfing all column families
     {
         let mut opts = Options::new();
@@ -119,10 +139,25 @@ pub fn test_column_family() {
                                  &["cf1"],
                                  &[&Options::new()])
             .unwrap();
+        let cf1 = *db.cf_handle("cf1").unwrap();
+        db.put_cf(cf1, b"k1", b"v1").unwrap();
         match db.drop_cf("cf1") {
             Ok(_) => println!("cf1 successfully dropped."),
             Err(e) => panic!("failed to drop column family: {}", e),
         }
+        assert!(db.cf_handle("cf1").is_none());
+        assert_eq!(db.cf_names(), vec!["default"]);
+        // The copy of the handle is still valid.
+        assert_eq!(&*db.get_cf(cf1, b"k1").unwrap().unwrap(), b"v1");
+        assert_eq!(db.drop_cf("cf1"),
+                   Err(Error::InvalidColumnFamily(Some("cf1".to_owned()))));
+        match db.drop_cf("default") {
+            Ok(_) => panic!("should not drop the default column family"),
+            Err(Error::InvalidArgument(e)) => {
+                assert!(e.contains("Can't drop default column family"))
+            }
+            Err(e) => panic!("unexpected {:?}", e),
+        }
     }
 }
 
@@ -147,3 +182,117 @@ fn test_provided_merge(_: &[u8],
     }
     result
 }
+
+fn reverse_compare(a: &[u8], b: &[u8]) -> i32 {
+    b.cmp(a) as i32
+}
+
+#[test]
+pub fn test_cf_comparator_and_merge_operator() {
+    let path = TempDir::new("_rust_rocksdb_cf_callbacks").expect("");
+    let path_str = path.path().to_str().unwrap();
+
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    let mut index_opts = Options::new();
+    index_opts.add_comparator("reverse", reverse_compare);
+    let mut data_opts = Options::new();
+    data_opts.add_merge_operator("test operator", test_provided_merge);
+    let db = DB::open_cf(&opts,
+                         path_str,
+                         &["index", "data"],
+                         &[&index_opts, &data_opts])
+        .unwrap();
+    // The callbacks must outlive the options they were added to.
+    drop(index_opts);
+    drop(data_opts);
+
+    let index = *db.cf_handle("index").unwrap();
+    let data = *db.cf_handle("data").unwrap();
+    for k in &[b"k1", b"k2", b"k3"] {
+        db.put_cf(index, *k, b"").unwrap();
+    }
+    let mut iter = db.iter_cf(index);
+    iter.seek(SeekKey::Start);
+    let keys: Vec<_> = (&mut iter).map(|(k, _)| k).collect();
+    assert_eq!(keys, vec![b"k3".to_vec(), b"k2".to_vec(), b"k1".to_vec()]);
+
+    db.put_cf(data, b"k1", b"a").unwrap();
+    db.merge_cf(data, b"k1", b"b").unwrap();
+    db.merge_cf(data, b"k1", b"c").unwrap();
+    db.flush(true).unwrap();
+    assert_eq!(db.get_cf(data, b"k1").unwrap().unwrap(), &b"abc"[..]);
+}
+
+#[test]
+fn test_cf_with_ttl() {
+    let path = TempDir::new("_rust_rocksdb_cfttltest").expect("");
+    let path_str = path.path().to_str().unwrap();
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    assert!(DB::open_cf_with_ttl(&opts, path_str, &["raft"], &[&opts], &[])
+        .is_err());
+
+    let mut db = DB::open_cf_with_ttl(&opts,
+                                      path_str,
+                                      &["raft", "data"],
+                                      &[&opts, &opts],
+                                      &[3600, 0])
+        .unwrap();
+    let raft = *db.cf_handle("raft").unwrap();
+    db.put_cf(raft, b"k1", b"v1").unwrap();
+    db.flush(true).unwrap();
+    assert_eq!(db.get_cf(raft, b"k1").unwrap().unwrap(), &b"v1"[..]);
+
+    let meta = db.create_cf_with_ttl("meta", &opts, 60).unwrap();
+    db.put_cf(meta, b"k2", b"v2").unwrap();
+    assert_eq!(db.get_cf(meta, b"k2").unwrap().unwrap(), &b"v2"[..]);
+    drop(db);
+
+    let db = DB::open_cf_with_ttl(&opts,
+                                  path_str,
+                                  &["raft", "data", "meta"],
+                                  &[&opts, &opts, &opts],
+                                  &[3600, 0, 60])
+        .unwrap();
+    let meta = *db.cf_handle("meta").unwrap();
+    assert_eq!(db.get_cf(meta, b"k2").unwrap().unwrap(), &b"v2"[..]);
+    drop(db);
+
+    // A DB opened without a ttl can't create column families with one.
+    let mut db = DB::open_cf(&opts,
+                             path_str,
+                             &["raft", "data", "meta"],
+                             &[&opts, &opts, &opts])
+        .unwrap();
+    assert!(db.create_cf_with_ttl("logs", &opts, 60).is_err());
+    assert!(db.cf_handle("logs").is_none());
+}
+
+#[test]
+fn test_list_column_families() {
+    let path = TempDir::new("_rust_rocksdb_listcftest").expect("");
+    let path_str = path.path().to_str().unwrap();
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    assert!(DB::list_column_families(&opts, path_str).is_err());
+
+    {
+        let mut db = DB::open(&opts, path_str).unwrap();
+        db.create_cf("raft", &opts).unwrap();
+        db.create_cf("data", &opts).unwrap();
+    }
+
+    let mut cfs = DB::list_column_families(&opts, path_str).unwrap();
+    cfs.sort();
+    assert_eq!(cfs, vec!["data", "default", "raft"]);
+
+    // Open the DB with whatever column families it has.
+    let names: Vec<&str> = cfs.iter().map(|n| n.as_str()).collect();
+    let cf_opts: Vec<&Options> = names.iter().map(|_| &opts).collect();
+    let db = DB::open_cf(&opts, path_str, &names, &cf_opts).unwrap();
+    assert_eq!(db.cf_names(), vec!["data", "default", "raft"]);
+}
@@ -1,4 +1,4 @@
-use rocksdb::{DB, Writable, SeekKey, DBIterator, Kv};
+use rocksdb::{DB, Writable, SeekKey, DBIterator, Kv, ReadOptions};
 use tempdir::TempDir;
 
 fn prev_collect<'a>(iter: &mut DBIterator<'a>) -> Vec<Kv> {
@@ -113,3 +113,103 @@ pub fn test_iterator() {
     iter.prev();
     assert!(!iter.valid());
 }
+
+#[test]
+pub fn test_iterator_entry() {
+    let path = TempDir::new("_rust_rocksdb_iteratortest_entry").expect("");
+    let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+
+    let mut iter = db.iter();
+    // Not positioned yet.
+    assert_eq!(iter.key(), None);
+    assert_eq!(iter.entry(), None);
+    assert!(iter.seek(SeekKey::Start));
+    assert_eq!(iter.key(), Some(&b"k1"[..]));
+    assert_eq!(iter.value(), Some(&b"v1"[..]));
+    assert_eq!(iter.entry(), Some((&b"k1"[..], &b"v1"[..])));
+    assert!(!iter.next());
+    assert_eq!(iter.value(), None);
+    assert_eq!(iter.entry(), None);
+    assert_eq!(iter.timestamp(), None);
+}
+
+#[test]
+pub fn test_iter_at() {
+    let path = TempDir::new("_rust_rocksdb_iteratortest_at").expect("");
+    let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.put(b"k2", b"v2").unwrap();
+    db.put(b"k3", b"v3").unwrap();
+    let snap = db.snapshot();
+    db.put(b"k2", b"v22").unwrap();
+    db.put(b"k4", b"v4").unwrap();
+
+    let (start, end): (&[u8], &[u8]) = (b"k2", b"k4");
+    let mut iter = db.iter_at(&snap, Some(start), Some(end));
+    assert_eq!(iter.collect::<Vec<_>>(),
+               vec![(b"k2".to_vec(), b"v2".to_vec()),
+                    (b"k3".to_vec(), b"v3".to_vec())]);
+
+    // The lower bound stops backward iteration too.
+    iter.seek(SeekKey::End);
+    assert_eq!(prev_collect(&mut iter),
+               vec![(b"k3".to_vec(), b"v3".to_vec()),
+                    (b"k2".to_vec(), b"v2".to_vec())]);
+
+    let mut iter = db.iter_at(&snap, None, None);
+    assert_eq!(iter.collect::<Vec<_>>().len(), 3);
+}
+
+#[test]
+pub fn test_iterator_owns_read_options() {
+    let path = TempDir::new("_rust_rocksdb_iteratortest_opts").expect("");
+    let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.put(b"k2", b"v2").unwrap();
+    db.put(b"k3", b"v3").unwrap();
+
+    let mut iter = {
+        // Neither the options nor the bound outlive this block.
+        let bound = b"k3".to_vec();
+        let mut opts = ReadOptions::new();
+        opts.set_iterate_upper_bound(&bound);
+        db.iter_opt(opts)
+    };
+    iter.seek(SeekKey::Start);
+    assert_eq!(iter.collect::<Vec<_>>(),
+               vec![(b"k1".to_vec(), b"v1".to_vec()),
+                    (b"k2".to_vec(), b"v2".to_vec())]);
+
+    let snap = db.snapshot();
+    db.put(b"k4", b"v4").unwrap();
+    let cf = *db.cf_handle("default").unwrap();
+    let mut iter = snap.iter_cf(cf);
+    iter.seek(SeekKey::Start);
+    assert_eq!(iter.collect::<Vec<_>>().len(), 3);
+}
+
+#[test]
+fn test_max_skippable_internal_keys() {
+    let path = TempDir::new("_rust_rocksdb_maxskippable").expect("");
+    let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+    for i in 0..100 {
+        let k = format!("k{:03}", i);
+        db.put(k.as_bytes(), b"v").unwrap();
+        db.delete(k.as_bytes()).unwrap();
+    }
+    db.put(b"z", b"v").unwrap();
+
+    let mut opts = ReadOptions::new();
+    opts.set_max_skippable_internal_keys(10);
+    opts.set_background_purge_on_iterator_cleanup(true);
+    let mut iter = db.iter_opt(opts);
+    assert!(!iter.seek(SeekKey::Start));
+    let err = iter.status().unwrap_err();
+    assert!(err.starts_with("Result incomplete"), "{}", err);
+
+    // Without a limit the tombstones are skipped.
+    let mut iter = db.iter();
+    assert!(iter.seek(SeekKey::Start));
+    assert_eq!(iter.key(), Some(&b"z"[..]));
+}
@@ -0,0 +1,35 @@
+use rocksdb::{DB, Options, Writable};
+use rocksdb::manifest::dump_manifest;
+use tempdir::TempDir;
+
+#[test]
+fn test_dump_manifest() {
+    let path = TempDir::new("_rust_rocksdb_manifest").expect("");
+    let path_str = path.path().to_str().unwrap();
+    {
+        let mut db = DB::open_default(path_str).unwrap();
+        let cf1 = db.create_cf("cf1", &Options::new()).unwrap();
+        db.put(b"a", b"v").unwrap();
+        db.put(b"c", b"v").unwrap();
+        db.flush(true).unwrap();
+        db.put_cf(cf1, b"k", b"v").unwrap();
+    }
+
+    let info = dump_manifest(path_str).unwrap();
+    assert!(info.manifest.starts_with("MANIFEST-"));
+    assert!(info.last_sequence >= 3);
+    let names: Vec<_> = info.column_families
+        .iter()
+        .map(|cf| cf.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["default", "cf1"]);
+
+    let files = &info.column_families[0].levels[&0];
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].smallest_key, b"a");
+    assert_eq!(files[0].largest_key, b"c");
+    assert!(files[0].size > 0);
+
+    assert!(dump_manifest(path.path().join("nonexist").to_str().unwrap())
+        .is_err());
+}
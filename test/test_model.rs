@@ -0,0 +1,187 @@
+use rocksdb::{DB, DBIterator, Kv, MergeOperands, Options, SeekKey, Writable,
+              WriteBatch};
+use rocksdb::rocksdb::Snapshot;
+use std::collections::BTreeMap;
+use tempdir::TempDir;
+
+// Random op sequences are applied both to a DB and to an in-memory model,
+// and every read from the DB is checked against the model.
+
+const SEEDS: u64 = 8;
+const OPS_PER_SEED: usize = 2000;
+const NUM_KEYS: u64 = 64;
+const CFS: [&'static str; 2] = ["default", "cf1"];
+
+type Model = BTreeMap<Vec<u8>, Vec<u8>>;
+
+// xorshift64*, good enough to pick ops and keys.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn key(&mut self) -> Vec<u8> {
+        format!("key{:03}", self.below(NUM_KEYS)).into_bytes()
+    }
+
+    fn value(&mut self) -> Vec<u8> {
+        let len = self.below(16) as usize;
+        (0..len).map(|_| b'a' + self.below(26) as u8).collect()
+    }
+}
+
+fn concat_merge(_: &[u8],
+                existing_val: Option<&[u8]>,
+                operands: &mut MergeOperands)
+                -> Vec<u8> {
+    let mut result = existing_val.map_or(vec![], |v| v.to_vec());
+    for op in operands {
+        result.extend_from_slice(op);
+    }
+    result
+}
+
+fn model_merge(model: &mut Model, key: Vec<u8>, value: &[u8]) {
+    model.entry(key).or_insert_with(Vec::new).extend_from_slice(value);
+}
+
+fn model_delete_range(model: &mut Model, begin: &[u8], end: &[u8]) {
+    let keys: Vec<Vec<u8>> = model.keys()
+        .filter(|k| &k[..] >= begin && &k[..] < end)
+        .cloned()
+        .collect();
+    for k in keys {
+        model.remove(&k);
+    }
+}
+
+fn check_iter(mut iter: DBIterator, model: &Model, seed: u64, step: usize) {
+    let expected: Vec<Kv> =
+        model.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    iter.seek(SeekKey::Start);
+    let forward: Vec<Kv> = (&mut iter).collect();
+    assert_eq!(forward, expected, "seed {} step {}", seed, step);
+
+    iter.seek(SeekKey::End);
+    let mut backward = vec![];
+    while iter.valid() {
+        backward.push(iter.kv().unwrap());
+        iter.prev();
+    }
+    backward.reverse();
+    assert_eq!(backward, expected, "seed {} step {}", seed, step);
+    iter.status().unwrap();
+}
+
+fn run_seed(seed: u64) {
+    let path = TempDir::new("_rust_rocksdb_modeltest").expect("");
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_write_buffer_size(16 << 10);
+    opts.add_merge_operator("concat", concat_merge);
+    let path_str = path.path().to_str().unwrap();
+    let db = DB::open_cf(&opts, path_str, &CFS, &[&opts, &opts]).unwrap();
+    let handles: Vec<_> =
+        CFS.iter().map(|n| *db.cf_handle(n).unwrap()).collect();
+
+    let mut rng = Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) + 1);
+    let mut models = vec![Model::new(), Model::new()];
+    let mut snaps: Vec<(Snapshot, Vec<Model>)> = vec![];
+
+    for step in 0..OPS_PER_SEED {
+        let c = rng.below(CFS.len() as u64) as usize;
+        let cf = handles[c];
+        match rng.below(12) {
+            0 | 1 | 2 => {
+                let (k, v) = (rng.key(), rng.value());
+                db.put_cf(cf, &k, &v).unwrap();
+                models[c].insert(k, v);
+            }
+            3 => {
+                let k = rng.key();
+                db.delete_cf(cf, &k).unwrap();
+                models[c].remove(&k);
+            }
+            4 | 5 => {
+                let (k, v) = (rng.key(), rng.value());
+                db.merge_cf(cf, &k, &v).unwrap();
+                model_merge(&mut models[c], k, &v);
+            }
+            6 => {
+                let (a, b) = (rng.key(), rng.key());
+                let (begin, end) = if a <= b { (a, b) } else { (b, a) };
+                db.delete_range_cf(cf, &begin, &end).unwrap();
+                model_delete_range(&mut models[c], &begin, &end);
+            }
+            7 => {
+                let batch = WriteBatch::new();
+                for _ in 0..rng.below(8) {
+                    let c = rng.below(CFS.len() as u64) as usize;
+                    let k = rng.key();
+                    if rng.below(3) == 0 {
+                        batch.delete_cf(handles[c], &k).unwrap();
+                        models[c].remove(&k);
+                    } else {
+                        let v = rng.value();
+                        batch.put_cf(handles[c], &k, &v).unwrap();
+                        models[c].insert(k, v);
+                    }
+                }
+                db.write(batch).unwrap();
+            }
+            8 => {
+                let k = rng.key();
+                let got = db.get_cf(cf, &k).unwrap().map(|v| v.to_vec());
+                assert_eq!(got.as_ref(),
+                           models[c].get(&k),
+                           "seed {} step {}",
+                           seed,
+                           step);
+            }
+            9 => check_iter(db.iter_cf(cf), &models[c], seed, step),
+            10 => {
+                if snaps.len() < 4 {
+                    snaps.push((db.snapshot(), models.clone()));
+                } else {
+                    let i = rng.below(snaps.len() as u64) as usize;
+                    let (snap, snap_models) = snaps.swap_remove(i);
+                    check_iter(snap.iter_cf(cf), &snap_models[c], seed, step);
+                    let k = rng.key();
+                    let got = snap.get_cf(cf, &k).unwrap().map(|v| v.to_vec());
+                    assert_eq!(got.as_ref(),
+                               snap_models[c].get(&k),
+                               "seed {} step {}",
+                               seed,
+                               step);
+                }
+            }
+            _ => db.flush(true).unwrap(),
+        }
+    }
+
+    for (c, cf) in handles.iter().enumerate() {
+        check_iter(db.iter_cf(*cf), &models[c], seed, OPS_PER_SEED);
+    }
+    for &(ref snap, ref snap_models) in &snaps {
+        for (c, cf) in handles.iter().enumerate() {
+            check_iter(snap.iter_cf(*cf), &snap_models[c], seed, OPS_PER_SEED);
+        }
+    }
+}
+
+#[test]
+fn test_random_ops_match_model() {
+    for seed in 0..SEEDS {
+        run_seed(seed);
+    }
+}
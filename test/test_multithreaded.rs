@@ -1,4 +1,4 @@
-use rocksdb::{DB, Writable};
+use rocksdb::{DB, Options, SyncDb, Writable};
 use std::thread;
 use std::sync::Arc;
 use tempdir::TempDir;
@@ -48,3 +48,58 @@ pub fn test_multithreaded() {
     j2.join().unwrap();
     j3.join().unwrap();
 }
+
+#[test]
+pub fn test_sync_db_cf_management() {
+    let path = TempDir::new("_rust_rocksdb_syncdbtest").expect("");
+    let db = DB::open_default(path.path().to_str().unwrap()).unwrap();
+    let db = Arc::new(SyncDb::new(db));
+    db.put_cf("default", b"key", b"value").unwrap();
+
+    let admin = db.clone();
+    let j1 = thread::spawn(move || {
+        let opts = Options::new();
+        for i in 0..50 {
+            let name = format!("cf{}", i % 3);
+            admin.create_cf(&name, &opts).unwrap();
+            admin.put_cf(&name, b"key", b"cf value").unwrap();
+            admin.drop_cf(&name).unwrap();
+        }
+    });
+
+    let mut readers = vec![];
+    for _ in 0..2 {
+        let db = db.clone();
+        readers.push(thread::spawn(move || {
+            for i in 0..N / 100 {
+                let value = db.get_cf("default", b"key").unwrap().unwrap();
+                assert_eq!(&*value, b"value");
+                // The column family may or may not exist, but is never
+                // read after being dropped.
+                let name = format!("cf{}", i % 3);
+                match db.get_cf(&name, b"key") {
+                    Ok(Some(v)) => assert_eq!(&*v, b"cf value"),
+                    Ok(None) => {}
+                    Err(e) => assert!(e.starts_with("Invalid column family")),
+                }
+            }
+        }));
+    }
+
+    j1.join().unwrap();
+    for j in readers {
+        j.join().unwrap();
+    }
+    assert_eq!(db.cf_names(), vec!["default".to_owned()]);
+
+    // A handle kept past its guard outlives the drop of its column family.
+    db.create_cf("kept", &Options::new()).unwrap();
+    db.put_cf("kept", b"key", b"kept value").unwrap();
+    let kept = *db.read().cf_handle("kept").unwrap();
+    db.drop_cf("kept").unwrap();
+    assert_eq!(&*db.read().get_cf(kept, b"key").unwrap().unwrap(),
+               b"kept value");
+
+    let db = Arc::try_unwrap(db).ok().unwrap().into_inner();
+    assert!(db.cf_handle("cf0").is_none());
+}
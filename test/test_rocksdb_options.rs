@@ -0,0 +1,334 @@
+use rocksdb::{BlockBasedOptions, Cache, CacheStats, DB, DBChecksumType,
+              DBDataBlockIndexType, Env, FlushOptions, Options, Priority,
+              RateLimiter, Writable};
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use tempdir::TempDir;
+
+#[test]
+fn test_set_db_paths() {
+    let path = TempDir::new("_rust_rocksdb_dbpaths").expect("");
+    let hot = path.path().join("hot");
+    let cold = path.path().join("cold");
+    let hot_str = hot.to_str().unwrap();
+    let cold_str = cold.to_str().unwrap();
+
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_db_paths(&[(hot_str, 0), (cold_str, 1 << 30)]);
+    let db = DB::open(&opts, path.path().join("db").to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.flush(true).unwrap();
+
+    // The first path has no room, so the flushed file goes to the second.
+    let ssts = fs::read_dir(&cold)
+        .unwrap()
+        .filter(|e| {
+            e.as_ref().unwrap().path().extension().map_or(false, |x| x == "sst")
+        })
+        .count();
+    assert_eq!(ssts, 1);
+}
+
+#[test]
+fn test_max_manifest_file_size() {
+    let path = TempDir::new("_rust_rocksdb_manifestsize").expect("");
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_max_manifest_file_size(1);
+    opts.set_manifest_preallocation_size(4096);
+    opts.set_wal_bytes_per_sync(1 << 20);
+    opts.set_max_total_wal_size(1 << 30);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    for i in 0..3 {
+        db.put(format!("k{}", i).as_bytes(), b"v").unwrap();
+        db.flush(true).unwrap();
+    }
+    drop(db);
+
+    // Each edit rolls the MANIFEST over, the DB must still recover.
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    for i in 0..3 {
+        assert!(db.get(format!("k{}", i).as_bytes()).unwrap().is_some());
+    }
+}
+
+#[test]
+fn test_options_shared_across_opens() {
+    let path = TempDir::new("_rust_rocksdb_sharedopts").expect("");
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    let opts = Arc::new(opts);
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let opts = opts.clone();
+            let db_path = path.path().join(format!("db{}", i));
+            thread::spawn(move || {
+                let db = DB::open(&opts, db_path.to_str().unwrap()).unwrap();
+                db.put(b"k", b"v").unwrap();
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    // A clone is independent of the original.
+    let mut cloned = (*opts).clone();
+    cloned.create_if_missing(false);
+    assert!(DB::open(&cloned, path.path().join("db4").to_str().unwrap())
+        .is_err());
+    assert!(DB::open(&opts, path.path().join("db4").to_str().unwrap()).is_ok());
+}
+
+#[test]
+fn test_set_ratelimiter() {
+    let path = TempDir::new("_rust_rocksdb_ratelimiter").expect("");
+    let limiter = RateLimiter::new_auto_tuned(10 << 20, 100_000, 10);
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_ratelimiter(&limiter);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    drop(opts);
+    limiter.set_bytes_per_second(100 << 20);
+    db.put(b"k", b"v").unwrap();
+    db.flush(true).unwrap();
+    drop(limiter);
+    db.put(b"k", b"v2").unwrap();
+    db.flush(true).unwrap();
+}
+
+#[test]
+fn test_max_file_opening_threads() {
+    let path = TempDir::new("_rust_rocksdb_fileopening").expect("");
+    let path_str = path.path().to_str().unwrap();
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    opts.set_max_open_files(-1);
+    opts.set_max_file_opening_threads(8);
+    let names: Vec<String> = (0..16).map(|i| format!("cf{}", i)).collect();
+    let cfs: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+    let cf_opts: Vec<&Options> = cfs.iter().map(|_| &opts).collect();
+    {
+        let db = DB::open_cf(&opts, path_str, &cfs, &cf_opts).unwrap();
+        for cf in &cfs {
+            let handle = *db.cf_handle(cf).unwrap();
+            db.put_cf(handle, b"k", b"v").unwrap();
+        }
+        db.flush(true).unwrap();
+    }
+    let db = DB::open_cf(&opts, path_str, &cfs, &cf_opts).unwrap();
+    for cf in &cfs {
+        let handle = *db.cf_handle(cf).unwrap();
+        assert!(db.get_cf(handle, b"k").unwrap().is_some());
+    }
+}
+
+#[test]
+fn test_allow_mmap() {
+    let path = TempDir::new("_rust_rocksdb_mmap").expect("");
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.allow_mmap_reads(true);
+    opts.allow_mmap_writes(true);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.flush(true).unwrap();
+    assert_eq!(db.get(b"k1").unwrap().unwrap(), &b"v1"[..]);
+}
+
+#[test]
+fn test_env_background_threads() {
+    let path = TempDir::new("_rust_rocksdb_envthreads").expect("");
+    let mut env = Env::new();
+    env.set_background_threads(Priority::Low, 4);
+    env.set_background_threads(Priority::High, 2);
+    env.set_background_threads(Priority::Bottom, 1);
+    assert_eq!(env.get_background_threads(Priority::Low), 4);
+    assert_eq!(env.get_background_threads(Priority::High), 2);
+    assert_eq!(env.get_background_threads(Priority::Bottom), 1);
+
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_env(&env);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    db.put(b"k", b"v").unwrap();
+    db.flush(true).unwrap();
+    let jobs = db.get_background_jobs().unwrap();
+    assert_eq!(jobs.running_flushes, 0);
+    assert_eq!(jobs.pending_flushes, 0);
+}
+
+#[test]
+fn test_block_based_checksum() {
+    let path = TempDir::new("_rust_rocksdb_checksum").expect("");
+    let mut block_opts = BlockBasedOptions::new();
+    block_opts.set_checksum(DBChecksumType::DBxxHash);
+    block_opts.set_format_version(2);
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_block_based_table_factory(&block_opts);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.flush(true).unwrap();
+    drop(db);
+
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    assert_eq!(&*db.get(b"k1").unwrap().unwrap(), b"v1");
+}
+
+#[test]
+fn test_data_block_hash_index() {
+    let path = TempDir::new("_rust_rocksdb_datablockhash").expect("");
+    let mut block_opts = BlockBasedOptions::new();
+    block_opts.set_data_block_index_type(DBDataBlockIndexType::DBBinarySearchAndHash);
+    block_opts.set_data_block_hash_ratio(0.5);
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_block_based_table_factory(&block_opts);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    for i in 0..1000 {
+        db.put(format!("k{:04}", i).as_bytes(), b"v").unwrap();
+    }
+    db.flush(true).unwrap();
+    assert!(db.get(b"k0500").unwrap().is_some());
+    assert!(db.get(b"k0500x").unwrap().is_none());
+}
+
+#[test]
+fn test_cache_index_and_filter_blocks_with_high_priority() {
+    let path = TempDir::new("_rust_rocksdb_highpricache").expect("");
+    let cache = Cache::new_lru_with_high_pri_pool(8 << 20, 0.5);
+    let mut block_opts = BlockBasedOptions::new();
+    block_opts.set_block_cache(&cache);
+    block_opts.set_bloom_filter(10, false);
+    block_opts.set_cache_index_and_filter_blocks(true);
+    block_opts.set_cache_index_and_filter_blocks_with_high_priority(true);
+    block_opts.set_pin_top_level_index_and_filter(true);
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.set_block_based_table_factory(&block_opts);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.flush(true).unwrap();
+    assert!(db.get(b"k1").unwrap().is_some());
+    assert!(cache.get_usage() > 0);
+}
+
+#[test]
+fn test_cache_stats() {
+    let path = TempDir::new("_rust_rocksdb_cachestats").expect("");
+    let open = |name: &str, cache: &Cache| {
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_block_cache(cache);
+        block_opts.set_cache_index_and_filter_blocks(true);
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.enable_statistics();
+        opts.set_block_based_table_factory(&block_opts);
+        cache.add_statistics(&opts);
+        DB::open(&opts, path.path().join(name).to_str().unwrap()).unwrap()
+    };
+    let busy_cache = Cache::new_lru(8 << 20);
+    let idle_cache = Cache::new_lru(8 << 20);
+    let busy = open("busy", &busy_cache);
+    let _idle = open("idle", &idle_cache);
+    assert_eq!(busy_cache.stats().hit_ratio(), 0.0);
+
+    busy.put(b"k1", b"v1").unwrap();
+    busy.flush(true).unwrap();
+    for _ in 0..10 {
+        assert!(busy.get(b"k1").unwrap().is_some());
+    }
+    let stats = busy_cache.stats();
+    assert!(stats.data_hits > 0);
+    assert!(stats.inserts > 0);
+    assert!(stats.hits >= stats.data_hits + stats.index_hits);
+    assert!(stats.hit_ratio() > 0.5);
+    assert_eq!(idle_cache.stats(), CacheStats::default());
+}
+
+#[test]
+fn test_report_bg_io_stats() {
+    let path = TempDir::new("_rust_rocksdb_bgiostats").expect("");
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    assert!(opts.get_statistics().is_none());
+    opts.enable_statistics();
+    opts.set_report_bg_io_stats(true);
+    opts.set_stats_dump_period_sec(0);
+    let db = DB::open(&opts, path.path().to_str().unwrap()).unwrap();
+    db.put(b"k1", b"v1").unwrap();
+    db.flush(true).unwrap();
+    let stats = opts.get_statistics().unwrap();
+    assert!(stats.contains("rocksdb.flush.write.bytes"));
+    assert!(stats.contains("rocksdb.compact.write.bytes"));
+}
+
+#[test]
+fn test_cf_templates() {
+    let path = TempDir::new("_rust_rocksdb_cftemplates").expect("");
+    let cache = Cache::new_lru(8 << 20);
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    let cfs = ["raft", "lock", "write"];
+    let cf_opts = [Options::new_log_cf(),
+                   Options::new_point_lookup_cf(&cache),
+                   Options::new_scan_cf(&cache)];
+    let db = DB::open_cf(&opts,
+                         path.path().to_str().unwrap(),
+                         &cfs,
+                         &[&cf_opts[0], &cf_opts[1], &cf_opts[2]])
+        .unwrap();
+    for cf in &cfs {
+        let handle = *db.cf_handle(cf).unwrap();
+        db.put_cf(handle, b"k1", b"v1").unwrap();
+        db.flush_cf(handle, &FlushOptions::new()).unwrap();
+        assert_eq!(&*db.get_cf(handle, b"k1").unwrap().unwrap(), b"v1");
+    }
+    assert!(cache.get_usage() > 0);
+}
+
+#[cfg(feature = "cache-dump")]
+#[test]
+fn test_block_cache_dump() {
+    let path = TempDir::new("_rust_rocksdb_cachedump").expect("");
+    let db_path = path.path().join("db");
+    let dump_path = path.path().join("cache.dump");
+    let dump_str = dump_path.to_str().unwrap();
+    let open = |cache: &Cache| {
+        let mut block_opts = BlockBasedOptions::new();
+        block_opts.set_block_cache(cache);
+        let mut opts = Options::new();
+        opts.create_if_missing(true);
+        opts.set_block_based_table_factory(&block_opts);
+        DB::open(&opts, db_path.to_str().unwrap()).unwrap()
+    };
+
+    let cache = Cache::new_lru(8 << 20);
+    {
+        let db = open(&cache);
+        for i in 0..100 {
+            db.put(format!("k{:03}", i).as_bytes(), b"value").unwrap();
+        }
+        db.flush(true).unwrap();
+        assert!(db.get(b"k050").unwrap().is_some());
+        cache.dump(&db, dump_str).unwrap();
+    }
+    assert!(fs::metadata(&dump_path).unwrap().len() > 0);
+
+    let cache = Cache::new_lru_from_dump(8 << 20, 8 << 20, dump_str).unwrap();
+    let db = open(&cache);
+    assert_eq!(&*db.get(b"k050").unwrap().unwrap(), b"value");
+    assert!(cache.get_usage() > 0);
+
+    let missing = path.path().join("missing");
+    assert!(Cache::new_lru_from_dump(8 << 20,
+                                     8 << 20,
+                                     missing.to_str().unwrap())
+        .is_err());
+}
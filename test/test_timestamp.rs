@@ -0,0 +1,93 @@
+use rocksdb::{DB, Options, ReadOptions, SeekKey};
+use tempdir::TempDir;
+
+fn bytewise_compare(a: &[u8], b: &[u8]) -> i32 {
+    a.cmp(b) as i32
+}
+
+fn open_ts_db(path: &TempDir) -> DB {
+    let mut opts = Options::new();
+    opts.create_if_missing(true);
+    opts.add_comparator_with_u64_ts("leveldb.BytewiseComparator.u64ts",
+                                    bytewise_compare);
+    DB::open(&opts, path.path().to_str().unwrap()).unwrap()
+}
+
+fn read_at(ts: u64) -> ReadOptions {
+    let mut opts = ReadOptions::new();
+    opts.set_timestamp(ts);
+    opts
+}
+
+#[test]
+fn test_get_with_ts() {
+    let path = TempDir::new("_rust_rocksdb_gettstest").expect("");
+    let db = open_ts_db(&path);
+    db.put_with_ts(b"k1", 10, b"v10").unwrap();
+    db.put_with_ts(b"k1", 20, b"v20").unwrap();
+    db.delete_with_ts(b"k1", 30).unwrap();
+
+    assert!(db.get_with_ts(b"k1", &read_at(5)).unwrap().is_none());
+    let (v, ts) = db.get_with_ts(b"k1", &read_at(15)).unwrap().unwrap();
+    assert_eq!((&*v, ts), (&b"v10"[..], 10));
+    let (v, ts) = db.get_with_ts(b"k1", &read_at(25)).unwrap().unwrap();
+    assert_eq!((&*v, ts), (&b"v20"[..], 20));
+    assert!(db.get_with_ts(b"k1", &read_at(30)).unwrap().is_none());
+
+    // The history survives a flush.
+    db.flush(true).unwrap();
+    let (v, _) = db.get_with_ts(b"k1", &read_at(15)).unwrap().unwrap();
+    assert_eq!(&*v, b"v10");
+
+    assert!(db.get_with_ts(b"k1", &ReadOptions::new()).is_err());
+}
+
+#[test]
+fn test_iter_with_ts() {
+    let path = TempDir::new("_rust_rocksdb_itertstest").expect("");
+    let db = open_ts_db(&path);
+    db.put_with_ts(b"k1", 10, b"a").unwrap();
+    db.put_with_ts(b"k2", 10, b"b").unwrap();
+    db.put_with_ts(b"k1", 20, b"c").unwrap();
+
+    let mut iter = db.iter_opt(read_at(15));
+    iter.seek(SeekKey::Start);
+    let mut seen = vec![];
+    while iter.valid() {
+        seen.push((iter.key().unwrap().to_vec(), iter.timestamp()));
+        iter.next();
+    }
+    assert_eq!(seen,
+               vec![(b"k1".to_vec(), Some(10)), (b"k2".to_vec(), Some(10))]);
+
+    // With a start timestamp every version in the window is returned,
+    // newest first.
+    let mut opts = read_at(20);
+    opts.set_iter_start_ts(0);
+    let mut iter = db.iter_opt(opts);
+    iter.seek(SeekKey::Start);
+    let mut versions = vec![];
+    while iter.valid() {
+        versions.push(iter.timestamp().unwrap());
+        iter.next();
+    }
+    assert_eq!(versions, vec![20, 10, 10]);
+}
+
+#[test]
+fn test_check_ts_increasing() {
+    let path = TempDir::new("_rust_rocksdb_tsincreasingtest").expect("");
+    let db = open_ts_db(&path);
+    let cf = *db.cf_handle("default").unwrap();
+    db.check_ts_increasing_cf(cf, b"k1", 1).unwrap();
+    db.put_with_ts(b"k1", 10, b"v").unwrap();
+    assert!(db.check_ts_increasing_cf(cf, b"k1", 10).is_err());
+    assert!(db.check_ts_increasing_cf(cf, b"k1", 5).is_err());
+    db.check_ts_increasing_cf(cf, b"k1", 11).unwrap();
+
+    // A deletion hides older writes as well.
+    db.delete_with_ts(b"k1", 20).unwrap();
+    assert!(db.check_ts_increasing_cf(cf, b"k1", 15).is_err());
+    db.check_ts_increasing_cf(cf, b"k1", 21).unwrap();
+    db.check_ts_increasing_cf(cf, b"k", 1).unwrap();
+}